@@ -13,8 +13,32 @@ pub struct FuturesAccount {
     pub balances: HashMap<Vec<u8>, u64>,
     /// state hash of the account
     pub state_hash: Vec<u8>,
+    /// Open positions of the account, all marked at the price of the futures market the account trades
+    #[serde(default)]
+    pub positions: Vec<FuturesPosition>,
+    /// Maintenance margin ratio in basis points of the open notional
+    #[serde(default)]
+    pub maintenance_ratio_bps: u16,
 }
 
+/// An open futures position, all amounts in 8 decimals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct FuturesPosition {
+    /// futures asset id
+    #[serde(with = "serde_bytes")]
+    pub asset_id: Vec<u8>,
+    /// is long position, short otherwise
+    pub is_long: bool,
+    /// position size in base units in 8 decimals
+    pub size: u64,
+    /// average entry price in 8 decimals
+    pub entry_price: u64,
+    /// collateral posted to the position in quote units in 8 decimals
+    pub margin: u64,
+}
+
+const SCALE: u128 = 1_0000_0000;
+
 impl AccountBalances for FuturesAccount {
     fn balances(&self) -> &HashMap<Vec<u8>, u64> {
         &self.balances
@@ -34,4 +58,36 @@ impl FuturesAccount {
         }
         hasher.finalize().as_bytes().to_vec()
     }
+
+    /// Required maintenance margin of the open positions at `mark_price`, using the account's `maintenance_ratio_bps`.
+    pub fn maintenance_margin(&self, mark_price: u64) -> u64 {
+        self.required_margin(mark_price, self.maintenance_ratio_bps)
+    }
+
+    /// Whether the equity of the account at `mark_price` fell below the margin required at `maintenance_ratio_bps`.
+    /// - equity is the posted margin plus the unrealized PnL of the positions, it may be negative.
+    pub fn is_liquidatable(&self, mark_price: u64, maintenance_ratio_bps: u16) -> bool {
+        self.equity(mark_price) < self.required_margin(mark_price, maintenance_ratio_bps) as i128
+    }
+
+    /// Posted margin plus the unrealized PnL of the positions at `mark_price`, in quote units.
+    pub fn equity(&self, mark_price: u64) -> i128 {
+        self.positions
+            .iter()
+            .map(|position| {
+                let pnl = (position.size as i128).saturating_mul(mark_price as i128 - position.entry_price as i128)
+                    / SCALE as i128;
+                position.margin as i128 + if position.is_long { pnl } else { -pnl }
+            })
+            .fold(0, i128::saturating_add)
+    }
+
+    fn required_margin(&self, mark_price: u64, ratio_bps: u16) -> u64 {
+        let notional: u128 = self
+            .positions
+            .iter()
+            .map(|position| position.size as u128 * mark_price as u128 / SCALE)
+            .fold(0, u128::saturating_add);
+        (notional.saturating_mul(ratio_bps as u128) / 10_000).min(u64::MAX as u128) as u64
+    }
 }
@@ -9,13 +9,14 @@ pub trait AccountBalances {
     fn balances(&self) -> &HashMap<Vec<u8>, u64>;
 }
 
-/// Collect all balances from multiple accounts into a flat list.
+/// Collect all balances from multiple accounts into a flat list, summing the balances of the same asset.
 pub fn collect_balances(accounts: &[&dyn AccountBalances], asset_ids: &[Vec<u8>]) -> Vec<(Vec<u8>, u64)> {
     let mut balances = HashMap::new();
     for account in accounts {
         for asset_id in asset_ids {
             if let Some(amount) = account.balances().get(asset_id) {
-                balances.insert(asset_id.clone(), *amount);
+                let entry = balances.entry(asset_id.clone()).or_insert(0u64);
+                *entry = entry.saturating_add(*amount);
             }
         }
     }
@@ -13,8 +13,44 @@ pub struct OptionsAccount {
     pub balances: HashMap<Vec<u8>, u64>,
     /// state hash of the account
     pub state_hash: Vec<u8>,
+    /// Option positions held by the account
+    #[serde(default)]
+    pub positions: Vec<OptionPosition>,
 }
 
+/// An option position held by an options account, all amounts in 8 decimals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct OptionPosition {
+    /// option asset id
+    #[serde(with = "serde_bytes")]
+    pub asset_id: Vec<u8>,
+    /// is call option, put otherwise
+    pub is_call: bool,
+    /// is long position, short otherwise
+    pub is_long: bool,
+    /// strike price in 8 decimals
+    pub strike: u64,
+    /// number of contracts in 8 decimals
+    pub quantity: u64,
+    /// time to expiry in years in 8 decimals, as of the last mark of the account
+    pub time_to_expiry: u64,
+}
+
+/// Black-Scholes sensitivities of an options account in 8 decimals, negative for short or put exposure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Greeks {
+    /// change of the value per unit change of the underlying price
+    pub delta: i64,
+    /// change of the delta per unit change of the underlying price
+    pub gamma: i64,
+    /// change of the value per unit (100%) change of the volatility
+    pub vega: i64,
+    /// change of the value per year passing
+    pub theta: i64,
+}
+
+const SCALE: f64 = 1_0000_0000.0;
+
 impl AccountBalances for OptionsAccount {
     fn balances(&self) -> &HashMap<Vec<u8>, u64> {
         &self.balances
@@ -34,4 +70,73 @@ impl OptionsAccount {
         }
         hasher.finalize().as_bytes().to_vec()
     }
+
+    /// Computes the Black-Scholes Greeks of the held positions.
+    /// - `underlying_price`, `vol` and `rate` are in 8 decimals, e.g. `2000_0000` for a 20% volatility.
+    /// - a position without volatility or time left is valued at intrinsic: its delta is saturated to 0 or 1
+    ///   and the other Greeks are zero.
+    pub fn greeks(&self, underlying_price: u64, vol: u64, rate: u64) -> Greeks {
+        let spot = underlying_price as f64 / SCALE;
+        let vol = vol as f64 / SCALE;
+        let rate = rate as f64 / SCALE;
+        let (mut delta, mut gamma, mut vega, mut theta) = (0.0, 0.0, 0.0, 0.0);
+        for position in &self.positions {
+            let strike = position.strike as f64 / SCALE;
+            let time = position.time_to_expiry as f64 / SCALE;
+            let size = position.quantity as f64 / SCALE * if position.is_long { 1.0 } else { -1.0 };
+            let discounted_strike = strike * (-rate * time).exp();
+
+            if vol == 0.0 || time == 0.0 || spot == 0.0 || strike == 0.0 {
+                let in_the_money = if position.is_call { spot > discounted_strike } else { spot < discounted_strike };
+                if in_the_money {
+                    delta += size * if position.is_call { 1.0 } else { -1.0 };
+                }
+                continue;
+            }
+
+            let sqrt_time = time.sqrt();
+            let d1 = ((spot / strike).ln() + (rate + vol * vol / 2.0) * time) / (vol * sqrt_time);
+            let d2 = d1 - vol * sqrt_time;
+            let density = normal_pdf(d1);
+            let decay = -spot * density * vol / (2.0 * sqrt_time);
+            gamma += size * density / (spot * vol * sqrt_time);
+            vega += size * spot * density * sqrt_time;
+            if position.is_call {
+                delta += size * normal_cdf(d1);
+                theta += size * (decay - rate * discounted_strike * normal_cdf(d2));
+            } else {
+                delta += size * (normal_cdf(d1) - 1.0);
+                theta += size * (decay + rate * discounted_strike * normal_cdf(-d2));
+            }
+        }
+        // `as` saturates at the bounds of i64
+        Greeks {
+            delta: (delta * SCALE).round() as i64,
+            gamma: (gamma * SCALE).round() as i64,
+            vega: (vega * SCALE).round() as i64,
+            theta: (theta * SCALE).round() as i64,
+        }
+    }
+}
+
+fn normal_pdf(x: f64) -> f64 {
+    (-x * x / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+// complementary error function with a fractional error below 1.2e-7 (Numerical Recipes, erfcc)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + z / 2.0);
+    let r = t * (-z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+        .exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    erfc(-x / std::f64::consts::SQRT_2) / 2.0
 }
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+use super::orders::Order;
+
+/// How an order left the book for good
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TerminalState {
+    /// the order was filled completely, a dust remainder cleared with the last fill included
+    Filled,
+    /// the order was cancelled by its owner, swept as dust or its taker remainder was not rested
+    Cancelled,
+    /// the order was removed at its `expires_at`
+    Expired,
+}
+
+/// Callback receiving every order as it terminates with the id of its pair, e.g. to keep a queryable order history
+/// It is not part of the book state, so it is skipped on serialization and ignored on comparison.
+/// The callback runs while the orderbook is borrowed and must not block for long.
+#[derive(Clone, Default)]
+pub struct OrderArchive {
+    callback: Option<Arc<ArchiveFn>>,
+    // pair the orders are handed over with, see `for_pair`
+    pair_id: Vec<u8>,
+}
+
+type ArchiveFn = dyn Fn(&[u8], &Order, TerminalState) + Send + Sync;
+
+impl OrderArchive {
+    pub fn new(archive: impl Fn(&[u8], &Order, TerminalState) + Send + Sync + 'static) -> Self {
+        Self { callback: Some(Arc::new(archive)), pair_id: Vec::new() }
+    }
+
+    /// Archive discarding every order, the default
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// The same callback handed the orders with `pair_id`, the matching engine gives each orderbook its own
+    pub fn for_pair(&self, pair_id: impl Into<Vec<u8>>) -> Self {
+        Self { callback: self.callback.clone(), pair_id: pair_id.into() }
+    }
+
+    /// Hands `order` in the state it left the book with to the callback
+    pub fn archive(&self, order: &Order, state: TerminalState) {
+        if let Some(archive) = &self.callback {
+            archive(&self.pair_id, order, state);
+        }
+    }
+}
+
+impl fmt::Debug for OrderArchive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.callback.is_some() { "OrderArchive" } else { "OrderArchive(none)" })
+    }
+}
+
+impl PartialEq for OrderArchive {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for OrderArchive {}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// Source of the current time used by the orderbook for expiry and event timestamps
+pub trait Clock: Send + Sync {
+    /// current time in milliseconds since the unix epoch
+    fn now_millis(&self) -> i64;
+}
+
+/// Unit the `expires_at` of orders is expressed in, clocks always read milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TimeUnit {
+    #[default]
+    Millis,
+    Seconds,
+}
+
+impl TimeUnit {
+    /// Converts a time in milliseconds to this unit, rounding down
+    pub fn from_millis(self, millis: i64) -> i64 {
+        match self {
+            TimeUnit::Millis => millis,
+            TimeUnit::Seconds => millis.div_euclid(1000),
+        }
+    }
+}
+
+/// Clock reading the system time, a system clock set before the epoch reads as 0
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64
+    }
+}
+
+/// Manually driven clock for deterministic tests, clones share the same time
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now: Arc<AtomicI64>,
+}
+
+impl MockClock {
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: Arc::new(AtomicI64::new(now)),
+        }
+    }
+
+    /// Sets the current time in milliseconds
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Moves the current time forward by `millis`
+    pub fn advance(&self, millis: i64) {
+        self.now.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_millis(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// Shared clock handle stored on the orderbook.
+/// It is not part of the book state, so it is skipped on serialization and ignored on comparison.
+#[derive(Clone)]
+pub struct ClockHandle(Arc<dyn Clock>);
+
+impl ClockHandle {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self(Arc::new(clock))
+    }
+
+    /// Current time of the clock, a time before the epoch is clamped to 0
+    pub fn now_millis(&self) -> i64 {
+        self.0.now_millis().max(0)
+    }
+}
+
+impl Default for ClockHandle {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for ClockHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClockHandle")
+    }
+}
+
+impl PartialEq for ClockHandle {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ClockHandle {}
@@ -1,10 +1,17 @@
 // core_events/src/lib.rs
 use once_cell::sync::OnceCell;
-use std::sync::{mpsc, Mutex};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use std::fmt;
 use serde::{Serialize, Deserialize};
+use ulid::Ulid;
 
+/// Events emitted by the engine
+/// The binary codecs encode a variant by its index, so new variants are appended at the end.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SpotEvent {
     SpotPairClientAccountChanged {
@@ -55,6 +62,55 @@ pub enum SpotEvent {
         /// timestamp
         timestamp: i64,
     },
+    /// Funds reserved for an order, quote asset for bids and base asset for asks, including the most fee it is charged
+    Lock {
+        /// client id
+        #[serde(with = "serde_bytes")]
+        cid: Vec<u8>,
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// order id
+        #[serde(with = "serde_bytes")]
+        order_id: Vec<u8>,
+        /// account id of the order owner
+        #[serde(with = "serde_bytes")]
+        account_id: Vec<u8>,
+        /// locked asset id
+        #[serde(with = "serde_bytes")]
+        asset: Vec<u8>,
+        /// is bid
+        is_bid: bool,
+        /// locked amount
+        amnt: u64,
+        /// timestamp
+        timestamp: i64,
+    },
+    /// Unfilled remainder, and the fee reserved for it, released from the `Lock` of the same order on cancel,
+    /// expiry or dust clearing
+    Unlock {
+        /// client id
+        #[serde(with = "serde_bytes")]
+        cid: Vec<u8>,
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// order id
+        #[serde(with = "serde_bytes")]
+        order_id: Vec<u8>,
+        /// account id of the order owner
+        #[serde(with = "serde_bytes")]
+        account_id: Vec<u8>,
+        /// released asset id
+        #[serde(with = "serde_bytes")]
+        asset: Vec<u8>,
+        /// is bid
+        is_bid: bool,
+        /// released amount
+        amnt: u64,
+        /// timestamp
+        timestamp: i64,
+    },
     /// Spot order block changed in the orderbook
     SpotOrderBlockChanged {
         /// pair id
@@ -71,6 +127,30 @@ pub enum SpotEvent {
         /// timestamp
         timestamp: i64,
     },
+    /// Price level removed from the orderbook after its last order left it
+    SpotLevelRemoved {
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// is bid
+        is_bid: bool,
+        /// price
+        price: u64,
+        /// timestamp
+        timestamp: i64,
+    },
+    /// Checksum of the top levels of the orderbook for clients to verify their local book
+    SpotBookChecksum {
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// number of levels per side included in the checksum
+        top_n: u32,
+        /// CRC32 checksum of the top levels, see `L2::checksum`
+        checksum: u32,
+        /// timestamp
+        timestamp: i64,
+    },
     /// Spot order placed in the orderbook being a maker
     SpotOrderPlaced { 
         /// client id
@@ -106,7 +186,9 @@ pub enum SpotEvent {
         /// timestamp, i64 is chosen because of js type compatibility
         timestamp: i64, 
         /// expires at timestamp, i64 is chosen because of js type compatibility
-        expires_at: i64 
+        expires_at: i64,
+        /// fee bps of the resting order, negative for a maker rebate
+        fee_bps: i32,
     },
     /// Spot order partially filled in the orderbook being a taker for taker spot order history
     SpotOrderPartiallyFilled { 
@@ -153,10 +235,10 @@ pub enum SpotEvent {
         base_fee: u64,
         /// quote fee
         quote_fee: u64,
-        /// maker fee bps
-        maker_fee_bps: u16,
+        /// maker fee bps, negative for a maker rebate
+        maker_fee_bps: i32,
         /// taker fee bps
-        taker_fee_bps: u16,
+        taker_fee_bps: i32,
         /// whole amount
         amnt: u64,
         /// iceberg quantity
@@ -215,10 +297,10 @@ pub enum SpotEvent {
         base_fee: u64,
         /// quote fee
         quote_fee: u64,
-        /// maker fee bps
-        maker_fee_bps: u16,
+        /// maker fee bps, negative for a maker rebate
+        maker_fee_bps: i32,
         /// taker fee bps
-        taker_fee_bps: u16,
+        taker_fee_bps: i32,
         /// whole amount
         amnt: u64,
         /// iceberg quantity
@@ -232,6 +314,47 @@ pub enum SpotEvent {
         /// expires at timestamp, i64 is chosen because of js type compatibility
         expires_at: i64 
     },
+    /// Taker order finished matching against the book, `amnt - cqty` of it was filled
+    SpotTakerMatched {
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// taker order id
+        #[serde(with = "serde_bytes")]
+        order_id: Vec<u8>,
+        /// taker order is bid
+        is_bid: bool,
+        /// whole amount
+        amnt: u64,
+        /// current quantity left after matching
+        cqty: u64,
+        /// timestamp
+        timestamp: i64,
+    },
+    /// A single match between a taker and a maker, emitted once next to the per-order fill events
+    SpotTrade {
+        /// trade id
+        trade_id: Ulid,
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// price of the maker order the trade executed at
+        price: u64,
+        /// matched base amount
+        base_volume: u64,
+        /// matched quote amount
+        quote_volume: u64,
+        /// taker order id
+        #[serde(with = "serde_bytes")]
+        taker_order_id: Vec<u8>,
+        /// maker order id
+        #[serde(with = "serde_bytes")]
+        maker_order_id: Vec<u8>,
+        /// taker order is bid
+        taker_is_bid: bool,
+        /// timestamp
+        timestamp: i64,
+    },
     /// Spot order cancelled in the orderbook regardless of being a maker or taker
     SpotOrderCancelled { 
         /// client id
@@ -260,6 +383,29 @@ pub enum SpotEvent {
         /// expires at timestamp, i64 is chosen because of js type compatibility
         expires_at: i64 
     },
+    /// Resting spot order cancelled by the dust sweep because its current quantity fell to or below the dust limit
+    SpotOrderDustSwept {
+        /// client id
+        #[serde(with = "serde_bytes")]
+        cid: Vec<u8>,
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// order id
+        #[serde(with = "serde_bytes")]
+        order_id: Vec<u8>,
+        /// maker account id
+        #[serde(with = "serde_bytes")]
+        maker_account_id: Vec<u8>,
+        /// is bid
+        is_bid: bool,
+        /// price
+        price: u64,
+        /// current quantity swept
+        cqty: u64,
+        /// timestamp
+        timestamp: i64,
+    },
     /// Spot order expired in the orderbook regardless of being a maker
     SpotOrderExpired { 
         /// client id
@@ -307,44 +453,133 @@ pub enum SpotEvent {
         timestamp: i64, 
         /// expires at timestamp, i64 is chosen because of js type compatibility
         expires_at: i64 
+    },
+    /// Resting spot order reduced in size by its owner, keeping its place in the queue
+    SpotOrderReduced {
+        /// client id
+        #[serde(with = "serde_bytes")]
+        cid: Vec<u8>,
+        /// pair id
+        #[serde(with = "serde_bytes")]
+        pair_id: Vec<u8>,
+        /// order id
+        #[serde(with = "serde_bytes")]
+        order_id: Vec<u8>,
+        /// maker account id
+        #[serde(with = "serde_bytes")]
+        maker_account_id: Vec<u8>,
+        /// is bid
+        is_bid: bool,
+        /// price
+        price: u64,
+        /// quantity the order was reduced by
+        amnt: u64,
+        /// remaining public quantity
+        pqty: u64,
+        /// remaining current quantity
+        cqty: u64,
+        /// timestamp, i64 is chosen because of js type compatibility
+        timestamp: i64,
     }
 }
 
+/// An event with the sequence number assigned on emission.
+/// Sequence numbers increase by one per event, so consumers can detect gaps in the stream.
+/// The envelope on the wire is `{ seq, event }`: the orderbook position is only handed to the backends in process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SequencedEvent {
+    /// sequence number of the event
+    pub seq: u64,
+    /// the event
+    pub event: SpotEvent,
+    /// position of the event in the stream of the orderbook it was emitted on, None outside of an orderbook
+    #[serde(skip)]
+    pub book: Option<BookSeq>,
+}
+
+/// Position of an event in the stream of one orderbook, see `OrderBook::sequence_events`.
+/// Unlike the global `seq`, the sequence of a book has no gaps, so the events of a book can be replayed on their own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSeq {
+    /// pair id of the orderbook
+    #[serde(with = "serde_bytes")]
+    pub pair_id: Vec<u8>,
+    /// sequence number of the event in the orderbook
+    pub seq: u64,
+}
+
 /// A queue of events that can be formatted and displayed.
-/// This is a newtype wrapper around `Vec<SpotEvent>` that provides better formatting support.
+/// This is a wrapper around `Vec<SpotEvent>` that provides better formatting support.
+/// Events taken from the bus keep the sequence number they were emitted with, so publishing
+/// the queue hands them on unchanged, see `publish_event_queue`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(transparent)]
-pub struct EventQueue(pub Vec<SpotEvent>);
+pub struct EventQueue {
+    events: Vec<SpotEvent>,
+    // sequence number of the event at the same index, None for an event that was never emitted
+    #[serde(skip)]
+    seqs: Vec<Option<u64>>,
+    // orderbook position of the event at the same index, see `SequencedEvent::book`
+    #[serde(skip)]
+    books: Vec<Option<BookSeq>>,
+}
 
 impl EventQueue {
     /// Create a new empty event queue
     pub fn new() -> Self {
-        EventQueue(Vec::new())
+        Self::from_vec(Vec::new())
     }
 
-    /// Create an event queue from a vector of events
+    /// Create an event queue from a vector of events that were not emitted yet
     pub fn from_vec(events: Vec<SpotEvent>) -> Self {
-        EventQueue(events)
+        let seqs = vec![None; events.len()];
+        let books = vec![None; events.len()];
+        EventQueue { events, seqs, books }
+    }
+
+    /// Create an event queue from emitted events, keeping their sequence numbers
+    pub fn from_sequenced(events: Vec<SequencedEvent>) -> Self {
+        let seqs = events.iter().map(|sequenced| Some(sequenced.seq)).collect();
+        let books = events.iter().map(|sequenced| sequenced.book.clone()).collect();
+        let events = events.into_iter().map(|sequenced| sequenced.event).collect();
+        EventQueue { events, seqs, books }
     }
 
     /// Get a reference to the underlying vector
     pub fn as_vec(&self) -> &Vec<SpotEvent> {
-        &self.0
+        &self.events
     }
 
     /// Consume the wrapper and return the underlying vector
     pub fn into_vec(self) -> Vec<SpotEvent> {
-        self.0
+        self.events
+    }
+
+    /// Consume the wrapper and return the events with their sequence numbers, None for the ones never emitted
+    pub fn into_sequenced(self) -> Vec<(Option<u64>, SpotEvent)> {
+        let mut seqs = self.seqs;
+        // a deserialized queue carries no sequence numbers
+        seqs.resize(self.events.len(), None);
+        seqs.into_iter().zip(self.events).collect()
+    }
+
+    /// Append an event that was not emitted yet
+    pub fn push(&mut self, event: SpotEvent) {
+        self.seqs.resize(self.events.len(), None);
+        self.books.resize(self.events.len(), None);
+        self.events.push(event);
+        self.seqs.push(None);
+        self.books.push(None);
     }
 
     /// Check if the queue is empty
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.events.is_empty()
     }
 
     /// Get the number of events in the queue
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.events.len()
     }
 
     /// Log all events in this queue using `println!`.
@@ -352,12 +587,12 @@ impl EventQueue {
     /// This is primarily intended for debugging or tests. In production code,
     /// prefer sending events to a structured logging backend.
     pub fn log(&self, prefix: &str) {
-        if self.0.is_empty() {
+        if self.events.is_empty() {
             println!("{prefix}[]");
             return;
         }
 
-        for (i, event) in self.0.iter().enumerate() {
+        for (i, event) in self.events.iter().enumerate() {
             println!("{prefix}[{i}]: {:?}", event);
         }
     }
@@ -371,24 +606,24 @@ impl Default for EventQueue {
 
 impl From<Vec<SpotEvent>> for EventQueue {
     fn from(events: Vec<SpotEvent>) -> Self {
-        EventQueue(events)
+        EventQueue::from_vec(events)
     }
 }
 
 impl From<EventQueue> for Vec<SpotEvent> {
     fn from(queue: EventQueue) -> Self {
-        queue.0
+        queue.events
     }
 }
 
 impl fmt::Display for EventQueue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0.is_empty() {
+        if self.events.is_empty() {
             return write!(f, "[]");
         }
         
         write!(f, "[")?;
-        for (i, event) in self.0.iter().enumerate() {
+        for (i, event) in self.events.iter().enumerate() {
             if i > 0 {
                 write!(f, ", ")?;
             }
@@ -402,67 +637,338 @@ impl std::ops::Deref for EventQueue {
     type Target = Vec<SpotEvent>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.events
+    }
+}
+
+pub trait EventBackend: Send + 'static {
+    fn handle_event(&mut self, event: SpotEvent);
+}
+
+/// What a bounded backend does with a new event when its queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Evict the oldest queued event to make room for the new one
+    DropOldest,
+    /// Discard the new event and keep the queued ones
+    DropNewest,
+    /// Wait until the backend consumes an event, which holds up the dispatcher and so every other backend
+    Block,
+}
+
+/// Selects the events a backend receives, see `register_backend_with_filter`
+#[derive(Clone)]
+pub struct EventFilter(Arc<dyn Fn(&SpotEvent) -> bool + Send + Sync>);
+
+impl EventFilter {
+    /// Filter passing the events `pred` returns true for
+    pub fn new(pred: impl Fn(&SpotEvent) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(pred))
+    }
+
+    /// Filter passing every event
+    pub fn all() -> Self {
+        Self::new(|_| true)
+    }
+
+    pub fn matches(&self, event: &SpotEvent) -> bool {
+        (self.0)(event)
     }
 }
 
-impl std::ops::DerefMut for EventQueue {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self::all()
     }
 }
 
-pub trait EventBackend: Send + 'static {
-    fn handle_event(&mut self, event: SpotEvent);
+impl fmt::Debug for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventFilter")
+    }
+}
+
+/// Capacity of a backend registered with `register_backend`
+pub const DEFAULT_BACKEND_CAPACITY: usize = 65_536;
+
+/// Delivery statistics of a registered backend, see `backend_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackendStats {
+    /// id of the backend, see `EventReceiver::id`
+    pub id: u64,
+    /// events waiting to be received
+    pub queued: usize,
+    /// most events the backend holds before its overflow policy applies
+    pub capacity: usize,
+    /// events the backend lost by falling behind
+    pub lagged: u64,
+}
+
+// Per-backend event queue shared by the dispatcher and the backend's receiver
+struct BackendQueue {
+    id: u64,
+    events: Mutex<VecDeque<SequencedEvent>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    filter: EventFilter,
+    dropped: AtomicU64,
+    connected: AtomicBool,
+}
+
+impl BackendQueue {
+    fn new(capacity: usize, policy: OverflowPolicy, filter: EventFilter) -> Self {
+        Self {
+            id: NEXT_BACKEND_ID.fetch_add(1, Ordering::SeqCst),
+            events: Mutex::new(VecDeque::new()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+            policy,
+            filter,
+            dropped: AtomicU64::new(0),
+            connected: AtomicBool::new(true),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+        DROPPED_EVENTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Enqueues the event according to the overflow policy of the backend.
+    fn push(&self, event: SequencedEvent) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    events.pop_front();
+                    self.record_drop();
+                }
+                OverflowPolicy::DropNewest => {
+                    self.record_drop();
+                    return;
+                }
+                OverflowPolicy::Block => {
+                    while events.len() >= self.capacity && self.is_connected() {
+                        events = self.not_full.wait(events).unwrap();
+                    }
+                    if !self.is_connected() {
+                        return;
+                    }
+                }
+            }
+        }
+        events.push_back(event);
+        self.not_empty.notify_one();
+    }
+
+    fn stats(&self) -> BackendStats {
+        BackendStats {
+            id: self.id,
+            queued: self.events.lock().unwrap().len(),
+            capacity: self.capacity,
+            lagged: self.dropped.load(Ordering::SeqCst),
+        }
+    }
+
+    fn pop(&self, events: &mut VecDeque<SequencedEvent>) -> Option<SequencedEvent> {
+        let event = events.pop_front();
+        if event.is_some() {
+            self.not_full.notify_one();
+        }
+        event
+    }
+}
+
+/// Receiving end of a registered backend, mirroring `mpsc::Receiver`.
+pub struct EventReceiver {
+    queue: Arc<BackendQueue>,
+}
+
+impl EventReceiver {
+    /// Blocks until an event is available.
+    pub fn recv(&self) -> Result<SequencedEvent, mpsc::RecvError> {
+        let mut events = self.queue.events.lock().unwrap();
+        loop {
+            if let Some(event) = self.queue.pop(&mut events) {
+                return Ok(event);
+            }
+            events = self.queue.not_empty.wait(events).unwrap();
+        }
+    }
+
+    /// Waits up to `timeout` for an event.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<SequencedEvent, mpsc::RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut events = self.queue.events.lock().unwrap();
+        loop {
+            if let Some(event) = self.queue.pop(&mut events) {
+                return Ok(event);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(mpsc::RecvTimeoutError::Timeout);
+            }
+            events = self.queue.not_empty.wait_timeout(events, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Returns an event if one is queued, without blocking.
+    pub fn try_recv(&self) -> Result<SequencedEvent, mpsc::TryRecvError> {
+        let mut events = self.queue.events.lock().unwrap();
+        self.queue.pop(&mut events).ok_or(mpsc::TryRecvError::Empty)
+    }
+
+    /// Number of events dropped for this backend by its overflow policy.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Id of the backend in `backend_stats`.
+    pub fn id(&self) -> u64 {
+        self.queue.id
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        // release a dispatcher blocked on this backend and stop feeding it
+        self.queue.connected.store(false, Ordering::SeqCst);
+        self.queue.not_full.notify_all();
+    }
 }
 
 // Sender into the dispatcher
-static DISPATCH_TX: OnceCell<mpsc::Sender<SpotEvent>> = OnceCell::new();
+static DISPATCH_TX: OnceCell<mpsc::Sender<SequencedEvent>> = OnceCell::new();
 
-// List of per-backend senders
-static BACKEND_TXS: OnceCell<Mutex<Vec<mpsc::Sender<SpotEvent>>>> = OnceCell::new();
+// List of per-backend queues
+static BACKEND_QUEUES: OnceCell<Mutex<Vec<Arc<BackendQueue>>>> = OnceCell::new();
 
 // In-memory event queue that stores events before they are published
-static EVENT_QUEUE: OnceCell<Mutex<Vec<SpotEvent>>> = OnceCell::new();
+static EVENT_QUEUE: OnceCell<Mutex<Vec<SequencedEvent>>> = OnceCell::new();
+
+// Events dropped across all backends
+static DROPPED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+// Id of the next registered backend
+static NEXT_BACKEND_ID: AtomicU64 = AtomicU64::new(1);
 
-fn backend_txs() -> &'static Mutex<Vec<mpsc::Sender<SpotEvent>>> {
-    BACKEND_TXS.get_or_init(|| Mutex::new(Vec::new()))
+fn backend_queues() -> &'static Mutex<Vec<Arc<BackendQueue>>> {
+    BACKEND_QUEUES.get_or_init(|| Mutex::new(Vec::new()))
 }
 
-fn event_queue() -> &'static Mutex<Vec<SpotEvent>> {
+fn event_queue() -> &'static Mutex<Vec<SequencedEvent>> {
     EVENT_QUEUE.get_or_init(|| Mutex::new(Vec::new()))
 }
 
+/// Fans out an event to every registered backend whose filter it passes, forgetting backends whose receiver was dropped.
+fn dispatch(event: &SequencedEvent) {
+    // pushed once the list is unlocked, so a blocking backend waiting for room does not hold up
+    // registering a backend or reading the stats of the others
+    let backends: Vec<Arc<BackendQueue>> = {
+        let mut backends = backend_queues().lock().unwrap();
+        backends.retain(|backend| backend.is_connected());
+        backends.iter().filter(|backend| backend.filter.matches(&event.event)).cloned().collect()
+    };
+    for backend in backends {
+        // clone once per backend
+        backend.push(event.clone());
+    }
+}
+
 /// Call once at process startup to create the dispatcher thread.
 pub fn init_event_bus() {
-    let (tx, rx) = mpsc::channel::<SpotEvent>();
+    let (tx, rx) = mpsc::channel::<SequencedEvent>();
     DISPATCH_TX.set(tx).ok(); // ignore if already set
 
     // Dispatcher thread: fan out every event to all registered backends.
     thread::spawn(move || {
         while let Ok(event) = rx.recv() {
-            // clone once per backend
-            let backends = backend_txs().lock().unwrap();
-            for backend_tx in backends.iter() {
-                // Ignore send errors (backend might have shut down)
-                let _ = backend_tx.send(event.clone());
-            }
+            dispatch(&event);
         }
     });
 }
 
+// Last sequence number assigned to an event, 0 before the first event
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+fn next_seq() -> u64 {
+    SEQ.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+/// Returns the sequence number of the last emitted event, 0 if none was emitted yet.
+pub fn current_seq() -> u64 {
+    SEQ.load(Ordering::SeqCst)
+}
+
+/// Continues the sequence after `seq`, e.g. with the last sequence number persisted before a restart.
+/// The sequence never moves backwards, a `seq` below the current one is ignored.
+pub fn restore_seq(seq: u64) {
+    SEQ.fetch_max(seq, Ordering::SeqCst);
+}
+
 /// Called from anywhere (engine, core logic) to emit an event.
-/// This stores the event in the event queue. Use `publish_events()` to actually send them.
+/// This stores the event in the event queue with the next sequence number. Use `publish_events()` to actually send them.
 pub fn emit_event(event: SpotEvent) {
-    let mut queue = event_queue().lock().unwrap();
-    queue.push(event);
+    let uncaptured = CAPTURED_EVENTS.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(captured) => {
+            captured.push(SequencedEvent { seq: next_seq(), event, book: None });
+            None
+        }
+        None => Some(event),
+    });
+    if let Some(event) = uncaptured {
+        let mut queue = event_queue().lock().unwrap();
+        // assigned under the queue lock so the queue is always in sequence order
+        let seq = next_seq();
+        queue.push(SequencedEvent { seq, event, book: None });
+    }
+}
+
+thread_local! {
+    // Events emitted on this thread while `capture_events` runs, None outside of a capture
+    static CAPTURED_EVENTS: RefCell<Option<Vec<SequencedEvent>>> = const { RefCell::new(None) };
+}
+
+// Restores the capture `capture_events` replaced, also when `f` panics
+struct CaptureGuard(Option<Vec<SequencedEvent>>);
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        CAPTURED_EVENTS.with(|captured| *captured.borrow_mut() = outer);
+    }
+}
+
+/// Runs `f` and returns the events it emitted on the current thread, in emission order.
+/// Captured events bypass the global queue, so operations running concurrently on other threads
+/// neither drain them nor hand theirs to `f`. Captures nest: an inner capture keeps its events to itself.
+pub fn capture_events<R>(f: impl FnOnce() -> R) -> (R, Vec<SequencedEvent>) {
+    let outer = CAPTURED_EVENTS.with(|captured| captured.borrow_mut().replace(Vec::new()));
+    let guard = CaptureGuard(outer);
+    let result = f();
+    let events = CAPTURED_EVENTS.with(|captured| captured.borrow_mut().take()).unwrap_or_default();
+    drop(guard);
+    (result, events)
+}
+
+/// Puts captured events back on the global queue, e.g. when the operation that emitted them failed.
+/// Draining sorts by `seq`, so they are returned in emission order among the queued ones.
+pub fn requeue_events(events: Vec<SequencedEvent>) {
+    event_queue().lock().unwrap().extend(events);
 }
 
 /// Publishes all events from the global queue to the event bus (if initialized).
 /// After publishing, the queue is drained and cleared.
 pub fn publish_events() {
     // Drain all events from the queue
-    let events: Vec<SpotEvent> = {
+    let events: Vec<SequencedEvent> = {
         let mut queue = event_queue().lock().unwrap();
         let drained = queue.clone();
         queue.clear();
@@ -480,37 +986,89 @@ pub fn publish_events() {
 
 /// Publishes an EventQueue to the event bus (if initialized).
 /// This is useful when you have an EventQueue returned from an operation.
+/// Events keep the sequence number and orderbook position they were emitted with, only events that were never emitted get a new number.
 pub fn publish_event_queue(events: EventQueue) {
     // Send each event to the dispatcher if it's initialized
     if let Some(tx) = DISPATCH_TX.get() {
-        for event in events.into_vec() {
+        let EventQueue { events, mut seqs, mut books } = events;
+        // a deserialized queue carries no sequence numbers
+        seqs.resize(events.len(), None);
+        books.resize(events.len(), None);
+        for ((event, seq), book) in events.into_iter().zip(seqs).zip(books) {
+            let seq = seq.unwrap_or_else(next_seq);
             // ignore error if dispatcher is down
-            let _ = tx.send(event);
+            let _ = tx.send(SequencedEvent { seq, event, book });
         }
     }
 }
 
-/// Register a backend; returns an `mpsc::Receiver<SpotEvent>` that you
+fn register(capacity: usize, policy: OverflowPolicy, filter: EventFilter) -> EventReceiver {
+    let queue = Arc::new(BackendQueue::new(capacity, policy, filter));
+    backend_queues().lock().unwrap().push(queue.clone());
+    EventReceiver { queue }
+}
+
+/// Register a backend; returns an `EventReceiver` that you
 /// can consume from a dedicated thread.
-pub fn register_backend() -> mpsc::Receiver<SpotEvent> {
-    let (tx, rx) = mpsc::channel::<SpotEvent>();
+/// A backend falling more than `DEFAULT_BACKEND_CAPACITY` events behind loses its oldest events
+/// and reports the lag in `backend_stats`, without holding up the other backends.
+pub fn register_backend() -> EventReceiver {
+    register_backend_with_filter(EventFilter::all())
+}
 
-    {
-        let mut list = backend_txs().lock().unwrap();
-        list.push(tx);
-    }
+/// Register a backend receiving only the events passing `filter`, with the capacity of `register_backend`.
+/// Events filtered out are never queued for the backend, so they neither count towards its capacity nor as lag.
+pub fn register_backend_with_filter(filter: EventFilter) -> EventReceiver {
+    register(DEFAULT_BACKEND_CAPACITY, OverflowPolicy::DropOldest, filter)
+}
+
+/// Register a backend holding at most `capacity` undelivered events;
+/// `policy` decides what happens to new events while the backend is full.
+pub fn register_backend_bounded(capacity: usize, policy: OverflowPolicy) -> EventReceiver {
+    register(capacity, policy, EventFilter::all())
+}
+
+/// Total number of events dropped by backends since startup.
+pub fn dropped_events() -> u64 {
+    DROPPED_EVENTS.load(Ordering::SeqCst)
+}
 
-    rx
+/// Delivery statistics of every connected backend, in registration order.
+pub fn backend_stats() -> Vec<BackendStats> {
+    let backends = backend_queues().lock().unwrap();
+    backends
+        .iter()
+        .filter(|backend| backend.is_connected())
+        .map(|backend| backend.stats())
+        .collect()
 }
 
-/// Drains all events from the event queue and returns them.
+/// Drains all events from the event queue and returns them in emission order.
 /// This clears the queue after draining.
 /// Useful for retrieving events after operations complete.
 pub fn drain_events() -> EventQueue {
-    let mut queue = event_queue().lock().unwrap();
-    let drained = queue.clone();
-    queue.clear();
-    EventQueue(drained)
+    EventQueue::from_sequenced(drain_sequenced_events())
+}
+
+/// Drains all events from the event queue together with their sequence numbers.
+/// Events are ordered by `seq`, which totally orders emission across threads.
+pub fn drain_sequenced_events() -> Vec<SequencedEvent> {
+    let mut events = {
+        let mut queue = event_queue().lock().unwrap();
+        std::mem::take(&mut *queue)
+    };
+    // already in order as `emit_event` assigns the seq under the queue lock, kept as an explicit guarantee
+    events.sort_by_key(|sequenced| sequenced.seq);
+    events
+}
+
+/// Drains all events from the event queue and keeps the ones matching `pred`, in emission order.
+/// Useful in tests, e.g. `events_of_type(|e| matches!(e, SpotEvent::SpotTrade { .. }))`.
+pub fn events_of_type<F>(pred: F) -> Vec<SpotEvent>
+where
+    F: Fn(&SpotEvent) -> bool,
+{
+    drain_events().into_vec().into_iter().filter(|event| pred(event)).collect()
 }
 
 /// Clears all events from the event queue without returning them.
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use ulid::Ulid;
+
+use super::orders::OrderId;
+
+/// Source of the ids assigned to new orders
+pub trait IdGenerator: Send + Sync {
+    /// id of the next order, never returned before
+    fn next_id(&self) -> OrderId;
+}
+
+/// Generator of random ulids ordered by their creation time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UlidGenerator;
+
+impl IdGenerator for UlidGenerator {
+    fn next_id(&self) -> OrderId {
+        Ulid::new()
+    }
+}
+
+/// Generator of the ids `start`, `start + 1`, ... for deterministic tests, clones share the same sequence
+#[derive(Debug, Clone, Default)]
+pub struct SequentialIdGenerator {
+    next: Arc<AtomicU64>,
+}
+
+impl SequentialIdGenerator {
+    pub fn new(start: u64) -> Self {
+        Self {
+            next: Arc::new(AtomicU64::new(start)),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn next_id(&self) -> OrderId {
+        Ulid::from(self.next.fetch_add(1, Ordering::SeqCst) as u128)
+    }
+}
+
+/// Shared id generator handle stored on the L3 book.
+/// It is not part of the book state, so it is skipped on serialization and ignored on comparison.
+#[derive(Clone)]
+pub struct IdGeneratorHandle(Arc<dyn IdGenerator>);
+
+impl IdGeneratorHandle {
+    pub fn new(generator: impl IdGenerator + 'static) -> Self {
+        Self(Arc::new(generator))
+    }
+
+    pub fn next_id(&self) -> OrderId {
+        self.0.next_id()
+    }
+}
+
+impl Default for IdGeneratorHandle {
+    fn default() -> Self {
+        Self::new(UlidGenerator)
+    }
+}
+
+impl fmt::Debug for IdGeneratorHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IdGeneratorHandle")
+    }
+}
+
+impl PartialEq for IdGeneratorHandle {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for IdGeneratorHandle {}
@@ -0,0 +1,255 @@
+//! Layouts of the state saved by older snapshot formats
+//!
+//! Postcard writes the fields of a struct one after the other and has no way to tell a missing field apart,
+//! so a state saved by an older layout is decoded into these types and migrated with `From`.
+//! - `V0` types are the state saved before snapshots carried a format version.
+//! - `V1` types are the state of format version 1, before orderbooks numbered their events.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::clock::TimeUnit;
+use super::market::L1;
+use super::orderbook::{MatchingPolicy, OrderBook, PrintPrice};
+use super::orders::{Node, Order, OrderId, L3};
+use super::pair::{ClientOrder, Pair};
+use super::prices::{Level, PriceNode, L2};
+
+/// `MatchingEngine` before snapshots carried a format version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchingEngineV0 {
+    pub pairs: HashMap<Vec<u8>, PairV0>,
+    pub total_pairs: u32,
+}
+
+/// `Pair` before snapshots carried a format version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairV0 {
+    pub pair_id: Vec<u8>,
+    pub base_asset_id: Vec<u8>,
+    pub quote_asset_id: Vec<u8>,
+    pub l1: L1,
+    pub orderbook: OrderBookV0,
+    pub clients: Vec<Vec<u8>>,
+    pub client_admin_account_ids: HashMap<Vec<u8>, Vec<u8>>,
+    pub client_fee_account_ids: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+/// `OrderBook` before snapshots carried a format version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderBookV0 {
+    pub l2: L2V0,
+    pub l3: L3V0,
+    pub fee_recipients: HashMap<Vec<u8>, Vec<u8>>,
+    pub dust: u64,
+}
+
+/// `L2` before it cached the best quantities
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L2V0 {
+    pub bid_price_head: Option<u64>,
+    pub ask_price_head: Option<u64>,
+    pub bid_price_tail: Option<u64>,
+    pub ask_price_tail: Option<u64>,
+    pub bid_price_nodes: BTreeMap<u64, PriceNode>,
+    pub ask_price_nodes: BTreeMap<u64, PriceNode>,
+    pub public_bid_level_map: BTreeMap<u64, u64>,
+    pub public_ask_level_map: BTreeMap<u64, u64>,
+    pub current_bid_level_map: BTreeMap<u64, u64>,
+    pub current_ask_level_map: BTreeMap<u64, u64>,
+    pub bid_level_list: BTreeMap<u64, Vec<Level>>,
+    pub ask_level_list: BTreeMap<u64, Vec<Level>>,
+}
+
+/// `L3` before it indexed the orders by owner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L3V0 {
+    pub price_head: BTreeMap<u64, OrderId>,
+    pub price_tail: BTreeMap<u64, OrderId>,
+    pub order_nodes: HashMap<OrderId, Node>,
+    pub orders: HashMap<OrderId, OrderV0>,
+    pub dust: u64,
+    pub dormant_order: Option<OrderId>,
+}
+
+/// `Order` before fee basis points were signed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderV0 {
+    pub cid: Vec<u8>,
+    pub id: OrderId,
+    pub owner: Vec<u8>,
+    pub is_bid: bool,
+    pub price: u64,
+    pub amnt: u64,
+    pub iqty: u64,
+    pub pqty: u64,
+    pub cqty: u64,
+    pub timestamp: i64,
+    pub expires_at: i64,
+    pub fee_bps: u16,
+}
+
+impl From<OrderV0> for Order {
+    fn from(order: OrderV0) -> Self {
+        Self {
+            cid: order.cid,
+            id: order.id,
+            owner: order.owner,
+            is_bid: order.is_bid,
+            price: order.price,
+            amnt: order.amnt,
+            iqty: order.iqty,
+            pqty: order.pqty,
+            cqty: order.cqty,
+            timestamp: order.timestamp,
+            expires_at: order.expires_at,
+            fee_bps: order.fee_bps.into(),
+        }
+    }
+}
+
+impl From<L2V0> for L2 {
+    fn from(l2: L2V0) -> Self {
+        let best_bid_qty = l2.bid_price_head.and_then(|price| l2.public_bid_level_map.get(&price).copied()).unwrap_or(0);
+        let best_ask_qty = l2.ask_price_head.and_then(|price| l2.public_ask_level_map.get(&price).copied()).unwrap_or(0);
+        Self {
+            bid_price_head: l2.bid_price_head,
+            ask_price_head: l2.ask_price_head,
+            bid_price_tail: l2.bid_price_tail,
+            ask_price_tail: l2.ask_price_tail,
+            bid_price_nodes: l2.bid_price_nodes,
+            ask_price_nodes: l2.ask_price_nodes,
+            public_bid_level_map: l2.public_bid_level_map,
+            public_ask_level_map: l2.public_ask_level_map,
+            current_bid_level_map: l2.current_bid_level_map,
+            current_ask_level_map: l2.current_ask_level_map,
+            bid_level_list: l2.bid_level_list,
+            ask_level_list: l2.ask_level_list,
+            best_bid_qty,
+            best_ask_qty,
+        }
+    }
+}
+
+impl From<L3V0> for L3 {
+    fn from(l3: L3V0) -> Self {
+        let mut migrated = L3::new();
+        migrated.price_head = l3.price_head;
+        migrated.price_tail = l3.price_tail;
+        migrated.order_nodes = l3.order_nodes;
+        migrated.dust = l3.dust;
+        migrated.dormant_order = l3.dormant_order;
+        for (id, order) in l3.orders {
+            migrated.owner_orders.entry(order.owner.clone()).or_default().insert(id);
+            migrated.orders.insert(id, order.into());
+        }
+        migrated.rebuild_indexes();
+        migrated
+    }
+}
+
+impl From<OrderBookV0> for OrderBook {
+    fn from(orderbook: OrderBookV0) -> Self {
+        let mut migrated = OrderBook::new();
+        migrated.l2 = orderbook.l2.into();
+        migrated.l3 = orderbook.l3.into();
+        migrated.fee_recipients = orderbook.fee_recipients;
+        migrated.dust = orderbook.dust;
+        migrated
+    }
+}
+
+impl From<PairV0> for Pair {
+    fn from(pair: PairV0) -> Self {
+        let mut migrated = Pair::new();
+        migrated.pair_id = pair.pair_id;
+        migrated.base_asset_id = pair.base_asset_id;
+        migrated.quote_asset_id = pair.quote_asset_id;
+        migrated.l1 = pair.l1;
+        migrated.orderbook = pair.orderbook.into();
+        migrated.clients = pair.clients;
+        migrated.client_admin_account_ids = pair.client_admin_account_ids;
+        migrated.client_fee_account_ids = pair.client_fee_account_ids;
+        migrated
+    }
+}
+
+/// `MatchingEngine` of snapshot format version 1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchingEngineV1 {
+    pub pairs: HashMap<Vec<u8>, PairV1>,
+    pub total_pairs: u32,
+    pub time_unit: TimeUnit,
+}
+
+/// `Pair` of snapshot format version 1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairV1 {
+    pub pair_id: Vec<u8>,
+    pub base_asset_id: Vec<u8>,
+    pub quote_asset_id: Vec<u8>,
+    pub l1: L1,
+    pub orderbook: OrderBookV1,
+    pub clients: Vec<Vec<u8>>,
+    pub client_admin_account_ids: HashMap<Vec<u8>, Vec<u8>>,
+    pub client_fee_account_ids: HashMap<Vec<u8>, Vec<u8>>,
+    pub tick_size: u64,
+    pub min_qty: u64,
+    pub max_reprice: u64,
+    pub client_order_ids: HashMap<(Vec<u8>, Vec<u8>), ClientOrder>,
+    pub client_order_id_ttl_ms: i64,
+    pub max_makers_per_order: u32,
+}
+
+/// `OrderBook` before it numbered its events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderBookV1 {
+    pub l2: L2,
+    pub l3: L3,
+    pub fee_recipients: HashMap<Vec<u8>, Vec<u8>>,
+    pub default_fee_recipient: Option<Vec<u8>>,
+    pub dust: u64,
+    pub matching_policy: MatchingPolicy,
+    pub print_price: PrintPrice,
+    pub price_decimals: u32,
+    pub time_unit: TimeUnit,
+    pub max_price_levels: u32,
+}
+
+impl From<OrderBookV1> for OrderBook {
+    fn from(orderbook: OrderBookV1) -> Self {
+        let mut migrated = OrderBook::new();
+        migrated.l2 = orderbook.l2;
+        migrated.l3 = orderbook.l3;
+        migrated.fee_recipients = orderbook.fee_recipients;
+        migrated.default_fee_recipient = orderbook.default_fee_recipient;
+        migrated.dust = orderbook.dust;
+        migrated.matching_policy = orderbook.matching_policy;
+        migrated.print_price = orderbook.print_price;
+        migrated.price_decimals = orderbook.price_decimals;
+        migrated.time_unit = orderbook.time_unit;
+        migrated.max_price_levels = orderbook.max_price_levels;
+        migrated
+    }
+}
+
+impl From<PairV1> for Pair {
+    fn from(pair: PairV1) -> Self {
+        let mut migrated = Pair::new();
+        migrated.pair_id = pair.pair_id;
+        migrated.base_asset_id = pair.base_asset_id;
+        migrated.quote_asset_id = pair.quote_asset_id;
+        migrated.l1 = pair.l1;
+        migrated.orderbook = pair.orderbook.into();
+        migrated.clients = pair.clients;
+        migrated.client_admin_account_ids = pair.client_admin_account_ids;
+        migrated.client_fee_account_ids = pair.client_fee_account_ids;
+        migrated.tick_size = pair.tick_size;
+        migrated.min_qty = pair.min_qty;
+        migrated.max_reprice = pair.max_reprice;
+        migrated.client_order_ids = pair.client_order_ids;
+        migrated.client_order_id_ttl_ms = pair.client_order_id_ttl_ms;
+        migrated.max_makers_per_order = pair.max_makers_per_order;
+        migrated
+    }
+}
@@ -8,13 +8,13 @@ pub struct L1 {
     pub bid_head: Option<u64>,
     /// Head of the ask list
     pub ask_head: Option<u64>,
-    /// Slippage limit for limit buy orders in 8 decimals
+    /// Slippage limit for limit buy orders in basis points (10000 = 100%)
     pub limit_buy_slippage_limit: Option<u64>,
-    /// Slippage limit for limit sell orders in 8 decimals
+    /// Slippage limit for limit sell orders in basis points (10000 = 100%)
     pub limit_sell_slippage_limit: Option<u64>,
-    /// Slippage limit for market buy orders in 8 decimals
+    /// Slippage limit for market buy orders in basis points (10000 = 100%)
     pub market_buy_slippage_limit: Option<u64>,
-    /// Slippage limit for market sell orders in 8 decimals
+    /// Slippage limit for market sell orders in basis points (10000 = 100%)
     pub market_sell_slippage_limit: Option<u64>,
 }
 
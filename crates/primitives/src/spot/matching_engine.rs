@@ -1,9 +1,16 @@
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use crate::spot::event::SpotEvent;
 
+use super::archive::OrderArchive;
+use super::clock::TimeUnit;
 use super::event::{self, EventQueue};
+use super::legacy::{MatchingEngineV0, MatchingEngineV1};
 use super::orderbook::OrderBookError;
 use super::orders::OrderId;
 use super::pair::Pair;
@@ -25,28 +32,121 @@ use super::time_in_force::TimeInForce;
 /// assert_eq!(engine.pair_count(), 0);
 /// assert!(!engine.has_pair(&b"BTC-USD".to_vec()));
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// Every pair sits behind its own lock, so orders on different pairs can be processed concurrently
+/// through handles returned by `share`.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct MatchingEngine {
-    pairs: HashMap<Vec<u8>, Pair>,
+    // serialized through `lock_pair`, serde fails on a poisoned lock
+    #[serde(serialize_with = "serialize_pairs")]
+    pairs: HashMap<Vec<u8>, Arc<Mutex<Pair>>>,
     total_pairs: u32,
+    // unit the `expires_at` of orders is expressed in on every pair
+    time_unit: TimeUnit,
+    // callback receiving the terminated orders of every pair, not part of the engine state
+    #[serde(skip)]
+    order_archive: OrderArchive,
+}
+
+// Pair locks recovered from poisoning across all engines
+static PAIR_LOCK_POISON_RECOVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// Total number of times a pair lock poisoned by a panicked thread was recovered since startup.
+pub fn pair_lock_poison_recoveries() -> u64 {
+    PAIR_LOCK_POISON_RECOVERIES.load(Ordering::Relaxed)
+}
+
+// A pair whose lock was poisoned by a panicking thread is still served, the poison is cleared
+// so every panic is counted once in `pair_lock_poison_recoveries`
+fn lock_pair(pair: &Mutex<Pair>) -> MutexGuard<'_, Pair> {
+    pair.lock().unwrap_or_else(|e| {
+        PAIR_LOCK_POISON_RECOVERIES.fetch_add(1, Ordering::Relaxed);
+        pair.clear_poison();
+        e.into_inner()
+    })
+}
+
+fn serialize_pairs<S: Serializer>(pairs: &HashMap<Vec<u8>, Arc<Mutex<Pair>>>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(pairs.len()))?;
+    for (pair_id, pair) in pairs {
+        map.serialize_entry(pair_id, &*lock_pair(pair))?;
+    }
+    map.end()
 }
 
+/// Deep copy: the clone gets its own pair locks, see `share` for a handle on the same pairs
+impl Clone for MatchingEngine {
+    fn clone(&self) -> Self {
+        Self {
+            pairs: self
+                .pairs
+                .iter()
+                .map(|(pair_id, pair)| (pair_id.clone(), Arc::new(Mutex::new(lock_pair(pair).clone()))))
+                .collect(),
+            total_pairs: self.total_pairs,
+            time_unit: self.time_unit,
+            order_archive: self.order_archive.clone(),
+        }
+    }
+}
+
+impl PartialEq for MatchingEngine {
+    fn eq(&self, other: &Self) -> bool {
+        self.total_pairs == other.total_pairs
+            && self.time_unit == other.time_unit
+            && self.pairs.len() == other.pairs.len()
+            && self.pairs.iter().all(|(pair_id, pair)| {
+                other.pairs.get(pair_id).is_some_and(|other_pair| {
+                    // a shared pair would otherwise be locked twice
+                    Arc::ptr_eq(pair, other_pair) || *lock_pair(pair) == *lock_pair(other_pair)
+                })
+            })
+    }
+}
+
+impl Eq for MatchingEngine {}
+
 impl MatchingEngine {
     /// Create a new exchange instance
     pub fn new() -> Self {
         Self {
             pairs: HashMap::new(),
             total_pairs: 0,
+            time_unit: TimeUnit::Millis,
+            order_archive: OrderArchive::none(),
         }
     }
 
+    /// Sets the callback receiving every filled, cancelled and expired order, on every pair including the ones added later
+    /// Each orderbook hands its orders over with the id of its pair.
+    /// The archive is not part of a snapshot, it is set again on a loaded engine.
+    pub fn set_order_archive(&mut self, order_archive: OrderArchive) {
+        self.order_archive = order_archive.clone();
+        for mut pair in self.pairs_mut() {
+            let archive = order_archive.for_pair(pair.pair_id.clone());
+            pair.orderbook.set_order_archive(archive);
+        }
+    }
+
+    /// Sets the unit the `expires_at` of orders is expressed in, on every pair including the ones added later
+    pub fn set_time_unit(&mut self, time_unit: TimeUnit) {
+        self.time_unit = time_unit;
+        for mut pair in self.pairs_mut() {
+            pair.orderbook.set_time_unit(time_unit);
+        }
+    }
+
+    pub fn time_unit(&self) -> TimeUnit {
+        self.time_unit
+    }
+
     pub fn add_pair(&mut self, cid: impl Into<Vec<u8>>, client_admin_account_id: impl Into<Vec<u8>>, client_fee_account_id: impl Into<Vec<u8>>, pair_id: impl Into<Vec<u8>>, timestamp: i64) {
         // check if the pair already exists
         let pair_id_vec = pair_id.into();
         if self.pairs.contains_key(&pair_id_vec) {
             // add the client to the pair
             let cid_vec = cid.into();
-            self.pairs.get_mut(&pair_id_vec).unwrap().add_client(cid_vec.clone(), client_admin_account_id, client_fee_account_id);
+            lock_pair(&self.pairs[&pair_id_vec]).add_client(cid_vec.clone(), client_admin_account_id, client_fee_account_id);
             // emit the event
             event::emit_event(SpotEvent::SpotPairAdded {
                 cid: cid_vec,
@@ -59,8 +159,12 @@ impl MatchingEngine {
         // create the pair
         let mut pair = Pair::new();
         pair.pair_id = pair_id_vec.clone();
+        pair.orderbook.set_time_unit(self.time_unit);
+        pair.orderbook.set_order_archive(self.order_archive.for_pair(pair_id_vec.clone()));
         let cid_vec = cid.into();
         pair.add_client(cid_vec.clone(), client_admin_account_id, client_fee_account_id);
+        self.pairs.insert(pair_id_vec.clone(), Arc::new(Mutex::new(pair)));
+        self.total_pairs += 1;
         // emit the event
         event::emit_event(SpotEvent::SpotPairAdded {
             cid: cid_vec,
@@ -69,6 +173,22 @@ impl MatchingEngine {
         });
     }
 
+    /// Register a pair like `add_pair` and set its dust limit, see `Pair::set_dust`
+    /// - pairs whose assets have different decimals need different dust limits.
+    pub fn add_pair_with_dust(
+        &mut self,
+        cid: impl Into<Vec<u8>>,
+        client_admin_account_id: impl Into<Vec<u8>>,
+        client_fee_account_id: impl Into<Vec<u8>>,
+        pair_id: impl Into<Vec<u8>>,
+        dust: u64,
+        timestamp: i64,
+    ) {
+        let pair_id_vec = pair_id.into();
+        self.add_pair(cid, client_admin_account_id, client_fee_account_id, pair_id_vec.clone(), timestamp);
+        lock_pair(&self.pairs[&pair_id_vec]).set_dust(dust);
+    }
+
     pub fn add_pair_client(
         &mut self,
         cid: impl Into<Vec<u8>>,
@@ -77,9 +197,10 @@ impl MatchingEngine {
         fee_account_id: impl Into<Vec<u8>>,
     ) -> Result<EventQueue, OrderBookError> {
         let pair_id_vec = pair_id.into();
-        let pair = self.pairs.get_mut(&pair_id_vec).unwrap();
-        pair.add_client(cid.into(), admin_account_id, fee_account_id);
-        Ok(event::drain_events())
+        self.on_pair(&pair_id_vec, |pair| {
+            pair.add_client(cid.into(), admin_account_id, fee_account_id);
+            Ok(())
+        })
     }
 
     /// Place a limit sell order (ask order)
@@ -90,7 +211,7 @@ impl MatchingEngine {
     /// - `found_dormant`: Whether a dormant order was found and reused
     /// - `events`: Vector of events emitted during this operation
     pub fn limit_sell(
-        &mut self,
+        &self,
         cid: impl Into<Vec<u8>>,
         pair_id: impl Into<Vec<u8>>,
         existing_order_id: Option<OrderId>,
@@ -102,19 +223,15 @@ impl MatchingEngine {
         iqty: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
         taker_fee_bps: u16,
         time_in_force: TimeInForce,
     ) -> Result<EventQueue, OrderBookError> {
         // find a pair
         let pair_id_vec = pair_id.into();
-        let pair = self.pairs.get_mut(&pair_id_vec).unwrap();
-        pair.limit_sell(cid, existing_order_id, owner, price, amnt, iqty, timestamp, expires_at, maker_fee_bps, taker_fee_bps, time_in_force)?;
-        
-        // Drain all events that were emitted during this operation
-        let events = event::drain_events();
-        
-        Ok(events)
+        self.on_pair(&pair_id_vec, |pair| {
+            pair.limit_sell(cid, existing_order_id, owner, price, amnt, iqty, timestamp, expires_at, maker_fee_bps, taker_fee_bps, time_in_force)
+        })
     }
 
     /// Place a limit buy order (bid order)
@@ -125,7 +242,7 @@ impl MatchingEngine {
     /// - `found_dormant`: Whether a dormant order was found and reused
     /// - `events`: Vector of events emitted during this operation
     pub fn limit_buy(
-        &mut self,
+        &self,
         cid: impl Into<Vec<u8>>,
         pair_id: impl Into<Vec<u8>>,
         existing_order_id: Option<OrderId>,
@@ -135,31 +252,27 @@ impl MatchingEngine {
         public_amount: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
         taker_fee_bps: u16,
         time_in_force: TimeInForce,
     ) -> Result<EventQueue, OrderBookError> {
         // find a pair
         let pair_id_vec = pair_id.into();
-        let pair = self.pairs.get_mut(&pair_id_vec).unwrap();
-        pair.limit_buy(
-            cid,
-            existing_order_id,
-            owner,
-            price,
-            amount,
-            public_amount,
-            timestamp,
-            expires_at,
-            maker_fee_bps,
-            taker_fee_bps,
-            time_in_force,
-        )?;
-        
-        // Drain all events that were emitted during this operation
-        let events = event::drain_events();
-        
-        Ok(events)
+        self.on_pair(&pair_id_vec, |pair| {
+            pair.limit_buy(
+                cid,
+                existing_order_id,
+                owner,
+                price,
+                amount,
+                public_amount,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            )
+        })
     }
 
     /// Execute a market sell order
@@ -169,7 +282,7 @@ impl MatchingEngine {
     /// - `OrderMatch`: Contains trade execution details
     /// - `events`: Vector of events emitted during this operation
     pub fn market_sell(
-        &mut self,
+        &self,
         cid: impl Into<Vec<u8>>,
         pair_id: impl Into<Vec<u8>>,
         existing_order_id: Option<OrderId>,
@@ -178,29 +291,25 @@ impl MatchingEngine {
         public_amount: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
         taker_fee_bps: u16,
         time_in_force: TimeInForce,
     ) -> Result<EventQueue, OrderBookError> {
         let pair_id_vec = pair_id.into();
-        let pair = self.pairs.get_mut(&pair_id_vec).unwrap();
-        pair.market_sell(
-            cid,
-            existing_order_id,
-            owner,
-            amount,
-            public_amount,
-            timestamp,
-            expires_at,
-            maker_fee_bps,
-            taker_fee_bps,
-            time_in_force,
-        )?;
-        
-        // Drain all events that were emitted during this operation
-        let events = event::drain_events();
-        
-        Ok(events)
+        self.on_pair(&pair_id_vec, |pair| {
+            pair.market_sell(
+                cid,
+                existing_order_id,
+                owner,
+                amount,
+                public_amount,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            )
+        })
     }
 
     /// Execute a market buy order
@@ -210,7 +319,7 @@ impl MatchingEngine {
     /// - `OrderMatch`: Contains trade execution details
     /// - `events`: Vector of events emitted during this operation
     pub fn market_buy(
-        &mut self,
+        &self,
         cid: impl Into<Vec<u8>>,
         pair_id: impl Into<Vec<u8>>,
         existing_order_id: Option<OrderId>,
@@ -219,29 +328,25 @@ impl MatchingEngine {
         public_amount: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
         taker_fee_bps: u16,
         time_in_force: TimeInForce,
     ) -> Result<EventQueue, OrderBookError> {
         let pair_id_vec = pair_id.into();
-        let pair = self.pairs.get_mut(&pair_id_vec).unwrap();
-        pair.market_buy(
-            cid,
-            existing_order_id,
-            owner,
-            amount,
-            public_amount,
-            timestamp,
-            expires_at,
-            maker_fee_bps,
-            taker_fee_bps,
-            time_in_force,
-        )?;
-        
-        // Drain all events that were emitted during this operation
-        let events = event::drain_events();
-        
-        Ok(events)
+        self.on_pair(&pair_id_vec, |pair| {
+            pair.market_buy(
+                cid,
+                existing_order_id,
+                owner,
+                amount,
+                public_amount,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            )
+        })
     }
 
     /// Cancel an order
@@ -252,7 +357,7 @@ impl MatchingEngine {
     /// - `owner`: The owner of the order (for authorization)
     /// - `is_bid`: Whether the order is a bid (true) or ask (false)
     pub fn cancel_order(
-        &mut self,
+        &self,
         cid: impl Into<Vec<u8>>,
         pair_id: impl Into<Vec<u8>>,
         order_id: OrderId,
@@ -260,13 +365,7 @@ impl MatchingEngine {
         is_bid: bool,
         ) -> Result<EventQueue, OrderBookError> {
         let pair_id_vec = pair_id.into();
-        let pair = self.pairs.get_mut(&pair_id_vec).unwrap();
-        pair.cancel_order(cid, pair_id_vec, is_bid, order_id, owner)?;
-        
-        // Drain all events that were emitted during this operation
-        let events = event::drain_events();
-        
-        Ok(events)
+        self.on_pair(&pair_id_vec, |pair| pair.cancel_order(cid, pair_id_vec.clone(), is_bid, order_id, owner))
     }
 
     /// Get the number of pairs in the matching engine
@@ -274,10 +373,89 @@ impl MatchingEngine {
         self.pairs.len()
     }
 
+    /// Number of pairs whose lock was poisoned by a panicked thread and not recovered yet
+    pub fn poisoned_pairs(&self) -> usize {
+        self.pairs.values().filter(|pair| pair.is_poisoned()).count()
+    }
+
     /// Check if a pair exists
     pub fn has_pair(&self, pair_id: &Vec<u8>) -> bool {
         self.pairs.contains_key(&pair_id.clone())
     }
+
+    /// Check if a pair is registered
+    pub fn pair_exists(&self, pair_id: &[u8]) -> bool {
+        self.pairs.contains_key(pair_id)
+    }
+
+    /// List the ids of all registered pairs, sorted
+    pub fn list_pairs(&self) -> Vec<Vec<u8>> {
+        let mut pair_ids: Vec<Vec<u8>> = self.pairs.keys().cloned().collect();
+        pair_ids.sort();
+        pair_ids
+    }
+
+    /// Get a pair by its id, locked until the guard is dropped
+    pub fn get_pair(&self, pair_id: &[u8]) -> Option<MutexGuard<'_, Pair>> {
+        self.pairs.get(pair_id).map(|pair| lock_pair(pair))
+    }
+
+    /// Get the lock of a pair, e.g. to work on one pair without holding on to the engine
+    pub fn pair_lock(&self, pair_id: &[u8]) -> Option<Arc<Mutex<Pair>>> {
+        self.pairs.get(pair_id).cloned()
+    }
+
+    /// Iterate over all pairs mutably, e.g. for periodic housekeeping
+    /// Each pair is locked in turn while its guard is alive, so the other pairs keep trading.
+    pub fn pairs_mut(&mut self) -> impl Iterator<Item = MutexGuard<'_, Pair>> {
+        self.pairs.values().map(|pair| lock_pair(pair))
+    }
+
+    /// Time spent waiting to take every pair lock once, in turn
+    /// Each lock is released right away, e.g. to tell how long a snapshot would wait on trading.
+    pub fn pair_lock_wait(&self) -> Duration {
+        self.pairs
+            .values()
+            .map(|pair| {
+                let started = Instant::now();
+                drop(lock_pair(pair));
+                started.elapsed()
+            })
+            .sum()
+    }
+
+    /// Handle on the same pairs behind the same locks
+    ///
+    /// A pair registered on one handle is not visible on the others, register pairs on the engine
+    /// the handles are taken from.
+    pub fn share(&self) -> MatchingEngine {
+        Self {
+            pairs: self.pairs.clone(),
+            total_pairs: self.total_pairs,
+            time_unit: self.time_unit,
+            order_archive: self.order_archive.clone(),
+        }
+    }
+
+    /// Run `f` on a pair under its lock and return the events it emitted
+    ///
+    /// Events are captured on the calling thread, so operations on other pairs running concurrently
+    /// do not pick them up. Only the events of `f` are returned: events queued outside of an operation,
+    /// e.g. by `add_pair` or housekeeping, stay on the global queue for `event::publish_events`,
+    /// and so do the events of a failed operation.
+    /// The events are numbered in the stream of the pair's orderbook before its lock is released, see `Pair::capture`.
+    fn on_pair<T>(
+        &self,
+        pair_id: &[u8],
+        f: impl FnOnce(&mut Pair) -> Result<T, OrderBookError>,
+    ) -> Result<EventQueue, OrderBookError> {
+        let (result, captured) = lock_pair(self.pairs.get(pair_id).unwrap()).capture(f);
+        if let Err(e) = result {
+            event::requeue_events(captured);
+            return Err(e);
+        }
+        Ok(EventQueue::from_sequenced(captured))
+    }
 }
 
 impl Default for MatchingEngine {
@@ -285,3 +463,33 @@ impl Default for MatchingEngine {
         Self::new()
     }
 }
+
+impl From<MatchingEngineV0> for MatchingEngine {
+    fn from(engine: MatchingEngineV0) -> Self {
+        Self {
+            pairs: engine
+                .pairs
+                .into_iter()
+                .map(|(pair_id, pair)| (pair_id, Arc::new(Mutex::new(Pair::from(pair)))))
+                .collect(),
+            total_pairs: engine.total_pairs,
+            time_unit: TimeUnit::Millis,
+            order_archive: OrderArchive::none(),
+        }
+    }
+}
+
+impl From<MatchingEngineV1> for MatchingEngine {
+    fn from(engine: MatchingEngineV1) -> Self {
+        Self {
+            pairs: engine
+                .pairs
+                .into_iter()
+                .map(|(pair_id, pair)| (pair_id, Arc::new(Mutex::new(Pair::from(pair)))))
+                .collect(),
+            total_pairs: engine.total_pairs,
+            time_unit: engine.time_unit,
+            order_archive: OrderArchive::none(),
+        }
+    }
+}
@@ -6,9 +6,16 @@ pub mod orderbook;
 pub mod pair;
 pub mod time_in_force;
 pub mod matching_engine;
+pub mod clock;
+pub mod id_generator;
+pub mod archive;
+pub mod legacy;
 
 pub use market::L1;
 pub use prices::{L2, Level};
 pub use orders::{L3, L3Error, Order, Node};
-pub use pair::Pair;
-pub use matching_engine::MatchingEngine;
\ No newline at end of file
+pub use pair::{ClientOrder, OrderOutcome, OrderRequest, OrderStatus, Pair};
+pub use matching_engine::MatchingEngine;
+pub use clock::{Clock, MockClock, SystemClock, TimeUnit};
+pub use archive::{OrderArchive, TerminalState};
+pub use id_generator::{IdGenerator, SequentialIdGenerator, UlidGenerator};
\ No newline at end of file
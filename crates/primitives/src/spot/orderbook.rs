@@ -1,17 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use serde::{Deserialize, Serialize};
 
 use crate::spot::{
-    event::{self, SpotEvent},
+    event::{self, BookSeq, SequencedEvent, SpotEvent},
     Order,
 };
 
 use super::{
+    archive::{OrderArchive, TerminalState},
+    clock::{Clock, ClockHandle, TimeUnit},
+    id_generator::IdGenerator,
     orders::{L3Error, OrderId},
-    prices::L2Error,
-    L2, L3,
+    prices::{L2Error, QtyView},
+    L1, L2, L3,
 };
+use ulid::Ulid;
 
 /// In-memory order book for spot markets.
 ///
@@ -28,7 +33,7 @@ use super::{
 ///
 /// assert_eq!(ob.lmp(), Some(100));
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OrderBook {
     // L2 state
     pub l2: L2,
@@ -36,8 +41,169 @@ pub struct OrderBook {
     pub l3: L3,
     // Fee recipients map where key is the client id, and value is the fee recipient account id
     pub fee_recipients: HashMap<Vec<u8>, Vec<u8>>,
+    // fee recipient account id used for a client id missing from `fee_recipients`
+    pub default_fee_recipient: Option<Vec<u8>>,
     // dust limit to determine if the order should be deleted
     pub dust: u64,
+    // clock used for expiry and event timestamps, defaults to the system clock
+    #[serde(skip)]
+    pub clock: ClockHandle,
+    // callback receiving the orders leaving the book, discards them unless set
+    #[serde(skip)]
+    pub archive: OrderArchive,
+    // how a taker is allocated across the makers of a price level
+    pub matching_policy: MatchingPolicy,
+    // price trades print at when the taker and maker prices differ
+    pub print_price: PrintPrice,
+    // decimals of prices, a price is the quote quantity paid for `10^price_decimals` base quantity
+    pub price_decimals: u32,
+    // unit the `expires_at` of the orders is expressed in
+    pub time_unit: TimeUnit,
+    // most price levels a side may hold, 0 disables the cap
+    pub max_price_levels: u32,
+    // sequence number of the last event emitted on this book, see `sequence_events`
+    pub event_seq: u64,
+}
+
+/// Decimals of prices unless configured otherwise
+pub const DEFAULT_PRICE_DECIMALS: u32 = 8;
+
+/// Most decimals of prices, `10^19` is the largest power of ten in a u64
+pub const MAX_PRICE_DECIMALS: u32 = 19;
+
+/// Lowest maker fee, a rebate of the whole matched amount
+pub const MIN_MAKER_FEE_BPS: i32 = -10_000;
+
+// every field is empty or zero except the price decimals, which a derived default would leave at 0
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self {
+            l2: L2::default(),
+            l3: L3::default(),
+            fee_recipients: HashMap::new(),
+            default_fee_recipient: None,
+            dust: 0,
+            clock: ClockHandle::default(),
+            archive: OrderArchive::none(),
+            matching_policy: MatchingPolicy::default(),
+            print_price: PrintPrice::default(),
+            price_decimals: DEFAULT_PRICE_DECIMALS,
+            time_unit: TimeUnit::Millis,
+            max_price_levels: 0,
+            event_seq: 0,
+        }
+    }
+}
+
+/// Allocation of a taker across the makers resting at the same price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum MatchingPolicy {
+    /// makers are filled one after another in time priority (FIFO)
+    #[default]
+    PriceTime,
+    /// every maker at the level takes a share of the taker proportional to its current quantity
+    ProRata,
+}
+
+/// Price a match is converted and printed at when a marketable limit crosses a maker at a better price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum PrintPrice {
+    /// the resting maker's price, the taker gets the price improvement
+    #[default]
+    Maker,
+    /// the taker's limit price
+    Taker,
+}
+
+/// Best bid/ask of the order book with their public quantities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct TopOfBook {
+    /// best bid price in 8 decimals
+    pub best_bid: Option<u64>,
+    /// best ask price in 8 decimals
+    pub best_ask: Option<u64>,
+    /// public quantity at the best bid in 8 decimals
+    pub bid_qty: u64,
+    /// public quantity at the best ask in 8 decimals
+    pub ask_qty: u64,
+    /// best ask minus best bid, only when both sides exist
+    pub spread: Option<u64>,
+}
+
+/// Mismatch between an L2 level and the L3 orders resting at its price, reported by `verify_invariants`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inconsistency {
+    /// side of the level
+    pub is_bid: bool,
+    /// price of the level in 8 decimals
+    pub price: u64,
+    /// quantity of the level that is out of sync
+    pub view: QtyView,
+    /// sum of the L3 order quantities at the price
+    pub expected: u64,
+    /// quantity of the L2 level, 0 when the level is missing
+    pub actual: u64,
+}
+
+/// Version of the `BookSnapshot` layout, bumped whenever a field changes
+pub const BOOK_SNAPSHOT_VERSION: u32 = 1;
+
+/// L1 and L2 state of the order book in a single payload, returned by `full_snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct BookSnapshot {
+    /// layout version, `BOOK_SNAPSHOT_VERSION` when built
+    pub version: u32,
+    /// last match price in 8 decimals
+    pub lmp: Option<u64>,
+    /// best bid price in 8 decimals
+    pub bid_head: Option<u64>,
+    /// best ask price in 8 decimals
+    pub ask_head: Option<u64>,
+    /// slippage limits in basis points (10000 = 100%), in the order limit buy, limit sell, market buy, market sell
+    pub slippage_limits: [Option<u64>; 4],
+    /// bid levels as in `get_snapshot_raw`, [price, public quantity, current quantity]
+    pub bids: Vec<Vec<u64>>,
+    /// ask levels as in `get_snapshot_raw`, [price, public quantity, current quantity]
+    pub asks: Vec<Vec<u64>>,
+    /// time the snapshot was taken in milliseconds, from the order book clock
+    pub timestamp: i64,
+}
+
+/// Resting order as listed by `l3_snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderSummary {
+    /// order id
+    pub id: OrderId,
+    /// owner of the order, None when redacted
+    pub owner: Option<Vec<u8>>,
+    /// current quantity of the order in 8 decimals
+    pub cqty: u64,
+    /// public quantity of the order in 8 decimals
+    pub pqty: u64,
+    /// timestamp of the order in milliseconds
+    pub timestamp: i64,
+}
+
+/// Price level of the market-by-order view returned by `l3_snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L3Level {
+    /// price of the level in 8 decimals
+    pub price: u64,
+    /// orders resting at the price in queue order
+    pub orders: Vec<OrderSummary>,
+}
+
+/// Volumes and taker fee of a single match, returned by `execute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Fill {
+    /// matched base amount in 8 decimals
+    pub base_volume: u64,
+    /// matched quote amount in 8 decimals
+    pub quote_volume: u64,
+    /// fee charged to the taker, in quote for a bid taker and in base for an ask taker
+    pub taker_fee: u64,
+    /// number of maker orders matched
+    pub makers: u32,
 }
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
@@ -68,6 +234,40 @@ pub enum OrderBookError {
     NoAskOrdersInOrderbook,
     #[error("no bid orders in the orderbook")]
     NoBidOrdersInOrderbook,
+    #[error("fee recipient is not set for the client id")]
+    FeeRecipientNotFound,
+    #[error("price is not on the tick size of the pair")]
+    PriceNotOnTick,
+    #[error("amount is below the minimum order size of the pair")]
+    BelowMinQty,
+    #[error("insufficient balance to place the order")]
+    InsufficientBalance,
+    #[error("best bid is at or above the best ask")]
+    BookCrossed,
+    #[error("event carries a malformed order id")]
+    InvalidOrderId,
+    #[error("minimum fill cannot be reached against the book")]
+    MinFillNotReachable,
+    #[error("client order id is already used by a resting order of the client")]
+    DuplicateClientOrderId,
+    #[error("amend moves the price further than the pair allows")]
+    RepriceTooLarge,
+    #[error("replaced order is on the other side of the book")]
+    ReplacedOrderOnOtherSide,
+    #[error("side of the book already holds the most price levels it may")]
+    TooManyPriceLevels,
+    #[error("price decimals {0} exceed the most a price scale holds")]
+    TooManyPriceDecimals(u32),
+    #[error("maker fee {0} bps rebates more than the matched amount")]
+    MakerRebateTooLarge(i32),
+}
+
+// Fee recipient lookups served by `default_fee_recipient` across all orderbooks
+static FEE_RECIPIENT_FALLBACKS: AtomicU64 = AtomicU64::new(0);
+
+/// Total number of times a missing fee recipient fell back to `default_fee_recipient` since startup.
+pub fn fee_recipient_fallbacks() -> u64 {
+    FEE_RECIPIENT_FALLBACKS.load(Ordering::Relaxed)
 }
 
 impl From<L3Error> for OrderBookError {
@@ -88,15 +288,144 @@ impl OrderBook {
             l2: L2::new(),
             l3: L3::new(),
             fee_recipients: HashMap::new(),
+            default_fee_recipient: None,
             dust: 1000,
+            clock: ClockHandle::default(),
+            archive: OrderArchive::none(),
+            matching_policy: MatchingPolicy::PriceTime,
+            print_price: PrintPrice::Maker,
+            price_decimals: DEFAULT_PRICE_DECIMALS,
+            time_unit: TimeUnit::Millis,
+            max_price_levels: 0,
+            event_seq: 0,
         }
     }
 
+    /// Numbers `events` in the stream of this book, continuing after `event_seq`.
+    /// Call it with the events of an operation on the book while the book cannot change, e.g. under its pair lock,
+    /// so the sequence of a book follows the order its events were emitted in.
+    pub fn sequence_events(&mut self, pair_id: &[u8], events: &mut [SequencedEvent]) {
+        for sequenced in events {
+            self.event_seq += 1;
+            sequenced.book = Some(BookSeq { pair_id: pair_id.to_vec(), seq: self.event_seq });
+        }
+    }
+
+    /// Sets the clock used for expiry and event timestamps
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = ClockHandle::new(clock);
+    }
+
+    /// Sets the callback receiving every filled, cancelled and expired order, takers included
+    pub fn set_order_archive(&mut self, archive: OrderArchive) {
+        self.archive = archive;
+    }
+
+    /// Sets the generator of the ids of new orders, takers included
+    pub fn set_id_generator(&mut self, id_generator: impl IdGenerator + 'static) {
+        self.l3.set_id_generator(id_generator);
+    }
+
     /// Sets the dust limit to determine if the order should be deleted
     pub fn set_dust(&mut self, dust: u64) {
         self.dust = dust;
     }
 
+    /// Sets the most price levels a side may hold, 0 disables the cap
+    pub fn set_max_price_levels(&mut self, max_price_levels: u32) {
+        self.max_price_levels = max_price_levels;
+    }
+
+    /// Validates that an order resting at `price` fits in the price level cap of its side
+    /// - a price that is already a level of the side always fits.
+    pub fn ensure_price_level(&self, is_bid: bool, price: u64) -> Result<(), OrderBookError> {
+        if self.max_price_levels != 0
+            && !self.l2.price_exists(is_bid, price)
+            && self.l2.level_count(is_bid) >= self.max_price_levels as usize
+        {
+            Err(OrderBookError::TooManyPriceLevels)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the unit the `expires_at` of the orders is compared in
+    pub fn set_time_unit(&mut self, time_unit: TimeUnit) {
+        self.time_unit = time_unit;
+    }
+
+    /// Returns the current time of the clock in `time_unit`.
+    pub fn now_in_unit(&self) -> i64 {
+        self.time_unit.from_millis(self.clock.now_millis())
+    }
+
+    /// Returns whether an order expiring at `expires_at` is expired at `now` milliseconds.
+    pub fn is_expired(&self, expires_at: i64, now: i64) -> bool {
+        expires_at <= self.time_unit.from_millis(now)
+    }
+
+    /// Sets the fee recipient used for client ids without an entry in `fee_recipients`, `None` disables the fallback
+    pub fn set_default_fee_recipient(&mut self, default_fee_recipient: Option<Vec<u8>>) {
+        self.default_fee_recipient = default_fee_recipient;
+    }
+
+    /// Returns the fee recipient of a client id, falling back to `default_fee_recipient`.
+    /// - every use of the fallback is counted by `fee_recipient_fallbacks`.
+    pub fn fee_recipient(&self, cid: &[u8]) -> Result<Vec<u8>, OrderBookError> {
+        if let Some(recipient) = self.fee_recipients.get(cid) {
+            return Ok(recipient.clone());
+        }
+        let recipient = self.default_fee_recipient.clone().ok_or(OrderBookError::FeeRecipientNotFound)?;
+        FEE_RECIPIENT_FALLBACKS.fetch_add(1, Ordering::Relaxed);
+        Ok(recipient)
+    }
+
+    /// Sets how a taker is allocated across the makers of a price level
+    pub fn set_matching_policy(&mut self, matching_policy: MatchingPolicy) {
+        self.matching_policy = matching_policy;
+    }
+
+    /// Sets the decimals of prices, see `price_scale`
+    /// - fails with `TooManyPriceDecimals` above `MAX_PRICE_DECIMALS`, the scale would overflow a u64.
+    pub fn set_price_decimals(&mut self, price_decimals: u32) -> Result<(), OrderBookError> {
+        if price_decimals > MAX_PRICE_DECIMALS {
+            return Err(OrderBookError::TooManyPriceDecimals(price_decimals));
+        }
+        self.price_decimals = price_decimals;
+        Ok(())
+    }
+
+    /// Returns `10^price_decimals`, the base quantity a price is quoted for.
+    pub fn price_scale(&self) -> u64 {
+        10u64.pow(self.price_decimals)
+    }
+
+    /// Converts a base quantity to the quote quantity it is worth at `price`, saturating at `u64::MAX`.
+    pub fn base_to_quote(&self, base: u64, price: u64) -> u64 {
+        (base as u128 * price as u128 / self.price_scale() as u128).min(u64::MAX as u128) as u64
+    }
+
+    /// Converts a quote quantity to the base quantity it buys at `price`, saturating at `u64::MAX` and `0` for a zero price.
+    pub fn quote_to_base(&self, quote: u64, price: u64) -> u64 {
+        if price == 0 {
+            return 0;
+        }
+        (quote as u128 * self.price_scale() as u128 / price as u128).min(u64::MAX as u128) as u64
+    }
+
+    /// Sets the price matches are converted and printed at
+    pub fn set_print_price(&mut self, print_price: PrintPrice) {
+        self.print_price = print_price;
+    }
+
+    /// Returns the price a taker at `taker_price` matches a maker at `maker_price` at, following `print_price`.
+    pub fn match_price(&self, taker_price: u64, maker_price: u64) -> u64 {
+        match self.print_price {
+            PrintPrice::Maker => maker_price,
+            PrintPrice::Taker => taker_price,
+        }
+    }
+
     /// Gets the required amount to match an order as taker to match with the maker order and clear it.
     /// - `taker_order` is the taker order.
     /// - `price` is the price of the maker order.
@@ -108,14 +437,244 @@ impl OrderBook {
         amount: u64,
     ) -> Result<u64, OrderBookError> {
         if taker_order.is_bid {
-            Ok(amount.saturating_mul(price).saturating_div(1_0000_0000))
+            Ok(self.base_to_quote(amount, price))
+        } else {
+            Ok(self.quote_to_base(amount, price))
+        }
+    }
+
+    /// Returns the best bid/ask, their public quantities and the spread.
+    pub fn top_of_book(&self) -> TopOfBook {
+        let best_bid = self.l2.best_bid();
+        let best_ask = self.l2.best_ask();
+        TopOfBook {
+            best_bid: best_bid.map(|(price, _)| price),
+            best_ask: best_ask.map(|(price, _)| price),
+            bid_qty: best_bid.map(|(_, qty)| qty).unwrap_or(0),
+            ask_qty: best_ask.map(|(_, qty)| qty).unwrap_or(0),
+            spread: match (best_bid, best_ask) {
+                (Some((bid, _)), Some((ask, _))) => Some(ask.saturating_sub(bid)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns the mid price weighted by the public quantities at the top of book, `None` unless both sides exist.
+    /// - the bid quantity is converted to base so both sides are weighed in the same asset.
+    /// - `(best_bid * ask_qty + best_ask * bid_qty) / (bid_qty + ask_qty)`, so the price moves toward the ask when bids are heavier.
+    pub fn microprice(&self) -> Option<u64> {
+        let (best_bid, bid_quote) = self.l2.best_bid()?;
+        let (best_ask, ask_qty) = self.l2.best_ask()?;
+        let bid_qty = self.quote_to_base(bid_quote, best_bid) as u128;
+        let ask_qty = ask_qty as u128;
+        let total = bid_qty + ask_qty;
+        if total == 0 {
+            return Some(((best_bid as u128 + best_ask as u128) / 2) as u64);
+        }
+        Some(((best_bid as u128 * ask_qty + best_ask as u128 * bid_qty) / total) as u64)
+    }
+
+    /// Returns `(bid_vol - ask_vol) / (bid_vol + ask_vol)` over the public quantities of the top `depth` levels per side.
+    /// - bid volumes are converted to base at their level price, the result is in `[-1.0, 1.0]` and 0.0 for an empty book.
+    pub fn imbalance(&self, depth: u32) -> f64 {
+        let bid_vol: u128 = self
+            .l2
+            .bid_prices()
+            .take(depth as usize)
+            .map(|price| self.quote_to_base(self.l2.public_bid_level(price).unwrap_or(0), price) as u128)
+            .sum();
+        let ask_vol: u128 = self
+            .l2
+            .ask_prices()
+            .take(depth as usize)
+            .map(|price| self.l2.public_ask_level(price).unwrap_or(0) as u128)
+            .sum();
+        if bid_vol + ask_vol == 0 {
+            return 0.0;
+        }
+        (bid_vol as f64 - ask_vol as f64) / (bid_vol + ask_vol) as f64
+    }
+
+    /// Returns the last match price, slippage limits of `l1` and `depth` levels per side at `scale` in one snapshot.
+    /// - `l1` is the L1 state of the pair owning the order book.
+    /// - `scale` and `depth` are the same as in `get_snapshot_raw`.
+    pub fn full_snapshot(&self, l1: &L1, scale: u64, depth: u32) -> Result<BookSnapshot, OrderBookError> {
+        Ok(BookSnapshot {
+            version: BOOK_SNAPSHOT_VERSION,
+            lmp: l1.lmp(),
+            bid_head: self.l2.bid_head(),
+            ask_head: self.l2.ask_head(),
+            slippage_limits: [
+                l1.limit_buy_slippage_limit,
+                l1.limit_sell_slippage_limit,
+                l1.market_buy_slippage_limit,
+                l1.market_sell_slippage_limit,
+            ],
+            bids: self.l2.get_snapshot_raw(true, scale, depth)?,
+            asks: self.l2.get_snapshot_raw(false, scale, depth)?,
+            timestamp: self.clock.now_millis(),
+        })
+    }
+
+    /// Returns the order-by-order view of one side, best price first and every level in queue order.
+    /// - `depth_levels` is the number of price levels to list.
+    /// - `redact_owner` leaves out the owners of the orders.
+    pub fn l3_snapshot(&self, is_bid: bool, depth_levels: u32, redact_owner: bool) -> Vec<L3Level> {
+        let prices = if is_bid {
+            self.l2.collect_bid_prices()
+        } else {
+            self.l2.collect_ask_prices()
+        };
+        prices
+            .into_iter()
+            .take(depth_levels as usize)
+            .map(|price| {
+                let mut orders = Vec::new();
+                let mut current = self.l3.price_head.get(&price).copied();
+                while let Some(id) = current {
+                    if let Some(order) = self.l3.orders.get(&id) {
+                        orders.push(OrderSummary {
+                            id,
+                            owner: (!redact_owner).then(|| order.owner.clone()),
+                            cqty: order.cqty,
+                            pqty: order.pqty,
+                            timestamp: order.timestamp,
+                        });
+                    }
+                    current = self.l3.order_nodes.get(&id).and_then(|node| node.next);
+                }
+                L3Level { price, orders }
+            })
+            .collect()
+    }
+
+    /// Returns whether the best bid is at or above the best ask, which matching must never leave behind.
+    pub fn is_crossed(&self) -> bool {
+        match (self.l2.bid_head(), self.l2.ask_head()) {
+            (Some(bid), Some(ask)) => bid >= ask,
+            _ => false,
+        }
+    }
+
+    /// Checks that every L2 level holds the sum of the L3 order quantities at its price.
+    /// - returns every mismatch of the current and public quantities, ordered by side and price.
+    pub fn verify_invariants(&self) -> Result<(), Vec<Inconsistency>> {
+        // (is_bid, price) -> (cqty, pqty) summed over L3
+        let mut expected: BTreeMap<(bool, u64), (u64, u64)> = BTreeMap::new();
+        for (_, order) in self.l3.iter_orders() {
+            let level = expected.entry((order.is_bid, order.price)).or_default();
+            level.0 = level.0.saturating_add(order.cqty);
+            level.1 = level.1.saturating_add(order.pqty);
+        }
+        // levels without orders are expected to be empty
+        for (is_bid, current) in [(true, &self.l2.current_bid_level_map), (false, &self.l2.current_ask_level_map)] {
+            for price in current.keys() {
+                expected.entry((is_bid, *price)).or_default();
+            }
+        }
+
+        let mut inconsistencies = Vec::new();
+        for ((is_bid, price), (cqty, pqty)) in expected {
+            let (current, public) = if is_bid {
+                (self.l2.current_bid_level(price), self.l2.public_bid_level(price))
+            } else {
+                (self.l2.current_ask_level(price), self.l2.public_ask_level(price))
+            };
+            for (view, expected, actual) in [(QtyView::Current, cqty, current), (QtyView::Public, pqty, public)] {
+                let actual = actual.unwrap_or(0);
+                if actual != expected {
+                    inconsistencies.push(Inconsistency { is_bid, price, view, expected, actual });
+                }
+            }
+        }
+
+        if inconsistencies.is_empty() {
+            Ok(())
         } else {
-            Ok(amount.saturating_mul(1_0000_0000).saturating_div(price))
+            Err(inconsistencies)
         }
     }
 
+    /// Checks the book invariants when the `invariant-checks` feature is enabled, a no-op otherwise.
+    fn check_invariants(&self) -> Result<(), OrderBookError> {
+        if cfg!(feature = "invariant-checks") && self.is_crossed() {
+            return Err(OrderBookError::BookCrossed);
+        }
+        Ok(())
+    }
+
+    /// Quotes the fill of a taker order against the opposite side of the book without mutating it.
+    /// - returns `(avg_price, filled_qty, levels_consumed)` where `avg_price` is the quantity-weighted average price
+    ///   in 8 decimals and `filled_qty` is smaller than `qty` when the book is too thin.
+    /// - `is_bid` is whether the taker order is a bid order, which walks the ask levels.
+    /// - `qty` is the base quantity to fill in 8 decimals.
+    pub fn quote_fill(&self, is_bid: bool, qty: u64) -> Result<(u64, u64, u64), OrderBookError> {
+        let prices = if is_bid {
+            self.l2.collect_ask_prices()
+        } else {
+            self.l2.collect_bid_prices()
+        };
+        if prices.is_empty() {
+            return Err(if is_bid {
+                OrderBookError::NoAskOrdersInOrderbook
+            } else {
+                OrderBookError::NoBidOrdersInOrderbook
+            });
+        }
+
+        let mut remaining = qty;
+        let mut notional: u128 = 0;
+        let mut levels_consumed = 0;
+        for price in prices {
+            if remaining == 0 {
+                break;
+            }
+            // ask levels are held in base, bid levels in quote
+            let level_base = if is_bid {
+                self.l2.current_ask_level(price).unwrap_or(0)
+            } else {
+                self.quote_to_base(self.l2.current_bid_level(price).unwrap_or(0), price)
+            };
+            if level_base == 0 {
+                continue;
+            }
+            let take = remaining.min(level_base);
+            notional += take as u128 * price as u128;
+            remaining -= take;
+            levels_consumed += 1;
+        }
+
+        let filled_qty = qty - remaining;
+        let avg_price = if filled_qty == 0 {
+            0
+        } else {
+            (notional / filled_qty as u128) as u64
+        };
+        Ok((avg_price, filled_qty, levels_consumed))
+    }
+
+    /// Returns the FIFO position of a resting order at its price level.
+    /// - returns `(rank, qty_ahead)` where `rank` is the number of orders ahead and `qty_ahead` is the sum of their
+    ///   current quantities in 8 decimals, `(0, 0)` for the head of the level.
+    /// - `order_id` is the id of the resting order.
+    pub fn queue_position(&self, order_id: OrderId) -> Result<(usize, u64), OrderBookError> {
+        self.l3.get_order(order_id)?;
+
+        let mut rank = 0;
+        let mut qty_ahead = 0u64;
+        let mut current = self.l3.order_nodes.get(&order_id).and_then(|node| node.prev);
+        while let Some(id) = current {
+            let order = self.l3.get_order(id)?;
+            rank += 1;
+            qty_ahead = qty_ahead.saturating_add(order.cqty);
+            current = self.l3.order_nodes.get(&id).and_then(|node| node.prev);
+        }
+        Ok((rank, qty_ahead))
+    }
+
     /// clears empty head of the order book where price is in linked list, but order is not in the price level
-    pub fn clear_empty_head(&mut self, is_bid: bool) -> Result<u64, OrderBookError> {
+    /// - every removed level is reported with `SpotLevelRemoved`.
+    pub fn clear_empty_head(&mut self, pair_id: &[u8], is_bid: bool) -> Result<u64, OrderBookError> {
         // Get the current head price
         let mut head = if is_bid {
             self.l2.bid_head()
@@ -133,8 +692,13 @@ impl OrderBook {
                 return Ok(head_price);
             }
 
-            // No orders at this price level, clear the head and move to next
-            head = self.l2.clear_head(is_bid)?;
+            // No orders at this price level, remove it and move to next
+            self._remove_empty_level(pair_id, is_bid, head_price)?;
+            head = if is_bid {
+                self.l2.bid_head()
+            } else {
+                self.l2.ask_head()
+            };
         }
 
         // No head exists (all heads were empty and cleared)
@@ -142,18 +706,21 @@ impl OrderBook {
     }
 
     /// clears empty head of the order book, returns 0 if no head exists (matches Solidity behavior)
-    pub fn clear_empty_head_or_zero(&mut self, is_bid: bool) -> u64 {
-        self.clear_empty_head(is_bid).unwrap_or(0)
+    pub fn clear_empty_head_or_zero(&mut self, pair_id: &[u8], is_bid: bool) -> u64 {
+        self.clear_empty_head(pair_id, is_bid).unwrap_or(0)
     }
 
     /// pop front on the orderbook
-    pub fn pop_front(&mut self, is_bid: bool) -> Result<Order, OrderBookError> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
+    pub fn pop_front(
+        &mut self,
+        pair_id: &[u8],
+        base_asset_id: &[u8],
+        quote_asset_id: &[u8],
+        is_bid: bool,
+    ) -> Result<Order, OrderBookError> {
+        let now = self.clock.now_millis();
         loop {
-            self.clear_empty_head(is_bid)?;
+            self.clear_empty_head(pair_id, is_bid)?;
             let head = if is_bid {
                 self.l2.bid_head()
             } else {
@@ -168,19 +735,20 @@ impl OrderBook {
             if let Some(order_id) = head_id {
                 let order = self.l3.get_order(order_id)?;
                 // if the order is expired, expire it and continue
-                if order.expires_at <= now {
-                    self._expire_order(order_id, is_bid, Vec::new(), now)?;
+                if self.is_expired(order.expires_at, now) {
+                    self._expire_order(order_id, is_bid, pair_id.to_vec(), base_asset_id, quote_asset_id, now)?;
                     continue;
                 }
                 // if the expired order empties the price level, remove the price level, move to next head and continue
                 if self.l3.is_empty(order.price) {
-                    self.l2.remove_price(is_bid, order.price)?;
+                    let price = order.price;
+                    self._remove_empty_level(pair_id, is_bid, price)?;
                     continue;
                 }
                 // if the order is not expired, pop it from the orderbook
                 let (order, is_empty) = self.l3.pop_front(head_price)?;
                 if is_empty {
-                    self.l2.clear_head(is_bid)?;
+                    self._remove_empty_level(pair_id, is_bid, head_price)?;
                 }
                 return Ok(order
                     .expect("head price must have at least one order")
@@ -209,17 +777,18 @@ impl OrderBook {
         iqty: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
     ) -> Result<Order, OrderBookError> {
         let cid = cid.into();
         let pair_id = pair_id.into();
         let base_asset_id = base_asset_id.into();
         let quote_asset_id = quote_asset_id.into();
         let owner = owner.into();
-        let price = price;
         if iqty > amnt {
             return Err(OrderBookError::IcebergQuantityIsBiggerThanWholeAmount);
         }
+        Self::ensure_maker_fee(maker_fee_bps)?;
+        self.ensure_price_level(true, price)?;
         let pqty = amnt - iqty;
 
         let order = self.l3.create_order(
@@ -250,10 +819,24 @@ impl OrderBook {
             cqty: amnt,
             timestamp: timestamp,
             expires_at: expires_at,
+            fee_bps: order.fee_bps,
+        });
+
+        // reserve the quote the bid spends and the most fee it is charged for it
+        event::emit_event(SpotEvent::Lock {
+            cid: cid.clone(),
+            pair_id: pair_id.clone(),
+            order_id: order.id.to_bytes().to_vec(),
+            account_id: order.owner.clone(),
+            asset: quote_asset_id,
+            is_bid: true,
+            amnt: amnt + Order::fee_reserve(amnt, order.fee_bps),
+            timestamp,
         });
 
         // update the price level on the orderbook
-        self.update_price_level(pair_id, true, true, price, pqty, amnt, None)?;
+        self.update_price_level(pair_id, true, true, price, pqty, amnt, None, timestamp)?;
+        self.check_invariants()?;
         Ok(order)
     }
 
@@ -277,19 +860,18 @@ impl OrderBook {
         iqty: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
     ) -> Result<Order, OrderBookError> {
         let cid = cid.into();
         let pair_id = pair_id.into();
         let base_asset_id = base_asset_id.into();
         let quote_asset_id = quote_asset_id.into();
         let owner = owner.into();
-        let price = price.into();
-        let amnt = amnt.into();
-        let iqty = iqty.into();
         if iqty > amnt {
             return Err(OrderBookError::IcebergQuantityIsBiggerThanWholeAmount);
         }
+        Self::ensure_maker_fee(maker_fee_bps)?;
+        self.ensure_price_level(false, price)?;
         let pqty = amnt - iqty;
 
         let order = self.l3.create_order(
@@ -320,15 +902,223 @@ impl OrderBook {
             cqty: amnt,
             timestamp: timestamp,
             expires_at: expires_at,
+            fee_bps: order.fee_bps,
+        });
+
+        // reserve the base the ask spends and the most fee it is charged for it
+        event::emit_event(SpotEvent::Lock {
+            cid: cid.clone(),
+            pair_id: pair_id.clone(),
+            order_id: order.id.to_bytes().to_vec(),
+            account_id: order.owner.clone(),
+            asset: base_asset_id,
+            is_bid: false,
+            amnt: amnt + Order::fee_reserve(amnt, order.fee_bps),
+            timestamp,
         });
 
         // update the price level on the orderbook
-        self.update_price_level(pair_id, true, false, price, pqty, amnt, None)?;
+        self.update_price_level(pair_id, true, false, price, pqty, amnt, None, timestamp)?;
+        self.check_invariants()?;
+        Ok(order)
+    }
+
+    /// Opens a taker order that is matched without resting on the book.
+    /// - returns the order, which is kept out of L3/L2 so it never rests as a maker.
+    /// - locks the whole amount and its fee reserve like `place_bid`/`place_ask`, but emits no `SpotOrderPlaced`.
+    /// - whatever is left after matching is released with `cancel_taker`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_taker(
+        &mut self,
+        cid: impl Into<Vec<u8>>,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        owner: impl Into<Vec<u8>>,
+        is_bid: bool,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        taker_fee_bps: i32,
+    ) -> Result<Order, OrderBookError> {
+        self.ensure_taker(price, amnt, iqty)?;
+        let order = Order::new(
+            cid.into(),
+            self.l3.id_generator.next_id(),
+            owner.into(),
+            is_bid,
+            price,
+            amnt,
+            iqty,
+            amnt - iqty,
+            amnt,
+            timestamp,
+            expires_at,
+            taker_fee_bps,
+        );
+
+        // reserve what the taker spends, quote for a bid and base for an ask, and the most taker fee it is charged
+        event::emit_event(SpotEvent::Lock {
+            cid: order.cid.clone(),
+            pair_id: pair_id.into(),
+            order_id: order.id.to_bytes().to_vec(),
+            account_id: order.owner.clone(),
+            asset: if is_bid { quote_asset_id.into() } else { base_asset_id.into() },
+            is_bid,
+            amnt: amnt + Order::fee_reserve(amnt, order.fee_bps),
+            timestamp,
+        });
+        Ok(order)
+    }
+
+    /// Checks that a maker fee rebates at most the matched amount, see `MIN_MAKER_FEE_BPS`.
+    pub fn ensure_maker_fee(maker_fee_bps: i32) -> Result<(), OrderBookError> {
+        if maker_fee_bps < MIN_MAKER_FEE_BPS {
+            return Err(OrderBookError::MakerRebateTooLarge(maker_fee_bps));
+        }
+        Ok(())
+    }
+
+    /// Checks a taker order for everything `place_taker` rejects it for, without opening it.
+    pub fn ensure_taker(&self, price: u64, amnt: u64, iqty: u64) -> Result<(), OrderBookError> {
+        if price == 0 {
+            return Err(OrderBookError::L3(L3Error::PriceIsZero));
+        }
+        if iqty > amnt {
+            return Err(OrderBookError::IcebergQuantityIsBiggerThanWholeAmount);
+        }
+        Ok(())
+    }
+
+    /// Cancels what is left of a taker order opened with `place_taker`.
+    /// - emits `SpotOrderCancelled` and unlocks the remaining quantity, nothing when the taker was filled.
+    pub fn cancel_taker(
+        &mut self,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        taker_order: &Order,
+    ) {
+        if taker_order.cqty == 0 {
+            return;
+        }
+        event::emit_event(SpotEvent::SpotOrderCancelled {
+            cid: taker_order.cid.clone(),
+            order_id: taker_order.id.to_bytes().to_vec(),
+            maker_account_id: taker_order.owner.clone(),
+            is_bid: taker_order.is_bid,
+            price: taker_order.price,
+            amnt: taker_order.amnt,
+            iqty: taker_order.iqty,
+            pqty: taker_order.pqty,
+            cqty: taker_order.cqty,
+            timestamp: taker_order.timestamp,
+            expires_at: taker_order.expires_at,
+        });
+        self._emit_unlock(
+            taker_order,
+            pair_id.into(),
+            &base_asset_id.into(),
+            &quote_asset_id.into(),
+            taker_order.cqty,
+            self.clock.now_millis(),
+        );
+        self.archive.archive(taker_order, TerminalState::Cancelled);
+    }
+
+    /// Rests what is left of a taker order opened with `place_taker` as a maker on the book.
+    /// - returns the resting order, which keeps the taker's id.
+    /// - emits `SpotOrderPlaced` with the remaining quantities, the amount is already locked by `place_taker`.
+    /// - the order rests with `maker_fee_bps`, the fee reserve locked at the taker fee is topped up with a `Lock` or
+    ///   released with an `Unlock` to match it.
+    /// - a remainder at a new price beyond the price level cap is rejected with `TooManyPriceLevels`, the taker is left
+    ///   as it was.
+    pub fn rest_taker(
+        &mut self,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        taker_order: &Order,
+        maker_fee_bps: i32,
+    ) -> Result<Order, OrderBookError> {
+        let pair_id = pair_id.into();
+        let base_asset_id = base_asset_id.into();
+        let quote_asset_id = quote_asset_id.into();
+        let order = Order {
+            fee_bps: maker_fee_bps,
+            ..taker_order.clone()
+        };
+        self.ensure_price_level(order.is_bid, order.price)?;
+        self.l3.insert_order(order.clone())?;
+
+        // emit the event for the order created
+        event::emit_event(SpotEvent::SpotOrderPlaced {
+            cid: order.cid.clone(),
+            pair_id: pair_id.clone(),
+            base_asset_id: base_asset_id.clone(),
+            quote_asset_id: quote_asset_id.clone(),
+            order_id: order.id.to_bytes().to_vec(),
+            maker_account_id: order.owner.clone(),
+            is_bid: order.is_bid,
+            price: order.price,
+            amnt: order.amnt,
+            iqty: order.iqty,
+            pqty: order.pqty,
+            cqty: order.cqty,
+            timestamp: order.timestamp,
+            expires_at: order.expires_at,
+            fee_bps: order.fee_bps,
+        });
+
+        let now = self.clock.now_millis();
+        let locked_fee = Order::fee_reserve(order.cqty, taker_order.fee_bps);
+        let required_fee = Order::fee_reserve(order.cqty, order.fee_bps);
+        let asset = if order.is_bid { quote_asset_id } else { base_asset_id };
+        if required_fee > locked_fee {
+            event::emit_event(SpotEvent::Lock {
+                cid: order.cid.clone(),
+                pair_id: pair_id.clone(),
+                order_id: order.id.to_bytes().to_vec(),
+                account_id: order.owner.clone(),
+                asset,
+                is_bid: order.is_bid,
+                amnt: required_fee - locked_fee,
+                timestamp: now,
+            });
+        } else if locked_fee > required_fee {
+            event::emit_event(SpotEvent::Unlock {
+                cid: order.cid.clone(),
+                pair_id: pair_id.clone(),
+                order_id: order.id.to_bytes().to_vec(),
+                account_id: order.owner.clone(),
+                asset,
+                is_bid: order.is_bid,
+                amnt: locked_fee - required_fee,
+                timestamp: now,
+            });
+        }
+
+        // update the price level on the orderbook
+        self.update_price_level(
+            pair_id,
+            true,
+            order.is_bid,
+            order.price,
+            order.pqty,
+            order.cqty,
+            None,
+            now,
+        )?;
+        self.check_invariants()?;
         Ok(order)
     }
 
     /// Executes a trade.
-    /// - returns the trade details.
+    /// - returns the taker order with its remaining quantities, zero when it was filled, and the matched volumes.
+    /// - the taker is either resting in L3 or a transient order from `place_taker`.
+    /// - an expired maker is expired instead, the taker is returned unmatched with an empty fill.
     /// - `is_bid` is whether the order from client is a bid order.
     /// - `taker_order` is the taker order.
     /// - `maker_order` is the maker order.
@@ -346,57 +1136,126 @@ impl OrderBook {
         base_asset_id: impl Into<Vec<u8>>,
         quote_asset_id: impl Into<Vec<u8>>,
         now: i64,
-    ) -> Result<(), OrderBookError> {
+    ) -> Result<(Order, Fill), OrderBookError> {
+        self.execute_up_to(taker_order, maker_order, u64::MAX, pair_id, base_asset_id, quote_asset_id, now)
+    }
+
+    /// Executes a trade matching at most `max_amount` of the taker, in the taker's units.
+    /// - used to hand out a share of the taker to each maker of a level, see `MatchingPolicy::ProRata`.
+    /// - the rest of the arguments and the return value are the same as `execute`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_up_to(
+        &mut self,
+        taker_order: Order,
+        maker_order: Order,
+        max_amount: u64,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        now: i64,
+    ) -> Result<(Order, Fill), OrderBookError> {
         // Normalize IDs up front so we don't move the Into<Vec<u8>> values multiple times
         let pair_id_vec = pair_id.into();
         let base_asset_id_vec = base_asset_id.into();
         let quote_asset_id_vec = quote_asset_id.into();
         let taker_is_bid = taker_order.is_bid;
-        let (matching_amount, taker_clear, maker_clear) = self._get_matching_amount(taker_order.clone(), maker_order.clone())?;
-        // matching_amount is expressed in taker terms; convert to base/quote by side
+        let price = self.match_price(taker_order.price, maker_order.price);
+        let (matching_amount, taker_clear, maker_clear) =
+            self._get_matching_amount(taker_order.clone(), maker_order.clone(), max_amount, price)?;
+        // matching_amount is expressed in taker terms; convert to base/quote by side at the match price
         let matching_base_amount = if taker_is_bid {
-            matching_amount.saturating_mul(1_0000_0000).saturating_div(taker_order.price)
+            self.quote_to_base(matching_amount, price)
         } else {
             matching_amount
         };
         let matching_quote_amount = if taker_is_bid {
             matching_amount
         } else {
-            matching_amount.saturating_mul(taker_order.price).saturating_div(1_0000_0000)
+            self.base_to_quote(matching_amount, price)
         };
 
         let taker_matching_amount = if taker_is_bid { matching_quote_amount.clone() } else { matching_base_amount.clone() };
         let maker_matching_amount = if taker_is_bid { matching_base_amount.clone() } else { matching_quote_amount.clone() };
 
         // Get order data before mutable borrow
-        if maker_order.expires_at <= now {
-            self._expire_order(maker_order.id, !taker_is_bid, pair_id_vec.clone(), now)?;
-            // let _match_at at pair.rs handle the expired order error
-            return Err(OrderBookError::OrderExpired);
+        if self.is_expired(maker_order.expires_at, now) {
+            self._expire_order(
+                maker_order.id,
+                !taker_is_bid,
+                pair_id_vec.clone(),
+                &base_asset_id_vec,
+                &quote_asset_id_vec,
+                now,
+            )?;
+            // nothing is matched, the taker goes on with the next maker
+            return Ok((taker_order, Fill::default()));
         }
-        let (_taker_delete_amount, taker_delete_price) =
-            self.l3
-                .decrease_order(taker_order.id, taker_matching_amount, self.dust, taker_clear)?;
-        let (_maker_delete_amount, maker_delete_price) =
-            self.l3
-                .decrease_order(maker_order.id, maker_matching_amount, self.dust, maker_clear)?;
 
         // Calculate fees using fee table
-        let (base_fee, quote_fee) = self._calculate_fees(
+        let (base_fee, quote_fee, maker_rebate) = self._calculate_fees(
             taker_is_bid,
             matching_base_amount,
             matching_quote_amount,
-            maker_order.fee_bps,
-            taker_order.fee_bps,
+            price,
+            &maker_order,
+            &taker_order,
         );
+        // the rebate is paid out from the maker client's fee account, so resolve it before touching the book
+        let rebate_payer = if maker_rebate > 0 {
+            Some(self.fee_recipient(&maker_order.cid)?)
+        } else {
+            None
+        };
+        // fees are collected by the fee account of the payer's client, also resolved before touching the book
+        let (buyer, seller) = if taker_is_bid { (&taker_order, &maker_order) } else { (&maker_order, &taker_order) };
+        let base_fee_recipient = if base_fee > 0 {
+            Some(self.fee_recipient(&seller.cid)?)
+        } else {
+            None
+        };
+        let quote_fee_recipient = if quote_fee > 0 {
+            Some(self.fee_recipient(&buyer.cid)?)
+        } else {
+            None
+        };
+
+        let taker_is_resting = self.l3.orders.contains_key(&taker_order.id);
+        let mut updated_taker = taker_order.clone();
+        let (taker_delete_amount, taker_delete_price) = if taker_is_resting {
+            self.l3
+                .decrease_order(taker_order.id, taker_matching_amount, self.dust, taker_clear)?
+        } else {
+            // a transient taker is decreased the same way L3 decreases a resting order
+            let amount = taker_matching_amount.min(updated_taker.cqty);
+            let decreased = updated_taker.cqty - amount;
+            if taker_clear || decreased <= self.dust {
+                let cleared = updated_taker.cqty;
+                updated_taker.cqty = 0;
+                updated_taker.pqty = 0;
+                (cleared, None)
+            } else {
+                updated_taker.cqty = decreased;
+                updated_taker.pqty = updated_taker.pqty.min(decreased);
+                (amount, None)
+            }
+        };
+        let (maker_delete_amount, maker_delete_price) =
+            self.l3
+                .decrease_order(maker_order.id, maker_matching_amount, self.dust, maker_clear)?;
 
         // emit the event for order matched
         let match_timestamp = now;
 
-        let (taker_remaining_cqty, taker_remaining_pqty) = match self.l3.get_order(taker_order.id) {
-            Ok(updated) => (updated.cqty, updated.pqty),
-            Err(_) => (0, 0),
+        let (taker_remaining_cqty, taker_remaining_pqty) = if taker_is_resting {
+            match self.l3.get_order(taker_order.id) {
+                Ok(updated) => (updated.cqty, updated.pqty),
+                Err(_) => (0, 0),
+            }
+        } else {
+            (updated_taker.cqty, updated_taker.pqty)
         };
+        updated_taker.cqty = taker_remaining_cqty;
+        updated_taker.pqty = taker_remaining_pqty;
 
         // emit event for both maker and taker
         let (maker_remaining_cqty, maker_remaining_pqty) = match self.l3.get_order(maker_order.id) {
@@ -426,19 +1285,117 @@ impl OrderBook {
             taker_order.expires_at,
             maker_order.expires_at,
         )?;
+        event::emit_event(SpotEvent::SpotTrade {
+            trade_id: Ulid::new(),
+            pair_id: pair_id_vec.clone(),
+            price,
+            base_volume: matching_base_amount,
+            quote_volume: matching_quote_amount,
+            taker_order_id: taker_order.id.to_bytes().to_vec(),
+            maker_order_id: maker_order.id.to_bytes().to_vec(),
+            taker_is_bid,
+            timestamp: match_timestamp,
+        });
+
+        // settle the match: base moves from the seller to the buyer and quote the other way in full,
+        // then each side pays its own fee out of its locked funds to its client's fee account,
+        // the seller in base and the buyer in quote
+        event::emit_event(SpotEvent::Transfer {
+            cid: seller.cid.clone(),
+            from: seller.owner.clone(),
+            to: buyer.owner.clone(),
+            asset: base_asset_id_vec.clone(),
+            amnt: matching_base_amount,
+            timestamp: match_timestamp,
+        });
+        event::emit_event(SpotEvent::Transfer {
+            cid: buyer.cid.clone(),
+            from: buyer.owner.clone(),
+            to: seller.owner.clone(),
+            asset: quote_asset_id_vec.clone(),
+            amnt: matching_quote_amount,
+            timestamp: match_timestamp,
+        });
+        if let Some(fee_recipient) = base_fee_recipient {
+            event::emit_event(SpotEvent::Transfer {
+                cid: seller.cid.clone(),
+                from: seller.owner.clone(),
+                to: fee_recipient,
+                asset: base_asset_id_vec.clone(),
+                amnt: base_fee,
+                timestamp: match_timestamp,
+            });
+        }
+        if let Some(fee_recipient) = quote_fee_recipient {
+            event::emit_event(SpotEvent::Transfer {
+                cid: buyer.cid.clone(),
+                from: buyer.owner.clone(),
+                to: fee_recipient,
+                asset: quote_asset_id_vec.clone(),
+                amnt: quote_fee,
+                timestamp: match_timestamp,
+            });
+        }
+
+        // release what was cleared on top of the matched amount (dust) back to the owners
+        let taker_after_match = Order {
+            cqty: taker_order.cqty - taker_matching_amount.min(taker_order.cqty),
+            ..taker_order.clone()
+        };
+        self._emit_unlock(
+            &taker_after_match,
+            pair_id_vec.clone(),
+            &base_asset_id_vec,
+            &quote_asset_id_vec,
+            taker_delete_amount.saturating_sub(taker_matching_amount),
+            match_timestamp,
+        );
+        let maker_after_match = Order {
+            cqty: maker_order.cqty - maker_matching_amount.min(maker_order.cqty),
+            ..maker_order.clone()
+        };
+        self._emit_unlock(
+            &maker_after_match,
+            pair_id_vec.clone(),
+            &base_asset_id_vec,
+            &quote_asset_id_vec,
+            maker_delete_amount.saturating_sub(maker_matching_amount),
+            match_timestamp,
+        );
+
+        // credit the maker rebate in the asset the maker fee would have been charged in
+        if let Some(rebate_payer) = rebate_payer {
+            let rebate_asset_id = if taker_is_bid {
+                base_asset_id_vec.clone()
+            } else {
+                quote_asset_id_vec.clone()
+            };
+            event::emit_event(SpotEvent::Transfer {
+                cid: maker_order.cid.clone(),
+                from: rebate_payer,
+                to: maker_order.owner.clone(),
+                asset: rebate_asset_id,
+                amnt: maker_rebate,
+                timestamp: match_timestamp,
+            });
+        }
 
         // adjust price level on the matched amount
         // Update levels and remove price if level becomes 0 or below
         // Also handle delete_price removal if an order was fully consumed
-        self.update_price_level(
-            pair_id_vec.clone(),
-            false,
-            taker_order.is_bid,
-            taker_order.price,
-            taker_delta_pqty,
-            taker_delta_cqty,
-            taker_delete_price,
-        )?;
+        // a transient taker never added its quantity to L2
+        if taker_is_resting {
+            self.update_price_level(
+                pair_id_vec.clone(),
+                false,
+                taker_order.is_bid,
+                taker_order.price,
+                taker_delta_pqty,
+                taker_delta_cqty,
+                taker_delete_price,
+                now,
+            )?;
+        }
         self.update_price_level(
             pair_id_vec,
             false,
@@ -447,63 +1404,99 @@ impl OrderBook {
             maker_delta_pqty,
             maker_delta_cqty,
             maker_delete_price,
+            now,
         )?;
+        self.check_invariants()?;
 
-        Ok(())
+        // a taker or maker cleared by the match left the book filled
+        if !self.l3.orders.contains_key(&maker_order.id) {
+            self.archive.archive(&Order { pqty: 0, cqty: 0, ..maker_order }, TerminalState::Filled);
+        }
+        if updated_taker.cqty == 0 {
+            self.archive.archive(&updated_taker, TerminalState::Filled);
+        }
+
+        Ok((
+            updated_taker,
+            Fill {
+                base_volume: matching_base_amount,
+                quote_volume: matching_quote_amount,
+                taker_fee: if taker_is_bid { quote_fee } else { base_fee },
+                makers: 1,
+            },
+        ))
     }
 
     /// Determines the matching amount between the taker and maker orders.
     /// - `taker_order` is the taker order.
     /// - `maker_order` is the maker order.
+    /// - `price` is the match price both orders are converted at, see `match_price`.
     /// - returns the matching amount and whether the taker order is fully matched and whether the maker order is fully matched.
     fn _get_matching_amount(
         &mut self,
         taker_order: Order,
         maker_order: Order,
+        max_amount: u64,
+        price: u64,
     ) -> Result<(u64, bool, bool), OrderBookError> {
+        // only the taker's share up to `max_amount` is matched, the taker is cleared only when it is not capped
+        let taker_cqty = taker_order.cqty.min(max_amount);
+        // the taker's share in the maker's units, base against asks and quote against bids
         let taker_converted_matching_cqty = if taker_order.is_bid {
-            taker_order.cqty.saturating_mul(1_0000_0000).saturating_div(taker_order.price)
+            self.quote_to_base(taker_cqty, price)
         } else {
-            taker_order.cqty.saturating_mul(taker_order.price).saturating_div(1_0000_0000)
+            self.base_to_quote(taker_cqty, price)
         };
         // there are two cases:
-        // 1. taker order's converted matching amount is bigger than maker order's matching amount
+        // 1. taker order's converted matching amount covers the maker order, which is cleared
         if taker_converted_matching_cqty >= maker_order.cqty {
             // get the taker's matching amount from the maker order
-            let taker_matching_amount = self.get_required(maker_order.clone(), taker_order.price, maker_order.cqty)?;
-            return Ok((taker_matching_amount, false, true));
-        } 
-        // 2. taker order's converted matching amount is smaller than maker order's matching amount
-        else if taker_converted_matching_cqty < maker_order.cqty {
-            // get the maker's matching amount from the taker order
-            let maker_matching_cqty = self.get_required(taker_order.clone(), maker_order.price, taker_order.cqty)?;
-            return Ok((maker_matching_cqty, true, false));
+            let taker_matching_amount = self.get_required(taker_order.clone(), price, maker_order.cqty)?.min(taker_cqty);
+            Ok((taker_matching_amount, false, true))
         }
-        // 3. taker order's converted matching amount is equal to maker order's matching amount
-        else { 
-            // get the taker's matching amount from the maker order
-            return Ok((taker_order.cqty, true, true)); 
+        // 2. taker order's converted matching amount is smaller than maker order's matching amount
+        else {
+            Ok((taker_cqty, taker_cqty == taker_order.cqty, false))
         }
     }
 
+    /// Calculates the fees of a match.
+    /// - returns `(base_fee, quote_fee, maker_rebate)`, the seller pays the base fee and the buyer the quote fee,
+    ///   each out of the fee reserve of its order with `Order::fee_on`.
+    /// - a negative maker `fee_bps` charges nothing on the maker side and returns the credit as `maker_rebate`
+    ///   in the asset the maker fee would have been charged in.
+    /// - the rebate is capped at the taker fee of the match, converted to the rebate asset at `price`, so the fee
+    ///   accounts never pay out more than they collect.
+    /// - taker fees are never negative, a negative taker `fee_bps` is treated as zero.
     fn _calculate_fees(
         &self,
         is_bid: bool,
         matching_base_amount: u64,
         matching_quote_amount: u64,
-        maker_fee_bps: u16,
-        taker_fee_bps: u16,
-    ) -> (u64, u64) {
+        price: u64,
+        maker_order: &Order,
+        taker_order: &Order,
+    ) -> (u64, u64, u64) {
+        let maker_rebate_bps = if maker_order.fee_bps < 0 {
+            maker_order.fee_bps.unsigned_abs() as u128
+        } else {
+            0
+        };
+        let rebate_on = |amount: u64| u64::try_from(amount as u128 * maker_rebate_bps / 10000).unwrap_or(u64::MAX);
         // find maker and taker from base and quote amount
         if is_bid {
+            let taker_fee = taker_order.fee_on(matching_quote_amount);
             (
-                matching_base_amount * maker_fee_bps as u64 / 10000,
-                matching_quote_amount * taker_fee_bps as u64 / 10000,
+                maker_order.fee_on(matching_base_amount),
+                taker_fee,
+                rebate_on(matching_base_amount).min(self.quote_to_base(taker_fee, price)),
             )
         } else {
+            let taker_fee = taker_order.fee_on(matching_base_amount);
             (
-                matching_base_amount * taker_fee_bps as u64 / 10000,
-                matching_quote_amount * maker_fee_bps as u64 / 10000,
+                taker_fee,
+                maker_order.fee_on(matching_quote_amount),
+                rebate_on(matching_quote_amount).min(self.base_to_quote(taker_fee, price)),
             )
         }
     }
@@ -513,19 +1506,22 @@ impl OrderBook {
         order_id: OrderId,
         is_bid: bool,
         pair_id: Vec<u8>,
+        base_asset_id: &[u8],
+        quote_asset_id: &[u8],
         now: i64,
     ) -> Result<(), OrderBookError> {
         let order = self.l3.get_order(order_id)?.clone();
         let deleted_price_opt = self.l3.delete_order(order_id)?;
         // update the price level on the orderbook
         self.update_price_level(
-            pair_id,
+            pair_id.clone(),
             false,
             is_bid,
             order.price,
             order.pqty,
             order.cqty,
             deleted_price_opt,
+            now,
         )?;
         // emit event for the order expired
         event::emit_event(SpotEvent::SpotOrderExpired {
@@ -541,9 +1537,38 @@ impl OrderBook {
             timestamp: now,
             expires_at: order.expires_at,
         });
+        self._emit_unlock(&order, pair_id, base_asset_id, quote_asset_id, order.cqty, now);
+        self.archive.archive(&order, TerminalState::Expired);
         Ok(())
     }
 
+    /// Emits an `Unlock` releasing `amnt` of the order's locked funds and the fee reserved for them, nothing is
+    /// emitted for a zero amount.
+    /// - `order` holds the quantity it had before the release.
+    fn _emit_unlock(
+        &self,
+        order: &Order,
+        pair_id: Vec<u8>,
+        base_asset_id: &[u8],
+        quote_asset_id: &[u8],
+        amnt: u64,
+        timestamp: i64,
+    ) {
+        if amnt == 0 {
+            return;
+        }
+        event::emit_event(SpotEvent::Unlock {
+            cid: order.cid.clone(),
+            pair_id,
+            order_id: order.id.to_bytes().to_vec(),
+            account_id: order.owner.clone(),
+            asset: if order.is_bid { quote_asset_id.to_vec() } else { base_asset_id.to_vec() },
+            is_bid: order.is_bid,
+            amnt: amnt + order.fee_on(amnt),
+            timestamp,
+        });
+    }
+
     fn _emit_taker_maker_match(
         &self,
         taker_order: Order,
@@ -696,7 +1721,9 @@ impl OrderBook {
     /// - `delta_pqty` is the delta quantity of the public quantity.
     /// - `delta_cqty` is the delta quantity of the current quantity.
     /// - `delete_price` is an optional price that should be removed (when an order was fully consumed).
+    /// - `timestamp` is the time of the triggering operation, stamped on the emitted level events.
     /// Removes the price if the level becomes 0 or below.
+    #[allow(clippy::too_many_arguments)]
     pub fn update_price_level(
         &mut self,
         pair_id: Vec<u8>,
@@ -706,6 +1733,7 @@ impl OrderBook {
         delta_pqty: u64,
         delta_cqty: u64,
         delete_price: Option<u64>,
+        timestamp: i64,
     ) -> Result<(), OrderBookError> {
         if is_placed {
             // insert price if the price does not exist
@@ -776,10 +1804,7 @@ impl OrderBook {
                 price,
                 pqty: new_pqty,
                 cqty: new_cqty,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
+                timestamp,
             });
             Ok(())
         } else {
@@ -818,6 +1843,9 @@ impl OrderBook {
                 ))?;
             };
 
+            // prices removed from L2, reported after the zeroed block change
+            let mut removed_prices = Vec::new();
+
             // Calculate new level quantity (subtract amount)
             // Use saturating_sub to prevent underflow, but we still check for 0
             let new_cqty = current_cqty.saturating_sub(delta_cqty);
@@ -833,8 +1861,8 @@ impl OrderBook {
                     self.l2.set_public_bid_level(price, 0)?;
                     self.l2.set_current_bid_level(price, 0)?;
                     // Check if price level is empty in L3, and if so, remove it
-                    if self.l3.is_empty(price) {
-                        self.l2.remove_price(is_bid, price)?;
+                    if self.l3.is_empty(price) && self._remove_level(is_bid, price)? {
+                        removed_prices.push(price);
                     }
                 }
             } else {
@@ -846,8 +1874,8 @@ impl OrderBook {
                     self.l2.set_public_ask_level(price, 0)?;
                     self.l2.set_current_ask_level(price, 0)?;
                     // Check if price level is empty in L3, and if so, remove it
-                    if self.l3.is_empty(price) {
-                        self.l2.remove_price(is_bid, price)?;
+                    if self.l3.is_empty(price) && self._remove_level(is_bid, price)? {
+                        removed_prices.push(price);
                     }
                 }
             }
@@ -855,40 +1883,85 @@ impl OrderBook {
             // If delete_price is Some, it means an order was fully consumed and price level was emptied
             // Remove that price level
             if let Some(delete_price) = delete_price {
-                self.l2.remove_price(is_bid, delete_price)?;
+                if self._remove_level(is_bid, delete_price)? {
+                    removed_prices.push(delete_price);
+                }
             }
 
             // emit the event for the price level update on the orderbook
             event::emit_event(SpotEvent::SpotOrderBlockChanged {
-                pair_id,
+                pair_id: pair_id.clone(),
                 is_bid,
                 price,
                 pqty: new_pqty,
                 cqty: new_cqty,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
+                timestamp,
             });
+            for removed_price in removed_prices {
+                event::emit_event(SpotEvent::SpotLevelRemoved {
+                    pair_id: pair_id.clone(),
+                    is_bid,
+                    price: removed_price,
+                    timestamp,
+                });
+            }
 
             Ok(())
         }
     }
 
+    /// Removes a price from L2 if it is still listed.
+    /// - returns whether the price was removed, so each removal is reported once.
+    fn _remove_level(&mut self, is_bid: bool, price: u64) -> Result<bool, OrderBookError> {
+        Ok(self.l2.remove_price(is_bid, price)?)
+    }
+
+    /// Removes a price level left without orders and reports it with `SpotLevelRemoved`, once.
+    fn _remove_empty_level(&mut self, pair_id: &[u8], is_bid: bool, price: u64) -> Result<bool, OrderBookError> {
+        let removed = self._remove_level(is_bid, price)?;
+        if removed {
+            event::emit_event(SpotEvent::SpotLevelRemoved {
+                pair_id: pair_id.to_vec(),
+                is_bid,
+                price,
+                timestamp: self.clock.now_millis(),
+            });
+        }
+        Ok(removed)
+    }
+
+    /// Emits the checksum of the top `top_n` levels on each side so clients can verify their local book.
+    /// - returns the checksum.
+    pub fn emit_book_checksum(&self, pair_id: impl Into<Vec<u8>>, top_n: u32, timestamp: i64) -> u32 {
+        let checksum = self.l2.checksum(top_n as usize);
+        event::emit_event(SpotEvent::SpotBookChecksum {
+            pair_id: pair_id.into(),
+            top_n,
+            checksum,
+            timestamp,
+        });
+        checksum
+    }
+
     /// Cancels an order.
     /// - returns the amount to send and the delete price.
     /// - `order_id` is the id of the order to cancel.
     /// - `owner` is the owner of the order.
+    #[allow(clippy::too_many_arguments)]
     pub fn cancel_order(
         &mut self,
         cid: impl Into<Vec<u8>>,
         pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
         is_bid: bool,
         order_id: OrderId,
         owner: impl Into<Vec<u8>>,
     ) -> Result<(), OrderBookError> {
         let cid = cid.into();
         let pair_id = pair_id.into();
+        let base_asset_id = base_asset_id.into();
+        let quote_asset_id = quote_asset_id.into();
         let owner = owner.into();
         // check if the order exists
         let order = self.l3.get_order(order_id)?.clone();
@@ -896,10 +1969,9 @@ impl OrderBook {
         if order.owner != owner {
             return Err(OrderBookError::OrderNotOwnedBySender);
         }
+        // the emptied price level is removed from L2 by `update_price_level` below
         let deleted_price_opt = self.l3.delete_order(order_id)?;
-        if let Some(price) = deleted_price_opt {
-            self.l2.remove_price(is_bid, price)?;
-        }
+        let now = self.clock.now_millis();
 
         // emit the event for the order cancelled
         event::emit_event(SpotEvent::SpotOrderCancelled {
@@ -925,25 +1997,147 @@ impl OrderBook {
             order.pqty,
             order.cqty,
             deleted_price_opt,
+            now,
+        )?;
+        self._emit_unlock(&order, pair_id, &base_asset_id, &quote_asset_id, order.cqty, now);
+        self.archive.archive(&order, TerminalState::Cancelled);
+        Ok(())
+    }
+
+    /// Reduces a resting order by `reduce_by` while it keeps its place in the queue.
+    /// - `reduce_by` is clamped to the current quantity of the order.
+    /// - the order is removed only when its remainder falls to or below the dust limit.
+    /// - emits `SpotOrderReduced` with the remaining quantities and unlocks the reduced amount.
+    #[allow(clippy::too_many_arguments)]
+    pub fn reduce_order(
+        &mut self,
+        cid: impl Into<Vec<u8>>,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        is_bid: bool,
+        order_id: OrderId,
+        reduce_by: u64,
+        owner: impl Into<Vec<u8>>,
+    ) -> Result<(), OrderBookError> {
+        let pair_id = pair_id.into();
+        let base_asset_id = base_asset_id.into();
+        let quote_asset_id = quote_asset_id.into();
+        let owner = owner.into();
+        let order = self.l3.get_order(order_id)?.clone();
+        if order.owner != owner {
+            return Err(OrderBookError::OrderNotOwnedBySender);
+        }
+        let (reduced, deleted_price_opt) = self.l3.decrease_order(order_id, reduce_by, self.dust, false)?;
+        // a dust remainder is removed with the order
+        let (pqty, cqty) = self
+            .l3
+            .orders
+            .get(&order_id)
+            .map_or((0, 0), |remaining| (remaining.pqty, remaining.cqty));
+        let now = self.clock.now_millis();
+
+        event::emit_event(SpotEvent::SpotOrderReduced {
+            cid: cid.into(),
+            pair_id: pair_id.clone(),
+            order_id: order_id.to_bytes().to_vec(),
+            maker_account_id: order.owner.clone(),
+            is_bid,
+            price: order.price,
+            amnt: reduced,
+            pqty,
+            cqty,
+            timestamp: now,
+        });
+
+        self.update_price_level(
+            pair_id.clone(),
+            false,
+            is_bid,
+            order.price,
+            order.pqty - pqty,
+            reduced,
+            deleted_price_opt,
+            now,
         )?;
+        self._emit_unlock(&order, pair_id, &base_asset_id, &quote_asset_id, reduced, now);
+        if !self.l3.orders.contains_key(&order_id) {
+            self.archive.archive(&Order { pqty, cqty, ..order }, TerminalState::Cancelled);
+        }
         Ok(())
     }
 
+    /// Cancels every resting order owned by `owner`, emitting one `SpotOrderCancelled` per order.
+    /// - returns the number of cancelled orders, `0` when the owner has no resting orders.
+    pub fn cancel_all(
+        &mut self,
+        cid: impl Into<Vec<u8>>,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        owner: impl Into<Vec<u8>>,
+    ) -> Result<usize, OrderBookError> {
+        let cid = cid.into();
+        let pair_id = pair_id.into();
+        let base_asset_id = base_asset_id.into();
+        let quote_asset_id = quote_asset_id.into();
+        let owner = owner.into();
+        let orders = self.l3.orders_by_owner(&owner);
+        for order in &orders {
+            self.cancel_order(
+                cid.clone(),
+                pair_id.clone(),
+                base_asset_id.clone(),
+                quote_asset_id.clone(),
+                order.is_bid,
+                order.id,
+                owner.clone(),
+            )?;
+        }
+        Ok(orders.len())
+    }
+
     pub fn expire_orders(
         &mut self,
         is_bid: bool,
         pair_id: impl Into<Vec<u8>>,
         base_asset_id: impl Into<Vec<u8>>,
         quote_asset_id: impl Into<Vec<u8>>,
-        managing_account_id: impl Into<Vec<u8>>,
         now: i64,
     ) -> Result<(), OrderBookError> {
+        self.expire_orders_bounded(is_bid, pair_id, base_asset_id, quote_asset_id, now, usize::MAX)?;
+        Ok(())
+    }
+
+    /// Expires at most `max_removals` orders of one side, earliest expiry first, like `expire_orders`.
+    /// - `now` is in milliseconds, it is converted to `time_unit` before comparing it to `expires_at`.
+    /// - the expired orders are popped from an index of L3, so a batch does not scan the resting orders.
+    /// - returns the number of expired orders, fewer than `max_removals` once no expired order is left.
+    /// - the remaining quantity of an expired order is unlocked, the same as an order expired while matching.
+    /// - lets a caller holding a lock on the book release it between batches.
+    pub fn expire_orders_bounded(
+        &mut self,
+        is_bid: bool,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        now: i64,
+        max_removals: usize,
+    ) -> Result<usize, OrderBookError> {
         let pair_id = pair_id.into();
         let base_asset_id = base_asset_id.into();
         let quote_asset_id = quote_asset_id.into();
-        let managing_account_id = managing_account_id.into();
-        let expired_orders = self.l3.remove_dormant_orders(now);
+        // only the requested side is expired, the other side has its price levels in the other tree
+        let now_in_unit = self.time_unit.from_millis(now);
+        let expired_orders: Vec<(OrderId, Order)> = self
+            .l3
+            .expired_order_ids(is_bid, now_in_unit, max_removals)
+            .into_iter()
+            .map(|id| (id, self.l3.orders[&id].clone()))
+            .collect();
+        let expired = expired_orders.len();
         for (order_id, order) in expired_orders {
+            self.l3.delete_order(order_id)?;
             // emit event for the order expired
             event::emit_event(SpotEvent::SpotOrderExpired {
                 cid: order.cid.clone(),
@@ -958,20 +2152,9 @@ impl OrderBook {
                 timestamp: now,
                 expires_at: order.expires_at,
             });
-            // emit event for transfer of the expired asset to order owner
-            let expired_asset_id = if is_bid {
-                quote_asset_id.clone()
-            } else {
-                base_asset_id.clone()
-            };
-            event::emit_event(SpotEvent::Transfer {
-                cid: order.cid.clone(),
-                from: managing_account_id.clone(),
-                to: order.owner.clone(),
-                asset: expired_asset_id,
-                amnt: order.amnt,
-                timestamp: now,
-            });
+            // the remainder is still locked with the owner, releasing it is the whole refund
+            self._emit_unlock(&order, pair_id.clone(), &base_asset_id, &quote_asset_id, order.cqty, now);
+            self.archive.archive(&order, TerminalState::Expired);
 
             // update the price level on the orderbook
             let delete_price = if self.l3.is_empty(order.price) {
@@ -987,9 +2170,75 @@ impl OrderBook {
                 order.pqty,
                 order.cqty,
                 delete_price,
+                now,
             )?;
         }
-        Ok(())
+        Ok(expired)
+    }
+
+    /// Cancels every resting order whose current quantity fell to or below the dust limit.
+    /// - unlocks the remaining quantity with the owner, no funds move between accounts.
+    /// - emits `SpotOrderDustSwept` for each swept order.
+    /// - returns the number of swept orders.
+    pub fn sweep_dust(
+        &mut self,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        now: i64,
+    ) -> Result<usize, OrderBookError> {
+        self.sweep_dust_bounded(pair_id, base_asset_id, quote_asset_id, now, usize::MAX)
+    }
+
+    /// Sweeps at most `max_removals` dust orders, smallest remainder first, like `sweep_dust`.
+    /// - the dust orders are popped from an index of L3, so a batch does not scan the resting orders.
+    /// - returns the number of swept orders, fewer than `max_removals` once no dust order is left.
+    pub fn sweep_dust_bounded(
+        &mut self,
+        pair_id: impl Into<Vec<u8>>,
+        base_asset_id: impl Into<Vec<u8>>,
+        quote_asset_id: impl Into<Vec<u8>>,
+        now: i64,
+        max_removals: usize,
+    ) -> Result<usize, OrderBookError> {
+        let pair_id = pair_id.into();
+        let base_asset_id = base_asset_id.into();
+        let quote_asset_id = quote_asset_id.into();
+
+        let dust_orders: Vec<Order> = self
+            .l3
+            .dust_order_ids(self.dust, max_removals)
+            .into_iter()
+            .map(|id| self.l3.orders[&id].clone())
+            .collect();
+
+        for order in &dust_orders {
+            let deleted_price_opt = self.l3.delete_order(order.id)?;
+            self.update_price_level(
+                pair_id.clone(),
+                false,
+                order.is_bid,
+                order.price,
+                order.pqty,
+                order.cqty,
+                deleted_price_opt,
+                now,
+            )?;
+            event::emit_event(SpotEvent::SpotOrderDustSwept {
+                cid: order.cid.clone(),
+                pair_id: pair_id.clone(),
+                order_id: order.id.to_bytes().to_vec(),
+                maker_account_id: order.owner.clone(),
+                is_bid: order.is_bid,
+                price: order.price,
+                cqty: order.cqty,
+                timestamp: now,
+            });
+            // the remainder is still locked with the owner, releasing it is the whole refund
+            self._emit_unlock(order, pair_id.clone(), &base_asset_id, &quote_asset_id, order.cqty, now);
+            self.archive.archive(order, TerminalState::Cancelled);
+        }
+        Ok(dust_orders.len())
     }
 
     pub fn set_iceberg_quantity(
@@ -1018,6 +2267,7 @@ impl OrderBook {
                 delta_pqty,
                 0,
                 None,
+                self.clock.now_millis(),
             )?;
         }
         // emit event for the iceberg quantity changed
@@ -1033,4 +2283,130 @@ impl OrderBook {
         });
         Ok(())
     }
+
+    /// Re-applies an event emitted by this orderbook, e.g. when replaying a persisted event log.
+    /// - a pure transform: no events are emitted and the clock is never read.
+    /// - replaying the events of a session in order onto a book with the same config reproduces its state.
+    /// - events that do not change the book, like `Lock` or `SpotTrade`, are ignored.
+    /// - levels are listed on placement and removed on `SpotLevelRemoved`, their quantities are summed from the
+    ///   orders resting at the price, so they never go stale whatever order the block changes come in.
+    pub fn apply_event(&mut self, event: &SpotEvent) -> Result<(), OrderBookError> {
+        let level = match event {
+            SpotEvent::SpotOrderPlaced {
+                cid,
+                order_id,
+                maker_account_id,
+                is_bid,
+                price,
+                amnt,
+                iqty,
+                pqty,
+                cqty,
+                timestamp,
+                expires_at,
+                fee_bps,
+                ..
+            } => {
+                self.l3.insert_order(Order::new(
+                    cid.clone(),
+                    Self::_order_id(order_id)?,
+                    maker_account_id.clone(),
+                    *is_bid,
+                    *price,
+                    *amnt,
+                    *iqty,
+                    *pqty,
+                    *cqty,
+                    *timestamp,
+                    *expires_at,
+                    *fee_bps,
+                ))?;
+                if !self.l2.price_exists(*is_bid, *price) {
+                    self.l2.insert_price(*is_bid, *price)?;
+                }
+                Some((*is_bid, *price))
+            }
+            SpotEvent::SpotOrderBlockChanged { is_bid, price, .. } => Some((*is_bid, *price)),
+            SpotEvent::SpotLevelRemoved { is_bid, price, .. } => {
+                self._remove_level(*is_bid, *price)?;
+                None
+            }
+            SpotEvent::SpotOrderPartiallyFilled { is_taker_event, taker_order_id, maker_order_id, pqty, cqty, .. }
+            | SpotEvent::SpotOrderFullyFilled { is_taker_event, taker_order_id, maker_order_id, pqty, cqty, .. } => {
+                let order_id = Self::_order_id(if *is_taker_event { taker_order_id } else { maker_order_id })?;
+                // a transient taker never rested in L3
+                self._resize_order(order_id, *pqty, *cqty)?
+            }
+            SpotEvent::SpotOrderReduced { order_id, pqty, cqty, .. } => {
+                self._resize_order(Self::_order_id(order_id)?, *pqty, *cqty)?
+            }
+            SpotEvent::SpotOrderCancelled { order_id, .. }
+            | SpotEvent::SpotOrderExpired { order_id, .. }
+            | SpotEvent::SpotOrderDustSwept { order_id, .. } => {
+                // the remainder of a transient taker is cancelled without resting in L3
+                self._resize_order(Self::_order_id(order_id)?, 0, 0)?
+            }
+            SpotEvent::SpotOrderIcebergQuantityChanged { order_id, iqty, .. } => {
+                let order = self.l3.set_iceberg_quantity(Self::_order_id(order_id)?, *iqty)?;
+                Some((order.is_bid, order.price))
+            }
+            _ => None,
+        };
+        if let Some((is_bid, price)) = level {
+            self._resync_level(is_bid, price)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the remaining quantities of a resting order, deleting it at 0.
+    /// Returns the level of the order, None if it does not rest in L3.
+    fn _resize_order(&mut self, order_id: OrderId, pqty: u64, cqty: u64) -> Result<Option<(bool, u64)>, OrderBookError> {
+        let Some(order) = self.l3.orders.get(&order_id) else {
+            return Ok(None);
+        };
+        let level = (order.is_bid, order.price);
+        if cqty == 0 {
+            self.l3.delete_order(order_id)?;
+        } else {
+            self.l3.set_quantities(order_id, pqty, cqty)?;
+        }
+        Ok(Some(level))
+    }
+
+    /// Sets the quantities of a listed level to the sum of the orders resting at its price.
+    fn _resync_level(&mut self, is_bid: bool, price: u64) -> Result<(), OrderBookError> {
+        if !self.l2.price_exists(is_bid, price) {
+            return Ok(());
+        }
+        let (mut pqty, mut cqty) = (0u64, 0u64);
+        let mut current = self.l3.head(price);
+        while let Some(id) = current {
+            if let Some(order) = self.l3.orders.get(&id).filter(|order| order.is_bid == is_bid) {
+                pqty = pqty.saturating_add(order.pqty);
+                cqty = cqty.saturating_add(order.cqty);
+            }
+            current = self.l3.next(price, id);
+        }
+        self._set_level(is_bid, price, pqty, cqty)
+    }
+
+    fn _order_id(bytes: &[u8]) -> Result<OrderId, OrderBookError> {
+        let bytes: [u8; 16] = bytes.try_into().map_err(|_| OrderBookError::InvalidOrderId)?;
+        Ok(OrderId::from_bytes(bytes))
+    }
+
+    /// Sets both levels of a price, inserting the price if it is not listed yet.
+    fn _set_level(&mut self, is_bid: bool, price: u64, pqty: u64, cqty: u64) -> Result<(), OrderBookError> {
+        if !self.l2.price_exists(is_bid, price) {
+            self.l2.insert_price(is_bid, price)?;
+        }
+        if is_bid {
+            self.l2.set_public_bid_level(price, pqty)?;
+            self.l2.set_current_bid_level(price, cqty)?;
+        } else {
+            self.l2.set_public_ask_level(price, pqty)?;
+            self.l2.set_current_ask_level(price, cqty)?;
+        }
+        Ok(())
+    }
 }
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use ulid::Ulid;
 
+use super::id_generator::{IdGenerator, IdGeneratorHandle};
+
 pub type OrderId = Ulid;
 
 /// Represents an order stored in the order book.
@@ -27,10 +29,10 @@ pub struct Order {
     pub cqty: u64,
     /// timestamp of the order in milliseconds
     pub timestamp: i64,
-    /// expires at timestamp in milliseconds
+    /// expires at timestamp in the time unit of the order book, milliseconds by default
     pub expires_at: i64,
-    /// fee basis points of the order (maker or taker)
-    pub fee_bps: u16,
+    /// fee basis points of the order (maker or taker), negative for a maker rebate
+    pub fee_bps: i32,
 }
 
 impl Order {
@@ -47,7 +49,7 @@ impl Order {
         cqty: u64,
         timestamp: i64,
         expires_at: i64,
-        fee_bps: u16,
+        fee_bps: i32,
     ) -> Self {
         Self {
             cid,
@@ -64,6 +66,28 @@ impl Order {
             fee_bps,
         }
     }
+
+    /// Returns whether this order has time priority over `other`.
+    /// - compares the millisecond timestamps of the ulid ids, falling back to the `timestamp` field on a tie.
+    /// - orders that tie on both are not older than each other and keep their arrival order.
+    pub fn is_older_than(&self, other: &Order) -> bool {
+        (self.id.timestamp_ms(), self.timestamp) < (other.id.timestamp_ms(), other.timestamp)
+    }
+
+    /// Returns the most fee spending `qty` at `fee_bps` is charged, locked on top of `qty` when an order is placed.
+    /// A rebate (negative `fee_bps`) reserves nothing.
+    pub fn fee_reserve(qty: u64, fee_bps: i32) -> u64 {
+        (qty as u128 * fee_bps.max(0) as u128 / 10000) as u64
+    }
+
+    /// Returns the fee the order is charged for spending `amount` of its current quantity.
+    /// - the fee is taken from the order's fee reserve, so the charges and the reserve released with the
+    ///   remainder always add up to what was locked.
+    /// - `amount` is clamped to the current quantity of the order.
+    pub fn fee_on(&self, amount: u64) -> u64 {
+        let remaining = self.cqty - amount.min(self.cqty);
+        Self::fee_reserve(self.cqty, self.fee_bps) - Self::fee_reserve(remaining, self.fee_bps)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -83,6 +107,7 @@ pub enum L3Error {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(from = "SavedL3")]
 pub struct L3 {
     /// Mapping price -> head of the linked list in a price level.
     pub price_head: BTreeMap<u64, OrderId>,
@@ -92,10 +117,50 @@ pub struct L3 {
     pub order_nodes: HashMap<OrderId, Node>,
     /// Mapping order_id -> Order.
     pub orders: HashMap<OrderId, Order>,
+    /// Mapping owner -> ids of the resting orders placed by the owner.
+    pub owner_orders: HashMap<Vec<u8>, HashSet<OrderId>>,
     /// dust limit to determine if the order should be deleted
     pub dust: u64,
     /// Last displaced order when IDs collide.
     pub dormant_order: Option<OrderId>,
+    /// generator of the ids of new orders, defaults to random ulids
+    #[serde(skip)]
+    pub id_generator: IdGeneratorHandle,
+    /// ids of the resting orders by side and `expires_at`, derived from `orders` so it is not saved
+    #[serde(skip)]
+    expiry_index: BTreeSet<(bool, i64, OrderId)>,
+    /// ids of the resting orders by current quantity, derived from `orders` so it is not saved
+    #[serde(skip)]
+    quantity_index: BTreeSet<(u64, OrderId)>,
+}
+
+/// The saved fields of `L3`, its indexes are rebuilt from the orders on load
+#[derive(Deserialize)]
+struct SavedL3 {
+    price_head: BTreeMap<u64, OrderId>,
+    price_tail: BTreeMap<u64, OrderId>,
+    order_nodes: HashMap<OrderId, Node>,
+    orders: HashMap<OrderId, Order>,
+    owner_orders: HashMap<Vec<u8>, HashSet<OrderId>>,
+    dust: u64,
+    dormant_order: Option<OrderId>,
+}
+
+impl From<SavedL3> for L3 {
+    fn from(saved: SavedL3) -> Self {
+        let mut l3 = L3 {
+            price_head: saved.price_head,
+            price_tail: saved.price_tail,
+            order_nodes: saved.order_nodes,
+            orders: saved.orders,
+            owner_orders: saved.owner_orders,
+            dust: saved.dust,
+            dormant_order: saved.dormant_order,
+            ..L3::new()
+        };
+        l3.rebuild_indexes();
+        l3
+    }
 }
 
 impl L3 {
@@ -105,11 +170,31 @@ impl L3 {
             price_tail: BTreeMap::new(),
             order_nodes: HashMap::new(),
             orders: HashMap::new(),
+            owner_orders: HashMap::new(),
             dust: 1,
             dormant_order: None,
+            id_generator: IdGeneratorHandle::default(),
+            expiry_index: BTreeSet::new(),
+            quantity_index: BTreeSet::new(),
         }
     }
 
+    /// Rebuilds the expiry and quantity indexes from `orders`
+    pub(crate) fn rebuild_indexes(&mut self) {
+        self.expiry_index = self.orders.values().map(|order| (order.is_bid, order.expires_at, order.id)).collect();
+        self.quantity_index = self.orders.values().map(|order| (order.cqty, order.id)).collect();
+    }
+
+    fn unindex_order(&mut self, order: &Order) {
+        self.expiry_index.remove(&(order.is_bid, order.expires_at, order.id));
+        self.quantity_index.remove(&(order.cqty, order.id));
+    }
+
+    /// Sets the generator of the ids of new orders
+    pub fn set_id_generator(&mut self, id_generator: impl IdGenerator + 'static) {
+        self.id_generator = IdGeneratorHandle::new(id_generator);
+    }
+
     fn ensure_price(price: u64) -> Result<(), L3Error> {
         if price == 0 {
             Err(L3Error::PriceIsZero)
@@ -125,32 +210,47 @@ impl L3 {
 
     /// Inserts an order id into the linked structure for a given price level,
     /// keeping FIFO (append at tail).
+    /// An order that `is_older_than` orders already resting is inserted ahead of them, so the level keeps strict time
+    /// priority even if ids are generated out of clock order.
     pub fn insert_id(&mut self, price: u64, id: OrderId, _amount: u128) -> Result<(), L3Error> {
         Self::ensure_price(price)?;
         // ensure the order exists from the orders map
-        let order_node = self.order_nodes.get_mut(&id).ok_or(L3Error::OrderDoesNotExist(id))?;
+        if !self.order_nodes.contains_key(&id) {
+            return Err(L3Error::OrderDoesNotExist(id));
+        }
 
-        let price_tail = self.price_tail.get(&price).copied();
-
-        // if the price level is not empty
-        if let Some(price_tail) = price_tail {
-            // insert the order node at the tail of the price level
-            // First, update the prev of the new order node to point to old tail
-            order_node.prev = Some(price_tail);
-            order_node.next = None;
-            // To avoid double mutable borrow of self.order_nodes, we do this after:
-            let price_tail_node_ptr = self.order_nodes.get_mut(&price_tail).ok_or(L3Error::OrderDoesNotExist(price_tail))?;
-            price_tail_node_ptr.next = Some(id);
-            // Update the tail pointer to the new node
-            self.price_tail.insert(price, id);
-        } 
-        // if the price level is empty
-        else {
-            // insert the order node at the head of the price level
-            order_node.prev = None;
-            order_node.next = None;
-            self.price_head.insert(price, id);
-            self.price_tail.insert(price, id);
+        // walk back from the tail past the orders the new one has priority over
+        let mut prev = self.price_tail.get(&price).copied();
+        if let Some(order) = self.orders.get(&id) {
+            while let Some(resting_id) = prev {
+                match self.orders.get(&resting_id) {
+                    Some(resting) if order.is_older_than(resting) => {
+                        prev = self.order_nodes.get(&resting_id).and_then(|node| node.prev);
+                    }
+                    _ => break,
+                }
+            }
+        }
+        let next = match prev {
+            Some(prev) => self.order_nodes.get(&prev).ok_or(L3Error::OrderDoesNotExist(prev))?.next,
+            None => self.price_head.get(&price).copied(),
+        };
+
+        let order_node = self.order_nodes.get_mut(&id).ok_or(L3Error::OrderDoesNotExist(id))?;
+        order_node.prev = prev;
+        order_node.next = next;
+        // link the neighbours to the new node, an empty side makes it the head or the tail of the price level
+        match prev {
+            Some(prev) => self.order_nodes.get_mut(&prev).ok_or(L3Error::OrderDoesNotExist(prev))?.next = Some(id),
+            None => {
+                self.price_head.insert(price, id);
+            }
+        }
+        match next {
+            Some(next) => self.order_nodes.get_mut(&next).ok_or(L3Error::OrderDoesNotExist(next))?.prev = Some(id),
+            None => {
+                self.price_tail.insert(price, id);
+            }
         }
 
         Ok(())
@@ -219,12 +319,12 @@ impl L3 {
         iqty: u64,
         timestamp: i64,
         expires_at: i64,
-        maker_fee_bps: u16,
+        maker_fee_bps: i32,
     ) -> Result<Order, L3Error> {
         Self::ensure_price(price)?;
         let cid = cid.into();
         // generate a new order id
-        let id = Ulid::new();
+        let id = self.id_generator.next_id();
         let owner = owner.into();
         if iqty > amnt {
             return Err(L3Error::IcebergQuantityIsBiggerThanWholeAmount);
@@ -245,6 +345,16 @@ impl L3 {
             maker_fee_bps,
         );
 
+        self.insert_order(order.clone())?;
+        Ok(order)
+    }
+
+    /// Rests an already created order, e.g. the remainder of a taker, at its time priority in its price level.
+    pub fn insert_order(&mut self, order: Order) -> Result<(), L3Error> {
+        Self::ensure_price(order.price)?;
+        let id = order.id;
+        let price = order.price;
+        let cqty = order.cqty;
         // Create a new node for the order
         self.order_nodes.insert(
             id,
@@ -253,10 +363,16 @@ impl L3 {
                 next: None,
             },
         );
-        self.orders.insert(id, order.clone());
-        self.insert_id(price, id, amnt as u128)?;
-
-        Ok(order)
+        self.owner_orders.entry(order.owner.clone()).or_default().insert(id);
+        let (expiry, quantity) = ((order.is_bid, order.expires_at, id), (order.cqty, id));
+        if let Some(replaced) = self.orders.insert(id, order) {
+            // an order of a colliding id is not indexed anymore
+            self.unindex_order(&replaced);
+        }
+        self.expiry_index.insert(expiry);
+        self.quantity_index.insert(quantity);
+        self.insert_id(price, id, cqty as u128)?;
+        Ok(())
     }
 
     /// Decreases the deposit amount for a given order id.
@@ -289,6 +405,8 @@ impl L3 {
                 amount_to_send = original;
                 should_delete = true;
             } else {
+                self.quantity_index.remove(&(order.cqty, id));
+                self.quantity_index.insert((decreased, id));
                 // update the current base quantity of the order
                 order.cqty = decreased;
                 // update the current public quantity of the order
@@ -357,8 +475,16 @@ impl L3 {
             emptied_price = Some(price);
         }
         
-        // remove order from the orders map
-        self.orders.remove(&id);
+        // remove order from the orders map and the owner index
+        if let Some(order) = self.orders.remove(&id) {
+            self.unindex_order(&order);
+            if let Some(ids) = self.owner_orders.get_mut(&order.owner) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.owner_orders.remove(&order.owner);
+                }
+            }
+        }
         Ok(emptied_price)
     }
 
@@ -445,18 +571,73 @@ impl L3 {
         self.orders.get(&id).ok_or(L3Error::OrderDoesNotExist(id))
     }
 
+    /// Iterates over every resting order, in no particular order.
+    pub fn iter_orders(&self) -> impl Iterator<Item = (&OrderId, &Order)> {
+        self.orders.iter()
+    }
+
+    /// Returns the number of resting orders.
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    // entries of the expiry index of one side expiring at or before `now`, earliest first
+    fn expired_entries(&self, is_bid: bool, now: i64) -> impl Iterator<Item = &(bool, i64, OrderId)> {
+        self.expiry_index.range((is_bid, i64::MIN, OrderId::nil())..=(is_bid, now, OrderId::from(u128::MAX)))
+    }
+
+    /// Returns the ids of at most `max` orders of one side whose `expires_at` is at or before `now`,
+    /// earliest expiry first, then in order id order. Looked up in an index, so the cost does not grow with the book.
+    pub fn expired_order_ids(&self, is_bid: bool, now: i64, max: usize) -> Vec<OrderId> {
+        self.expired_entries(is_bid, now).take(max).map(|(_, _, id)| *id).collect()
+    }
+
+    /// Returns the ids of at most `max` orders whose current quantity is at or below `dust`,
+    /// smallest quantity first, then in order id order. Looked up in an index like `expired_order_ids`.
+    pub fn dust_order_ids(&self, dust: u64, max: usize) -> Vec<OrderId> {
+        self.quantity_index.range(..=(dust, OrderId::from(u128::MAX))).take(max).map(|(_, id)| *id).collect()
+    }
+
+    /// Sets the public and current quantities of a resting order, keeping it at its place in its level.
+    pub fn set_quantities(&mut self, id: OrderId, pqty: u64, cqty: u64) -> Result<(), L3Error> {
+        let order = self.orders.get_mut(&id).ok_or(L3Error::OrderDoesNotExist(id))?;
+        self.quantity_index.remove(&(order.cqty, id));
+        self.quantity_index.insert((cqty, id));
+        order.pqty = pqty;
+        order.cqty = cqty;
+        Ok(())
+    }
+
+    /// Returns all resting orders placed by `owner`, ordered by order id.
+    pub fn orders_by_owner(&self, owner: &[u8]) -> Vec<Order> {
+        let mut result: Vec<Order> = self
+            .owner_orders
+            .get(owner)
+            .map(|ids| ids.iter().filter_map(|id| self.orders.get(id).cloned()).collect())
+            .unwrap_or_default();
+        result.sort_by_key(|order| order.id);
+        result
+    }
+
     /// Remove orders that have expired. Returns removed order ids.
+    /// - `now` is compared to `expires_at` as is, so it is in the time unit of the orders, see `OrderBook::is_expired`.
     pub fn remove_dormant_orders(&mut self, now: i64) -> Vec<(OrderId, Order)> {
-        let expired_orders: Vec<(OrderId, Order)> = self
-            .orders
-            .iter()
-            .filter_map(|(id, order)| {
-                if order.expires_at <= now {
-                    Some((*id, order.clone()))
-                } else {
-                    None
-                }
-            })
+        self.remove_dormant_orders_bounded(now, usize::MAX)
+    }
+
+    // at most `max_removals` expired orders, earliest expiry first, with `now` in the time unit of the orders
+    fn remove_dormant_orders_bounded(&mut self, now: i64, max_removals: usize) -> Vec<(OrderId, Order)> {
+        // the earliest expiries of both sides, merged
+        let mut expired: Vec<(i64, OrderId)> = [true, false]
+            .into_iter()
+            .flat_map(|is_bid| self.expired_entries(is_bid, now).take(max_removals))
+            .map(|(_, expires_at, id)| (*expires_at, *id))
+            .collect();
+        expired.sort();
+        expired.truncate(max_removals);
+        let expired_orders: Vec<(OrderId, Order)> = expired
+            .into_iter()
+            .map(|(_, id)| (id, self.orders[&id].clone()))
             .collect();
 
         for (id, _) in &expired_orders {
@@ -1,16 +1,183 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use serde::{Deserialize, Serialize};
 
+use crate::account::AccountBalances;
 use crate::spot::Order;
 
-use super::event::{self, SpotEvent};
-use super::orderbook::{OrderBook, OrderBookError};
+use super::event::{self, SequencedEvent, SpotEvent};
+use super::orderbook::{BookSnapshot, Fill, MatchingPolicy, OrderBook, OrderBookError};
 use super::orders::OrderId;
 use super::time_in_force::TimeInForce;
 
 use super::market::L1;
 
+/// An order of a batch submitted with `Pair::submit_batch`, carrying the arguments of the matching entry method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderRequest {
+    LimitBuy {
+        cid: Vec<u8>,
+        existing_order_id: Option<OrderId>,
+        owner: Vec<u8>,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    },
+    LimitSell {
+        cid: Vec<u8>,
+        existing_order_id: Option<OrderId>,
+        owner: Vec<u8>,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    },
+    MarketBuy {
+        cid: Vec<u8>,
+        existing_order_id: Option<OrderId>,
+        owner: Vec<u8>,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    },
+    MarketSell {
+        cid: Vec<u8>,
+        existing_order_id: Option<OrderId>,
+        owner: Vec<u8>,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    },
+}
+
+impl OrderRequest {
+    /// Gateway client id of the request
+    pub fn cid(&self) -> &[u8] {
+        match self {
+            OrderRequest::LimitBuy { cid, .. }
+            | OrderRequest::LimitSell { cid, .. }
+            | OrderRequest::MarketBuy { cid, .. }
+            | OrderRequest::MarketSell { cid, .. } => cid,
+        }
+    }
+}
+
+/// State of an accepted order once its entry method returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    /// nothing was filled, the whole order rests on the book
+    New,
+    /// part of the order was filled and the remainder rests on the book
+    PartiallyFilled,
+    /// the order was filled completely
+    Filled,
+    /// the unfilled remainder was cancelled, e.g. for a market or IOC order
+    Cancelled,
+}
+
+/// How long the outcome of an order accepted with a client order id is kept once it terminated, unless configured
+pub const DEFAULT_CLIENT_ORDER_ID_TTL_MS: i64 = 300_000;
+
+/// An order accepted with a client order id, kept to answer retries of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientOrder {
+    /// the accepted request, a retry carries the same one
+    pub request: OrderRequest,
+    /// outcome the order was accepted with
+    pub outcome: OrderOutcome,
+    /// when the order was accepted, in milliseconds
+    pub accepted_at: i64,
+}
+
+/// Result of an accepted order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderOutcome {
+    /// id of the order, also the id of its resting remainder
+    pub order_id: OrderId,
+    /// base amount filled in 8 decimals
+    pub filled_base: u64,
+    /// quote amount filled in 8 decimals
+    pub filled_quote: u64,
+    /// quantity-weighted average fill price in the price decimals of the pair, 0 when nothing was filled
+    pub avg_price: u64,
+    /// taker fees charged on the fills, in quote for a buy and in base for a sell
+    pub fees: u64,
+    /// quantity left resting on the book, in quote for a buy and in base for a sell
+    pub resting_qty: u64,
+    pub status: OrderStatus,
+}
+
+impl OrderOutcome {
+    /// `price_scale` is the `OrderBook::price_scale` the average price is expressed in
+    fn new(order: &Order, totals: Fill, resting_qty: u64, price_scale: u64) -> Self {
+        let status = if order.cqty == 0 {
+            OrderStatus::Filled
+        } else if resting_qty == 0 {
+            OrderStatus::Cancelled
+        } else if totals.base_volume == 0 {
+            OrderStatus::New
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        Self {
+            order_id: order.id,
+            filled_base: totals.base_volume,
+            filled_quote: totals.quote_volume,
+            avg_price: if totals.base_volume == 0 {
+                0
+            } else {
+                (totals.quote_volume as u128 * price_scale as u128 / totals.base_volume as u128) as u64
+            },
+            fees: totals.taker_fee,
+            resting_qty,
+            status,
+        }
+    }
+}
+
+// Takers stopped by `max_makers_per_order` across all pairs
+static CAPPED_ORDERS: AtomicU64 = AtomicU64::new(0);
+
+/// Total number of takers whose matching was stopped by `Pair::max_makers_per_order` since startup.
+pub fn capped_orders() -> u64 {
+    CAPPED_ORDERS.load(Ordering::Relaxed)
+}
+
+/// Farthest price a taker may match at, `slippage_limit` basis points away from `head` in its direction
+/// - no limit or no head leaves the price unbounded.
+fn slippage_bound(head: u64, slippage_limit: Option<u64>, is_bid: bool) -> u64 {
+    const DENOM: u128 = 10000;
+    match slippage_limit {
+        Some(limit) if head != 0 => {
+            if is_bid {
+                (head as u128 * (DENOM + limit as u128) / DENOM).min(u64::MAX as u128) as u64
+            } else {
+                (head as u128 * DENOM.saturating_sub(limit as u128) / DENOM) as u64
+            }
+        }
+        _ if is_bid => u64::MAX,
+        _ => 0,
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Pair {
     /// Pair ID
@@ -29,6 +196,18 @@ pub struct Pair {
     pub client_admin_account_ids: HashMap<Vec<u8>, Vec<u8>>,
     /// Hash map of client id -> client fee account id
     pub client_fee_account_ids: HashMap<Vec<u8>, Vec<u8>>,
+    /// tick size of the price grid in 8 decimals, 0 disables the check
+    pub tick_size: u64,
+    /// minimum whole amount of an order in 8 decimals
+    pub min_qty: u64,
+    /// largest price change of an amend in 8 decimals, 0 disables the check
+    pub max_reprice: u64,
+    /// orders accepted with a client order id, keyed by (client id, client order id)
+    pub client_order_ids: HashMap<(Vec<u8>, Vec<u8>), ClientOrder>,
+    /// how long the outcome of a terminated order is kept for retries of it, in milliseconds
+    pub client_order_id_ttl_ms: i64,
+    /// most maker orders a taker is matched against before matching stops, 0 disables the cap
+    pub max_makers_per_order: u32,
 }
 
 impl Pair {
@@ -43,6 +222,155 @@ impl Pair {
             clients: Vec::new(),
             client_admin_account_ids: HashMap::new(),
             client_fee_account_ids: HashMap::new(),
+            tick_size: 1,
+            min_qty: 0,
+            max_reprice: 0,
+            client_order_ids: HashMap::new(),
+            client_order_id_ttl_ms: DEFAULT_CLIENT_ORDER_ID_TTL_MS,
+            max_makers_per_order: 0,
+        }
+    }
+
+    /// Sets the tick size orders must be priced on
+    pub fn set_tick_size(&mut self, tick_size: u64) {
+        self.tick_size = tick_size;
+    }
+
+    /// Sets the dust limit below which a partially filled order is cleared instead of resting
+    pub fn set_dust(&mut self, dust: u64) {
+        self.orderbook.set_dust(dust);
+    }
+
+    /// Sets the decimals prices of the pair are expressed in, 8 unless configured
+    pub fn set_price_decimals(&mut self, price_decimals: u32) -> Result<(), OrderBookError> {
+        self.orderbook.set_price_decimals(price_decimals)
+    }
+
+    /// Sets the minimum whole amount of an order
+    pub fn set_min_qty(&mut self, min_qty: u64) {
+        self.min_qty = min_qty;
+    }
+
+    /// Sets the most price levels each side of the book may hold, 0 disables the cap
+    /// A limit order that would rest at a new price beyond the cap is rejected with `TooManyPriceLevels`.
+    pub fn set_max_price_levels(&mut self, max_price_levels: u32) {
+        self.orderbook.set_max_price_levels(max_price_levels);
+    }
+
+    /// Sets the largest price change an amend may make, 0 disables the check
+    pub fn set_max_reprice(&mut self, max_reprice: u64) {
+        self.max_reprice = max_reprice;
+    }
+
+    /// Sets how long the outcome of a terminated order accepted with a client order id answers retries of it
+    pub fn set_client_order_id_ttl_ms(&mut self, client_order_id_ttl_ms: i64) {
+        self.client_order_id_ttl_ms = client_order_id_ttl_ms;
+    }
+
+    /// Sets the most maker orders a taker is matched against, 0 disables the cap
+    /// A taker stopped by the cap is handled by its time in force, a remainder that still crosses the book is cancelled.
+    pub fn set_max_makers_per_order(&mut self, max_makers_per_order: u32) {
+        self.max_makers_per_order = max_makers_per_order;
+    }
+
+    /// How many more makers a taker with `totals` may be matched against, u32::MAX without a cap
+    fn makers_left(&self, totals: &Fill) -> u32 {
+        if self.max_makers_per_order == 0 {
+            u32::MAX
+        } else {
+            self.max_makers_per_order.saturating_sub(totals.makers)
+        }
+    }
+
+    /// Validates that an amend moves the price of the resting order by at most `max_reprice`
+    fn ensure_reprice(&self, resting_price: u64, price: u64) -> Result<(), OrderBookError> {
+        if self.max_reprice != 0 && resting_price.abs_diff(price) > self.max_reprice {
+            Err(OrderBookError::RepriceTooLarge)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Validates the price against the tick size of the pair
+    fn ensure_tick(&self, price: u64) -> Result<(), OrderBookError> {
+        if self.tick_size != 0 && !price.is_multiple_of(self.tick_size) {
+            Err(OrderBookError::PriceNotOnTick)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Validates that a good till date order expires after it is placed
+    /// - `timestamp` and `expires_at` are both in the `time_unit` of the orderbook, they are compared as is.
+    fn ensure_time_in_force(&self, time_in_force: TimeInForce, timestamp: i64, expires_at: i64) -> Result<(), OrderBookError> {
+        if matches!(time_in_force, TimeInForce::GoodTillDate) && expires_at <= timestamp {
+            Err(OrderBookError::OrderExpired)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Validates that the remainder a limit order would rest with fits in the price level cap of the orderbook
+    /// - only a good till order the book cannot fill completely rests, it is checked before anything is matched.
+    /// - the level of the order it replaces counts as free if that order is the last one of the level.
+    /// - the book is only walked for an order at a new price on a side at its cap.
+    fn ensure_price_level(
+        &self,
+        is_bid: bool,
+        price: u64,
+        amnt: u64,
+        time_in_force: TimeInForce,
+        replaced: Option<OrderId>,
+    ) -> Result<(), OrderBookError> {
+        if !matches!(time_in_force, TimeInForce::GoodTillCanceled | TimeInForce::GoodTillDate)
+            || self.orderbook.ensure_price_level(is_bid, price).is_ok()
+            || replaced.is_some_and(|id| self.frees_price_level(is_bid, id))
+            || self.fillable_qty(price, is_bid, amnt) >= amnt
+        {
+            Ok(())
+        } else {
+            Err(OrderBookError::TooManyPriceLevels)
+        }
+    }
+
+    /// Whether cancelling the resting order `id` removes its price level from side `is_bid`
+    fn frees_price_level(&self, is_bid: bool, id: OrderId) -> bool {
+        let Ok(order) = self.orderbook.l3.get_order(id) else {
+            return false;
+        };
+        let level = if is_bid {
+            self.orderbook.l2.current_bid_level(order.price)
+        } else {
+            self.orderbook.l2.current_ask_level(order.price)
+        };
+        order.is_bid == is_bid && level == Some(order.cqty)
+    }
+
+    /// Validates that the hidden iceberg quantity fits in the whole amount
+    fn ensure_iqty(amnt: u64, iqty: u64) -> Result<(), OrderBookError> {
+        if iqty > amnt {
+            Err(OrderBookError::IcebergQuantityIsBiggerThanWholeAmount)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Validates the amount against the minimum order size of the pair
+    fn ensure_min_qty(&self, amnt: u64) -> Result<(), OrderBookError> {
+        if amnt < self.min_qty {
+            Err(OrderBookError::BelowMinQty)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Validates that the owner holds at least `required` of `asset_id`
+    fn ensure_balance(balances: &dyn AccountBalances, asset_id: &[u8], required: u64) -> Result<(), OrderBookError> {
+        let available = balances.balances().get(asset_id).copied().unwrap_or(0);
+        if required > available {
+            Err(OrderBookError::InsufficientBalance)
+        } else {
+            Ok(())
         }
     }
 
@@ -55,10 +383,7 @@ impl Pair {
         let cid = cid.into();
         let admin_account_id = admin_account_id.into();
         let fee_account_id = fee_account_id.into();
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
+        let timestamp = self.orderbook.clock.now_millis();
 
         // Store client and associated accounts
         self.clients.push(cid.clone());
@@ -95,10 +420,7 @@ impl Pair {
         // Emit an event indicating the client was removed from this pair.
         // We keep `cid` so downstream consumers know which client changed,
         // and set admin/fee accounts to None to indicate removal.
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
+        let timestamp = self.orderbook.clock.now_millis();
 
         event::emit_event(SpotEvent::SpotPairClientAccountChanged {
             pair_id: self.pair_id.clone(),
@@ -113,46 +435,60 @@ impl Pair {
     /// Returns remaining_amount after matching
     /// `is_matching_asks` indicates if we're matching against ask orders (true) or bid orders (false)
     /// Continues matching until remaining amount is 0 or no more orders at the price level
+    /// The makers are allocated following the orderbook's `MatchingPolicy`
     #[cfg_attr(test, allow(dead_code))]
     pub fn _match_at(
         &mut self,
         price: u64,
         is_matching_asks: bool,
         taker_order: &mut Order,
+        totals: &mut Fill,
     ) -> Result<Order, OrderBookError> {
-        let taker_id = taker_order.id;
-        let mut current_remaining = taker_order.cqty;
+        match self.orderbook.matching_policy {
+            MatchingPolicy::PriceTime => self._match_at_price_time(price, is_matching_asks, taker_order, totals),
+            MatchingPolicy::ProRata => self._match_at_pro_rata(price, is_matching_asks, taker_order, totals),
+        }
+    }
+
+    /// Fills the makers at the price level one after another in time priority
+    fn _match_at_price_time(
+        &mut self,
+        price: u64,
+        is_matching_asks: bool,
+        taker_order: &mut Order,
+        totals: &mut Fill,
+    ) -> Result<Order, OrderBookError> {
+        // the taker is tracked here, it may be a transient order that is not in L3
+        let mut taker_current = taker_order.clone();
 
         // Get the first order at this price level
         let mut maker_order_id = match self.orderbook.l3.head(price) {
             Some(id) => id,
-            None => return Ok(taker_order.clone()), // No more orders
+            None => return Ok(taker_current), // No more orders
         };
 
-        // Keep matching until remaining is 0 or price level is empty
-        while current_remaining > 0 {
+        // Keep matching until remaining is 0, price level is empty or the maker cap is reached
+        while taker_current.cqty > 0 && self.makers_left(totals) > 0 {
             // Check if price level is empty
             if self.orderbook.l3.is_empty(price) {
                 // Remove price level: if matching asks, price level is ask (is_bid = false)
                 // if matching bids, price level is bid (is_bid = true)
                 let is_bid = !is_matching_asks;
-                self.orderbook.l2.remove_price(is_bid, price)?;
+                if self.orderbook.l2.remove_price(is_bid, price)? {
+                    event::emit_event(SpotEvent::SpotLevelRemoved {
+                        pair_id: self.pair_id.clone(),
+                        is_bid,
+                        price,
+                        timestamp: self.orderbook.clock.now_millis(),
+                    });
+                }
                 break;
             }
 
-            // Get maker order and refresh taker order before executing
             let maker_order = self.orderbook.l3.get_order(maker_order_id)?.clone();
-            let taker_current = match self.orderbook.l3.get_order(taker_id) {
-                Ok(order) => order.clone(),
-                Err(_) => break,
-            };
+            let now = self.orderbook.clock.now_millis();
 
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as i64;
-
-            self.orderbook.execute(
+            let (updated, fill) = self.orderbook.execute(
                 taker_current,
                 maker_order,
                 self.pair_id.clone(),
@@ -160,13 +496,11 @@ impl Pair {
                 self.quote_asset_id.clone(),
                 now,
             )?;
-
-            match self.orderbook.l3.get_order(taker_id) {
-                Ok(updated) => current_remaining = updated.cqty,
-                Err(_) => {
-                    break;
-                }
-            }
+            taker_current = updated;
+            totals.base_volume += fill.base_volume;
+            totals.quote_volume += fill.quote_volume;
+            totals.taker_fee += fill.taker_fee;
+            totals.makers += fill.makers;
 
             // traverse to the next order at the price level
             maker_order_id = match self.orderbook.l3.next(price, maker_order_id) {
@@ -175,35 +509,144 @@ impl Pair {
             };
         }
 
-        let updated = match self.orderbook.l3.get_order(taker_id) {
-            Ok(order) => order.clone(),
-            Err(_) => {
-                // if the taker order is not found, it means the taker order is fully filled
-                let mut fallback = taker_order.clone();
-                fallback.cqty = 0;
-                fallback.pqty = 0;
-                return Ok(fallback);
-            }
+        Ok(taker_current)
+    }
+
+    /// Fills every maker at the price level with a share of the taker proportional to its current quantity
+    /// - each maker gets `taker * maker_cqty / level_cqty` in the maker's units, rounded down.
+    /// - the units left over by the rounding go one each to the makers in time priority.
+    /// - a share is converted back into the taker's units rounding down, so a maker is never over-allocated,
+    ///   and what the conversion leaves of the taker is matched in time priority.
+    /// - a taker covering the whole level fills every maker, the same as price-time.
+    /// - a level with more makers than the maker cap leaves is matched in price-time.
+    fn _match_at_pro_rata(
+        &mut self,
+        price: u64,
+        is_matching_asks: bool,
+        taker_order: &mut Order,
+        totals: &mut Fill,
+    ) -> Result<Order, OrderBookError> {
+        // collect the makers of the level in time priority
+        let mut makers = Vec::new();
+        let mut next = self.orderbook.l3.head(price);
+        while let Some(id) = next {
+            makers.push(self.orderbook.l3.get_order(id)?.clone());
+            next = self.orderbook.l3.next(price, id);
+        }
+        let level_cqty: u64 = makers.iter().map(|maker| maker.cqty).sum();
+
+        // the taker in the makers' units, base against asks and quote against bids, at the match price
+        let match_price = self.orderbook.match_price(taker_order.price, price);
+        let taker_cqty = if taker_order.is_bid {
+            self.orderbook.quote_to_base(taker_order.cqty, match_price)
+        } else {
+            self.orderbook.base_to_quote(taker_order.cqty, match_price)
         };
-        Ok(updated)
+        if makers.is_empty()
+            || level_cqty == 0
+            || taker_cqty >= level_cqty
+            || makers.len() as u64 > self.makers_left(totals) as u64
+        {
+            return self._match_at_price_time(price, is_matching_asks, taker_order, totals);
+        }
+
+        let mut shares: Vec<u64> = makers
+            .iter()
+            .map(|maker| (taker_cqty as u128 * maker.cqty as u128 / level_cqty as u128) as u64)
+            .collect();
+        let mut leftover = taker_cqty - shares.iter().sum::<u64>();
+        for (share, maker) in shares.iter_mut().zip(&makers) {
+            if leftover == 0 {
+                break;
+            }
+            if *share < maker.cqty {
+                *share += 1;
+                leftover -= 1;
+            }
+        }
+
+        let mut taker_current = taker_order.clone();
+        for (maker_order, share) in makers.into_iter().zip(shares) {
+            // the share in the taker's units, quote for a bid taker and base for an ask taker
+            let max_amount = if taker_current.is_bid {
+                self.orderbook.base_to_quote(share, match_price)
+            } else {
+                self.orderbook.quote_to_base(share, match_price)
+            };
+            if max_amount == 0 || taker_current.cqty == 0 {
+                continue;
+            }
+            let now = self.orderbook.clock.now_millis();
+            let (updated, fill) = self.orderbook.execute_up_to(
+                taker_current,
+                maker_order,
+                max_amount,
+                self.pair_id.clone(),
+                self.base_asset_id.clone(),
+                self.quote_asset_id.clone(),
+                now,
+            )?;
+            taker_current = updated;
+            totals.base_volume += fill.base_volume;
+            totals.quote_volume += fill.quote_volume;
+            totals.taker_fee += fill.taker_fee;
+            totals.makers += fill.makers;
+        }
+
+        if taker_current.cqty > 0 {
+            return self._match_at_price_time(price, is_matching_asks, &mut taker_current, totals);
+        }
+        Ok(taker_current)
     }
 
     /// Place a limit order (internal helper)
     /// Returns (remaining_amount, bid_head, ask_head)
     /// Continues matching until remaining amount is 0 or no more matching orders available
+    /// The matched volumes and taker fees are added to `totals`
+    /// `slippage_limit` in basis points bounds how far matching may move from the best opposite price
+    /// at entry, a taker stopped by it is left crossing the book with its remainder
+    /// Matching also stops once the taker matched `max_makers_per_order` makers, counted in `capped_orders`
+    /// Every taker leaving matching is reported in a `SpotTakerMatched` event, one that filled nothing included
     #[cfg_attr(test, allow(dead_code))]
     pub fn _limit_order(
         &mut self,
         limit_price: u64,
         taker_order: &mut Order,
+        totals: &mut Fill,
+        slippage_limit: Option<u64>,
+    ) -> Result<(Order, u64, u64), OrderBookError> {
+        let matched = self._match_taker(limit_price, taker_order, totals, slippage_limit)?;
+
+        // report how much of the taker was filled once it stops matching
+        event::emit_event(SpotEvent::SpotTakerMatched {
+            pair_id: self.pair_id.clone(),
+            order_id: taker_order.id.to_bytes().to_vec(),
+            is_bid: taker_order.is_bid,
+            amnt: taker_order.amnt,
+            cqty: taker_order.cqty,
+            timestamp: self.orderbook.clock.now_millis(),
+        });
+
+        Ok(matched)
+    }
+
+    // matching of `_limit_order`
+    fn _match_taker(
+        &mut self,
+        limit_price: u64,
+        taker_order: &mut Order,
+        totals: &mut Fill,
+        slippage_limit: Option<u64>,
     ) -> Result<(Order, u64, u64), OrderBookError> {
 
         // Get last matched price
         let mut lmp = self.l1.lmp().unwrap_or(0);
+        // whether the maker cap stopped matching with the book still crossing
+        let mut capped = false;
 
         // Clear empty heads
-        let mut bid_head = self.orderbook.clear_empty_head_or_zero(true);
-        let mut ask_head = self.orderbook.clear_empty_head_or_zero(false);
+        let mut bid_head = self.orderbook.clear_empty_head_or_zero(&self.pair_id, true);
+        let mut ask_head = self.orderbook.clear_empty_head_or_zero(&self.pair_id, false);
 
         if taker_order.is_bid {
             // Limit Buy: match against ask orders
@@ -215,9 +658,14 @@ impl Pair {
                 }
             }
 
-            // Match against ask orders while ask_head <= limit_price
+            // Match against ask orders while ask_head <= limit_price and within the slippage bound
+            let max_price = slippage_bound(ask_head, slippage_limit, true);
             let mut current_remaining = taker_order.cqty;
-            while current_remaining > 0 && ask_head != 0 && ask_head <= limit_price {
+            while current_remaining > 0 && ask_head != 0 && ask_head <= limit_price && ask_head <= max_price {
+                if self.makers_left(totals) == 0 {
+                    capped = true;
+                    break;
+                }
                 lmp = ask_head; // Update lmp to current match price
                 let match_price = ask_head;
 
@@ -226,17 +674,18 @@ impl Pair {
                     match_price,
                     true, // matching against asks
                     taker_order,
+                    totals,
                 )?;
                 *taker_order = updated;
                 current_remaining = taker_order.cqty;
 
                 // Update ask_head after matching (price level might be empty now)
-                ask_head = self.orderbook.clear_empty_head_or_zero(false);
+                ask_head = self.orderbook.clear_empty_head_or_zero(&self.pair_id, false);
             }
 
 
             // Update bid_head
-            bid_head = self.orderbook.clear_empty_head_or_zero(true);
+            bid_head = self.orderbook.clear_empty_head_or_zero(&self.pair_id, true);
         } else {
             // Limit Sell: match against bid orders
             if lmp != 0 {
@@ -247,9 +696,14 @@ impl Pair {
                 }
             }
 
-            // Match against bid orders while bid_head >= limit_price
+            // Match against bid orders while bid_head >= limit_price and within the slippage bound
+            let min_price = slippage_bound(bid_head, slippage_limit, false);
             let mut current_remaining = taker_order.cqty;
-            while current_remaining > 0 && bid_head != 0 && bid_head >= limit_price {
+            while current_remaining > 0 && bid_head != 0 && bid_head >= limit_price && bid_head >= min_price {
+                if self.makers_left(totals) == 0 {
+                    capped = true;
+                    break;
+                }
                 lmp = bid_head; // Update lmp to current match price
                 let match_price = bid_head;
 
@@ -258,16 +712,21 @@ impl Pair {
                     match_price,
                     false, // matching against bids
                     taker_order,
+                    totals,
                 )?;
                 *taker_order = updated;
                 current_remaining = taker_order.cqty;
 
                 // Update bid_head after matching (price level might be empty now)
-                bid_head = self.orderbook.clear_empty_head_or_zero(true);
+                bid_head = self.orderbook.clear_empty_head_or_zero(&self.pair_id, true);
             }
 
             // Update ask_head
-            ask_head = self.orderbook.clear_empty_head_or_zero(false);
+            ask_head = self.orderbook.clear_empty_head_or_zero(&self.pair_id, false);
+        }
+
+        if capped {
+            CAPPED_ORDERS.fetch_add(1, Ordering::Relaxed);
         }
 
         // Set new market price if matches occurred
@@ -286,60 +745,141 @@ impl Pair {
     /// - `remaining`: The remaining amount after matching
     /// - `is_bid`: Whether this is a bid order (true) or ask order (false)
     /// - `time_in_force`: The time in force policy
-    /// Returns an error if FOK order is not fully filled
+    /// Returns the quantity left resting on the book, or an error if FOK order is not fully filled
     fn _handle_time_in_force_post_matching(
         &mut self,
         time_in_force: TimeInForce,
         maker_order: &mut Order,
-        is_limit_order: bool,
-        maker_fee_bps: u16,
-    ) -> Result<(), OrderBookError> {
+        maker_fee_bps: i32,
+    ) -> Result<u64, OrderBookError> {
         match time_in_force {
-            TimeInForce::ImmediateOrCancel => {
-                // IOC: Fill what can be filled immediately, cancel the rest
-                if maker_order.cqty > 0 {
-                    self.orderbook.cancel_order(maker_order.cid.clone(), self.pair_id.clone(), maker_order.is_bid, maker_order.id, maker_order.owner.clone())?;
-                }
-                Ok(())
+            // FOK: fillability is checked before matching, only the maker cap can leave a remainder to cancel
+            // IOC: Fill what can be filled immediately, cancel the rest
+            TimeInForce::FillOrKill | TimeInForce::ImmediateOrCancel => {
+                self.orderbook.cancel_taker(
+                    self.pair_id.clone(),
+                    self.base_asset_id.clone(),
+                    self.quote_asset_id.clone(),
+                    maker_order,
+                );
+                Ok(0)
             }
-            TimeInForce::GoodTillCanceled => {
-                // GTC: Place remaining in orderbook
+            TimeInForce::GoodTillCanceled | TimeInForce::GoodTillDate => {
+                // GTC/GTD: Place remaining in orderbook, GTD is swept as expired at `expires_at`
                 if maker_order.cqty > 0 {
-                    // set the order's fee as maker fee basis points
-                    maker_order.fee_bps = maker_fee_bps;
-
-                    // determine the make price 
-                    let make_price = if is_limit_order {
-                        if maker_order.is_bid {
-                            self.l1.det_limit_buy_make_price(maker_order.price, self.orderbook.l2.bid_head().unwrap_or(0), self.orderbook.l2.ask_head().unwrap_or(0), maker_fee_bps as u32)
-                        } else {
-                            self.l1.det_limit_sell_make_price(maker_order.price, self.orderbook.l2.bid_head().unwrap_or(0), self.orderbook.l2.ask_head().unwrap_or(0), maker_fee_bps as u32)
-                        }
-                    } else {
-                        if maker_order.is_bid {
-                            self.l1.det_market_buy_make_price(self.orderbook.l2.bid_head().unwrap_or(0), self.orderbook.l2.ask_head().unwrap_or(0), maker_fee_bps as u32)
-                        } else {
-                            self.l1.det_market_sell_make_price(self.orderbook.l2.bid_head().unwrap_or(0), self.orderbook.l2.ask_head().unwrap_or(0), maker_fee_bps as u32)
+                    // the order rests with the maker fee basis points
+                    match self.orderbook.rest_taker(
+                        self.pair_id.clone(),
+                        self.base_asset_id.clone(),
+                        self.quote_asset_id.clone(),
+                        maker_order,
+                        maker_fee_bps,
+                    ) {
+                        Ok(resting) => *maker_order = resting,
+                        // a remainder the price level check did not foresee is cancelled rather than rejected
+                        // once it matched
+                        Err(OrderBookError::TooManyPriceLevels) => {
+                            self.orderbook.cancel_taker(
+                                self.pair_id.clone(),
+                                self.base_asset_id.clone(),
+                                self.quote_asset_id.clone(),
+                                maker_order,
+                            );
+                            return Ok(0);
                         }
-                    };
-                } 
-                Ok(())
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(maker_order.cqty)
             }
-            _ => Err(OrderBookError::UnsupportedTimeInForce),
         }
     }
 
-    fn can_fill_fok(&self, limit_price: u64, taker_order: &Order) -> Result<bool, OrderBookError> {
-        let mut remaining = taker_order.cqty;
+    /// Whether what is left of `taker_order` still crosses the best opposite price
+    fn crosses_book(&self, taker_order: &Order) -> bool {
+        if taker_order.cqty == 0 {
+            return false;
+        }
+        if taker_order.is_bid {
+            self.orderbook.l2.ask_head().is_some_and(|ask_head| ask_head <= taker_order.price)
+        } else {
+            self.orderbook.l2.bid_head().is_some_and(|bid_head| bid_head >= taker_order.price)
+        }
+    }
 
-        let prices = if taker_order.is_bid {
+    /// Cancels the resting order a limit order replaces, emitting `SpotOrderCancelled`
+    /// The replacement is checked for anything that rejects it before the order is cancelled, so a rejected
+    /// replacement leaves the order resting. The order must be on the side of its replacement: the fill-or-kill
+    /// check then sees the same opposite side as the replacement will match against.
+    /// - `is_bid`, `price`, `amnt` and `time_in_force` are those of the replacement.
+    #[allow(clippy::too_many_arguments)]
+    fn cancel_replaced(
+        &mut self,
+        cid: Vec<u8>,
+        existing_order_id: OrderId,
+        owner: Vec<u8>,
+        is_bid: bool,
+        price: u64,
+        amnt: u64,
+        time_in_force: TimeInForce,
+    ) -> Result<(), OrderBookError> {
+        if self.orderbook.l3.get_order(existing_order_id)?.is_bid != is_bid {
+            return Err(OrderBookError::ReplacedOrderOnOtherSide);
+        }
+        let slippage_limit = if is_bid { self.l1.limit_buy_slippage_limit } else { self.l1.limit_sell_slippage_limit };
+        if matches!(time_in_force, TimeInForce::FillOrKill)
+            && self.fillable_qty(self.bounded_limit_price(is_bid, price, slippage_limit), is_bid, amnt) < amnt
+        {
+            return Err(OrderBookError::OrderNotFullyFilled);
+        }
+        self.orderbook
+            .cancel_order(
+                cid,
+                self.pair_id.clone(),
+                self.base_asset_id.clone(),
+                self.quote_asset_id.clone(),
+                is_bid,
+                existing_order_id,
+                owner,
+            )
+    }
+
+    /// Whether a fill-or-kill taker fills completely up to `limit_price` and within its slippage bound
+    fn can_fill_fok(
+        &self,
+        limit_price: u64,
+        slippage_limit: Option<u64>,
+        taker_order: &Order,
+    ) -> Result<bool, OrderBookError> {
+        let limit_price = self.bounded_limit_price(taker_order.is_bid, limit_price, slippage_limit);
+        Ok(self.fillable_qty(limit_price, taker_order.is_bid, taker_order.cqty) >= taker_order.cqty)
+    }
+
+    /// Tightens `limit_price` to the slippage bound `_limit_order` stops matching at, the bound is taken from
+    /// the current best opposite price
+    fn bounded_limit_price(&self, is_bid: bool, limit_price: u64, slippage_limit: Option<u64>) -> u64 {
+        let head = if is_bid { self.orderbook.l2.ask_head() } else { self.orderbook.l2.bid_head() };
+        let bound = slippage_bound(head.unwrap_or(0), slippage_limit, is_bid);
+        if is_bid { limit_price.min(bound) } else { limit_price.max(bound) }
+    }
+
+    /// How much of `qty` a taker could fill against the book up to `limit_price`
+    /// - `qty` is in the taker's units, quote for a bid and base for an ask.
+    /// - with a maker cap only the first `max_makers_per_order` makers in price-time priority count.
+    fn fillable_qty(&self, limit_price: u64, is_bid: bool, qty: u64) -> u64 {
+        let prices = if is_bid {
             self.orderbook.l2.collect_ask_prices()
         } else {
             self.orderbook.l2.collect_bid_prices()
         };
 
+        let mut fillable = 0u64;
+        let mut makers = 0u32;
         for price in prices {
-            if taker_order.is_bid {
+            if self.max_makers_per_order != 0 && makers >= self.max_makers_per_order {
+                break;
+            }
+            if is_bid {
                 if price > limit_price {
                     break;
                 }
@@ -347,53 +887,87 @@ impl Pair {
                 break;
             }
 
-            let level_cqty = if taker_order.is_bid {
-                match self.orderbook.l2.current_ask_level(price) {
-                    Some(level) => level,
-                    None => continue,
-                }
+            let level_cqty = if self.max_makers_per_order != 0 {
+                Some(self.capped_level_cqty(price, &mut makers))
+            } else if is_bid {
+                self.orderbook.l2.current_ask_level(price)
             } else {
-                match self.orderbook.l2.current_bid_level(price) {
-                    Some(level) => level,
-                    None => continue,
-                }
+                self.orderbook.l2.current_bid_level(price)
+            };
+            let Some(level_cqty) = level_cqty else {
+                continue;
             };
 
-            let required = self
-                .orderbook
-                .get_required(taker_order.clone(), price, level_cqty)?;
-
-            if remaining <= required {
-                return Ok(true);
+            // the level in the taker's units
+            let required = if is_bid {
+                self.orderbook.base_to_quote(level_cqty, price)
+            } else {
+                self.orderbook.quote_to_base(level_cqty, price)
+            };
+            fillable = fillable.saturating_add(required);
+            if fillable >= qty {
+                return qty;
             }
+        }
 
-            remaining = remaining.saturating_sub(required);
-            if remaining == 0 {
-                return Ok(true);
+        fillable
+    }
+
+    /// Current quantity of the makers at `price` the maker cap still leaves, counted into `makers`
+    fn capped_level_cqty(&self, price: u64, makers: &mut u32) -> u64 {
+        let mut level_cqty = 0u64;
+        let mut next = self.orderbook.l3.head(price);
+        while let Some(id) = next {
+            if *makers >= self.max_makers_per_order {
+                break;
+            }
+            if let Ok(maker) = self.orderbook.l3.get_order(id) {
+                level_cqty = level_cqty.saturating_add(maker.cqty);
             }
+            *makers += 1;
+            next = self.orderbook.l3.next(price, id);
         }
+        level_cqty
+    }
 
-        Ok(false)
+    /// Rejects a taker that cannot fill `min_fill` of `amnt` up to `limit_price`, 0 accepts any fill
+    /// - limit orders also stop at their slippage bound, so the bound caps `limit_price`.
+    fn ensure_min_fill(
+        &self,
+        is_bid: bool,
+        limit_price: u64,
+        slippage_limit: Option<u64>,
+        amnt: u64,
+        min_fill: u64,
+    ) -> Result<(), OrderBookError> {
+        if min_fill == 0 {
+            return Ok(());
+        }
+        let limit_price = self.bounded_limit_price(is_bid, limit_price, slippage_limit);
+        if min_fill > amnt || self.fillable_qty(limit_price, is_bid, amnt) < min_fill {
+            return Err(OrderBookError::MinFillNotReachable);
+        }
+        Ok(())
     }
 
     /// Matches against existing orders first, then places remaining in orderbook based on time_in_force
-    /// - returns the order id and if a dormant order was found.
+    /// - returns the outcome of the order with its fills and the quantity left resting.
     /// - `cid` is the gateway client id.
-    /// - `existing_order_id` is the order id to update with the transaction if it exists.
+    /// - `existing_order_id` is the resting order cancelled and replaced by this one, it keeps resting if this one is rejected.
     /// - `owner` is the owner of the order.
     /// - `price` is the price of the order.
     /// - `amount` is the total amount of the order.
     /// - `public_amount` is the public amount of the order in case of iceberg order.
     /// - `timestamp` is the timestamp of the order.
     /// - `expires_at` is the expiring timestamp of the order.
-    /// - `maker_fee_bps` is the maker fee basis points of the order.
+    /// - `maker_fee_bps` is the maker fee basis points of the order, negative for a maker rebate.
     /// - `taker_fee_bps` is the taker fee basis points of the order.
     /// - `time_in_force` is the time in force of the order.
     pub fn limit_sell(
         &mut self,
         // gateway client id
         cid: impl Into<Vec<u8>>,
-        // order id to update with the transaction if it exists
+        // resting order id to cancel and replace with this order
         existing_order_id: Option<OrderId>, // None if new order
         // owner of the order
         owner: impl Into<Vec<u8>>,
@@ -407,82 +981,144 @@ impl Pair {
         timestamp: i64,
         // expiring timestamp of the order
         expires_at: i64,
-        // maker fee basis points of the order
-        maker_fee_bps: u16,
+        // maker fee basis points of the order, negative for a maker rebate
+        maker_fee_bps: i32,
         // taker fee basis points of the order
         taker_fee_bps: u16,
         // time in force of the order
         time_in_force: TimeInForce,
-    ) -> Result<OrderId, OrderBookError> {
+    ) -> Result<OrderOutcome, OrderBookError> {
+        // Validate the order against the pair's trading rules before anything is created
+        self.ensure_tick(price)?;
+        self.ensure_min_qty(amnt)?;
+        Self::ensure_iqty(amnt, iqty)?;
+        OrderBook::ensure_maker_fee(maker_fee_bps)?;
+        self.ensure_time_in_force(time_in_force, timestamp, expires_at)?;
+        self.ensure_price_level(false, price, amnt, time_in_force, existing_order_id)?;
+        self.orderbook.ensure_taker(price, amnt, iqty)?;
+
         // If existing order id is provided, update the order
         let cid_vec: Vec<u8> = cid.into();
         let owner_vec: Vec<u8> = owner.into();
         if let Some(existing_order_id) = existing_order_id {
             let order = self.orderbook.l3.get_order(existing_order_id)?;
             if order.cid != cid_vec {
+                return Err(OrderBookError::OrderNotSupportedByClientId);
+            }
+            if order.owner != owner_vec {
                 return Err(OrderBookError::OrderNotOwnedBySender);
             }
+            self.ensure_reprice(order.price, price)?;
+            self.cancel_replaced(cid_vec.clone(), existing_order_id, owner_vec.clone(), false, price, amnt, time_in_force)?;
         }
 
-        // place taker order to feed into _limit_order function
-        let taker_order = self.orderbook.place_ask(
+        // the taker is matched before it rests, only the remainder is placed on the book
+        let taker_order = self.orderbook.place_taker(
             cid_vec.clone(),
             self.pair_id.clone(),
             self.base_asset_id.clone(),
             self.quote_asset_id.clone(),
             owner_vec.clone(),
+            false,
             price,
             amnt,
             iqty,
             timestamp,
             expires_at,
-            taker_fee_bps,
+            i32::from(taker_fee_bps),
         )?;
 
         if matches!(time_in_force, TimeInForce::FillOrKill)
-            && !self.can_fill_fok(price, &taker_order)?
+            && !self.can_fill_fok(price, self.l1.limit_sell_slippage_limit, &taker_order)?
         {
-            self.orderbook.cancel_order(
-                cid_vec.clone(),
+            self.orderbook.cancel_taker(
                 self.pair_id.clone(),
-                false,
-                taker_order.id,
-                owner_vec.clone(),
-            )?;
+                self.base_asset_id.clone(),
+                self.quote_asset_id.clone(),
+                &taker_order,
+            );
             return Err(OrderBookError::OrderNotFullyFilled);
         }
        
-        // Match against existing orders FIRST (before placing in orderbook)
+        let mut totals = Fill::default();
         let (taker_order, _bid_head, _ask_head) = self._limit_order(
             price,
             &mut taker_order.clone(),
+            &mut totals,
+            self.l1.limit_sell_slippage_limit,
         )?;
 
+        // a remainder stopped by the slippage bound would cross the book, it is cancelled instead of resting,
+        // a fill-or-kill keeps its time in force, its remainder is cancelled anyway
+        let time_in_force = if !matches!(time_in_force, TimeInForce::FillOrKill) && self.crosses_book(&taker_order) {
+            TimeInForce::ImmediateOrCancel
+        } else {
+            time_in_force
+        };
+
         // Handle time_in_force logic as maker order
-        self._handle_time_in_force_post_matching(time_in_force, &mut taker_order.clone(), true, maker_fee_bps)?;
+        let resting_qty = self._handle_time_in_force_post_matching(time_in_force, &mut taker_order.clone(), maker_fee_bps)?;
 
-        Ok(taker_order.id)
+        Ok(OrderOutcome::new(&taker_order, totals, resting_qty, self.orderbook.price_scale()))
+    }
+
+    /// Place a limit sell order after checking the owner's balances
+    /// A sell locks its whole amount in base and the fee reserved for it at the larger of the maker and taker fee,
+    /// so both must not exceed the owner's base balance.
+    /// - `balances` is the balances of the owner of the order.
+    /// - the rest of the arguments are the same as `limit_sell`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn limit_sell_checked(
+        &mut self,
+        balances: &dyn AccountBalances,
+        cid: impl Into<Vec<u8>>,
+        existing_order_id: Option<OrderId>,
+        owner: impl Into<Vec<u8>>,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        let fee_reserve = Order::fee_reserve(amnt, maker_fee_bps.max(taker_fee_bps as i32));
+        Self::ensure_balance(balances, &self.base_asset_id, amnt + fee_reserve)?;
+        self.limit_sell(
+            cid,
+            existing_order_id,
+            owner,
+            price,
+            amnt,
+            iqty,
+            timestamp,
+            expires_at,
+            maker_fee_bps,
+            taker_fee_bps,
+            time_in_force,
+        )
     }
 
     /// Place a limit buy order (bid order)
     /// Matches against existing orders first, then places remaining in orderbook based on time_in_force
-    /// - returns the order id and if a dormant order was found.
+    /// - returns the outcome of the order with its fills and the quantity left resting.
     /// - `cid` is the gateway client id.
-    /// - `existing_order_id` is the order id to update with the transaction if it exists.
+    /// - `existing_order_id` is the resting order cancelled and replaced by this one, it keeps resting if this one is rejected.
     /// - `owner` is the owner of the order.
     /// - `price` is the price of the order.
     /// - `amount` is the total amount of the order.
     /// - `public_amount` is the public amount of the order in case of iceberg order.
     /// - `timestamp` is the timestamp of the order.
     /// - `expires_at` is the expiring timestamp of the order.
-    /// - `maker_fee_bps` is the maker fee basis points of the order.
+    /// - `maker_fee_bps` is the maker fee basis points of the order, negative for a maker rebate.
     /// - `taker_fee_bps` is the taker fee basis points of the order.
     /// - `time_in_force` is the time in force of the order.
     pub fn limit_buy(
         &mut self,
         // gateway client id
         cid: impl Into<Vec<u8>>,
-        // order id to update with the transaction if it exists
+        // resting order id to cancel and replace with this order
         existing_order_id: Option<OrderId>, // None if new order
         // owner of the order
         owner: impl Into<Vec<u8>>,
@@ -496,13 +1132,21 @@ impl Pair {
         timestamp: i64,
         // expiring timestamp of the order
         expires_at: i64,
-        // maker fee basis points of the order
-        maker_fee_bps: u16,
+        // maker fee basis points of the order, negative for a maker rebate
+        maker_fee_bps: i32,
         // taker fee basis points of the order
         taker_fee_bps: u16,
         // time in force of the order
         time_in_force: TimeInForce,
-    ) -> Result<(), OrderBookError> {
+    ) -> Result<OrderOutcome, OrderBookError> {
+        // Validate the order against the pair's trading rules before anything is created
+        self.ensure_tick(price)?;
+        self.ensure_min_qty(amnt)?;
+        Self::ensure_iqty(amnt, iqty)?;
+        OrderBook::ensure_maker_fee(maker_fee_bps)?;
+        self.ensure_time_in_force(time_in_force, timestamp, expires_at)?;
+        self.ensure_price_level(true, price, amnt, time_in_force, existing_order_id)?;
+        self.orderbook.ensure_taker(price, amnt, iqty)?;
 
         let cid_vec: Vec<u8> = cid.into();
         let owner_vec: Vec<u8> = owner.into();
@@ -514,48 +1158,100 @@ impl Pair {
             if order.owner != owner_vec {
                 return Err(OrderBookError::OrderNotOwnedBySender);
             }
+            self.ensure_reprice(order.price, price)?;
+            self.cancel_replaced(cid_vec.clone(), existing_order_id, owner_vec.clone(), true, price, amnt, time_in_force)?;
         }
 
-        let taker_order = self.orderbook.place_bid(
+        // the taker is matched before it rests, only the remainder is placed on the book
+        let taker_order = self.orderbook.place_taker(
             cid_vec.clone(),
             self.pair_id.clone(),
             self.base_asset_id.clone(),
             self.quote_asset_id.clone(),
             owner_vec.clone(),
+            true,
             price,
             amnt,
             iqty,
             timestamp,
             expires_at,
-            taker_fee_bps,
+            i32::from(taker_fee_bps),
         )?;
 
         if matches!(time_in_force, TimeInForce::FillOrKill)
-            && !self.can_fill_fok(price, &taker_order)?
+            && !self.can_fill_fok(price, self.l1.limit_buy_slippage_limit, &taker_order)?
         {
-            self.orderbook.cancel_order(
-                cid_vec.clone(),
+            self.orderbook.cancel_taker(
                 self.pair_id.clone(),
-                true,
-                taker_order.id,
-                owner_vec.clone(),
-            )?;
+                self.base_asset_id.clone(),
+                self.quote_asset_id.clone(),
+                &taker_order,
+            );
             return Err(OrderBookError::OrderNotFullyFilled);
         }
 
+        let mut totals = Fill::default();
         let (taker_order, _bid_head, _ask_head) = self._limit_order(
             price,
             &mut taker_order.clone(),
+            &mut totals,
+            self.l1.limit_buy_slippage_limit,
         )?;
 
-        self._handle_time_in_force_post_matching(time_in_force, &mut taker_order.clone(), true, maker_fee_bps)?;
+        // a remainder stopped by the slippage bound would cross the book, it is cancelled instead of resting,
+        // a fill-or-kill keeps its time in force, its remainder is cancelled anyway
+        let time_in_force = if !matches!(time_in_force, TimeInForce::FillOrKill) && self.crosses_book(&taker_order) {
+            TimeInForce::ImmediateOrCancel
+        } else {
+            time_in_force
+        };
 
-        Ok(())
+        let resting_qty = self._handle_time_in_force_post_matching(time_in_force, &mut taker_order.clone(), maker_fee_bps)?;
+
+        Ok(OrderOutcome::new(&taker_order, totals, resting_qty, self.orderbook.price_scale()))
+    }
+
+    /// Place a limit buy order after checking the owner's balances
+    /// A buy locks its whole amount in quote and the fee reserved for it at the larger of the maker and taker fee,
+    /// so both must not exceed the owner's quote balance.
+    /// - `balances` is the balances of the owner of the order.
+    /// - the rest of the arguments are the same as `limit_buy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn limit_buy_checked(
+        &mut self,
+        balances: &dyn AccountBalances,
+        cid: impl Into<Vec<u8>>,
+        existing_order_id: Option<OrderId>,
+        owner: impl Into<Vec<u8>>,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        let fee_reserve = Order::fee_reserve(amnt, maker_fee_bps.max(taker_fee_bps as i32));
+        Self::ensure_balance(balances, &self.quote_asset_id, amnt + fee_reserve)?;
+        self.limit_buy(
+            cid,
+            existing_order_id,
+            owner,
+            price,
+            amnt,
+            iqty,
+            timestamp,
+            expires_at,
+            maker_fee_bps,
+            taker_fee_bps,
+            time_in_force,
+        )
     }
 
     /// Execute a market sell order
     /// Matches against existing orders first (market orders match at any price)
-    /// - returns the match result.
+    /// - returns the outcome of the order, the unfilled remainder is cancelled.
     /// - `cid` is the gateway client id.
     /// - `existing_order_id` is the order id to update with the transaction if it exists.
     /// - `owner` is the owner of the order.
@@ -579,13 +1275,17 @@ impl Pair {
         timestamp: i64,
         // expiring timestamp of the order
         expires_at: i64,
-        // maker fee basis points of the order
-        maker_fee_bps: u16,
+        // maker fee basis points of the order, unused as a market order never rests as a maker
+        _maker_fee_bps: i32,
         // taker fee basis points of the order
         taker_fee_bps: u16,
         // time in force of the order
         time_in_force: TimeInForce,
-    ) -> Result<(), OrderBookError> {
+    ) -> Result<OrderOutcome, OrderBookError> {
+        // Validate the order against the pair's minimum order size before anything is created
+        self.ensure_min_qty(amnt)?;
+        Self::ensure_iqty(amnt, iqty)?;
+        self.ensure_time_in_force(time_in_force, timestamp, expires_at)?;
 
         let cid_vec: Vec<u8> = cid.into();
         let owner_vec: Vec<u8> = owner.into();
@@ -607,46 +1307,57 @@ impl Pair {
         }
         let price = best_bid_price.unwrap();
 
-        let taker_order = self.orderbook.place_ask(
+        // the taker is matched without resting, so a market order never becomes a maker
+        let taker_order = self.orderbook.place_taker(
             cid_vec.clone(),
             self.pair_id.clone(),
             self.base_asset_id.clone(),
             self.quote_asset_id.clone(),
             owner_vec.clone(),
+            false,
             price,
             amnt,
             iqty,
             timestamp,
             expires_at,
-            taker_fee_bps,
+            i32::from(taker_fee_bps),
         )?;
 
+        // a market sell takes any bid price, so every bid level counts towards the fill
         if matches!(time_in_force, TimeInForce::FillOrKill)
-            && !self.can_fill_fok(price, &taker_order)?
+            && !self.can_fill_fok(0, None, &taker_order)?
         {
-            self.orderbook.cancel_order(
-                cid_vec.clone(),
+            self.orderbook.cancel_taker(
                 self.pair_id.clone(),
-                false,
-                taker_order.id,
-                owner_vec.clone(),
-            )?;
+                self.base_asset_id.clone(),
+                self.quote_asset_id.clone(),
+                &taker_order,
+            );
             return Err(OrderBookError::OrderNotFullyFilled);
         }
         
+        let mut totals = Fill::default();
         let (taker_order, _bid_head, _ask_head) = self._limit_order(
             0,
             &mut taker_order.clone(),
+            &mut totals,
+            None,
         )?;
 
-        self._handle_time_in_force_post_matching(time_in_force, &mut taker_order.clone(), false, maker_fee_bps)?;
+        // whatever is left after matching is cancelled, whatever the time in force
+        self.orderbook.cancel_taker(
+            self.pair_id.clone(),
+            self.base_asset_id.clone(),
+            self.quote_asset_id.clone(),
+            &taker_order,
+        );
 
-        Ok(())
+        Ok(OrderOutcome::new(&taker_order, totals, 0, self.orderbook.price_scale()))
     }
 
     /// Execute a market buy order
     /// Matches against existing orders first (market orders match at any price)
-    /// - returns the match result.
+    /// - returns the outcome of the order, the unfilled remainder is cancelled.
     /// - `cid` is the gateway client id.
     /// - `existing_order_id` is the order id to update with the transaction if it exists.
     /// - `owner` is the owner of the order.
@@ -669,13 +1380,17 @@ impl Pair {
         timestamp: i64,
         // expiring timestamp of the order
         expires_at: i64,
-        // maker fee basis points of the order
-        maker_fee_bps: u16,
+        // maker fee basis points of the order, unused as a market order never rests as a maker
+        _maker_fee_bps: i32,
         // taker fee basis points of the order
         taker_fee_bps: u16,
         // time in force of the order
         time_in_force: TimeInForce,
-    ) -> Result<(), OrderBookError> {
+    ) -> Result<OrderOutcome, OrderBookError> {
+        // Validate the order against the pair's minimum order size before anything is created
+        self.ensure_min_qty(amnt)?;
+        Self::ensure_iqty(amnt, iqty)?;
+        self.ensure_time_in_force(time_in_force, timestamp, expires_at)?;
 
         let cid_vec: Vec<u8> = cid.into();
         let owner_vec: Vec<u8> = owner.into();
@@ -697,43 +1412,383 @@ impl Pair {
         }
         let price = best_ask_price.unwrap();
 
-        let taker_order = self.orderbook.place_bid(
+        // the taker is matched without resting, so a market order never becomes a maker
+        let taker_order = self.orderbook.place_taker(
             cid_vec.clone(),
             self.pair_id.clone(),
             self.base_asset_id.clone(),
             self.quote_asset_id.clone(),
             owner_vec.clone(),
+            true,
             price,
             amnt,
             iqty,
             timestamp,
             expires_at,
-            taker_fee_bps,
+            i32::from(taker_fee_bps),
         )?;
 
+        // a market buy takes any ask price, so every ask level counts towards the fill
         if matches!(time_in_force, TimeInForce::FillOrKill)
-            && !self.can_fill_fok(price, &taker_order)?
+            && !self.can_fill_fok(u64::MAX, None, &taker_order)?
         {
-            self.orderbook.cancel_order(
-                cid_vec.clone(),
+            self.orderbook.cancel_taker(
                 self.pair_id.clone(),
-                true,
-                taker_order.id,
-                owner_vec.clone(),
-            )?;
+                self.base_asset_id.clone(),
+                self.quote_asset_id.clone(),
+                &taker_order,
+            );
             return Err(OrderBookError::OrderNotFullyFilled);
         }
 
+        let mut totals = Fill::default();
         let (taker_order, _bid_head, _ask_head) = self._limit_order(
             u64::MAX,
             &mut taker_order.clone(),
+            &mut totals,
+            None,
         )?;
 
-        self._handle_time_in_force_post_matching(time_in_force, &mut taker_order.clone(), false,maker_fee_bps)?;
+        // whatever is left after matching is cancelled, whatever the time in force
+        self.orderbook.cancel_taker(
+            self.pair_id.clone(),
+            self.base_asset_id.clone(),
+            self.quote_asset_id.clone(),
+            &taker_order,
+        );
+
+        Ok(OrderOutcome::new(&taker_order, totals, 0, self.orderbook.price_scale()))
+    }
+
+    /// Place a limit sell order only if at least `min_fill` of it can match right away
+    /// The achievable fill is checked against the book before execution, up to the limit price and the slippage bound.
+    /// - `min_fill` is in the order's base units, 0 accepts any fill.
+    /// - the rest of the arguments are the same as `limit_sell`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn limit_sell_min_fill(
+        &mut self,
+        min_fill: u64,
+        cid: impl Into<Vec<u8>>,
+        existing_order_id: Option<OrderId>,
+        owner: impl Into<Vec<u8>>,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        self.ensure_min_fill(false, price, self.l1.limit_sell_slippage_limit, amnt, min_fill)?;
+        self.limit_sell(
+            cid,
+            existing_order_id,
+            owner,
+            price,
+            amnt,
+            iqty,
+            timestamp,
+            expires_at,
+            maker_fee_bps,
+            taker_fee_bps,
+            time_in_force,
+        )
+    }
+
+    /// Place a limit buy order only if at least `min_fill` of it can match right away
+    /// The achievable fill is checked against the book before execution, up to the limit price and the slippage bound.
+    /// - `min_fill` is in the order's quote units, 0 accepts any fill.
+    /// - the rest of the arguments are the same as `limit_buy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn limit_buy_min_fill(
+        &mut self,
+        min_fill: u64,
+        cid: impl Into<Vec<u8>>,
+        existing_order_id: Option<OrderId>,
+        owner: impl Into<Vec<u8>>,
+        price: u64,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        self.ensure_min_fill(true, price, self.l1.limit_buy_slippage_limit, amnt, min_fill)?;
+        self.limit_buy(
+            cid,
+            existing_order_id,
+            owner,
+            price,
+            amnt,
+            iqty,
+            timestamp,
+            expires_at,
+            maker_fee_bps,
+            taker_fee_bps,
+            time_in_force,
+        )
+    }
+
+    /// Execute a market sell order only if at least `min_fill` of it can match right away
+    /// The achievable fill is checked against the book before execution, at any price.
+    /// - `min_fill` is in the order's base units, 0 accepts any fill.
+    /// - the rest of the arguments are the same as `market_sell`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn market_sell_min_fill(
+        &mut self,
+        min_fill: u64,
+        cid: impl Into<Vec<u8>>,
+        existing_order_id: Option<OrderId>,
+        owner: impl Into<Vec<u8>>,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        self.ensure_min_fill(false, 0, None, amnt, min_fill)?;
+        self.market_sell(
+            cid,
+            existing_order_id,
+            owner,
+            amnt,
+            iqty,
+            timestamp,
+            expires_at,
+            maker_fee_bps,
+            taker_fee_bps,
+            time_in_force,
+        )
+    }
+
+    /// Execute a market buy order only if at least `min_fill` of it can match right away
+    /// The achievable fill is checked against the book before execution, at any price.
+    /// - `min_fill` is in the order's quote units, 0 accepts any fill.
+    /// - the rest of the arguments are the same as `market_buy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn market_buy_min_fill(
+        &mut self,
+        min_fill: u64,
+        cid: impl Into<Vec<u8>>,
+        existing_order_id: Option<OrderId>,
+        owner: impl Into<Vec<u8>>,
+        amnt: u64,
+        iqty: u64,
+        timestamp: i64,
+        expires_at: i64,
+        maker_fee_bps: i32,
+        taker_fee_bps: u16,
+        time_in_force: TimeInForce,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        self.ensure_min_fill(true, u64::MAX, None, amnt, min_fill)?;
+        self.market_buy(
+            cid,
+            existing_order_id,
+            owner,
+            amnt,
+            iqty,
+            timestamp,
+            expires_at,
+            maker_fee_bps,
+            taker_fee_bps,
+            time_in_force,
+        )
+    }
+    /// Returns the L1 and L2 state of the pair in one snapshot, see `OrderBook::full_snapshot`
+    pub fn full_snapshot(&self, scale: u64, depth: u32) -> Result<BookSnapshot, OrderBookError> {
+        self.orderbook.full_snapshot(&self.l1, scale, depth)
+    }
+
+    /// Processes a batch of orders in order against the same book
+    /// - returns one result per request at the same position, a rejected order does not abort the rest of the batch.
+    /// - each order emits its events as if it was submitted on its own.
+    pub fn submit_batch(&mut self, requests: Vec<OrderRequest>) -> Vec<Result<OrderOutcome, OrderBookError>> {
+        requests.into_iter().map(|request| self.submit(request)).collect()
+    }
 
+    /// Processes an order tagged with the gateway's client order id, for retries of an at-least-once delivery
+    /// - a retry of an accepted order, the same request under the same id, is answered with the outcome the order
+    ///   was accepted with without touching the book, an empty id skips the check.
+    /// - rejects another order under a `client_order_id` the same client already uses for a resting order.
+    /// - once its order terminated the id may be reused, see `prune_client_order_ids` for how long it is kept.
+    pub fn submit_with_client_order_id(
+        &mut self,
+        client_order_id: impl Into<Vec<u8>>,
+        request: OrderRequest,
+    ) -> Result<OrderOutcome, OrderBookError> {
+        let key = (request.cid().to_vec(), client_order_id.into());
+        if key.1.is_empty() {
+            return self.submit(request);
+        }
+        if let Some(accepted) = self.client_order_ids.get(&key) {
+            if accepted.request == request {
+                return Ok(accepted.outcome.clone());
+            }
+            if self.orderbook.l3.get_order(accepted.outcome.order_id).is_ok() {
+                return Err(OrderBookError::DuplicateClientOrderId);
+            }
+        }
+
+        let outcome = self.submit(request.clone())?;
+        let accepted_at = self.orderbook.clock.now_millis();
+        self.client_order_ids.insert(key, ClientOrder { request, outcome: outcome.clone(), accepted_at });
+        Ok(outcome)
+    }
+
+    /// Clears the prices left at the head of both sides without resting orders
+    /// - returns the number of cleared prices, both heads then point at a level with orders or are `None`.
+    /// - every cleared price is reported with `SpotLevelRemoved`.
+    pub fn tidy_heads(&mut self) -> usize {
+        let mut cleared = 0;
+        for is_bid in [true, false] {
+            let before = self.orderbook.l2.level_count(is_bid);
+            self.orderbook.clear_empty_head_or_zero(&self.pair_id, is_bid);
+            cleared += before - self.orderbook.l2.level_count(is_bid);
+        }
+        cleared
+    }
+
+    /// Runs `f` on the pair and returns the events it emitted on the current thread, numbered in the stream
+    /// of its orderbook, see `OrderBook::sequence_events`
+    pub fn capture<T>(&mut self, f: impl FnOnce(&mut Pair) -> T) -> (T, Vec<SequencedEvent>) {
+        let (result, mut events) = event::capture_events(|| f(self));
+        self.orderbook.sequence_events(&self.pair_id, &mut events);
+        (result, events)
+    }
+
+    /// Re-applies an event emitted on the pair to its orderbook, see `OrderBook::apply_event`
+    /// - the fills of makers move the last match price to their level, as matching does.
+    pub fn apply_event(&mut self, event: &SpotEvent) -> Result<(), OrderBookError> {
+        self.orderbook.apply_event(event)?;
+        if let SpotEvent::SpotOrderPartiallyFilled { is_taker_event: false, price, .. }
+        | SpotEvent::SpotOrderFullyFilled { is_taker_event: false, price, .. } = event
+        {
+            self.l1.set_lmp(*price);
+        }
         Ok(())
     }
 
+    /// Releases the client order ids of orders no longer resting on the book and accepted longer than
+    /// `client_order_id_ttl_ms` ago
+    /// - returns the number of released ids.
+    pub fn prune_client_order_ids(&mut self) -> usize {
+        let before = self.client_order_ids.len();
+        let now = self.orderbook.clock.now_millis();
+        let ttl_ms = self.client_order_id_ttl_ms;
+        let l3 = &self.orderbook.l3;
+        self.client_order_ids.retain(|_, accepted| {
+            l3.get_order(accepted.outcome.order_id).is_ok() || now.saturating_sub(accepted.accepted_at) < ttl_ms
+        });
+        before - self.client_order_ids.len()
+    }
+
+    /// Processes a single order with the entry method matching its request
+    pub fn submit(&mut self, request: OrderRequest) -> Result<OrderOutcome, OrderBookError> {
+        match request {
+            OrderRequest::LimitBuy {
+                cid,
+                existing_order_id,
+                owner,
+                price,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            } => self.limit_buy(
+                cid,
+                existing_order_id,
+                owner,
+                price,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            ),
+            OrderRequest::LimitSell {
+                cid,
+                existing_order_id,
+                owner,
+                price,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            } => self.limit_sell(
+                cid,
+                existing_order_id,
+                owner,
+                price,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            ),
+            OrderRequest::MarketBuy {
+                cid,
+                existing_order_id,
+                owner,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            } => self.market_buy(
+                cid,
+                existing_order_id,
+                owner,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            ),
+            OrderRequest::MarketSell {
+                cid,
+                existing_order_id,
+                owner,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            } => self.market_sell(
+                cid,
+                existing_order_id,
+                owner,
+                amnt,
+                iqty,
+                timestamp,
+                expires_at,
+                maker_fee_bps,
+                taker_fee_bps,
+                time_in_force,
+            ),
+        }
+    }
+
     pub fn cancel_order(
         &mut self,
         cid: impl Into<Vec<u8>>,
@@ -742,8 +1797,15 @@ impl Pair {
         order_id: OrderId,
         owner: impl Into<Vec<u8>>,
     ) -> Result<(), OrderBookError> {
-        self.orderbook
-            .cancel_order(cid, pair_id, is_bid, order_id, owner)?;
+        self.orderbook.cancel_order(
+            cid,
+            pair_id,
+            self.base_asset_id.clone(),
+            self.quote_asset_id.clone(),
+            is_bid,
+            order_id,
+            owner,
+        )?;
         Ok(())
     }
 }
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::fmt;
+use std::ops::Bound::{Excluded, Unbounded};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Level {
@@ -29,6 +30,19 @@ fn format_levels(levels: &[Level]) -> String {
     format!("[{}]", formatted.join(", "))
 }
 
+/// CRC32 (IEEE) used for the orderbook checksum and snapshot integrity checks
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct PriceNode {
     pub prev: Option<u64>,
@@ -65,6 +79,19 @@ pub struct L2 {
     /// key is scale in 8 decimals integer (e.g. 100000000 for 1.00000000, 1000000000 for 10.00000000)
     /// value is a vector of levels in the quantized price space
     pub ask_level_list: BTreeMap<u64, Vec<Level>>,
+    /// Public quantity at the bid head, kept in sync by every mutation of the head or its level
+    pub best_bid_qty: u64,
+    /// Public quantity at the ask head, kept in sync by every mutation of the head or its level
+    pub best_ask_qty: u64,
+}
+
+/// Quantity of a level reported by a two-element snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QtyView {
+    /// public quantity, hiding the iceberg part of the orders
+    Public,
+    /// current quantity, including the iceberg part of the orders
+    Current,
 }
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
@@ -81,6 +108,8 @@ pub enum L2Error {
     FailedToSetBidLevels { scale: u64, levels: Vec<Level> },
     #[error("failed to set ask levels: scale={scale}, levels={}", format_levels(&levels))]
     FailedToSetAskLevels { scale: u64, levels: Vec<Level> },
+    #[error("bucket width is zero in L2 aggregated snapshot")]
+    BucketWidthIsZero,
 }
 
 impl L2 {
@@ -98,6 +127,8 @@ impl L2 {
             current_ask_level_map: BTreeMap::new(),
             bid_level_list: BTreeMap::new(),
             ask_level_list: BTreeMap::new(),
+            best_bid_qty: 0,
+            best_ask_qty: 0,
         }
     }
 
@@ -117,6 +148,31 @@ impl L2 {
         self.ask_price_head
     }
 
+    /// Best bid price and its public quantity
+    pub fn best_bid(&self) -> Option<(u64, u64)> {
+        self.bid_price_head.map(|price| (price, self.best_bid_qty))
+    }
+
+    /// Best ask price and its public quantity
+    pub fn best_ask(&self) -> Option<(u64, u64)> {
+        self.ask_price_head.map(|price| (price, self.best_ask_qty))
+    }
+
+    // re-reads the cached best quantity of a side from its head level
+    fn refresh_best_qty(&mut self, is_bid: bool) {
+        if is_bid {
+            self.best_bid_qty = self
+                .bid_price_head
+                .and_then(|price| self.public_bid_level_map.get(&price).copied())
+                .unwrap_or(0);
+        } else {
+            self.best_ask_qty = self
+                .ask_price_head
+                .and_then(|price| self.public_ask_level_map.get(&price).copied())
+                .unwrap_or(0);
+        }
+    }
+
     pub fn public_bid_level(&self, price: u64) -> Option<u64> {
         self.public_bid_level_map.get(&price).copied()
     }
@@ -163,11 +219,13 @@ impl L2 {
 
     pub fn set_public_bid_level(&mut self, price: u64, level: u64) -> Result<(), L2Error> {
         self.public_bid_level_map.insert(price, level);
+        self.refresh_best_qty(true);
         Ok(())
     }
 
     pub fn set_public_ask_level(&mut self, price: u64, level: u64) -> Result<(), L2Error> {
         self.public_ask_level_map.insert(price, level);
+        self.refresh_best_qty(false);
         Ok(())
     }
 
@@ -192,9 +250,14 @@ impl L2 {
             }
             else {
                 self.bid_price_head = self.bid_price_nodes.get(&old_head).and_then(|node| node.next);
+                // unlink the new head from the cleared one so later removals see it as the head
+                if let Some(node) = self.bid_price_head.and_then(|head| self.bid_price_nodes.get_mut(&head)) {
+                    node.prev = None;
+                }
             }
             // remove the node from the bid price nodes map
             self.bid_price_nodes.remove(&old_head);
+            self.refresh_best_qty(true);
             Ok(self.bid_price_head)
         } else {
             let old_head = self.ask_price_head.unwrap();
@@ -206,9 +269,14 @@ impl L2 {
             }
             else {
                 self.ask_price_head = self.ask_price_nodes.get(&old_head).and_then(|node| node.next);
+                // unlink the new head from the cleared one so later removals see it as the head
+                if let Some(node) = self.ask_price_head.and_then(|head| self.ask_price_nodes.get_mut(&head)) {
+                    node.prev = None;
+                }
             }
             // remove the node from the ask price nodes map
             self.ask_price_nodes.remove(&old_head);
+            self.refresh_best_qty(false);
             Ok(self.ask_price_head)
         }
     }
@@ -222,7 +290,20 @@ impl L2 {
         }
     }
 
+    /// Number of price levels on one side
+    pub fn level_count(&self, is_bid: bool) -> usize {
+        if is_bid {
+            self.bid_price_nodes.len()
+        } else {
+            self.ask_price_nodes.len()
+        }
+    }
+
+    /// Lists `price` with empty levels, a price already listed keeps its levels
     pub fn insert_price(&mut self, is_bid: bool, price: u64) -> Result<(), L2Error> {
+        if self.price_exists(is_bid, price) {
+            return Ok(());
+        }
         if is_bid {
             let _ = self._insert_bid_price(price)?;
             self.set_public_bid_level(price, 0)?;
@@ -239,185 +320,98 @@ impl L2 {
 
     /// inserts a bid price into the bid price linked list
     /// price linked list is sorted in descending order
+    /// the neighbours are looked up in `bid_price_nodes`, so an insert is O(log n) however deep the book is
     fn _insert_bid_price(&mut self, price: u64) -> Result<(), L2Error> {
         Self::ensure_price(price)?;
-        // compare head of the bid price head
-        if self.bid_price_head == None {
-            self.bid_price_head = Some(price);
-            // set the tail of the bid price linked list
-            self.bid_price_tail = Some(price);
-            // create a node for the first price
-            self.bid_price_nodes.insert(price, PriceNode {
-                prev: None,
-                next: None,
-            });
-            return Ok(());
-        }
-        else if price > self.bid_price_head.unwrap() {
-            let old_head = self.bid_price_head.unwrap();
-            self.bid_price_head = Some(price);
-            // update old head's node to point back to new head
-            self.bid_price_nodes.get_mut(&old_head).map(|node| node.prev = Some(price));
-            // create node for new head
-            self.bid_price_nodes.insert(price, PriceNode {
-                prev: None,
-                next: Some(old_head),
-            });
-            return Ok(());
-        } 
-        else if price == self.bid_price_head.unwrap() {
+        if self.bid_price_nodes.contains_key(&price) {
             return Ok(());
         }
-        else {
-            // traverse through the bid price linked list and insert the price at the correct position so that the list is sorted in descending order
-            let mut current = self.bid_price_head;
-            while current.is_some() {
-                let next = self.bid_price_nodes.get(&current.unwrap()).and_then(|node| node.next);
-                // next does not exist
-                if next.is_none() {
-                    // insert the price at the current position
-                    let curr = current.unwrap();
-                    self.bid_price_nodes.get_mut(&curr).map(|node| node.next = Some(price));
-                    // create a node for the new price
-                    self.bid_price_nodes.insert(price, PriceNode {
-                        prev: Some(curr),
-                        next: None,
-                    });
-                    // set the tail of the bid price linked list
-                    self.bid_price_tail = Some(price);
-                    return Ok(());
+        // the list is descending, so the previous node holds the next higher price and the next node the next lower one
+        let prev = self.bid_price_nodes.range((Excluded(price), Unbounded)).next().map(|(p, _)| *p);
+        let next = self.bid_price_nodes.range(..price).next_back().map(|(p, _)| *p);
+        match prev {
+            Some(prev_price) => {
+                if let Some(node) = self.bid_price_nodes.get_mut(&prev_price) {
+                    node.next = Some(price);
                 }
-                else {
-                    // next exists
-                    let next_val = next.unwrap();
-                    if next_val > price {
-                        // traverse until the price is bigger than the current price
-                        current = Some(next_val);
-                    }
-                    else if next_val < price {
-                        // To avoid mutable and immutable borrow at the same time, collect value first
-                        let curr = current.unwrap();
-                        // Update current's next to point to new price
-                        self.bid_price_nodes.get_mut(&curr).map(|node| node.next = Some(price));
-                        // Update next_val's prev to point to new price
-                        self.bid_price_nodes.get_mut(&next_val).map(|node| node.prev = Some(price));
-                        // Create node for new price
-                        self.bid_price_nodes.insert(price, PriceNode {
-                            prev: Some(curr),
-                            next: Some(next_val),
-                        });
-                        return Ok(());
-                    }
+            }
+            // no higher price, the new price becomes the head
+            None => self.bid_price_head = Some(price),
+        }
+        match next {
+            Some(next_price) => {
+                if let Some(node) = self.bid_price_nodes.get_mut(&next_price) {
+                    node.prev = Some(price);
                 }
             }
-            return Ok(());
+            // no lower price, the new price becomes the tail
+            None => self.bid_price_tail = Some(price),
         }
+        self.bid_price_nodes.insert(price, PriceNode { prev, next });
+        // a new head takes over the cached best quantity
+        self.refresh_best_qty(true);
+        Ok(())
     }
 
+    /// inserts an ask price into the ask price linked list
     /// price linked list is sorted in ascending order
-    /// linked list is sorted in ascending order
+    /// the neighbours are looked up in `ask_price_nodes`, so an insert is O(log n) however deep the book is
     fn _insert_ask_price(&mut self, price: u64) -> Result<(), L2Error> {
         Self::ensure_price(price)?;
-        // compare head of the ask price head
-        if self.ask_price_head == None {
-            self.ask_price_head = Some(price);
-            // set the tail of the ask price linked list
-            self.ask_price_tail = Some(price);
-            // create a node for the first price
-            self.ask_price_nodes.insert(price, PriceNode {
-                prev: None,
-                next: None,
-            });
-            return Ok(());
-        }
-        else if price < self.ask_price_head.unwrap() {
-            let old_head = self.ask_price_head.unwrap();
-            self.ask_price_head = Some(price);
-            // update old head's prev pointer to point to new head
-            self.ask_price_nodes.get_mut(&old_head).map(|node| node.prev = Some(price));
-            // create node for new head
-            self.ask_price_nodes.insert(price, PriceNode {
-                prev: None,
-                next: Some(old_head),
-            });
-            return Ok(());
-        }
-        else if price == self.ask_price_head.unwrap() {
+        if self.ask_price_nodes.contains_key(&price) {
             return Ok(());
         }
-        else {
-            // traverse through the ask price linked list and insert the price at the correct position so that the list is sorted in ascending order
-            let mut current = self.ask_price_head;
-            while current.is_some() {
-                let next = self.ask_price_nodes.get(&current.unwrap()).and_then(|node| node.next);
-                // next does not exist
-                if next.is_none() {
-                    // insert the price at the tail position
-                    let curr = current.unwrap();
-                    // update current node's next pointer
-                    self.ask_price_nodes.get_mut(&curr).map(|node| node.next = Some(price));
-                    // create a node for the new price
-                    self.ask_price_nodes.insert(price, PriceNode {
-                        prev: Some(curr),
-                        next: None,
-                    });
-                    // set the tail of the ask price linked list
-                    self.ask_price_tail = Some(price);
-                    return Ok(());
+        // the list is ascending, so the previous node holds the next lower price and the next node the next higher one
+        let prev = self.ask_price_nodes.range(..price).next_back().map(|(p, _)| *p);
+        let next = self.ask_price_nodes.range((Excluded(price), Unbounded)).next().map(|(p, _)| *p);
+        match prev {
+            Some(prev_price) => {
+                if let Some(node) = self.ask_price_nodes.get_mut(&prev_price) {
+                    node.next = Some(price);
                 }
-                else {
-                    // next exists
-                    let next_val = next.unwrap();
-                    if next_val < price {
-                        // traverse until the price is smaller than the current price
-                        current = Some(next_val);
-                    }
-                    else if next_val > price {
-                        // To avoid mutable and immutable borrow at the same time, collect value first
-                        let curr = current.unwrap();
-                        // Update current's next pointer to point to new price
-                        self.ask_price_nodes.get_mut(&curr).map(|node| node.next = Some(price));
-                        // Update next_val's prev pointer to point to new price
-                        self.ask_price_nodes.get_mut(&next_val).map(|node| node.prev = Some(price));
-                        // Create node for new price
-                        self.ask_price_nodes.insert(price, PriceNode {
-                            prev: Some(curr),
-                            next: Some(next_val),
-                        });
-                        return Ok(());
-                    }
-                    else {
-                        break;
-                    }
+            }
+            // no lower price, the new price becomes the head
+            None => self.ask_price_head = Some(price),
+        }
+        match next {
+            Some(next_price) => {
+                if let Some(node) = self.ask_price_nodes.get_mut(&next_price) {
+                    node.prev = Some(price);
                 }
             }
-            return Ok(());
+            // no higher price, the new price becomes the tail
+            None => self.ask_price_tail = Some(price),
         }
+        self.ask_price_nodes.insert(price, PriceNode { prev, next });
+        // a new head takes over the cached best quantity
+        self.refresh_best_qty(false);
+        Ok(())
     }
 
     // remove price from the price linked list
-    pub fn remove_price(&mut self, is_bid: bool, price: u64) -> Result<(), L2Error> {
+    // returns whether the price existed and was removed, so a removal is reported exactly once
+    pub fn remove_price(&mut self, is_bid: bool, price: u64) -> Result<bool, L2Error> {
         if is_bid {
-            self._remove_bid_price(price)?;
+            let removed = self._remove_bid_price(price)?;
             // remove the level from the level map
             self.public_bid_level_map.remove(&price);
             self.current_bid_level_map.remove(&price);
-            Ok(())
+            Ok(removed)
         }
         else {
-            self._remove_ask_price(price)?;
+            let removed = self._remove_ask_price(price)?;
             // remove the level from the level map
             self.public_ask_level_map.remove(&price);
             self.current_ask_level_map.remove(&price);
-            Ok(())
+            Ok(removed)
         }
     }
     // remove price from the bid price linked list
-    fn _remove_bid_price(&mut self, price: u64) -> Result<(), L2Error> {
+    fn _remove_bid_price(&mut self, price: u64) -> Result<bool, L2Error> {
         // Get the node to be removed before removing it
         let node = match self.bid_price_nodes.get(&price) {
             Some(node) => node.clone(),
-            None => return Ok(()), // Price doesn't exist, nothing to remove
+            None => return Ok(false), // Price doesn't exist, nothing to remove
         };
 
         // Update the previous node's next pointer
@@ -446,16 +440,17 @@ impl L2 {
         // Remove the level from the level map
         self.public_bid_level_map.remove(&price);
         self.current_bid_level_map.remove(&price);
+        self.refresh_best_qty(true);
 
-        Ok(())
+        Ok(true)
     }
 
     // remove price from the ask price linked list
-    fn _remove_ask_price(&mut self, price: u64) -> Result<(), L2Error> {
+    fn _remove_ask_price(&mut self, price: u64) -> Result<bool, L2Error> {
         // Get the node to be removed before removing it
         let node = match self.ask_price_nodes.get(&price) {
             Some(node) => node.clone(),
-            None => return Ok(()), // Price doesn't exist, nothing to remove
+            None => return Ok(false), // Price doesn't exist, nothing to remove
         };
 
         // Update the previous node's next pointer
@@ -484,30 +479,29 @@ impl L2 {
         // Remove the level from the level map
         self.public_ask_level_map.remove(&price);
         self.current_ask_level_map.remove(&price);
+        self.refresh_best_qty(false);
 
-        Ok(())
+        Ok(true)
     }
 
     /// Helper function to collect all bid prices in order (descending)
     pub fn collect_bid_prices(&self) -> Vec<u64> {
-        let mut prices = Vec::new();
-        let mut current = self.bid_price_head;
-        while let Some(price) = current {
-            prices.push(price);
-            current = self.bid_price_nodes.get(&price).and_then(|node| node.next);
-        }
-        prices
+        self.bid_prices().collect()
+    }
+
+    /// Iterates the bid prices from the best one (descending), following the price list only as far as it is consumed
+    pub fn bid_prices(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::successors(self.bid_price_head, |price| self.bid_price_nodes.get(price).and_then(|node| node.next))
     }
 
     /// Helper function to collect all ask prices in order (ascending)
     pub fn collect_ask_prices(&self) -> Vec<u64> {
-        let mut prices = Vec::new();
-        let mut current = self.ask_price_head;
-        while let Some(price) = current {
-            prices.push(price);
-            current = self.ask_price_nodes.get(&price).and_then(|node| node.next);
-        }
-        prices
+        self.ask_prices().collect()
+    }
+
+    /// Iterates the ask prices from the best one (ascending), following the price list only as far as it is consumed
+    pub fn ask_prices(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::successors(self.ask_price_head, |price| self.ask_price_nodes.get(price).and_then(|node| node.next))
     }
 
     /// Helper function to format a u64 number (in 8 decimals) to a string with 8 decimal places
@@ -521,6 +515,29 @@ impl L2 {
         format!("{}.{:08}", integer_part, decimal_part)
     }
 
+    /// CRC32 checksum of the top `top_n` levels on each side for clients to detect a desynced book
+    /// Each level is formatted as `price:cqty` with 8 decimals, asks first in ascending order then bids
+    /// in descending order, joined by `,` (e.g. `1.10000000:2.00000000,1.00000000:3.00000000`)
+    pub fn checksum(&self, top_n: usize) -> u32 {
+        let asks = self
+            .ask_prices()
+            .take(top_n)
+            .map(|price| (price, self.current_ask_level(price).unwrap_or(0)));
+        let bids = self
+            .bid_prices()
+            .take(top_n)
+            .map(|price| (price, self.current_bid_level(price).unwrap_or(0)));
+
+        let payload: Vec<String> = asks
+            .chain(bids)
+            .map(|(price, cqty)| {
+                format!("{}:{}", Self::format_8_decimals(price), Self::format_8_decimals(cqty))
+            })
+            .collect();
+
+        crc32(payload.join(",").as_bytes())
+    }
+
     /// get L2 snapshot (raw numbers)
     /// Returns an array of arrays where each inner array is [price in 8 decimals, base amount in 8 decimals]
     /// The outer array has step length
@@ -551,6 +568,70 @@ impl L2 {
         Ok(snapshot)
     }
 
+    /// get L2 snapshot with a single quantity per level (raw numbers)
+    /// Returns an array of [price in 8 decimals, quantity of `view` in 8 decimals], a smaller payload than `get_snapshot_raw`
+    /// The outer array has step length
+    pub fn get_snapshot_view_raw(&self, is_bid: bool, scale: u64, step: u32, view: QtyView) -> Result<Vec<[u64; 2]>, L2Error> {
+        let snapshot = self
+            .get_snapshot_raw(is_bid, scale, step)?
+            .into_iter()
+            .map(|level| match view {
+                QtyView::Public => [level[0], level[1]],
+                QtyView::Current => [level[0], level[2]],
+            })
+            .collect();
+        Ok(snapshot)
+    }
+
+    /// get L2 snapshot aggregated into fixed-width price buckets (raw numbers)
+    /// Returns up to `depth` buckets where each is [bucket price in 8 decimals, public quantity, current quantity]
+    /// Bid prices are rounded down and ask prices are rounded up to the bucket boundary,
+    /// so every level in a bucket is at least as good as the bucket price
+    pub fn get_aggregated_snapshot(&self, is_bid: bool, bucket_width: u64, depth: usize) -> Result<Vec<[u64; 3]>, L2Error> {
+        if bucket_width == 0 {
+            return Err(L2Error::BucketWidthIsZero);
+        }
+
+        let prices = if is_bid {
+            self.collect_bid_prices()
+        } else {
+            self.collect_ask_prices()
+        };
+
+        let mut buckets: Vec<[u64; 3]> = Vec::new();
+        for price in prices {
+            let (bucket, pqty, cqty) = if is_bid {
+                (
+                    price - price % bucket_width,
+                    self.public_bid_level(price).unwrap_or(0),
+                    self.current_bid_level(price).unwrap_or(0),
+                )
+            } else {
+                (
+                    price.div_ceil(bucket_width).saturating_mul(bucket_width),
+                    self.public_ask_level(price).unwrap_or(0),
+                    self.current_ask_level(price).unwrap_or(0),
+                )
+            };
+
+            // prices are sorted, so levels of the same bucket are adjacent
+            match buckets.last_mut() {
+                Some(last) if last[0] == bucket => {
+                    last[1] = last[1].saturating_add(pqty);
+                    last[2] = last[2].saturating_add(cqty);
+                }
+                _ => {
+                    if buckets.len() == depth {
+                        break;
+                    }
+                    buckets.push([bucket, pqty, cqty]);
+                }
+            }
+        }
+
+        Ok(buckets)
+    }
+
     /// get L2 snapshot (formatted strings)
     /// Returns an array of arrays where each inner array is [price as string with 8 decimals, base amount as string with 8 decimals]
     /// The outer array has step length
@@ -573,4 +654,15 @@ impl L2 {
 
         Ok(snapshot)
     }
-}
\ No newline at end of file
+
+    /// get L2 snapshot with a single quantity per level (formatted strings)
+    /// Returns an array of [price as string with 8 decimals, quantity of `view` as string with 8 decimals]
+    pub fn get_snapshot_view(&self, is_bid: bool, scale: u64, step: u32, view: QtyView) -> Result<Vec<[String; 2]>, L2Error> {
+        let snapshot = self
+            .get_snapshot_view_raw(is_bid, scale, step, view)?
+            .iter()
+            .map(|level| [Self::format_8_decimals(level[0]), Self::format_8_decimals(level[1])])
+            .collect();
+        Ok(snapshot)
+    }
+}
@@ -11,6 +11,9 @@ pub enum TimeInForce {
     /// Good Till Canceled (GTC): Order stays in the orderbook until filled or manually canceled
     /// This is the default behavior for limit orders
     GoodTillCanceled,
+    /// Good Till Date (GTD): Order stays in the orderbook until filled, canceled, or `expires_at` is reached
+    /// The remaining is swept as expired once the order's expiry passes
+    GoodTillDate,
 }
 
 impl Default for TimeInForce {
@@ -1,6 +1,6 @@
 use offgrid_primitives::account::collect_balances;
-use offgrid_primitives::account::futures::FuturesAccount;
-use offgrid_primitives::account::option::OptionsAccount;
+use offgrid_primitives::account::futures::{FuturesAccount, FuturesPosition};
+use offgrid_primitives::account::option::{Greeks, OptionPosition, OptionsAccount};
 use offgrid_primitives::account::spot::SpotAccount;
 use offgrid_primitives::account::AccountBalances;
 
@@ -20,6 +20,120 @@ fn collect_balances_across_accounts() {
     let balances_map: std::collections::HashMap<Vec<u8>, u64> = balances.into_iter().collect();
 
     assert_eq!(balances_map.len(), 2);
-    assert_eq!(balances_map.get(&vec![1]), Some(&300));
+    assert_eq!(balances_map.get(&vec![1]), Some(&400));
     assert_eq!(balances_map.get(&vec![2]), Some(&200));
 }
+
+#[test]
+fn collect_balances_sums_the_same_asset() {
+    let mut first = SpotAccount::default();
+    let mut second = SpotAccount::default();
+    first.balances.insert(vec![1], 100);
+    second.balances.insert(vec![1], 100);
+
+    let accounts: [&dyn AccountBalances; 2] = [&first, &second];
+    assert_eq!(collect_balances(&accounts, &[vec![1]]), vec![(vec![1], 200)]);
+
+    second.balances.insert(vec![1], u64::MAX);
+    let accounts: [&dyn AccountBalances; 2] = [&first, &second];
+    assert_eq!(collect_balances(&accounts, &[vec![1]]), vec![(vec![1], u64::MAX)]);
+}
+
+fn at_the_money(is_call: bool, is_long: bool) -> OptionPosition {
+    OptionPosition {
+        asset_id: vec![3],
+        is_call,
+        is_long,
+        strike: 100 * 1_0000_0000,
+        quantity: 1_0000_0000,
+        time_to_expiry: 1_0000_0000,
+    }
+}
+
+fn assert_close(actual: i64, expected: f64) {
+    let expected = (expected * 1e8).round() as i64;
+    assert!((actual - expected).abs() <= 100, "{actual} is not close to {expected}");
+}
+
+#[test]
+fn greeks_of_an_at_the_money_call_match_black_scholes() {
+    let options = OptionsAccount { positions: vec![at_the_money(true, true)], ..Default::default() };
+    let greeks = options.greeks(100 * 1_0000_0000, 2000_0000, 500_0000);
+
+    // S = K = 100, vol = 20%, r = 5%, T = 1 year
+    assert_close(greeks.delta, 0.63683065);
+    assert_close(greeks.gamma, 0.01876202);
+    assert_close(greeks.vega, 37.52403469);
+    assert_close(greeks.theta, -6.41402755);
+}
+
+#[test]
+fn greeks_of_a_short_put_are_negated() {
+    let options = OptionsAccount { positions: vec![at_the_money(false, false)], ..Default::default() };
+    let greeks = options.greeks(100 * 1_0000_0000, 2000_0000, 500_0000);
+
+    assert_close(greeks.delta, 0.36316935);
+    assert_close(greeks.gamma, -0.01876202);
+    assert_close(greeks.vega, -37.52403469);
+    assert_close(greeks.theta, 1.65788042);
+}
+
+#[test]
+fn greeks_without_vol_or_time_are_saturated() {
+    let mut options = OptionsAccount { positions: vec![at_the_money(true, true)], ..Default::default() };
+
+    // with no volatility the call is in the money once the strike is discounted
+    let greeks = options.greeks(100 * 1_0000_0000, 0, 500_0000);
+    assert_eq!(greeks, Greeks { delta: 1_0000_0000, gamma: 0, vega: 0, theta: 0 });
+
+    options.positions[0].time_to_expiry = 0;
+    assert_eq!(options.greeks(99 * 1_0000_0000, 2000_0000, 500_0000), Greeks::default());
+    assert_eq!(options.greeks(101 * 1_0000_0000, 2000_0000, 500_0000).delta, 1_0000_0000);
+}
+
+fn futures_with(is_long: bool, margin: u64) -> FuturesAccount {
+    FuturesAccount {
+        positions: vec![FuturesPosition {
+            asset_id: vec![4],
+            is_long,
+            size: 10 * 1_0000_0000,
+            entry_price: 100 * 1_0000_0000,
+            margin,
+        }],
+        maintenance_ratio_bps: 500,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn well_collateralized_positions_are_not_liquidatable() {
+    // 10 contracts at 100 with 200 of margin, a 5% maintenance ratio
+    let long = futures_with(true, 200 * 1_0000_0000);
+    assert_eq!(long.maintenance_margin(90 * 1_0000_0000), 45 * 1_0000_0000);
+    assert!(!long.is_liquidatable(90 * 1_0000_0000, 500));
+
+    let short = futures_with(false, 200 * 1_0000_0000);
+    assert_eq!(short.equity(110 * 1_0000_0000), 100 * 1_0000_0000);
+    assert!(!short.is_liquidatable(110 * 1_0000_0000, 500));
+}
+
+#[test]
+fn underwater_positions_are_liquidatable() {
+    // a 15% move against 100 of margin leaves no equity
+    let long = futures_with(true, 100 * 1_0000_0000);
+    assert_eq!(long.equity(85 * 1_0000_0000), -50 * 1_0000_0000);
+    assert!(long.is_liquidatable(85 * 1_0000_0000, 500));
+
+    let short = futures_with(false, 100 * 1_0000_0000);
+    assert!(short.is_liquidatable(115 * 1_0000_0000, 500));
+    // a smaller move leaves the same short above maintenance
+    assert!(!short.is_liquidatable(104 * 1_0000_0000, 500));
+}
+
+#[test]
+fn maintenance_margin_does_not_overflow_at_large_notionals() {
+    let mut account = futures_with(true, u64::MAX);
+    account.positions[0].size = u64::MAX;
+    assert_eq!(account.maintenance_margin(u64::MAX), u64::MAX);
+    assert!(!account.is_liquidatable(u64::MAX, 0));
+}
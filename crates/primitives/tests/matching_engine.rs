@@ -0,0 +1,156 @@
+use offgrid_primitives::spot::event::SpotEvent;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::matching_engine::pair_lock_poison_recoveries;
+use offgrid_primitives::spot::MatchingEngine;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn engine_with_two_pairs() -> MatchingEngine {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    engine.add_pair(vec![1], vec![2], vec![3], b"ETH-USD".to_vec(), 0);
+    engine
+}
+
+fn rest_bid(engine: &MatchingEngine, pair_id: &[u8], price: u64) -> Vec<SpotEvent> {
+    engine
+        .limit_buy(vec![1], pair_id.to_vec(), None, vec![10], price, SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap()
+        .into_vec()
+}
+
+#[test]
+fn list_pairs_returns_every_registered_pair() {
+    let mut engine = MatchingEngine::new();
+    let pair_ids = [b"BTC-USD".to_vec(), b"ETH-USD".to_vec(), b"SOL-USD".to_vec()];
+    for pair_id in &pair_ids {
+        engine.add_pair(vec![1], vec![2], vec![3], pair_id.clone(), 0);
+    }
+    // registering a second client on a pair does not add a pair
+    engine.add_pair(vec![4], vec![5], vec![6], b"BTC-USD".to_vec(), 0);
+
+    assert_eq!(engine.pair_count(), 3);
+    let listed = engine.list_pairs();
+    assert_eq!(listed.len(), 3);
+    for pair_id in &pair_ids {
+        assert!(listed.contains(pair_id));
+        assert!(engine.pair_exists(pair_id));
+    }
+    assert!(!engine.pair_exists(b"DOGE-USD"));
+}
+
+#[test]
+fn add_pair_with_dust_sets_the_dust_of_the_pair() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair_with_dust(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 5000, 0);
+    engine.add_pair(vec![1], vec![2], vec![3], b"ETH-USD".to_vec(), 0);
+
+    assert_eq!(engine.get_pair(b"BTC-USD").unwrap().orderbook.dust, 5000);
+    assert_eq!(engine.get_pair(b"ETH-USD").unwrap().orderbook.dust, 0);
+}
+
+#[test]
+fn orders_on_two_pairs_run_concurrently_without_mixing_events() {
+    const ORDERS: u64 = 200;
+    let engine = engine_with_two_pairs();
+
+    thread::scope(|scope| {
+        for (pair_id, price) in [(b"BTC-USD", 100 * SCALE_8), (b"ETH-USD", 200 * SCALE_8)] {
+            let handle = engine.share();
+            scope.spawn(move || {
+                for _ in 0..ORDERS {
+                    for event in rest_bid(&handle, pair_id, price) {
+                        if let SpotEvent::SpotOrderPlaced { pair_id: placed_on, .. } = event {
+                            assert_eq!(placed_on, pair_id.to_vec(), "picked up an event of the other pair");
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let btc = engine.get_pair(b"BTC-USD").unwrap().orderbook.l2.clone();
+    let eth = engine.get_pair(b"ETH-USD").unwrap().orderbook.l2.clone();
+    assert_eq!(btc.current_bid_level(100 * SCALE_8), Some(ORDERS * SCALE_8));
+    assert_eq!(btc.current_bid_level(200 * SCALE_8), None);
+    assert_eq!(eth.current_bid_level(200 * SCALE_8), Some(ORDERS * SCALE_8));
+    assert_eq!(eth.current_bid_level(100 * SCALE_8), None);
+}
+
+#[test]
+fn snapshot_waiting_on_a_busy_pair_does_not_block_the_other_pair() {
+    let engine = engine_with_two_pairs();
+    let busy = engine.pair_lock(b"BTC-USD").unwrap();
+    let busy_guard = busy.lock().unwrap();
+
+    // the snapshot serializes one pair at a time and sooner or later waits for the busy one
+    let snapshot = {
+        let handle = engine.share();
+        thread::spawn(move || postcard::to_allocvec(&handle).unwrap())
+    };
+    let (done_tx, done_rx) = mpsc::channel();
+    let trader = {
+        let handle = engine.share();
+        thread::spawn(move || {
+            rest_bid(&handle, b"ETH-USD", 200 * SCALE_8);
+            done_tx.send(()).unwrap();
+        })
+    };
+    done_rx
+        .recv_timeout(Duration::from_secs(5))
+        .expect("order on the other pair was blocked");
+    trader.join().unwrap();
+
+    drop(busy_guard);
+    let bytes = snapshot.join().unwrap();
+    let restored: MatchingEngine = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(restored.pair_count(), 2);
+    assert!(engine.pair_lock(b"NOPE").is_none());
+}
+
+#[test]
+fn snapshot_of_a_pair_poisoned_by_a_panicked_thread_succeeds() {
+    let engine = engine_with_two_pairs();
+    rest_bid(&engine, b"BTC-USD", 100 * SCALE_8);
+    let poisoned = engine.pair_lock(b"BTC-USD").unwrap();
+    let panicked = thread::spawn(move || {
+        let _guard = poisoned.lock().unwrap();
+        panic!("order handler panicked holding the pair lock");
+    })
+    .join();
+    assert!(panicked.is_err());
+    assert_eq!(engine.poisoned_pairs(), 1);
+    let recoveries = pair_lock_poison_recoveries();
+
+    let bytes = postcard::to_allocvec(&engine).expect("snapshot of the poisoned pair");
+    let restored: MatchingEngine = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(restored, engine);
+    // recovered once, the next snapshot finds the lock clean
+    assert_eq!(engine.poisoned_pairs(), 0);
+    assert!(pair_lock_poison_recoveries() > recoveries);
+    assert!(postcard::to_allocvec(&engine).is_ok());
+}
+
+#[test]
+fn clone_does_not_share_pair_locks() {
+    let engine = engine_with_two_pairs();
+    let copy = engine.clone();
+    assert_eq!(copy, engine);
+    rest_bid(&engine.share(), b"BTC-USD", 100 * SCALE_8);
+    // the shared handle sees the order, the deep copy does not
+    assert_eq!(engine.share(), engine);
+    assert_ne!(copy, engine);
+}
+
+#[test]
+fn operation_returns_only_its_own_events() {
+    // the events of `add_pair` are queued globally, outside of any order
+    let engine = engine_with_two_pairs();
+    let events = rest_bid(&engine, b"BTC-USD", 100 * SCALE_8);
+
+    assert!(!events.iter().any(|event| matches!(event, SpotEvent::SpotPairAdded { .. })), "{:?}", events);
+    assert!(events.iter().any(|event| matches!(event, SpotEvent::SpotOrderPlaced { .. })));
+}
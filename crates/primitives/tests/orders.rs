@@ -22,3 +22,16 @@ fn remove_dormant_orders_removes_expired() {
     assert!(l3.get_order(expired_id).is_err());
     assert!(l3.get_order(active_id).is_ok());
 }
+
+#[test]
+fn remove_dormant_orders_removes_both_sides_earliest_expiry_first() {
+    let mut l3 = L3::new();
+    let mut ids = Vec::new();
+    for (is_bid, expires_at) in [(true, 30), (false, 10), (true, 20)] {
+        ids.push(l3.create_order(vec![1], vec![2], is_bid, 100, 10, 0, 0, expires_at, 10).expect("create expired order").id);
+    }
+
+    let removed: Vec<_> = l3.remove_dormant_orders(1_000).into_iter().map(|(id, _)| id).collect();
+    assert_eq!(removed, vec![ids[1], ids[2], ids[0]]);
+    assert!(l3.orders.is_empty());
+}
@@ -0,0 +1,298 @@
+use offgrid_primitives::spot::event::{self, EventQueue, EventReceiver, OverflowPolicy, SpotEvent};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// backends see every published event, so tests publishing to the bus run one at a time
+static BUS_MUTEX: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn lock_bus() -> std::sync::MutexGuard<'static, ()> {
+    BUS_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn pair_added(timestamp: i64) -> SpotEvent {
+    SpotEvent::SpotPairAdded {
+        cid: vec![1],
+        pair_id: vec![2],
+        timestamp,
+    }
+}
+
+fn publish(timestamps: &[i64]) {
+    event::init_event_bus();
+    event::publish_event_queue(EventQueue::from_vec(timestamps.iter().map(|t| pair_added(*t)).collect()));
+}
+
+fn wait_for_drops(receiver: &EventReceiver, dropped: u64) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while receiver.dropped() < dropped {
+        assert!(Instant::now() < deadline, "dispatcher did not drop {dropped} events");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn received_timestamps(receiver: &EventReceiver) -> Vec<i64> {
+    let mut timestamps = Vec::new();
+    while let Ok(sequenced) = receiver.recv_timeout(Duration::from_millis(50)) {
+        if let SpotEvent::SpotPairAdded { timestamp, .. } = sequenced.event {
+            timestamps.push(timestamp);
+        }
+    }
+    timestamps
+}
+
+#[test]
+fn bounded_backend_drop_oldest_keeps_latest_events() {
+    let _guard = lock_bus();
+    let receiver = event::register_backend_bounded(2, OverflowPolicy::DropOldest);
+    publish(&[1, 2, 3]);
+
+    wait_for_drops(&receiver, 1);
+    assert_eq!(received_timestamps(&receiver), vec![2, 3]);
+    assert_eq!(receiver.dropped(), 1);
+}
+
+#[test]
+fn bounded_backend_drop_newest_keeps_earliest_events() {
+    let _guard = lock_bus();
+    let receiver = event::register_backend_bounded(2, OverflowPolicy::DropNewest);
+    let dropped_before = event::dropped_events();
+    publish(&[1, 2, 3]);
+
+    wait_for_drops(&receiver, 1);
+    assert_eq!(received_timestamps(&receiver), vec![1, 2]);
+    assert!(event::dropped_events() > dropped_before);
+}
+
+#[test]
+fn bounded_backend_block_delivers_every_event() {
+    let _guard = lock_bus();
+    let receiver = event::register_backend_bounded(2, OverflowPolicy::Block);
+    publish(&[1, 2, 3]);
+
+    // the dispatcher waits for room instead of dropping the third event
+    let first = receiver.recv_timeout(Duration::from_secs(5)).expect("first event");
+    assert_eq!(first.event, pair_added(1));
+    assert_eq!(received_timestamps(&receiver), vec![2, 3]);
+    assert_eq!(receiver.dropped(), 0);
+}
+
+#[test]
+fn blocked_dispatch_does_not_hold_up_the_backend_list() {
+    let _guard = lock_bus();
+    let blocking = event::register_backend_bounded(1, OverflowPolicy::Block);
+    publish(&[1, 2]);
+    // the dispatcher waits for room in the blocking backend, the list of backends stays usable meanwhile
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        // by then the dispatcher waits for room for the second event
+        std::thread::sleep(Duration::from_millis(50));
+        let late = event::register_backend();
+        let stats = event::backend_stats();
+        tx.send((late.id(), stats)).unwrap();
+    });
+    let (late_id, stats) = rx.recv_timeout(Duration::from_secs(5)).expect("registered while dispatch blocks");
+    assert!(stats.iter().any(|stats| stats.id == late_id));
+
+    assert_eq!(received_timestamps(&blocking), vec![1, 2]);
+}
+
+#[test]
+fn slow_backend_lags_without_holding_up_a_fast_one() {
+    let _guard = lock_bus();
+    let fast = event::register_backend();
+    // never consumed while the events are published
+    let slow = event::register_backend_bounded(4, OverflowPolicy::DropOldest);
+    publish(&(0..100).collect::<Vec<_>>());
+
+    assert_eq!(received_timestamps(&fast), (0..100).collect::<Vec<_>>());
+    wait_for_drops(&slow, 96);
+
+    let stats = event::backend_stats();
+    let fast_stats = stats.iter().find(|stats| stats.id == fast.id()).expect("fast backend stats");
+    let slow_stats = stats.iter().find(|stats| stats.id == slow.id()).expect("slow backend stats");
+    assert_eq!((fast_stats.queued, fast_stats.lagged), (0, 0));
+    assert_eq!((slow_stats.queued, slow_stats.capacity, slow_stats.lagged), (4, 4, 96));
+    assert_eq!(received_timestamps(&slow), vec![96, 97, 98, 99]);
+
+    let slow_id = slow.id();
+    drop(slow);
+    assert!(event::backend_stats().iter().all(|stats| stats.id != slow_id));
+}
+
+#[test]
+fn emitted_events_get_contiguous_sequence_numbers() {
+    // nothing else may emit or publish while the sequence is checked
+    let _bus = lock_bus();
+    let _orderbook = crate::orderbook::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    let _pair = crate::pair::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    event::clear_events();
+
+    let start = event::current_seq();
+    for timestamp in 0..5 {
+        event::emit_event(pair_added(timestamp));
+    }
+    let seqs: Vec<u64> = event::drain_sequenced_events().iter().map(|sequenced| sequenced.seq).collect();
+    assert_eq!(seqs, (start + 1..=start + 5).collect::<Vec<_>>());
+    assert_eq!(event::current_seq(), start + 5);
+
+    for timestamp in 5..10 {
+        event::emit_event(pair_added(timestamp));
+    }
+    assert_eq!(event::drain_events().into_vec(), (5..10).map(pair_added).collect::<Vec<_>>());
+}
+
+#[test]
+fn events_emitted_from_two_threads_drain_in_seq_order() {
+    let _bus = lock_bus();
+    let _orderbook = crate::orderbook::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    let _pair = crate::pair::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    event::clear_events();
+
+    let emitters: Vec<_> = [0, 1000]
+        .into_iter()
+        .map(|offset| {
+            thread::spawn(move || {
+                for timestamp in offset..offset + 200 {
+                    event::emit_event(pair_added(timestamp));
+                }
+            })
+        })
+        .collect();
+    for emitter in emitters {
+        emitter.join().unwrap();
+    }
+
+    let sequenced = event::drain_sequenced_events();
+    assert_eq!(sequenced.len(), 400);
+    assert!(sequenced.windows(2).all(|pair| pair[0].seq + 1 == pair[1].seq));
+    // each thread's own events keep their relative order
+    let first_thread: Vec<i64> = sequenced
+        .iter()
+        .filter_map(|sequenced| match sequenced.event {
+            SpotEvent::SpotPairAdded { timestamp, .. } if timestamp < 1000 => Some(timestamp),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(first_thread, (0..200).collect::<Vec<_>>());
+}
+
+#[test]
+fn events_of_type_keeps_matching_drained_events() {
+    let _bus = lock_bus();
+    let _orderbook = crate::orderbook::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    let _pair = crate::pair::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    event::clear_events();
+
+    for timestamp in 0..4 {
+        event::emit_event(pair_added(timestamp));
+    }
+    let even = event::events_of_type(|e| matches!(e, SpotEvent::SpotPairAdded { timestamp, .. } if timestamp % 2 == 0));
+    assert_eq!(even, vec![pair_added(0), pair_added(2)]);
+    assert!(event::drain_events().is_empty());
+}
+
+#[test]
+fn captured_events_bypass_the_global_queue() {
+    let _bus = lock_bus();
+    let _orderbook = crate::orderbook::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    let _pair = crate::pair::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    event::clear_events();
+
+    let ((), captured) = event::capture_events(|| {
+        event::emit_event(pair_added(0));
+        // an inner capture keeps its events from the outer one
+        let ((), inner) = event::capture_events(|| event::emit_event(pair_added(1)));
+        assert_eq!(inner.len(), 1);
+        event::emit_event(pair_added(2));
+    });
+    let captured: Vec<SpotEvent> = captured.into_iter().map(|sequenced| sequenced.event).collect();
+    assert_eq!(captured, vec![pair_added(0), pair_added(2)]);
+    assert!(event::drain_events().is_empty());
+
+    // requeued events are drained like emitted ones
+    let ((), captured) = event::capture_events(|| event::emit_event(pair_added(3)));
+    event::requeue_events(captured);
+    assert_eq!(event::drain_events().into_vec(), vec![pair_added(3)]);
+}
+
+#[test]
+fn restored_sequence_continues_and_never_moves_back() {
+    let _bus = lock_bus();
+    let _orderbook = crate::orderbook::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    let _pair = crate::pair::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    event::clear_events();
+
+    let restored = event::current_seq() + 100;
+    event::restore_seq(restored);
+    event::restore_seq(restored - 50);
+    event::emit_event(pair_added(0));
+
+    let seqs: Vec<u64> = event::drain_sequenced_events().iter().map(|sequenced| sequenced.seq).collect();
+    assert_eq!(seqs, vec![restored + 1]);
+}
+
+#[test]
+fn published_queue_keeps_the_sequence_numbers_of_emitted_events() {
+    let _bus = lock_bus();
+    let _orderbook = crate::orderbook::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    let _pair = crate::pair::EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    event::clear_events();
+    event::init_event_bus();
+    let receiver = event::register_backend();
+
+    event::emit_event(pair_added(0));
+    event::emit_event(pair_added(1));
+    let emitted: Vec<u64> = {
+        let mut events = event::drain_events();
+        // an event added by hand was never emitted and is sequenced on publish
+        events.push(pair_added(2));
+        let emitted = vec![event::current_seq() - 1, event::current_seq()];
+        event::publish_event_queue(events);
+        emitted
+    };
+
+    let mut seqs = Vec::new();
+    while let Ok(sequenced) = receiver.recv_timeout(Duration::from_millis(50)) {
+        seqs.push(sequenced.seq);
+    }
+    assert_eq!(seqs, vec![emitted[0], emitted[1], emitted[1] + 1]);
+}
+
+#[test]
+fn event_variant_indices_are_stable() {
+    // postcard encodes a variant by its index, logged and published events only decode while it stays put
+    let variants = [
+        "SpotPairClientAccountChanged",
+        "SpotPairAdded",
+        "Transfer",
+        "Lock",
+        "Unlock",
+        "SpotOrderBlockChanged",
+        "SpotLevelRemoved",
+        "SpotBookChecksum",
+        "SpotOrderPlaced",
+        "SpotOrderPartiallyFilled",
+        "SpotOrderFullyFilled",
+        "SpotTakerMatched",
+        "SpotTrade",
+        "SpotOrderCancelled",
+        "SpotOrderDustSwept",
+        "SpotOrderExpired",
+        "SpotOrderIcebergQuantityChanged",
+        "SpotOrderReduced",
+    ];
+    for (index, name) in variants.iter().enumerate() {
+        let mut bytes = vec![index as u8];
+        if *name == "SpotTrade" {
+            let trade_id = ulid::Ulid::nil().to_string();
+            bytes.push(trade_id.len() as u8);
+            bytes.extend(trade_id.bytes());
+        }
+        // the other fields decode from zeros: empty byte strings, None, false and 0
+        bytes.extend([0u8; 64]);
+        let (event, _): (SpotEvent, _) = postcard::take_from_bytes(&bytes).expect(name);
+        assert_eq!(format!("{:?}", event).split(' ').next(), Some(*name));
+    }
+}
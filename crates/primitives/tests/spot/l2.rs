@@ -1,4 +1,4 @@
-use offgrid_primitives::spot::prices::{L2, PriceNode, Level};
+use offgrid_primitives::spot::prices::{L2, L2Error, PriceNode, Level, QtyView};
 use std::collections::BTreeMap;
 
 // price linked list tests
@@ -99,6 +99,37 @@ fn insert_ask_price_in_middle_of_list() {
     assert_eq!(l2.ask_price_tail, Some(100));
 }
 
+// inserting many bid prices keeps the list descending without walking it on every insert
+#[test]
+fn insert_ten_thousand_bid_prices() {
+    let mut l2 = L2::new();
+    for price in (1..=10_000u64).rev() {
+        l2.insert_price(true, price).expect("insert bid price");
+    }
+    // interleave prices between existing levels to hit the middle of the list
+    for price in (1..=10_000u64).step_by(7) {
+        l2.insert_price(true, price).expect("insert existing bid price");
+    }
+    let prices = l2.collect_bid_prices();
+    assert_eq!(prices, (1..=10_000u64).rev().collect::<Vec<_>>());
+    assert_eq!(l2.bid_price_head, Some(10_000));
+    assert_eq!(l2.bid_price_tail, Some(1));
+}
+
+// inserting ask prices out of order still links every node to its neighbours
+#[test]
+fn insert_shuffled_ask_prices() {
+    let mut l2 = L2::new();
+    // 7919 is prime, so this visits every price in 1..=10_000 exactly once in scattered order
+    for i in 0..10_000u64 {
+        l2.insert_price(false, (i * 7919) % 10_000 + 1).expect("insert ask price");
+    }
+    assert_eq!(l2.collect_ask_prices(), (1..=10_000u64).collect::<Vec<_>>());
+    assert_eq!(l2.ask_price_head, Some(1));
+    assert_eq!(l2.ask_price_tail, Some(10_000));
+    assert_eq!(l2.ask_price_nodes.get(&5_000), Some(&PriceNode { prev: Some(4_999), next: Some(5_001) }));
+}
+
 #[test]
 fn clear_bid_head_clears_head_price() {
     let mut l2 = L2::new();
@@ -291,6 +322,18 @@ fn remove_bid_price_nonexistent() {
     assert_eq!(l2.collect_bid_prices(), vec![100, 90]);
 }
 
+#[test]
+fn remove_price_reports_whether_it_was_removed() {
+    let mut l2 = L2::new();
+    l2.insert_price(true, 100).expect("insert bid price 100");
+    l2.insert_price(false, 110).expect("insert ask price 110");
+
+    assert!(l2.remove_price(true, 100).expect("remove bid price 100"));
+    assert!(!l2.remove_price(true, 100).expect("remove removed bid price 100"));
+    assert!(l2.remove_price(false, 110).expect("remove ask price 110"));
+    assert!(!l2.remove_price(false, 120).expect("remove non-existent ask price"));
+}
+
 #[test]
 fn remove_ask_price_head() {
     let mut l2 = L2::new();
@@ -497,6 +540,27 @@ fn get_snapshot_bid_levels() {
     assert_eq!(snapshot[2], vec![98_000_000, 20_000_000, 20_000_000]);
 }
 
+#[test]
+fn get_snapshot_view_returns_the_chosen_quantity() {
+    let mut l2 = L2::new();
+    let scale = 100_000_000;
+
+    // iceberg levels, part of the current quantity is hidden from the public one
+    let levels = vec![
+        Level { price: 101_000_000, pqty: 10_000_000, cqty: 40_000_000 },
+        Level { price: 102_000_000, pqty: 5_000_000, cqty: 25_000_000 },
+    ];
+    let _ = l2.set_ask_levels(scale, levels);
+
+    let public = l2.get_snapshot_view_raw(false, scale, 5, QtyView::Public).expect("public snapshot");
+    assert_eq!(public, vec![[101_000_000, 10_000_000], [102_000_000, 5_000_000]]);
+    let current = l2.get_snapshot_view_raw(false, scale, 5, QtyView::Current).expect("current snapshot");
+    assert_eq!(current, vec![[101_000_000, 40_000_000], [102_000_000, 25_000_000]]);
+
+    let formatted = l2.get_snapshot_view(false, scale, 1, QtyView::Current).expect("formatted snapshot");
+    assert_eq!(formatted, vec![["1.01000000".to_string(), "0.40000000".to_string()]]);
+}
+
 #[test]
 fn get_snapshot_ask_levels() {
     let mut l2 = L2::new();
@@ -742,3 +806,186 @@ fn get_snapshot_formatted_strings_edge_cases() {
     assert_eq!(snapshot[1], vec!["0.00000001".to_string(), "0.00000001".to_string(), "0.00000001".to_string()]);
     assert_eq!(snapshot[2], vec!["10.00000000".to_string(), "5.00000000".to_string(), "5.00000000".to_string()]);
 }
+
+// checksum tests
+fn checksum_book(first_ask_cqty: u64, second_ask_cqty: u64) -> L2 {
+    let mut l2 = L2::new();
+    l2.insert_price(false, 110_000_000).expect("insert ask price 1.1");
+    l2.insert_price(false, 120_000_000).expect("insert ask price 1.2");
+    l2.insert_price(true, 100_000_000).expect("insert bid price 1.0");
+    l2.insert_price(true, 90_000_000).expect("insert bid price 0.9");
+    l2.set_current_ask_level(110_000_000, first_ask_cqty).expect("set ask level 1.1");
+    l2.set_current_ask_level(120_000_000, second_ask_cqty).expect("set ask level 1.2");
+    l2.set_current_bid_level(100_000_000, 300_000_000).expect("set bid level 1.0");
+    l2.set_current_bid_level(90_000_000, 400_000_000).expect("set bid level 0.9");
+    l2
+}
+
+#[test]
+fn checksum_is_stable_for_known_book() {
+    let l2 = checksum_book(200_000_000, 100_000_000);
+    // crc32("1.10000000:2.00000000,1.20000000:1.00000000,1.00000000:3.00000000,0.90000000:4.00000000")
+    assert_eq!(l2.checksum(2), 764341262);
+    assert_eq!(l2.checksum(2), l2.clone().checksum(2));
+    // deeper levels than the book has are ignored
+    assert_eq!(l2.checksum(10), 764341262);
+}
+
+#[test]
+fn checksum_changes_when_levels_are_reordered() {
+    let l2 = checksum_book(200_000_000, 100_000_000);
+    let reordered = checksum_book(100_000_000, 200_000_000);
+    assert_ne!(l2.checksum(2), reordered.checksum(2));
+    // the best ask itself changed, so the top level checksum differs too
+    assert_ne!(l2.checksum(1), reordered.checksum(1));
+    // no levels covered means an empty payload regardless of the book
+    assert_eq!(l2.checksum(0), L2::new().checksum(0));
+}
+
+// aggregated snapshot tests
+fn set_level(l2: &mut L2, is_bid: bool, price: u64, qty: u64) {
+    l2.insert_price(is_bid, price).expect("insert price");
+    if is_bid {
+        l2.set_public_bid_level(price, qty).expect("set public bid level");
+        l2.set_current_bid_level(price, qty * 2).expect("set current bid level");
+    } else {
+        l2.set_public_ask_level(price, qty).expect("set public ask level");
+        l2.set_current_ask_level(price, qty * 2).expect("set current ask level");
+    }
+}
+
+#[test]
+fn get_aggregated_snapshot_groups_bid_levels_rounding_down() {
+    let mut l2 = L2::new();
+    // 1.005, 1.001 fall in the 1.00 bucket, 0.999 and 0.995 in the 0.99 bucket
+    set_level(&mut l2, true, 100_500_000, 10);
+    set_level(&mut l2, true, 100_100_000, 20);
+    set_level(&mut l2, true, 99_900_000, 30);
+    set_level(&mut l2, true, 99_500_000, 40);
+
+    let snapshot = l2.get_aggregated_snapshot(true, 1_000_000, 10).expect("aggregated bid snapshot");
+    assert_eq!(snapshot, vec![[100_000_000, 30, 60], [99_000_000, 70, 140]]);
+
+    // depth limits the number of buckets, not levels
+    let snapshot = l2.get_aggregated_snapshot(true, 1_000_000, 1).expect("aggregated bid snapshot");
+    assert_eq!(snapshot, vec![[100_000_000, 30, 60]]);
+}
+
+#[test]
+fn get_aggregated_snapshot_groups_ask_levels_rounding_up() {
+    let mut l2 = L2::new();
+    // 1.001 and 1.005 fall in the 1.01 bucket, 1.01 stays on its boundary, 1.015 goes to 1.02
+    set_level(&mut l2, false, 100_100_000, 10);
+    set_level(&mut l2, false, 100_500_000, 20);
+    set_level(&mut l2, false, 101_000_000, 30);
+    set_level(&mut l2, false, 101_500_000, 40);
+
+    let snapshot = l2.get_aggregated_snapshot(false, 1_000_000, 10).expect("aggregated ask snapshot");
+    assert_eq!(snapshot, vec![[101_000_000, 60, 120], [102_000_000, 40, 80]]);
+}
+
+#[test]
+fn get_aggregated_snapshot_empty_book_and_zero_width() {
+    let l2 = L2::new();
+    assert_eq!(l2.get_aggregated_snapshot(true, 1_000_000, 10), Ok(vec![]));
+    assert_eq!(l2.get_aggregated_snapshot(false, 1_000_000, 10), Ok(vec![]));
+    assert_eq!(
+        l2.get_aggregated_snapshot(true, 0, 10),
+        Err(L2Error::BucketWidthIsZero)
+    );
+}
+
+// the cached best quantities always match the level maps at the heads
+fn assert_best_cached(l2: &L2) {
+    assert_eq!(l2.best_bid(), l2.bid_price_head.map(|price| (price, l2.public_bid_level(price).unwrap_or(0))));
+    assert_eq!(l2.best_ask(), l2.ask_price_head.map(|price| (price, l2.public_ask_level(price).unwrap_or(0))));
+}
+
+#[test]
+fn best_bid_and_ask_follow_inserts_and_level_changes() {
+    let mut l2 = L2::new();
+    assert_eq!(l2.best_bid(), None);
+    assert_eq!(l2.best_ask(), None);
+
+    l2.insert_price(true, 100).expect("insert bid price 100");
+    l2.set_public_bid_level(100, 5).expect("set bid level");
+    assert_eq!(l2.best_bid(), Some((100, 5)));
+
+    // a better bid takes over the cache, a worse one leaves it alone
+    l2.insert_price(true, 110).expect("insert bid price 110");
+    l2.set_public_bid_level(110, 7).expect("set bid level");
+    l2.insert_price(true, 90).expect("insert bid price 90");
+    l2.set_public_bid_level(90, 9).expect("set bid level");
+    assert_eq!(l2.best_bid(), Some((110, 7)));
+    assert_best_cached(&l2);
+
+    l2.insert_price(false, 120).expect("insert ask price 120");
+    l2.set_public_ask_level(120, 3).expect("set ask level");
+    l2.insert_price(false, 130).expect("insert ask price 130");
+    l2.set_public_ask_level(130, 4).expect("set ask level");
+    l2.set_public_ask_level(120, 1).expect("change ask level");
+    assert_eq!(l2.best_ask(), Some((120, 1)));
+    assert_best_cached(&l2);
+}
+
+#[test]
+fn best_bid_and_ask_are_read_right_after_an_insert() {
+    let mut l2 = L2::new();
+    l2.insert_price(true, 100).expect("insert bid price 100");
+    l2.set_public_bid_level(100, 5).expect("set bid level");
+    // a new head is read with its empty level before any order lands on it
+    l2.insert_price(true, 110).expect("insert bid price 110");
+    assert_eq!(l2.best_bid(), Some((110, 0)));
+    assert_best_cached(&l2);
+
+    l2.insert_price(false, 120).expect("insert ask price 120");
+    l2.set_public_ask_level(120, 3).expect("set ask level");
+    l2.set_current_ask_level(120, 3).expect("set ask level");
+    // inserting a listed price again keeps its levels
+    l2.insert_price(false, 120).expect("insert ask price 120 again");
+    assert_eq!(l2.best_ask(), Some((120, 3)));
+    assert_eq!(l2.current_ask_level(120), Some(3));
+    assert_best_cached(&l2);
+}
+
+#[test]
+fn best_bid_and_ask_follow_removals_and_cleared_heads() {
+    let mut l2 = L2::new();
+    for (price, level) in [(100, 1), (90, 2), (80, 3)] {
+        l2.insert_price(true, price).expect("insert bid price");
+        l2.set_public_bid_level(price, level).expect("set bid level");
+    }
+    for (price, level) in [(110, 4), (120, 5)] {
+        l2.insert_price(false, price).expect("insert ask price");
+        l2.set_public_ask_level(price, level).expect("set ask level");
+    }
+
+    l2.remove_price(true, 100).expect("remove bid head");
+    assert_eq!(l2.best_bid(), Some((90, 2)));
+    l2.clear_head(true).expect("clear bid head");
+    assert_eq!(l2.best_bid(), Some((80, 3)));
+    l2.remove_price(true, 80).expect("remove last bid");
+    assert_eq!(l2.best_bid(), None);
+
+    // removing a price behind the head keeps the cache
+    l2.remove_price(false, 120).expect("remove ask tail");
+    assert_eq!(l2.best_ask(), Some((110, 4)));
+    l2.clear_head(false).expect("clear ask head");
+    assert_eq!(l2.best_ask(), None);
+    assert_best_cached(&l2);
+}
+
+#[test]
+fn price_iterators_walk_from_the_best_price() {
+    let mut l2 = L2::new();
+    for price in [90, 100, 80] {
+        l2.insert_price(true, price).expect("insert bid price");
+    }
+    for price in [120, 110] {
+        l2.insert_price(false, price).expect("insert ask price");
+    }
+
+    assert_eq!(l2.bid_prices().take(2).collect::<Vec<_>>(), vec![100, 90]);
+    assert_eq!(l2.ask_prices().take(5).collect::<Vec<_>>(), vec![110, 120]);
+    assert_eq!(L2::new().bid_prices().next(), None);
+}
@@ -1,5 +1,5 @@
 use offgrid_primitives::spot::orders::{L3, L3Error, Node, Order, OrderId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 fn setup_orders() -> L3 {
     let mut storage = L3::new();
@@ -550,3 +550,80 @@ fn pop_front_removes_last_order_and_clears_price_level() {
     assert_eq!(storage.price_head.get(&100), None);
     assert_eq!(storage.price_tail.get(&100), None);
 }
+
+#[test]
+fn orders_by_owner_tracks_cancellations_and_expirations() {
+    let mut storage = L3::new();
+    let first = storage
+        .create_order("1", "alice", true, 100, 50, 0, 0, 10000, 1000)
+        .expect("create order 1")
+        .id;
+    let second = storage
+        .create_order("2", "alice", false, 200, 30, 0, 0, 20000, 1000)
+        .expect("create order 2")
+        .id;
+    let third = storage
+        .create_order("3", "bob", true, 100, 20, 0, 0, 10000, 1000)
+        .expect("create order 3")
+        .id;
+
+    let alice: HashSet<OrderId> = storage.orders_by_owner(b"alice").iter().map(|o| o.id).collect();
+    assert_eq!(alice, HashSet::from([first, second]));
+
+    storage.delete_order(first).expect("cancel order 1");
+    let alice: HashSet<OrderId> = storage.orders_by_owner(b"alice").iter().map(|o| o.id).collect();
+    assert_eq!(alice, HashSet::from([second]));
+    let bob: HashSet<OrderId> = storage.orders_by_owner(b"bob").iter().map(|o| o.id).collect();
+    assert_eq!(bob, HashSet::from([third]));
+
+    // clearing through decrease_order and expiration both drop the index entry
+    storage.decrease_order(third, 0, 1, true).expect("clear order 3");
+    assert!(storage.orders_by_owner(b"bob").is_empty());
+    storage.remove_dormant_orders(20000);
+    assert!(storage.orders_by_owner(b"alice").is_empty());
+    assert!(storage.owner_orders.is_empty());
+    assert!(storage.orders_by_owner(b"carol").is_empty());
+}
+
+#[test]
+fn iter_orders_yields_every_resting_order() {
+    let mut storage = L3::new();
+    let mut ids = HashSet::new();
+    for (i, price) in [100u64, 100, 101, 102, 102].into_iter().enumerate() {
+        let order = storage
+            .create_order(i.to_string(), "alice", i % 2 == 0, price, 50, 50, 0, 10000, 1000)
+            .expect("create order");
+        ids.insert(order.id);
+    }
+
+    assert_eq!(storage.order_count(), 5);
+    assert!(storage.iter_orders().all(|(id, order)| *id == order.id));
+    let iterated: HashSet<OrderId> = storage.iter_orders().map(|(id, _)| *id).collect();
+    assert_eq!(iterated, ids);
+}
+
+#[test]
+fn insert_order_keeps_time_priority_for_out_of_order_timestamps() {
+    let mut storage = L3::new();
+    let order = |owner: &str, id: OrderId, timestamp: i64| {
+        Order::new(vec![1], id, owner.as_bytes().to_vec(), true, 100, 10, 0, 10, 10, timestamp, 10000, 1000)
+    };
+    // ids minted in the same millisecond tie, so the later `timestamp` yields priority
+    let late = order("alice", OrderId::from_parts(1_000, 2), 20);
+    let early = order("bob", OrderId::from_parts(1_000, 1), 10);
+    // a younger id keeps arrival order at the tail
+    let newest = order("carol", OrderId::from_parts(2_000, 0), 0);
+    assert!(early.is_older_than(&late));
+    assert!(!late.is_older_than(&early));
+    assert!(!late.is_older_than(&late));
+
+    storage.insert_order(late.clone()).expect("insert late order");
+    storage.insert_order(newest.clone()).expect("insert newest order");
+    storage.insert_order(early.clone()).expect("insert early order");
+
+    assert_eq!(storage.get_order_ids(100, 3), vec![early.id, late.id, newest.id]);
+    assert_eq!(storage.price_head.get(&100), Some(&early.id));
+    assert_eq!(storage.price_tail.get(&100), Some(&newest.id));
+    assert_eq!(storage.order_nodes[&late.id].prev, Some(early.id));
+    assert_eq!(storage.order_nodes[&late.id].next, Some(newest.id));
+}
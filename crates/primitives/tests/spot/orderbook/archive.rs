@@ -0,0 +1,75 @@
+use super::EVENT_MUTEX;
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::orderbook::OrderBook;
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::{MockClock, Order, OrderArchive, TerminalState};
+use std::sync::{Arc, Mutex};
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+type Archived = Arc<Mutex<Vec<(Order, TerminalState)>>>;
+
+// orderbook handing its terminated orders to the returned list
+fn archiving_orderbook() -> (OrderBook, Archived) {
+    let archived = Arc::new(Mutex::new(Vec::new()));
+    let sink = archived.clone();
+    let mut orderbook = OrderBook::new();
+    orderbook.set_order_archive(OrderArchive::new(move |_, order, state| sink.lock().unwrap().push((order.clone(), state))));
+    (orderbook, archived)
+}
+
+fn states(archived: &Mutex<Vec<(Order, TerminalState)>>) -> Vec<(OrderId, TerminalState)> {
+    archived.lock().unwrap().iter().map(|(order, state)| (order.id, *state)).collect()
+}
+
+#[test]
+fn cancelled_and_expired_orders_are_archived() {
+    let _guard = lock_events();
+    let clock = MockClock::new(1_000);
+    let (mut orderbook, archived) = archiving_orderbook();
+    orderbook.set_clock(clock.clone());
+
+    let cancelled = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1_000, i64::MAX, 0)
+        .expect("place bid");
+    let expiring = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![11], 101 * SCALE_8, SCALE_8, 0, 1_000, 5_000, 0)
+        .expect("place ask");
+    assert!(states(&archived).is_empty());
+
+    orderbook.cancel_order(vec![1], vec![0], vec![1], vec![2], true, cancelled.id, vec![10]).expect("cancel");
+    orderbook.expire_orders(false, vec![0], vec![1], vec![2], 5_000).expect("expire");
+    let _ = event::drain_events();
+
+    assert_eq!(
+        states(&archived),
+        vec![(cancelled.id, TerminalState::Cancelled), (expiring.id, TerminalState::Expired)]
+    );
+    // a cancelled order keeps the quantity it was cancelled with
+    assert_eq!(archived.lock().unwrap()[0].0.cqty, 100 * SCALE_8);
+}
+
+#[test]
+fn orders_cleared_by_a_match_are_archived_as_filled() {
+    let _guard = lock_events();
+    let (mut orderbook, archived) = archiving_orderbook();
+
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    let taker = orderbook
+        .place_taker(vec![1], vec![0], vec![1], vec![2], vec![11], true, 100 * SCALE_8, 100 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place taker");
+    let (taker, _) = orderbook.execute(taker, maker.clone(), vec![0], vec![1], vec![2], 0).expect("execute");
+    let _ = event::drain_events();
+
+    assert_eq!(taker.cqty, 0);
+    assert_eq!(
+        states(&archived),
+        vec![(maker.id, TerminalState::Filled), (taker.id, TerminalState::Filled)]
+    );
+}
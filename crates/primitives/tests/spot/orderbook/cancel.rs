@@ -0,0 +1,213 @@
+use super::EVENT_MUTEX;
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBook;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn place(orderbook: &mut OrderBook, owner: &[u8], is_bid: bool, price: u64, amnt: u64) {
+    if is_bid {
+        orderbook
+            .place_bid(vec![1], vec![0], vec![1], vec![2], owner.to_vec(), price, amnt, 0, 0, i64::MAX, 0)
+            .expect("place bid");
+    } else {
+        orderbook
+            .place_ask(vec![1], vec![0], vec![1], vec![2], owner.to_vec(), price, amnt, 0, 0, i64::MAX, 0)
+            .expect("place ask");
+    }
+}
+
+#[test]
+fn cancel_all_removes_orders_across_price_levels() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place(&mut orderbook, b"alice", true, 99 * SCALE_8, 10 * SCALE_8);
+    place(&mut orderbook, b"alice", true, 98 * SCALE_8, 10 * SCALE_8);
+    place(&mut orderbook, b"alice", false, 101 * SCALE_8, SCALE_8);
+    place(&mut orderbook, b"bob", true, 98 * SCALE_8, 5 * SCALE_8);
+    let _ = event::drain_events();
+
+    let cancelled = orderbook.cancel_all(vec![9], vec![0], vec![1], vec![2], b"alice".to_vec()).expect("cancel all");
+    assert_eq!(cancelled, 3);
+
+    let events = event::drain_events();
+    let cancel_events = events
+        .iter()
+        .filter(|e| matches!(e, SpotEvent::SpotOrderCancelled { maker_account_id, .. } if maker_account_id == b"alice"))
+        .count();
+    assert_eq!(cancel_events, 3);
+
+    // emptied levels are gone, the shared level keeps bob's quantity
+    assert_eq!(orderbook.l2.collect_bid_prices(), vec![98 * SCALE_8]);
+    assert!(orderbook.l2.collect_ask_prices().is_empty());
+    assert_eq!(orderbook.l2.current_bid_level(98 * SCALE_8), Some(5 * SCALE_8));
+    assert_eq!(orderbook.l2.current_bid_level(99 * SCALE_8), None);
+    assert!(orderbook.l3.orders_by_owner(b"alice").is_empty());
+    assert_eq!(orderbook.l3.orders_by_owner(b"bob").len(), 1);
+}
+
+#[test]
+fn cancel_all_without_orders_is_a_no_op() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place(&mut orderbook, b"bob", false, 101 * SCALE_8, SCALE_8);
+    let before = orderbook.clone();
+    let _ = event::drain_events();
+
+    assert_eq!(orderbook.cancel_all(vec![9], vec![0], vec![1], vec![2], b"alice".to_vec()), Ok(0));
+    assert_eq!(orderbook, before);
+    assert!(event::drain_events().is_empty());
+}
+
+fn locked_and_unlocked(events: &[SpotEvent], order_id: &[u8]) -> (u64, u64) {
+    events.iter().fold((0, 0), |(locked, unlocked), e| match e {
+        SpotEvent::Lock { order_id: id, amnt, .. } if id == order_id => (locked + amnt, unlocked),
+        SpotEvent::Unlock { order_id: id, amnt, .. } if id == order_id => (locked, unlocked + amnt),
+        _ => (locked, unlocked),
+    })
+}
+
+#[test]
+fn placed_then_cancelled_order_unlocks_what_it_locked() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let _ = event::drain_events();
+    let order = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 99 * SCALE_8, 10 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place bid");
+    orderbook
+        .cancel_order(vec![1], vec![0], vec![1], vec![2], true, order.id, b"alice".to_vec())
+        .expect("cancel order");
+
+    let events = event::drain_events();
+    let order_id = order.id.to_bytes().to_vec();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::Lock { asset, is_bid: true, amnt, .. } if *asset == vec![2] && *amnt == 10 * SCALE_8
+    )));
+    assert_eq!(locked_and_unlocked(events.as_vec(), &order_id), (10 * SCALE_8, 10 * SCALE_8));
+}
+
+#[test]
+fn partially_filled_then_cancelled_order_nets_to_filled_amount() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let _ = event::drain_events();
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 100 * SCALE_8, 500 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    let taker = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], b"bob".to_vec(), 100 * SCALE_8, 100 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place bid");
+    orderbook
+        .execute(taker.clone(), maker.clone(), vec![0], vec![1], vec![2], 1)
+        .expect("execute");
+    let maker_remaining = orderbook.l3.get_order(maker.id).expect("maker rests").cqty;
+    assert!(maker_remaining < maker.cqty);
+    orderbook
+        .cancel_order(vec![1], vec![0], vec![1], vec![2], false, maker.id, b"alice".to_vec())
+        .expect("cancel remainder");
+
+    let events = event::drain_events();
+    let (locked, unlocked) = locked_and_unlocked(events.as_vec(), &maker.id.to_bytes());
+    assert_eq!(locked, maker.cqty);
+    assert_eq!(locked - unlocked, maker.cqty - maker_remaining);
+    // the taker was cleared by the match, so its whole lock was spent
+    let (locked, unlocked) = locked_and_unlocked(events.as_vec(), &taker.id.to_bytes());
+    assert_eq!((locked, unlocked), (taker.cqty, 0));
+}
+
+#[test]
+fn fee_reserve_is_locked_and_only_the_charged_fee_is_kept() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let _ = event::drain_events();
+    orderbook.set_default_fee_recipient(Some(b"fees".to_vec()));
+    // the maker ask pays 10 bps in base, the taker bid 20 bps in quote
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 100 * SCALE_8, 5 * SCALE_8, 0, 0, i64::MAX, 10)
+        .expect("place ask");
+    let taker = orderbook
+        .place_taker(vec![1], vec![0], vec![1], vec![2], b"bob".to_vec(), true, 100 * SCALE_8, 100 * SCALE_8, 0, 0, i64::MAX, 20)
+        .expect("place taker");
+    orderbook
+        .execute(taker.clone(), maker.clone(), vec![0], vec![1], vec![2], 1)
+        .expect("execute");
+    orderbook
+        .cancel_order(vec![1], vec![0], vec![1], vec![2], false, maker.id, b"alice".to_vec())
+        .expect("cancel remainder");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::Unlock { asset, is_bid: false, amnt, .. } if *asset == vec![1] && *amnt == 4 * SCALE_8 + 400_000
+    )));
+    // the maker locked 5 base and 0.005 base of fee, 1 base filled and 0.001 base was charged
+    let (locked, unlocked) = locked_and_unlocked(events.as_vec(), &maker.id.to_bytes());
+    assert_eq!(locked, 5 * SCALE_8 + 500_000);
+    assert_eq!(locked - unlocked, SCALE_8 + 100_000);
+    // the taker locked 100 quote and 0.2 quote of fee, all of it spent
+    let (locked, unlocked) = locked_and_unlocked(events.as_vec(), &taker.id.to_bytes());
+    assert_eq!((locked, unlocked), (100 * SCALE_8 + 2000_0000, 0));
+}
+
+#[test]
+fn cancelling_last_order_of_a_level_emits_single_level_removed() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let order = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 99 * SCALE_8, 10 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place bid");
+    place(&mut orderbook, b"bob", true, 98 * SCALE_8, 5 * SCALE_8);
+    let _ = event::drain_events();
+
+    orderbook
+        .cancel_order(vec![1], vec![0], vec![1], vec![2], true, order.id, b"alice".to_vec())
+        .expect("cancel order");
+
+    let removed: Vec<u64> = event::drain_events()
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotLevelRemoved { is_bid: true, price, .. } => Some(*price),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(removed, vec![99 * SCALE_8]);
+}
+
+#[test]
+fn reduce_order_keeps_queue_position_and_updates_levels() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place(&mut orderbook, b"bob", false, 100 * SCALE_8, 200 * SCALE_8);
+    let order = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 100 * SCALE_8, 1000 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    place(&mut orderbook, b"carol", false, 100 * SCALE_8, 300 * SCALE_8);
+    let position = orderbook.queue_position(order.id).expect("order rests");
+    let _ = event::drain_events();
+
+    assert_eq!(
+        orderbook.reduce_order(vec![1], vec![0], vec![1], vec![2], false, order.id, 400 * SCALE_8, b"bob".to_vec()),
+        Err(offgrid_primitives::spot::orderbook::OrderBookError::OrderNotOwnedBySender)
+    );
+    orderbook
+        .reduce_order(vec![1], vec![0], vec![1], vec![2], false, order.id, 400 * SCALE_8, b"alice".to_vec())
+        .expect("reduce order");
+
+    let resting = orderbook.l3.get_order(order.id).expect("order still rests");
+    assert_eq!((resting.cqty, resting.pqty), (600 * SCALE_8, 600 * SCALE_8));
+    assert_eq!(orderbook.queue_position(order.id), Ok(position));
+    assert_eq!(orderbook.l2.current_ask_level(100 * SCALE_8), Some(1100 * SCALE_8));
+    assert_eq!(orderbook.l2.public_ask_level(100 * SCALE_8), Some(1100 * SCALE_8));
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderReduced { amnt, cqty, .. } if *amnt == 400 * SCALE_8 && *cqty == 600 * SCALE_8
+    )));
+    assert_eq!(locked_and_unlocked(events.as_vec(), &order.id.to_bytes()), (0, 400 * SCALE_8));
+}
@@ -0,0 +1,261 @@
+use super::EVENT_MUTEX;
+use offgrid_primitives::spot::orderbook::{Inconsistency, OrderBook, OrderBookError, OrderSummary, TopOfBook};
+use offgrid_primitives::spot::prices::QtyView;
+use offgrid_primitives::spot::orders::{L3Error, OrderId};
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn place_ask(orderbook: &mut OrderBook, price: u64, amnt: u64) {
+    orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], price, amnt, amnt / 4, 0, i64::MAX, 0)
+        .expect("place ask");
+}
+
+fn place_bid(orderbook: &mut OrderBook, price: u64, amnt: u64) {
+    orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], vec![20], price, amnt, 0, 0, i64::MAX, 0)
+        .expect("place bid");
+}
+
+#[test]
+fn quote_fill_walks_ask_levels_for_a_fillable_size() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place_ask(&mut orderbook, 100 * SCALE_8, 2 * SCALE_8);
+    place_ask(&mut orderbook, 110 * SCALE_8, 2 * SCALE_8);
+    place_ask(&mut orderbook, 120 * SCALE_8, 2 * SCALE_8);
+    let before = orderbook.clone();
+
+    // 2 @ 100 + 1 @ 110 = 310 / 3
+    let (avg_price, filled_qty, levels_consumed) = orderbook.quote_fill(true, 3 * SCALE_8).expect("quote fill");
+    assert_eq!(avg_price, 310 * SCALE_8 / 3);
+    assert_eq!(filled_qty, 3 * SCALE_8);
+    assert_eq!(levels_consumed, 2);
+    assert_eq!(orderbook, before);
+}
+
+#[test]
+fn quote_fill_reports_partial_fill_against_a_thin_book() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    // bid levels are held in quote: 200 quote @ 100 = 2 base, 90 quote @ 90 = 1 base
+    place_bid(&mut orderbook, 100 * SCALE_8, 200 * SCALE_8);
+    place_bid(&mut orderbook, 90 * SCALE_8, 90 * SCALE_8);
+
+    let (avg_price, filled_qty, levels_consumed) = orderbook.quote_fill(false, 5 * SCALE_8).expect("quote fill");
+    assert_eq!(avg_price, 290 * SCALE_8 / 3);
+    assert_eq!(filled_qty, 3 * SCALE_8);
+    assert_eq!(levels_consumed, 2);
+}
+
+#[test]
+fn quote_fill_on_empty_book_returns_error() {
+    let _guard = lock_events();
+    let orderbook = OrderBook::new();
+    assert_eq!(orderbook.quote_fill(true, SCALE_8), Err(OrderBookError::NoAskOrdersInOrderbook));
+    assert_eq!(orderbook.quote_fill(false, SCALE_8), Err(OrderBookError::NoBidOrdersInOrderbook));
+}
+
+#[test]
+fn top_of_book_on_two_sided_book() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place_bid(&mut orderbook, 99 * SCALE_8, 4 * SCALE_8);
+    place_bid(&mut orderbook, 98 * SCALE_8, 4 * SCALE_8);
+    place_ask(&mut orderbook, 101 * SCALE_8, 4 * SCALE_8);
+    place_ask(&mut orderbook, 102 * SCALE_8, 4 * SCALE_8);
+
+    assert_eq!(
+        orderbook.top_of_book(),
+        TopOfBook {
+            best_bid: Some(99 * SCALE_8),
+            best_ask: Some(101 * SCALE_8),
+            bid_qty: 4 * SCALE_8,
+            // asks are placed with a quarter hidden as iceberg quantity
+            ask_qty: 3 * SCALE_8,
+            spread: Some(2 * SCALE_8),
+        }
+    );
+}
+
+#[test]
+fn top_of_book_on_one_sided_book() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place_bid(&mut orderbook, 99 * SCALE_8, 4 * SCALE_8);
+
+    let top = orderbook.top_of_book();
+    assert_eq!(top.best_bid, Some(99 * SCALE_8));
+    assert_eq!(top.bid_qty, 4 * SCALE_8);
+    assert_eq!(top.best_ask, None);
+    assert_eq!(top.ask_qty, 0);
+    assert_eq!(top.spread, None);
+}
+
+#[test]
+fn top_of_book_on_empty_book() {
+    let orderbook = OrderBook::new();
+    assert_eq!(orderbook.top_of_book(), TopOfBook::default());
+}
+
+#[test]
+fn is_crossed_detects_bid_at_or_above_ask() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    assert!(!orderbook.is_crossed());
+
+    orderbook.l2.insert_price(true, 101 * SCALE_8).expect("insert bid price");
+    assert!(!orderbook.is_crossed());
+    orderbook.l2.insert_price(false, 100 * SCALE_8).expect("insert ask price");
+    assert!(orderbook.is_crossed());
+}
+
+#[test]
+fn two_sided_book_is_not_crossed() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place_bid(&mut orderbook, 99 * SCALE_8, 4 * SCALE_8);
+    place_ask(&mut orderbook, 101 * SCALE_8, 4 * SCALE_8);
+    assert!(!orderbook.is_crossed());
+}
+
+#[test]
+fn queue_position_counts_the_orders_ahead() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let place = |orderbook: &mut OrderBook, amnt: u64, timestamp: i64| {
+        orderbook
+            .place_bid(vec![1], vec![0], vec![1], vec![2], vec![20], 100 * SCALE_8, amnt, 0, timestamp, i64::MAX, 0)
+            .expect("place bid")
+    };
+    let first = place(&mut orderbook, 3 * SCALE_8, 1);
+    let middle = place(&mut orderbook, 5 * SCALE_8, 2);
+    let last = place(&mut orderbook, 7 * SCALE_8, 3);
+
+    assert_eq!(orderbook.queue_position(first.id).unwrap(), (0, 0));
+    assert_eq!(orderbook.queue_position(middle.id).unwrap(), (1, first.cqty));
+    assert_eq!(orderbook.queue_position(last.id).unwrap(), (2, first.cqty + middle.cqty));
+}
+
+#[test]
+fn queue_position_of_an_unknown_order_is_an_error() {
+    let _guard = lock_events();
+    let orderbook = OrderBook::new();
+    let id = OrderId::new();
+    assert_eq!(orderbook.queue_position(id), Err(OrderBookError::L3(L3Error::OrderDoesNotExist(id))));
+}
+
+#[test]
+fn built_book_passes_verify_invariants() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place_ask(&mut orderbook, 100 * SCALE_8, 2 * SCALE_8);
+    place_ask(&mut orderbook, 100 * SCALE_8, 4 * SCALE_8);
+    place_ask(&mut orderbook, 110 * SCALE_8, 2 * SCALE_8);
+    place_bid(&mut orderbook, 90 * SCALE_8, 90 * SCALE_8);
+
+    assert_eq!(orderbook.verify_invariants(), Ok(()));
+}
+
+#[test]
+fn corrupted_level_is_flagged_by_verify_invariants() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    place_ask(&mut orderbook, 100 * SCALE_8, 2 * SCALE_8);
+    place_bid(&mut orderbook, 90 * SCALE_8, 90 * SCALE_8);
+    orderbook.l2.current_ask_level_map.insert(100 * SCALE_8, 3 * SCALE_8);
+
+    assert_eq!(
+        orderbook.verify_invariants(),
+        Err(vec![Inconsistency {
+            is_bid: false,
+            price: 100 * SCALE_8,
+            view: QtyView::Current,
+            expected: 2 * SCALE_8,
+            actual: 3 * SCALE_8,
+        }])
+    );
+}
+
+#[test]
+fn l3_snapshot_lists_orders_of_a_level_in_queue_order() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let first = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 99 * SCALE_8, 3 * SCALE_8, 0, 1, i64::MAX, 0)
+        .expect("place first bid");
+    let second = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], b"bob".to_vec(), 99 * SCALE_8, 5 * SCALE_8, 0, 2, i64::MAX, 0)
+        .expect("place second bid");
+    place_bid(&mut orderbook, 98 * SCALE_8, SCALE_8);
+
+    let snapshot = orderbook.l3_snapshot(true, 1, false);
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].price, 99 * SCALE_8);
+    let summary = |order: &offgrid_primitives::spot::Order, owner: Option<&[u8]>| OrderSummary {
+        id: order.id,
+        owner: owner.map(<[u8]>::to_vec),
+        cqty: order.cqty,
+        pqty: order.pqty,
+        timestamp: order.timestamp,
+    };
+    assert_eq!(
+        snapshot[0].orders,
+        vec![summary(&first, Some(b"alice")), summary(&second, Some(b"bob"))]
+    );
+
+    let redacted = orderbook.l3_snapshot(true, 10, true);
+    assert_eq!(redacted.iter().map(|level| level.price).collect::<Vec<_>>(), vec![99 * SCALE_8, 98 * SCALE_8]);
+    assert_eq!(redacted[0].orders, vec![summary(&first, None), summary(&second, None)]);
+    assert!(orderbook.l3_snapshot(false, 10, false).is_empty());
+}
+
+#[test]
+fn microprice_skews_toward_the_ask_when_bids_are_heavier() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    // 3 base bid @ 100, 1 base ask @ 104
+    place_bid(&mut orderbook, 100 * SCALE_8, 300 * SCALE_8);
+    orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 104 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+
+    // (100 * 1 + 104 * 3) / 4 = 103, above the mid of 102
+    assert_eq!(orderbook.microprice(), Some(103 * SCALE_8));
+    assert_eq!(orderbook.imbalance(5), 0.5);
+}
+
+#[test]
+fn microprice_skews_toward_the_bid_when_asks_are_heavier() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    // 1 base bid @ 100, 3 base asks @ 104 and 1 more @ 110 beyond the top level
+    place_bid(&mut orderbook, 100 * SCALE_8, 100 * SCALE_8);
+    orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 104 * SCALE_8, 3 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![11], 110 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+
+    // (100 * 3 + 104 * 1) / 4 = 101, below the mid of 102
+    assert_eq!(orderbook.microprice(), Some(101 * SCALE_8));
+    assert_eq!(orderbook.imbalance(1), -0.5);
+    assert_eq!(orderbook.imbalance(2), -0.6);
+}
+
+#[test]
+fn microprice_and_imbalance_of_one_sided_and_empty_books() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    assert_eq!(orderbook.microprice(), None);
+    assert_eq!(orderbook.imbalance(5), 0.0);
+
+    place_bid(&mut orderbook, 100 * SCALE_8, 100 * SCALE_8);
+    assert_eq!(orderbook.microprice(), None);
+    assert_eq!(orderbook.imbalance(5), 1.0);
+}
@@ -0,0 +1,99 @@
+use super::EVENT_MUTEX;
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBook;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+#[test]
+fn sweep_dust_cancels_dust_remainder_and_keeps_normal_order() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let dust = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, 500, 0, 0, i64::MAX, 0)
+        .expect("place dust ask");
+    let normal = orderbook
+        .place_ask(vec![2], vec![0], vec![1], vec![2], vec![11], 101 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place normal ask");
+    let _ = event::drain_events();
+
+    let swept = orderbook
+        .sweep_dust(vec![0], vec![1], vec![2], 7_000)
+        .expect("sweep dust");
+    assert_eq!(swept, 1);
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderDustSwept { order_id, cqty: 500, timestamp: 7_000, .. }
+            if *order_id == dust.id.to_bytes().to_vec()
+    )));
+    // the remainder is released to the owner in the base asset of the ask, nothing is paid from another account
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::Transfer { .. })));
+    let released: u64 = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::Unlock { account_id, asset, amnt, .. } if *account_id == vec![10] && *asset == vec![1] => Some(*amnt),
+            _ => None,
+        })
+        .sum();
+    assert_eq!(released, 500);
+
+    assert!(orderbook.l3.get_order(dust.id).is_err());
+    assert_eq!(orderbook.l3.get_order(normal.id).expect("normal order").cqty, SCALE_8);
+    assert_eq!(orderbook.l2.collect_ask_prices(), vec![101 * SCALE_8]);
+}
+
+#[test]
+fn sweep_dust_without_dust_orders_is_a_no_op() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], vec![10], 99 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place bid");
+    let before = orderbook.clone();
+    let _ = event::drain_events();
+
+    assert_eq!(orderbook.sweep_dust(vec![0], vec![1], vec![2], 0), Ok(0));
+    assert!(event::drain_events().is_empty());
+    assert_eq!(orderbook, before);
+}
+
+#[test]
+fn bounded_sweep_pops_the_smallest_remainder_first() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let mut ids = Vec::new();
+    for (price, amnt) in [(100, 30_000), (101, 10_000), (102, 20_000), (103, SCALE_8)] {
+        let order = orderbook
+            .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], price * SCALE_8, amnt, 0, 0, i64::MAX, 0)
+            .expect("place ask");
+        ids.push(order.id);
+    }
+    // a reduced order is swept by its remaining quantity
+    orderbook
+        .reduce_order(vec![1], vec![0], vec![1], vec![2], false, ids[3], SCALE_8 - 15_000, vec![10])
+        .expect("reduce order");
+    orderbook.set_dust(25_000);
+    let _ = event::drain_events();
+
+    let swept_ids = || -> Vec<Vec<u8>> {
+        event::drain_events()
+            .iter()
+            .filter_map(|e| match e {
+                SpotEvent::SpotOrderDustSwept { order_id, .. } => Some(order_id.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(orderbook.sweep_dust_bounded(vec![0], vec![1], vec![2], 0, 2), Ok(2));
+    assert_eq!(swept_ids(), vec![ids[1].to_bytes().to_vec(), ids[3].to_bytes().to_vec()]);
+    assert_eq!(orderbook.sweep_dust_bounded(vec![0], vec![1], vec![2], 0, 2), Ok(1));
+    assert_eq!(swept_ids(), vec![ids[2].to_bytes().to_vec()]);
+    assert_eq!(orderbook.sweep_dust_bounded(vec![0], vec![1], vec![2], 0, 2), Ok(0));
+    assert_eq!(orderbook.l2.collect_ask_prices(), vec![100 * SCALE_8]);
+}
+
@@ -0,0 +1,180 @@
+use super::EVENT_MUTEX;
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBook;
+use offgrid_primitives::spot::orders::Order;
+use offgrid_primitives::spot::{MockClock, TimeUnit};
+use ulid::Ulid;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+#[test]
+fn pop_front_expires_order_with_mock_clock() {
+    let _guard = lock_events();
+    let clock = MockClock::new(1_000);
+    let mut orderbook = OrderBook::new();
+    orderbook.set_clock(clock.clone());
+
+    let expiring = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, SCALE_8, 0, 1_000, 5_000, 0)
+        .expect("place expiring ask");
+    let resting = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![11], 101 * SCALE_8, SCALE_8, 0, 1_000, 10_000, 0)
+        .expect("place resting ask");
+
+    // nothing has expired yet, the head order is popped as is
+    let mut probe = orderbook.clone();
+    assert_eq!(probe.pop_front(&[0], &[1], &[2], false).expect("pop front").id, expiring.id);
+
+    clock.set(5_000);
+    let _ = event::drain_events();
+    let popped = orderbook.pop_front(&[0], &[1], &[2], false).expect("pop front");
+    assert_eq!(popped.id, resting.id);
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderExpired { order_id, timestamp, .. }
+            if *order_id == expiring.id.to_bytes().to_vec() && *timestamp == 5_000
+    )));
+    assert!(orderbook.l3.get_order(expiring.id).is_err());
+}
+
+#[test]
+fn bounded_expiry_removes_at_most_max_removals_per_call() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    for i in 0..100 {
+        orderbook
+            .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], (100 + i) * SCALE_8, SCALE_8, 0, 0, 1_000, 0)
+            .expect("place expiring ask");
+    }
+    let _ = event::drain_events();
+
+    let mut calls = 0;
+    while orderbook
+        .expire_orders_bounded(false, vec![0], vec![1], vec![2], 1_000, 10)
+        .expect("expire orders")
+        == 10
+    {
+        calls += 1;
+    }
+
+    // ten full batches, then a call finding nothing left
+    assert_eq!(calls, 10);
+    let expired = event::events_of_type(|e| matches!(e, SpotEvent::SpotOrderExpired { .. }));
+    assert_eq!(expired.len(), 100);
+    assert!(orderbook.l3.orders.is_empty());
+    assert_eq!(orderbook.l2.ask_head(), None);
+}
+
+#[test]
+fn bounded_expiry_pops_the_earliest_expiry_first() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let mut ids = Vec::new();
+    for (price, expires_at) in [(100, 3_000), (101, 1_000), (102, 2_000), (103, i64::MAX)] {
+        let order = orderbook
+            .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], price * SCALE_8, SCALE_8, 0, 0, expires_at, 0)
+            .expect("place ask");
+        ids.push(order.id);
+    }
+    // the index is rebuilt when the book is restored
+    let mut orderbook: OrderBook = postcard::from_bytes(&postcard::to_allocvec(&orderbook).unwrap()).unwrap();
+    let _ = event::drain_events();
+
+    let expired_ids = |events: event::EventQueue| -> Vec<Vec<u8>> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                SpotEvent::SpotOrderExpired { order_id, .. } => Some(order_id.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(orderbook.expire_orders_bounded(false, vec![0], vec![1], vec![2], 5_000, 2), Ok(2));
+    assert_eq!(expired_ids(event::drain_events()), vec![ids[1].to_bytes().to_vec(), ids[2].to_bytes().to_vec()]);
+    assert_eq!(orderbook.expire_orders_bounded(false, vec![0], vec![1], vec![2], 5_000, 2), Ok(1));
+    assert_eq!(expired_ids(event::drain_events()), vec![ids[0].to_bytes().to_vec()]);
+    // bids are indexed apart from asks
+    assert_eq!(orderbook.expire_orders_bounded(true, vec![0], vec![1], vec![2], i64::MAX, 10), Ok(0));
+    assert_eq!(orderbook.l2.collect_ask_prices(), vec![103 * SCALE_8]);
+}
+
+#[test]
+fn clock_before_the_epoch_is_clamped_to_zero() {
+    let _guard = lock_events();
+    let clock = MockClock::new(-5_000);
+    let mut orderbook = OrderBook::new();
+    orderbook.set_clock(clock);
+
+    let order = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, SCALE_8, 0, 0, 10_000, 0)
+        .expect("place bid");
+    let _ = event::drain_events();
+    orderbook
+        .cancel_order(vec![1], vec![0], vec![1], vec![2], true, order.id, vec![10])
+        .expect("cancel bid");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::Unlock { timestamp: 0, .. })));
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotLevelRemoved { timestamp: 0, .. })));
+}
+
+#[test]
+fn expiry_in_seconds_is_compared_against_the_clock_in_seconds() {
+    let _guard = lock_events();
+    let clock = MockClock::new(0);
+    let mut orderbook = OrderBook::new();
+    orderbook.set_clock(clock.clone());
+    orderbook.set_time_unit(TimeUnit::Seconds);
+
+    // expires 5 seconds after placement
+    let expiring = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, SCALE_8, 0, 0, 5, 0)
+        .expect("place expiring ask");
+    let resting = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![11], 101 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place resting ask");
+
+    clock.set(4_999);
+    assert_eq!(orderbook.now_in_unit(), 4);
+    let mut probe = orderbook.clone();
+    assert_eq!(probe.pop_front(&[0], &[1], &[2], false).expect("pop front").id, expiring.id);
+
+    clock.set(5_001);
+    assert_eq!(orderbook.now_in_unit(), 5);
+    assert_eq!(orderbook.pop_front(&[0], &[1], &[2], false).expect("pop front").id, resting.id);
+    assert!(orderbook.l3.get_order(expiring.id).is_err());
+    let _ = event::drain_events();
+}
+
+#[test]
+fn partially_filled_order_expires_unlocking_only_its_remainder() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * SCALE_8, 2 * SCALE_8, 0, 0, 1_000, 0)
+        .expect("place expiring ask");
+    // a taker buys half of the ask before it expires
+    let taker = Order::new(vec![1], Ulid::new(), vec![20], true, 100 * SCALE_8, 100 * SCALE_8, 0, 100 * SCALE_8, 100 * SCALE_8, 0, i64::MAX, 0);
+    orderbook.execute(taker, maker.clone(), vec![0], vec![1], vec![2], 0).expect("partial fill");
+    assert_eq!(orderbook.l3.get_order(maker.id).unwrap().cqty, SCALE_8);
+    let _ = event::drain_events();
+
+    assert_eq!(orderbook.expire_orders_bounded(false, vec![0], vec![1], vec![2], 2_000, 10), Ok(1));
+    let events = event::drain_events();
+    // the remainder is released once, no funds move between accounts
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::Transfer { .. })));
+    let unlocked: Vec<(Vec<u8>, Vec<u8>, u64)> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::Unlock { account_id, asset, amnt, .. } => Some((account_id.clone(), asset.clone(), *amnt)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(unlocked, vec![(vec![10], vec![1], SCALE_8)]);
+}
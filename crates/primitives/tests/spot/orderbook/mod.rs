@@ -6,3 +6,9 @@ pub(crate) static EVENT_MUTEX: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 mod snapshot;
 mod order_placement;
 mod trading;
+mod depth;
+mod cancel;
+mod expiry;
+mod dust;
+mod replay;
+mod archive;
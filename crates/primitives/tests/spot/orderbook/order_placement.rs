@@ -1,5 +1,7 @@
 use offgrid_primitives::spot::event::{self, SpotEvent};
 use offgrid_primitives::spot::orderbook::OrderBook;
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::{IdGenerator, SequentialIdGenerator};
 use super::EVENT_MUTEX;
 
 fn lock_events() -> std::sync::MutexGuard<'static, ()> {
@@ -40,6 +42,7 @@ fn assert_order_placed(
             cqty,
             timestamp,
             expires_at,
+            ..
         } if cid == &expected_cid
             && order_id == &expected_order_id
             && pair_id == &expected_pair_id
@@ -879,3 +882,43 @@ fn place_bid_handles_multiple_different_prices_without_expiration() {
 }
 
 // Test that place_ask handles multiple different prices correctly
+
+#[test]
+fn sequential_id_generator_assigns_fixed_order_ids() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    orderbook.set_id_generator(SequentialIdGenerator::new(1));
+
+    let bid = orderbook
+        .place_bid(vec![1], vec![0], vec![1], vec![2], vec![10], 100_000_000, 1_000_000_000, 0, 0, i64::MAX, 0)
+        .expect("place bid");
+    let ask = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![11], 200_000_000, 1_000_000_000, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    let events = event::drain_events();
+
+    assert_eq!(bid.id, OrderId::from(1u128));
+    assert_eq!(ask.id, OrderId::from(2u128));
+    assert_eq!(bid.id.to_string(), "00000000000000000000000001");
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderPlaced { order_id, .. } if *order_id == OrderId::from(2u128).to_bytes().to_vec()
+    )));
+}
+
+#[test]
+fn sequential_id_generator_numbers_takers_too() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let generator = SequentialIdGenerator::new(100);
+    orderbook.set_id_generator(generator.clone());
+
+    let taker = orderbook
+        .place_taker(vec![1], vec![0], vec![1], vec![2], vec![10], true, 100_000_000, 1_000_000_000, 0, 0, i64::MAX, 0)
+        .expect("place taker");
+    let _ = event::drain_events();
+
+    assert_eq!(taker.id, OrderId::from(100u128));
+    // clones share the sequence
+    assert_eq!(generator.clone().next_id(), OrderId::from(101u128));
+}
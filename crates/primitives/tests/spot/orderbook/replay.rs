@@ -0,0 +1,56 @@
+use super::EVENT_MUTEX;
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::orderbook::OrderBook;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[test]
+fn replaying_a_session_reproduces_the_live_book() {
+    let _guard = lock_events();
+    let _ = event::drain_events();
+    let mut live = OrderBook::new();
+    live.set_default_fee_recipient(Some(vec![99]));
+
+    let first_ask = live
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 1_0000_0000, 5 * 1_0000_0000, 1_0000_0000, 0, i64::MAX, 10)
+        .expect("place first ask");
+    let second_ask = live
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![11], 1_0000_0000, 3 * 1_0000_0000, 0, 0, i64::MAX, 10)
+        .expect("place second ask");
+    let far_ask = live
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![12], 1_1000_0000, 2 * 1_0000_0000, 0, 0, i64::MAX, 10)
+        .expect("place far ask");
+    let bid = live
+        .place_bid(vec![2], vec![0], vec![1], vec![2], vec![20], 9000_0000, 4 * 1_0000_0000, 0, 0, i64::MAX, 10)
+        .expect("place bid");
+
+    // a transient taker fills the first ask and part of the second one
+    let taker = live
+        .place_taker(vec![2], vec![0], vec![1], vec![2], vec![21], true, 1_0000_0000, 6 * 1_0000_0000, 0, 0, i64::MAX, 20)
+        .expect("place taker");
+    let (taker, _) = live
+        .execute(taker, first_ask, vec![0], vec![1], vec![2], 5)
+        .expect("fill first ask");
+    let second_ask = live.l3.get_order(second_ask.id).expect("second ask rests").clone();
+    let (taker, _) = live
+        .execute(taker, second_ask, vec![0], vec![1], vec![2], 6)
+        .expect("fill second ask");
+    live.cancel_taker(vec![0], vec![1], vec![2], &taker);
+
+    live.cancel_order(vec![1], vec![0], vec![1], vec![2], false, far_ask.id, vec![12]).expect("cancel far ask");
+    live.set_iceberg_quantity(vec![2], vec![0], true, bid.id, 1_0000_0000).expect("hide part of the bid");
+
+    let events = event::drain_events();
+    // fee recipients are configuration rather than book state, the replayed book is configured the same way
+    let mut replayed = OrderBook::new();
+    replayed.set_default_fee_recipient(Some(vec![99]));
+    for event in events.iter() {
+        replayed.apply_event(event).expect("apply event");
+    }
+
+    assert_eq!(replayed, live);
+    assert_eq!(replayed.l3.orders.len(), 2);
+    assert!(event::drain_events().is_empty(), "replay must not emit events");
+}
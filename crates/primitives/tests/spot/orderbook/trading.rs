@@ -1,7 +1,8 @@
 use super::EVENT_MUTEX;
+use std::collections::HashMap;
 use offgrid_primitives::spot::event::{self, SpotEvent};
 use offgrid_primitives::spot::orderbook::OrderBook;
-use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::orderbook::{self as orderbook_mod, Fill, OrderBookError, PrintPrice, MAX_PRICE_DECIMALS, MIN_MAKER_FEE_BPS};
 use offgrid_primitives::spot::orders::{Order, OrderId};
 use ulid::Ulid;
 
@@ -28,8 +29,8 @@ fn assert_order_filled(
     expected_quote_volume: u64,
     expected_base_fee: u64,
     expected_quote_fee: u64,
-    expected_maker_fee_bps: u16,
-    expected_taker_fee_bps: u16,
+    expected_maker_fee_bps: i32,
+    expected_taker_fee_bps: i32,
     expected_amnt: u64,
     expected_iqty: u64,
     expected_pqty: u64,
@@ -243,43 +244,31 @@ fn assert_order_expired_without_timestamp(
 }
 
 fn matching_amounts(orderbook: &OrderBook, taker: &Order, maker: &Order) -> (u64, u64, u64) {
+    let price = orderbook.match_price(taker.price, maker.price);
     let taker_converted_matching_cqty = if taker.is_bid {
-        taker
-            .cqty
-            .saturating_mul(1_0000_0000)
-            .saturating_div(taker.price)
+        orderbook.quote_to_base(taker.cqty, price)
     } else {
-        taker
-            .cqty
-            .saturating_mul(taker.price)
-            .saturating_div(1_0000_0000)
+        orderbook.base_to_quote(taker.cqty, price)
     };
 
     let matching_amount = if taker_converted_matching_cqty >= maker.cqty {
         orderbook
-            .get_required(maker.clone(), taker.price, maker.cqty)
+            .get_required(taker.clone(), price, maker.cqty)
             .expect("taker amount from maker")
-    } else if taker_converted_matching_cqty < maker.cqty {
-        orderbook
-            .get_required(taker.clone(), maker.price, taker.cqty)
-            .expect("maker amount from taker")
+            .min(taker.cqty)
     } else {
         taker.cqty
     };
 
     let matching_base_amount = if taker.is_bid {
-        matching_amount
-            .saturating_mul(1_0000_0000)
-            .saturating_div(taker.price)
+        orderbook.quote_to_base(matching_amount, price)
     } else {
         matching_amount
     };
     let matching_quote_amount = if taker.is_bid {
         matching_amount
     } else {
-        matching_amount
-            .saturating_mul(taker.price)
-            .saturating_div(1_0000_0000)
+        orderbook.base_to_quote(matching_amount, price)
     };
 
     (matching_amount, matching_base_amount, matching_quote_amount)
@@ -369,7 +358,7 @@ fn expired_order_on_pop_front_moves_to_next_price_level() {
         Some(2000 * 1_0000_0000)
     );
 
-    let popped = orderbook.pop_front(true).expect("pop front");
+    let popped = orderbook.pop_front(&[0], &[1], &[2], true).expect("pop front");
     assert_eq!(popped.id, active_id);
     assert!(orderbook.l3.get_order(expired_id).is_err());
     // After popping the only active order at price 100, the bid head
@@ -385,6 +374,17 @@ fn expired_order_on_pop_front_moves_to_next_price_level() {
     for e in events.iter() {
         println!("event: {:?}", e);
     }
+    // both emptied bid levels are reported exactly once
+    let removed: Vec<u64> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotLevelRemoved { is_bid: true, price, .. } => Some(*price),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(removed.len(), 2);
+    assert!(removed.contains(&(110 * 1_0000_0000)));
+    assert!(removed.contains(&(100 * 1_0000_0000)));
     assert_order_expired_without_timestamp(
         &events,
         vec![1, 2, 3],
@@ -777,14 +777,14 @@ fn expired_order_on_execute_is_removed_and_emits_event() {
     );
 
     let result = orderbook.execute(
-        taker_order,
+        taker_order.clone(),
         bid_order,
         vec![0],
         vec![0],
         vec![0],
         1,
     );
-    assert!(matches!(result, Err(OrderBookError::OrderExpired)));
+    assert_eq!(result, Ok((taker_order, Fill::default())));
     assert!(orderbook.l3.get_order(bid_order_id).is_err());
 
     let events = event::drain_events();
@@ -845,7 +845,7 @@ fn expired_order_on_pop_front_skips_to_next() {
         .expect("place active bid order");
     let active_id = active_order.id;
 
-    let popped = orderbook.pop_front(true).expect("pop front");
+    let popped = orderbook.pop_front(&[0], &[1], &[2], true).expect("pop front");
     assert_eq!(popped.id, active_id);
     assert!(orderbook.l3.get_order(expired_id).is_err());
 
@@ -866,3 +866,527 @@ fn expired_order_on_pop_front_skips_to_next() {
 }
 
 // expired order on pop_front should move to next price level when the best price is emptied
+
+// a negative maker fee is paid out to the maker as a rebate while the taker still pays its fee
+
+#[test]
+fn execute_trade_with_maker_rebate_credits_maker() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+
+    let ask_order = orderbook
+        .place_ask(
+            vec![1, 2, 3],
+            vec![0],
+            vec![1],
+            vec![2],
+            vec![10, 20],
+            100 * 1_0000_0000,
+            500 * 1_0000_0000,
+            0,
+            1234567890,
+            i64::MAX,
+            -5,
+        )
+        .expect("place ask order");
+    let taker_order = orderbook
+        .place_bid(
+            vec![9, 9, 9],
+            vec![0],
+            vec![1],
+            vec![2],
+            vec![7, 7, 7],
+            100 * 1_0000_0000,
+            500 * 1_0000_0000,
+            0,
+            0,
+            i64::MAX,
+            10,
+        )
+        .expect("place taker bid");
+    orderbook
+        .fee_recipients
+        .insert(ask_order.cid.clone(), b"ask_admin".to_vec());
+    orderbook
+        .fee_recipients
+        .insert(taker_order.cid.clone(), b"taker_admin".to_vec());
+
+    let _ = event::drain_events();
+    orderbook
+        .execute(
+            taker_order.clone(),
+            ask_order.clone(),
+            vec![0],
+            vec![1],
+            vec![2],
+            0,
+        )
+        .expect("execute trade");
+
+    let events = event::drain_events();
+    let (_, base_amount, quote_amount) = matching_amounts(&orderbook, &taker_order, &ask_order);
+    let rebate = base_amount * 5 / 10000;
+    assert!(rebate > 0);
+
+    // the maker is not charged, the taker pays 10 bps of quote
+    for e in events.iter() {
+        if let SpotEvent::SpotOrderPartiallyFilled { base_fee, quote_fee, maker_fee_bps, taker_fee_bps, .. }
+        | SpotEvent::SpotOrderFullyFilled { base_fee, quote_fee, maker_fee_bps, taker_fee_bps, .. } = e
+        {
+            assert_eq!(*base_fee, 0);
+            assert_eq!(*quote_fee, quote_amount * 10 / 10000);
+            assert_eq!(*maker_fee_bps, -5);
+            assert_eq!(*taker_fee_bps, 10);
+        }
+    }
+
+    // the rebate flows from the maker client's fee account to the maker
+    let rebates: Vec<_> = events
+        .iter()
+        .filter(|e| matches!(e, SpotEvent::Transfer { from, .. } if from == b"ask_admin"))
+        .collect();
+    assert_eq!(rebates.len(), 1);
+    assert!(matches!(
+        rebates[0],
+        SpotEvent::Transfer { cid, from, to, asset, amnt, timestamp }
+            if cid == &vec![1, 2, 3]
+                && from == &b"ask_admin".to_vec()
+                && to == &vec![10, 20]
+                && asset == &vec![1]
+                && *amnt == rebate
+                && *timestamp == 0
+    ));
+}
+
+// a rebate above the taker fee is capped at it, and a large notional does not overflow the rebate
+
+#[test]
+fn maker_rebate_on_a_large_notional_is_capped_at_the_taker_fee() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    // 10^9 base at 1.00, the rebate product of a whole-amount rebate exceeds a u64
+    let amount = 1_000_000_000 * 1_0000_0000;
+
+    let ask_order = orderbook
+        .place_ask(vec![1, 2, 3], vec![0], vec![1], vec![2], vec![10, 20], 1_0000_0000, amount, 0, 1, i64::MAX, MIN_MAKER_FEE_BPS)
+        .expect("place ask order");
+    let taker_order = orderbook
+        .place_bid(vec![9, 9, 9], vec![0], vec![1], vec![2], vec![7, 7, 7], 1_0000_0000, amount, 0, 0, i64::MAX, 10)
+        .expect("place taker bid");
+    orderbook.fee_recipients.insert(ask_order.cid.clone(), b"ask_admin".to_vec());
+    orderbook.fee_recipients.insert(taker_order.cid.clone(), b"taker_admin".to_vec());
+
+    let _ = event::drain_events();
+    orderbook
+        .execute(taker_order.clone(), ask_order.clone(), vec![0], vec![1], vec![2], 0)
+        .expect("execute trade");
+
+    // the taker pays 10 bps of quote, worth 10 bps of base at 1.00
+    let rebates: Vec<u64> = event::drain_events()
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::Transfer { from, amnt, .. } if from == b"ask_admin" => Some(*amnt),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rebates, vec![amount * 10 / 10000]);
+}
+
+#[test]
+fn maker_fee_rebating_more_than_the_matched_amount_is_rejected() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let result = orderbook.place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 1_0000_0000, 1_0000_0000, 0, 1, i64::MAX, -50_000);
+    assert_eq!(result, Err(OrderBookError::MakerRebateTooLarge(-50_000)));
+    assert!(orderbook.l3.orders.is_empty());
+}
+
+#[test]
+fn execute_trade_with_maker_rebate_requires_fee_recipient() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+
+    let ask_order = orderbook
+        .place_ask(
+            vec![1, 2, 3],
+            vec![0],
+            vec![1],
+            vec![2],
+            vec![10, 20],
+            100 * 1_0000_0000,
+            500 * 1_0000_0000,
+            0,
+            1234567890,
+            i64::MAX,
+            -5,
+        )
+        .expect("place ask order");
+    let taker_order = orderbook
+        .place_bid(
+            vec![9, 9, 9],
+            vec![0],
+            vec![1],
+            vec![2],
+            vec![7, 7, 7],
+            100 * 1_0000_0000,
+            500 * 1_0000_0000,
+            0,
+            0,
+            i64::MAX,
+            10,
+        )
+        .expect("place taker bid");
+
+    let result = orderbook.execute(
+        taker_order.clone(),
+        ask_order.clone(),
+        vec![0],
+        vec![1],
+        vec![2],
+        0,
+    );
+    assert!(matches!(result, Err(OrderBookError::FeeRecipientNotFound)));
+    // nothing was matched
+    assert_eq!(remaining_quantities(&orderbook, ask_order.id).1, ask_order.cqty);
+    assert_eq!(remaining_quantities(&orderbook, taker_order.id).1, taker_order.cqty);
+    let _ = event::drain_events();
+}
+
+#[test]
+fn fully_consumed_level_emits_zeroed_block_and_single_level_removed() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * 1_0000_0000, 1_0000_0000, 0, 0, i64::MAX, 0)
+        .expect("place ask order");
+    let taker = orderbook
+        .place_bid(vec![2], vec![0], vec![1], vec![2], vec![20], 110 * 1_0000_0000, 330 * 1_0000_0000, 0, 0, i64::MAX, 0)
+        .expect("place taker bid");
+    let _ = event::drain_events();
+
+    orderbook
+        .execute(taker, maker, vec![0], vec![1], vec![2], 0)
+        .expect("execute trade");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderBlockChanged { is_bid: false, price, pqty: 0, cqty: 0, .. } if *price == 100 * 1_0000_0000
+    )));
+    let removed: Vec<_> = events
+        .iter()
+        .filter(|e| matches!(e, SpotEvent::SpotLevelRemoved { is_bid: false, .. }))
+        .collect();
+    assert_eq!(removed.len(), 1);
+    assert!(matches!(
+        removed[0],
+        SpotEvent::SpotLevelRemoved { pair_id, price, .. } if pair_id == &vec![0] && *price == 100 * 1_0000_0000
+    ));
+    assert!(!orderbook.l2.price_exists(false, 100 * 1_0000_0000));
+}
+
+#[test]
+fn block_changed_events_carry_the_execute_timestamp() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 100 * 1_0000_0000, 2 * 1_0000_0000, 0, 0, i64::MAX, 0)
+        .expect("place ask order");
+    let taker = orderbook
+        .place_bid(vec![2], vec![0], vec![1], vec![2], vec![20], 110 * 1_0000_0000, 110 * 1_0000_0000, 0, 0, i64::MAX, 0)
+        .expect("place taker bid");
+    let _ = event::drain_events();
+
+    // far from wall-clock time, so a clock read would not match
+    let now = 1_234_567;
+    orderbook
+        .execute(taker, maker, vec![0], vec![1], vec![2], now)
+        .expect("execute trade");
+
+    let timestamps: Vec<i64> = event::drain_events()
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotOrderBlockChanged { timestamp, .. } | SpotEvent::SpotLevelRemoved { timestamp, .. } => Some(*timestamp),
+            _ => None,
+        })
+        .collect();
+    assert!(!timestamps.is_empty());
+    assert!(timestamps.iter().all(|timestamp| *timestamp == now));
+}
+
+#[test]
+fn execute_emits_one_trade_next_to_the_two_fill_events() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    let maker = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 1_0000_0000, 5 * 1_0000_0000, 0, 0, i64::MAX, 0)
+        .expect("place ask order");
+    let taker = orderbook
+        .place_bid(vec![2], vec![0], vec![1], vec![2], vec![20], 1_0000_0000, 2 * 1_0000_0000, 0, 0, i64::MAX, 0)
+        .expect("place taker bid");
+    let _ = event::drain_events();
+
+    orderbook
+        .execute(taker.clone(), maker.clone(), vec![0], vec![1], vec![2], 7)
+        .expect("execute trade");
+
+    let events = event::drain_events();
+    let fills: Vec<(u64, u64)> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotOrderPartiallyFilled { base_volume, quote_volume, .. }
+            | SpotEvent::SpotOrderFullyFilled { base_volume, quote_volume, .. } => Some((*base_volume, *quote_volume)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(fills.len(), 2);
+
+    let trades: Vec<&SpotEvent> = events.iter().filter(|e| matches!(e, SpotEvent::SpotTrade { .. })).collect();
+    assert_eq!(trades.len(), 1);
+    match trades[0] {
+        SpotEvent::SpotTrade {
+            pair_id,
+            price,
+            base_volume,
+            quote_volume,
+            taker_order_id,
+            maker_order_id,
+            taker_is_bid,
+            timestamp,
+            ..
+        } => {
+            assert_eq!(pair_id, &vec![0]);
+            assert_eq!(*price, maker.price);
+            assert_eq!((*base_volume, *quote_volume), fills[0]);
+            assert_eq!((*base_volume, *quote_volume), (2 * 1_0000_0000, 2 * 1_0000_0000));
+            assert_eq!(taker_order_id, &taker.id.to_bytes().to_vec());
+            assert_eq!(maker_order_id, &maker.id.to_bytes().to_vec());
+            assert!(*taker_is_bid);
+            assert_eq!(*timestamp, 7);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn execute_trade_with_maker_rebate_falls_back_to_default_fee_recipient() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    orderbook.set_default_fee_recipient(Some(b"house".to_vec()));
+
+    let ask_order = orderbook
+        .place_ask(
+            vec![1, 2, 3],
+            vec![0],
+            vec![1],
+            vec![2],
+            vec![10, 20],
+            100 * 1_0000_0000,
+            500 * 1_0000_0000,
+            0,
+            1234567890,
+            i64::MAX,
+            -5,
+        )
+        .expect("place ask order");
+    let taker_order = orderbook
+        .place_bid(
+            vec![9, 9, 9],
+            vec![0],
+            vec![1],
+            vec![2],
+            vec![7, 7, 7],
+            100 * 1_0000_0000,
+            500 * 1_0000_0000,
+            0,
+            0,
+            i64::MAX,
+            10,
+        )
+        .expect("place taker bid");
+    let _ = event::drain_events();
+    let fallbacks = orderbook_mod::fee_recipient_fallbacks();
+
+    orderbook
+        .execute(taker_order.clone(), ask_order.clone(), vec![0], vec![1], vec![2], 0)
+        .expect("execute trade without an explicit fee recipient");
+
+    // the maker client has no explicit recipient, so the rebate is paid from the fallback
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::Transfer { from, to, amnt, .. } if from == b"house" && *to == ask_order.owner && *amnt > 0
+    )));
+    assert!(orderbook_mod::fee_recipient_fallbacks() > fallbacks);
+    assert!(remaining_quantities(&orderbook, ask_order.id).1 < ask_order.cqty);
+}
+
+#[test]
+fn print_price_selects_the_price_a_marketable_limit_matches_at() {
+    let _guard = lock_events();
+    for (print_price, expected_price, expected_quote) in [
+        (PrintPrice::Maker, 1_0000_0000, 5 * 1_0000_0000),
+        (PrintPrice::Taker, 110_000_000, 550_000_000),
+    ] {
+        let mut orderbook = OrderBook::new();
+        orderbook.set_print_price(print_price);
+        let ask_order = orderbook
+            .place_ask(vec![1], vec![0], vec![1], vec![2], vec![10], 1_0000_0000, 5 * 1_0000_0000, 0, 0, i64::MAX, 0)
+            .expect("place ask order");
+        let taker_order = orderbook
+            .place_bid(vec![2], vec![0], vec![1], vec![2], vec![20], 110_000_000, 11 * 1_0000_0000, 0, 0, i64::MAX, 0)
+            .expect("place taker bid");
+        let _ = event::drain_events();
+
+        let (updated, fill) = orderbook
+            .execute(taker_order.clone(), ask_order.clone(), vec![0], vec![1], vec![2], 0)
+            .expect("execute trade");
+
+        assert_eq!((fill.base_volume, fill.quote_volume), (5 * 1_0000_0000, expected_quote));
+        assert_eq!(updated.cqty, taker_order.cqty - expected_quote);
+        assert!(orderbook.l3.get_order(ask_order.id).is_err());
+        assert!(event::drain_events().iter().any(|e| matches!(
+            e,
+            SpotEvent::SpotTrade { price, quote_volume, .. } if *price == expected_price && *quote_volume == expected_quote
+        )));
+    }
+}
+
+#[test]
+fn execute_trade_settles_full_legs_and_pays_each_fee_to_the_payer_fee_recipient() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    orderbook.fee_recipients.insert(vec![1], b"fees1".to_vec());
+    orderbook.fee_recipients.insert(vec![2], b"fees2".to_vec());
+    let ask_order = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"seller".to_vec(), 1_0000_0000, 5 * 1_0000_0000, 0, 0, i64::MAX, 20)
+        .expect("place ask order");
+    let taker_order = orderbook
+        .place_bid(vec![2], vec![0], vec![1], vec![2], b"buyer".to_vec(), 1_0000_0000, 3 * 1_0000_0000, 0, 0, i64::MAX, 30)
+        .expect("place taker bid");
+    let _ = event::drain_events();
+
+    let (_, fill) = orderbook
+        .execute(taker_order, ask_order, vec![0], vec![1], vec![2], 7)
+        .expect("execute trade");
+
+    // the maker seller pays its fee in base and the taker buyer in quote
+    let base_fee = fill.base_volume * 20 / 10000;
+    let quote_fee = fill.quote_volume * 30 / 10000;
+    assert_eq!(fill.taker_fee, quote_fee);
+    let transfers: Vec<_> = event::drain_events()
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::Transfer { cid, from, to, asset, amnt, timestamp: 7 } => {
+                Some((cid.clone(), from.clone(), to.clone(), asset.clone(), *amnt))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        transfers,
+        vec![
+            (vec![1], b"seller".to_vec(), b"buyer".to_vec(), vec![1], fill.base_volume),
+            (vec![2], b"buyer".to_vec(), b"seller".to_vec(), vec![2], fill.quote_volume),
+            (vec![1], b"seller".to_vec(), b"fees1".to_vec(), vec![1], base_fee),
+            (vec![2], b"buyer".to_vec(), b"fees2".to_vec(), vec![2], quote_fee),
+        ]
+    );
+}
+
+#[test]
+fn settlement_transfers_balance_per_account_and_asset() {
+    let _guard = lock_events();
+    let mut orderbook = OrderBook::new();
+    orderbook.set_default_fee_recipient(Some(b"fees".to_vec()));
+    // the maker pays 10 bps and the taker 25 bps, across two makers
+    let first_ask = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"alice".to_vec(), 1_0000_0000, 2 * 1_0000_0000, 0, 0, i64::MAX, 10)
+        .expect("place first ask");
+    let second_ask = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"carol".to_vec(), 1_1000_0000, 3 * 1_0000_0000, 0, 0, i64::MAX, 10)
+        .expect("place second ask");
+    let taker = orderbook
+        .place_taker(vec![2], vec![0], vec![1], vec![2], b"bob".to_vec(), true, 1_1000_0000, 4 * 1_0000_0000, 0, 0, i64::MAX, 25)
+        .expect("place taker");
+    let _ = event::drain_events();
+
+    let (taker, first) = orderbook
+        .execute(taker, first_ask.clone(), vec![0], vec![1], vec![2], 1)
+        .expect("fill first ask");
+    let (_, second) = orderbook
+        .execute(taker, second_ask.clone(), vec![0], vec![1], vec![2], 2)
+        .expect("fill second ask");
+
+    let mut balances: HashMap<(Vec<u8>, Vec<u8>), i128> = HashMap::new();
+    for event in event::drain_events().iter() {
+        if let SpotEvent::Transfer { from, to, asset, amnt, .. } = event {
+            *balances.entry((from.clone(), asset.clone())).or_default() -= *amnt as i128;
+            *balances.entry((to.clone(), asset.clone())).or_default() += *amnt as i128;
+        }
+    }
+    let balance = |account: &[u8], asset: u8| balances.get(&(account.to_vec(), vec![asset])).copied().unwrap_or(0);
+    let base = (first.base_volume + second.base_volume) as i128;
+    let quote = (first.quote_volume + second.quote_volume) as i128;
+    let maker_fee = |maker: &Order, fill: &Fill| maker.fee_on(fill.base_volume) as i128;
+    let taker_fee = (first.taker_fee + second.taker_fee) as i128;
+    assert!(taker_fee > 0);
+
+    // every asset moved is conserved, fees end up with the fee recipient
+    for asset in [1, 2] {
+        let total: i128 = balances.iter().filter(|((_, a), _)| *a == vec![asset]).map(|(_, v)| v).sum();
+        assert_eq!(total, 0);
+    }
+    assert_eq!(balance(b"bob", 1), base);
+    assert_eq!(balance(b"bob", 2), -quote - taker_fee);
+    assert_eq!(balance(b"alice", 1), -(first.base_volume as i128) - maker_fee(&first_ask, &first));
+    assert_eq!(balance(b"alice", 2), first.quote_volume as i128);
+    assert_eq!(balance(b"carol", 1), -(second.base_volume as i128) - maker_fee(&second_ask, &second));
+    assert_eq!(balance(b"carol", 2), second.quote_volume as i128);
+    assert_eq!(balance(b"fees", 1), maker_fee(&first_ask, &first) + maker_fee(&second_ask, &second));
+    assert_eq!(balance(b"fees", 2), taker_fee);
+}
+
+#[test]
+fn six_decimal_pair_converts_and_matches_at_its_price_scale() {
+    let _guard = lock_events();
+    const SCALE_6: u64 = 1_000_000;
+    let mut orderbook = OrderBook::new();
+    orderbook.set_price_decimals(6).expect("set price decimals");
+    orderbook.set_dust(0);
+    assert_eq!(orderbook.price_scale(), SCALE_6);
+
+    // 2.5 base at 60000.00 costs 150000 quote, and 150000 quote buys 2.5 base back
+    let price = 60_000 * SCALE_6;
+    let bid = Order { is_bid: true, ..Order::default() };
+    let ask = Order { is_bid: false, ..Order::default() };
+    assert_eq!(orderbook.get_required(bid, price, 2_500_000), Ok(150_000 * SCALE_6));
+    assert_eq!(orderbook.get_required(ask, price, 150_000 * SCALE_6), Ok(2_500_000));
+
+    let ask_order = orderbook
+        .place_ask(vec![1], vec![0], vec![1], vec![2], b"seller".to_vec(), price, 2 * SCALE_6, 0, 0, i64::MAX, 0)
+        .expect("place ask order");
+    let taker_order = orderbook
+        .place_bid(vec![2], vec![0], vec![1], vec![2], b"buyer".to_vec(), price, 30_000 * SCALE_6, 0, 0, i64::MAX, 0)
+        .expect("place taker bid");
+    let _ = event::drain_events();
+
+    let (updated, fill) = orderbook
+        .execute(taker_order, ask_order.clone(), vec![0], vec![1], vec![2], 0)
+        .expect("execute trade");
+
+    // 30000 quote takes half a base unit of the 2 resting
+    assert_eq!((fill.base_volume, fill.quote_volume), (SCALE_6 / 2, 30_000 * SCALE_6));
+    assert_eq!(updated.cqty, 0);
+    assert_eq!(remaining_quantities(&orderbook, ask_order.id).1, 3 * SCALE_6 / 2);
+    let _ = event::drain_events();
+}
+
+#[test]
+fn price_decimals_beyond_a_u64_scale_are_rejected() {
+    let mut orderbook = OrderBook::new();
+    orderbook.set_price_decimals(MAX_PRICE_DECIMALS).expect("set price decimals");
+    assert_eq!(orderbook.price_scale(), 10_000_000_000_000_000_000);
+    assert_eq!(orderbook.set_price_decimals(20), Err(OrderBookError::TooManyPriceDecimals(20)));
+    assert_eq!(orderbook.price_decimals, MAX_PRICE_DECIMALS);
+}
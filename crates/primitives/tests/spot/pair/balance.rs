@@ -0,0 +1,85 @@
+use offgrid_primitives::account::spot::SpotAccount;
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::Pair;
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn pair_with_assets() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    pair
+}
+
+fn account_with(asset_id: Vec<u8>, amount: u64) -> SpotAccount {
+    let mut account = SpotAccount {
+        id: vec![20],
+        ..Default::default()
+    };
+    account.balances.insert(asset_id, amount);
+    account
+}
+
+#[test]
+fn limit_buy_checked_accepts_exactly_sufficient_quote_balance() {
+    let _guard = lock_events();
+    let mut pair = pair_with_assets();
+    let account = account_with(vec![3], 100 * SCALE_8);
+
+    pair.limit_buy_checked(
+        &account, vec![1], None, vec![20], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    )
+    .expect("limit buy with exact balance");
+    assert_eq!(pair.orderbook.l3.orders_by_owner(&[20]).len(), 1);
+}
+
+#[test]
+fn limit_buy_checked_rejects_one_unit_short_quote_balance() {
+    let _guard = lock_events();
+    let mut pair = pair_with_assets();
+    let account = account_with(vec![3], 100 * SCALE_8 - 1);
+
+    let result = pair.limit_buy_checked(
+        &account, vec![1], None, vec![20], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    );
+    assert_eq!(result, Err(OrderBookError::InsufficientBalance));
+    assert!(pair.orderbook.l3.orders_by_owner(&[20]).is_empty());
+}
+
+#[test]
+fn limit_sell_checked_accepts_exactly_sufficient_base_balance() {
+    let _guard = lock_events();
+    let mut pair = pair_with_assets();
+    let account = account_with(vec![2], 2 * SCALE_8);
+
+    pair.limit_sell_checked(
+        &account, vec![1], None, vec![20], 100 * SCALE_8, 2 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    )
+    .expect("limit sell with exact balance");
+    assert_eq!(pair.orderbook.l3.orders_by_owner(&[20]).len(), 1);
+}
+
+#[test]
+fn limit_sell_checked_rejects_one_unit_short_base_balance() {
+    let _guard = lock_events();
+    let mut pair = pair_with_assets();
+    let account = account_with(vec![2], 2 * SCALE_8 - 1);
+
+    let result = pair.limit_sell_checked(
+        &account, vec![1], None, vec![20], 100 * SCALE_8, 2 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    );
+    assert_eq!(result, Err(OrderBookError::InsufficientBalance));
+    assert!(pair.orderbook.l3.orders_by_owner(&[20]).is_empty());
+}
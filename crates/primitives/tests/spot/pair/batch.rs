@@ -0,0 +1,88 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{L3Error, OrderRequest, Pair};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn new_pair() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    pair
+}
+
+fn limit_buy(owner: u8, price: u64, amnt: u64) -> OrderRequest {
+    OrderRequest::LimitBuy {
+        cid: vec![1],
+        existing_order_id: None,
+        owner: vec![owner],
+        price,
+        amnt,
+        iqty: 0,
+        timestamp: 1,
+        expires_at: i64::MAX,
+        maker_fee_bps: 0,
+        taker_fee_bps: 0,
+        time_in_force: TimeInForce::GoodTillCanceled,
+    }
+}
+
+#[test]
+fn batch_results_align_with_requests_and_skip_rejected_orders() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    let _ = event::drain_events();
+
+    let results = pair.submit_batch(vec![
+        limit_buy(10, SCALE_8, 5 * SCALE_8),
+        limit_buy(11, 0, 5 * SCALE_8),
+        OrderRequest::MarketSell {
+            cid: vec![1],
+            existing_order_id: None,
+            owner: vec![20],
+            amnt: 5 * SCALE_8,
+            iqty: 0,
+            timestamp: 2,
+            expires_at: i64::MAX,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+        },
+    ]);
+
+    assert_eq!(results.len(), 3);
+    let resting_id = results[0].as_ref().expect("limit buy").order_id;
+    assert_eq!(results[1], Err(OrderBookError::L3(L3Error::PriceIsZero)));
+    let market_id = results[2].as_ref().expect("market sell").order_id;
+    assert_ne!(resting_id, market_id);
+
+    // the market sell ran after the limit buy and filled against it
+    let events = event::drain_events();
+    let placed = events
+        .iter()
+        .position(|e| matches!(e, SpotEvent::SpotOrderPlaced { order_id, .. } if *order_id == resting_id.to_bytes().to_vec()))
+        .expect("limit buy placed");
+    let filled = events
+        .iter()
+        .position(|e| matches!(
+            e,
+            SpotEvent::SpotOrderFullyFilled { maker_order_id, taker_order_id, .. }
+                if *maker_order_id == resting_id.to_bytes().to_vec() && *taker_order_id == market_id.to_bytes().to_vec()
+        ))
+        .expect("market sell filled the limit buy");
+    assert!(placed < filled);
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderPlaced { maker_account_id, .. } | SpotEvent::Lock { account_id: maker_account_id, .. }
+            if *maker_account_id == vec![11]
+    )));
+    assert!(pair.orderbook.l3.orders.is_empty());
+}
@@ -0,0 +1,98 @@
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{MockClock, OrderRequest, Pair};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn limit_buy(cid: u8, price: u64, timestamp: i64) -> OrderRequest {
+    OrderRequest::LimitBuy {
+        cid: vec![cid],
+        existing_order_id: None,
+        owner: vec![10],
+        price,
+        amnt: price,
+        iqty: 0,
+        timestamp,
+        expires_at: i64::MAX,
+        maker_fee_bps: 0,
+        taker_fee_bps: 0,
+        time_in_force: TimeInForce::GoodTillCanceled,
+    }
+}
+
+#[test]
+fn duplicate_client_order_id_is_rejected() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+
+    let first = pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 1)).expect("first submission");
+    let _ = event::drain_events();
+
+    // a retry of the same order is answered with its outcome without touching the book
+    let retry = pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 1));
+    assert_eq!(retry, Ok(first.clone()));
+    assert!(event::drain_events().into_vec().is_empty());
+
+    // another order under the same id is rejected without touching the book
+    let retry = pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 2));
+    assert!(matches!(retry, Err(OrderBookError::DuplicateClientOrderId)));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook.l3.orders.len(), 1);
+    assert!(pair.orderbook.l3.get_order(first.order_id).is_ok());
+
+    // the id is scoped to its client, another one may use it
+    pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(2, SCALE_8, 3)).expect("other client");
+    let _ = event::drain_events();
+    assert_eq!(pair.orderbook.l3.orders.len(), 2);
+}
+
+#[test]
+fn retry_of_a_filled_order_is_answered_with_its_outcome() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_sell(vec![1], None, vec![20], SCALE_8, SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting ask");
+
+    // the bid fills completely and never rests
+    let filled = pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 2)).expect("filled");
+    assert_eq!(filled.resting_qty, 0);
+    let _ = event::drain_events();
+
+    let retry = pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 2));
+    assert_eq!(retry, Ok(filled));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert!(pair.orderbook.l3.orders.is_empty());
+}
+
+#[test]
+fn client_order_id_is_released_once_the_order_terminates() {
+    let _guard = lock_events();
+    let clock = MockClock::new(1_000);
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.orderbook.set_clock(clock.clone());
+    pair.set_client_order_id_ttl_ms(60_000);
+
+    let placed = pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 1)).expect("placed");
+    // a seller fills the resting bid completely
+    pair.limit_sell(vec![1], None, vec![20], SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("fill");
+    let _ = event::drain_events();
+    assert!(pair.orderbook.l3.get_order(placed.order_id).is_err());
+
+    // the outcome is kept for retries until the ttl passed
+    assert_eq!(pair.prune_client_order_ids(), 0);
+    clock.advance(60_000);
+    assert_eq!(pair.prune_client_order_ids(), 1);
+    pair.submit_with_client_order_id(b"order-1".to_vec(), limit_buy(1, SCALE_8, 3)).expect("id reused");
+    let _ = event::drain_events();
+}
@@ -1,6 +1,8 @@
-use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::orders::L3Error;
 use offgrid_primitives::spot::time_in_force::TimeInForce;
-use offgrid_primitives::spot::Pair;
+use offgrid_primitives::spot::{OrderStatus, Pair};
 
 use super::EVENT_MUTEX;
 
@@ -17,6 +19,7 @@ fn limit_buy_moves_lmp_to_best_ask() {
     pair.pair_id = vec![1];
     pair.base_asset_id = vec![2];
     pair.quote_asset_id = vec![3];
+    pair.orderbook.set_default_fee_recipient(Some(vec![99]));
 
     let _ = event::drain_events();
 
@@ -65,6 +68,7 @@ fn limit_sell_moves_lmp_to_best_bid() {
     pair.pair_id = vec![4];
     pair.base_asset_id = vec![5];
     pair.quote_asset_id = vec![6];
+    pair.orderbook.set_default_fee_recipient(Some(vec![99]));
 
     let _ = event::drain_events();
 
@@ -105,3 +109,516 @@ fn limit_sell_moves_lmp_to_best_bid() {
 
     assert_eq!(pair.orderbook.lmp(), Some(bid_price));
 }
+
+fn pair_with_trading_rules() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![7];
+    pair.base_asset_id = vec![8];
+    pair.quote_asset_id = vec![9];
+    pair.set_tick_size(SCALE_8 / 100);
+    pair.set_min_qty(10 * SCALE_8);
+    pair
+}
+
+#[test]
+fn limit_buy_rejects_price_off_the_tick_grid() {
+    let _guard = lock_events();
+    let mut pair = pair_with_trading_rules();
+    let _ = event::drain_events();
+
+    let result = pair.limit_buy(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8 + 1,
+        10 * SCALE_8,
+        0,
+        1,
+        i64::MAX,
+        5,
+        10,
+        TimeInForce::GoodTillCanceled,
+    );
+
+    assert_eq!(result, Err(OrderBookError::PriceNotOnTick));
+    let events = event::drain_events();
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::SpotOrderPlaced { .. })));
+    assert_eq!(pair.orderbook.l2.bid_head(), None);
+}
+
+#[test]
+fn limit_sell_rejects_amount_below_min_qty() {
+    let _guard = lock_events();
+    let mut pair = pair_with_trading_rules();
+    let _ = event::drain_events();
+
+    let result = pair.limit_sell(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        10 * SCALE_8 - 1,
+        0,
+        1,
+        i64::MAX,
+        5,
+        10,
+        TimeInForce::GoodTillCanceled,
+    );
+
+    assert_eq!(result, Err(OrderBookError::BelowMinQty));
+    let events = event::drain_events();
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::SpotOrderPlaced { .. })));
+    assert_eq!(pair.orderbook.l2.ask_head(), None);
+}
+
+#[test]
+fn limit_orders_reject_iceberg_quantity_above_the_amount_before_any_event() {
+    let _guard = lock_events();
+    let mut pair = pair_with_trading_rules();
+    let _ = event::drain_events();
+
+    let sell = pair.limit_sell(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        10 * SCALE_8,
+        10 * SCALE_8 + 1,
+        1,
+        i64::MAX,
+        5,
+        10,
+        TimeInForce::GoodTillCanceled,
+    );
+    let buy = pair.limit_buy(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        10 * SCALE_8,
+        10 * SCALE_8 + 1,
+        1,
+        i64::MAX,
+        5,
+        10,
+        TimeInForce::GoodTillCanceled,
+    );
+
+    assert_eq!(sell, Err(OrderBookError::IcebergQuantityIsBiggerThanWholeAmount));
+    assert_eq!(buy, Err(OrderBookError::IcebergQuantityIsBiggerThanWholeAmount));
+    // neither a placement nor a lock is emitted
+    assert!(event::drain_events().is_empty());
+    assert_eq!(pair.orderbook.l2.bid_head(), None);
+    assert_eq!(pair.orderbook.l2.ask_head(), None);
+}
+
+#[test]
+fn limit_sell_accepts_price_on_the_tick_grid_and_exact_min_qty() {
+    let _guard = lock_events();
+    let mut pair = pair_with_trading_rules();
+    let _ = event::drain_events();
+
+    pair.limit_sell(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8 + SCALE_8 / 100,
+        10 * SCALE_8,
+        0,
+        1,
+        i64::MAX,
+        5,
+        10,
+        TimeInForce::GoodTillCanceled,
+    )
+    .expect("limit sell on the grid and at the minimum");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotOrderPlaced { .. })));
+}
+
+#[test]
+fn crossing_limit_buy_reports_taker_fill_once_matching_ends() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    let _ = event::drain_events();
+
+    pair.limit_sell(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker ask");
+    // the ask found nothing to match and is reported unfilled
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotTakerMatched { amnt, cqty, .. } if amnt == cqty)));
+
+    pair.limit_buy(
+        vec![1], None, vec![20], SCALE_8 * 11 / 10, 10 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    )
+    .expect("crossing bid");
+    let events = event::drain_events();
+    let reports: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotTakerMatched { is_bid, amnt, cqty, .. } => Some((*is_bid, *amnt, *cqty)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(reports.len(), 1);
+    let (is_bid, amnt, cqty) = reports[0];
+    assert!(is_bid);
+    assert_eq!(amnt, 10 * SCALE_8);
+    assert!(cqty > 0 && cqty < amnt, "taker should be partially filled, cqty {}", cqty);
+}
+
+#[test]
+fn fully_filling_limit_buy_never_rests() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_sell(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker ask");
+    let _ = event::drain_events();
+
+    pair.limit_buy(vec![1], None, vec![20], SCALE_8, 5 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("crossing bid");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotOrderFullyFilled { is_taker_event: true, .. })));
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::SpotOrderPlaced { .. })));
+    assert!(pair.orderbook.l2.collect_bid_prices().is_empty());
+    assert!(pair.orderbook.l2.collect_ask_prices().is_empty());
+    assert!(pair.orderbook.l3.orders.is_empty());
+}
+
+#[test]
+fn partially_filling_limit_buy_rests_only_the_remainder() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_sell(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker ask");
+    let _ = event::drain_events();
+
+    pair.limit_buy(vec![1], None, vec![20], SCALE_8, 10 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("crossing bid");
+
+    let events = event::drain_events();
+    let placed: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotOrderPlaced { is_bid, amnt, cqty, .. } => Some((*is_bid, *amnt, *cqty)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(placed, vec![(true, 10 * SCALE_8, 5 * SCALE_8)]);
+    // the level only carries what is left after matching
+    assert_eq!(pair.orderbook.l2.current_bid_level(SCALE_8), Some(5 * SCALE_8));
+    assert!(pair.orderbook.l2.collect_ask_prices().is_empty());
+    assert_eq!(pair.orderbook.l3.orders_by_owner(&[20])[0].cqty, 5 * SCALE_8);
+}
+
+#[test]
+fn partially_filling_limit_buy_outcome_reports_fill_and_remainder() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    let maker = pair
+        .limit_sell(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker ask");
+    assert_eq!(maker.status, OrderStatus::New);
+    assert_eq!(maker.resting_qty, 5 * SCALE_8);
+    let _ = event::drain_events();
+
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], SCALE_8, 10 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("crossing bid");
+    let _ = event::drain_events();
+
+    assert_eq!(outcome.status, OrderStatus::PartiallyFilled);
+    assert_eq!(outcome.filled_base, 5 * SCALE_8);
+    assert_eq!(outcome.filled_quote, 5 * SCALE_8);
+    assert_eq!(outcome.avg_price, SCALE_8);
+    assert_eq!(outcome.fees, 0);
+    assert_eq!(outcome.resting_qty, 5 * SCALE_8);
+    assert_eq!(pair.orderbook.l3.get_order(outcome.order_id).expect("remainder rests").cqty, 5 * SCALE_8);
+}
+
+#[test]
+fn pair_dust_clears_small_remainders_and_rests_larger_ones() {
+    let _guard = lock_events();
+    for (remainder, rests) in [(4000, false), (6000, true)] {
+        let mut pair = Pair::new();
+        pair.pair_id = vec![7];
+        pair.set_dust(5000);
+        let maker = pair
+            .limit_sell(vec![1], None, vec![10], SCALE_8, SCALE_8 + remainder, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .expect("limit sell");
+        pair.limit_buy(vec![1], None, vec![20], SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .expect("limit buy");
+
+        let resting = pair.orderbook.l3.get_order(maker.order_id).ok().map(|order| order.cqty);
+        assert_eq!(resting, rests.then_some(remainder), "remainder {remainder}");
+        assert_eq!(pair.orderbook.l2.ask_head(), rests.then_some(SCALE_8));
+    }
+    let _ = event::drain_events();
+}
+
+#[test]
+fn amend_beyond_max_reprice_is_rejected() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    // an amend may move the price by at most 5.00
+    pair.set_max_reprice(5 * SCALE_8);
+
+    let resting = pair
+        .limit_buy(vec![1], None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting bid");
+    let _ = event::drain_events();
+    let before = pair.orderbook.clone();
+
+    // a fat-fingered 10x price is rejected before the book is touched
+    let result = pair.limit_buy(vec![1], Some(resting.order_id), vec![10], 1000 * SCALE_8, 100 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(result, Err(OrderBookError::RepriceTooLarge));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook, before);
+    assert_eq!(pair.orderbook.l3.get_order(resting.order_id).unwrap().price, 100 * SCALE_8);
+
+    // moving by the bound is accepted
+    pair.limit_buy(vec![1], Some(resting.order_id), vec![10], 95 * SCALE_8, 100 * SCALE_8, 0, 3, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("amend within the bound");
+    let _ = event::drain_events();
+}
+
+#[test]
+fn sell_amend_beyond_max_reprice_is_rejected() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.set_max_reprice(5 * SCALE_8);
+
+    let resting = pair
+        .limit_sell(vec![1], None, vec![10], 100 * SCALE_8, SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting ask");
+    let _ = event::drain_events();
+    let before = pair.orderbook.clone();
+
+    // a price dropped to a tenth is rejected before the book is touched
+    let result = pair.limit_sell(vec![1], Some(resting.order_id), vec![10], 10 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(result, Err(OrderBookError::RepriceTooLarge));
+    // the same amend by another owner fails on the ownership, before the bound is evaluated
+    let not_owner = pair.limit_sell(vec![1], Some(resting.order_id), vec![11], 10 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(not_owner, Err(OrderBookError::OrderNotOwnedBySender));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook, before);
+
+    // moving up by the bound is accepted
+    pair.limit_sell(vec![1], Some(resting.order_id), vec![10], 105 * SCALE_8, SCALE_8, 0, 3, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("amend within the bound");
+    assert_eq!(pair.orderbook.l2.ask_head(), Some(105 * SCALE_8));
+    let _ = event::drain_events();
+}
+
+#[test]
+fn limit_buy_with_existing_order_id_replaces_the_resting_bid() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+
+    let resting = pair
+        .limit_buy(vec![1], None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting bid");
+    let _ = event::drain_events();
+
+    let replacement = pair
+        .limit_buy(vec![1], Some(resting.order_id), vec![10], 99 * SCALE_8, 99 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("replacement");
+    let events = event::drain_events().into_vec();
+
+    // the old order is cancelled before the replacement is placed
+    let cancelled = events.iter().position(|event| {
+        matches!(event, SpotEvent::SpotOrderCancelled { order_id, .. } if *order_id == resting.order_id.to_bytes().to_vec())
+    });
+    let placed = events.iter().position(|event| matches!(event, SpotEvent::SpotOrderPlaced { .. }));
+    assert!(cancelled.expect("old order cancelled") < placed.expect("replacement placed"));
+
+    assert!(pair.orderbook.l3.get_order(resting.order_id).is_err());
+    assert_eq!(pair.orderbook.l3.get_order(replacement.order_id).unwrap().price, 99 * SCALE_8);
+    assert_eq!(pair.orderbook.l2.bid_head(), Some(99 * SCALE_8));
+    assert_eq!(pair.orderbook.l2.current_bid_level(100 * SCALE_8), None);
+}
+
+#[test]
+fn rejected_replacement_leaves_the_resting_order_in_place() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.set_tick_size(SCALE_8);
+
+    let resting = pair
+        .limit_buy(vec![1], None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting bid");
+    let _ = event::drain_events();
+    let before = pair.orderbook.clone();
+
+    // off the tick size
+    let off_tick = pair.limit_buy(vec![1], Some(resting.order_id), vec![10], 99 * SCALE_8 + 1, 100 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(off_tick, Err(OrderBookError::PriceNotOnTick));
+    // a fill-or-kill replacement with nothing to fill against
+    let unfillable = pair.limit_buy(vec![1], Some(resting.order_id), vec![10], 99 * SCALE_8, 99 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::FillOrKill);
+    assert_eq!(unfillable, Err(OrderBookError::OrderNotFullyFilled));
+    // a sell cannot replace a bid
+    let other_side = pair.limit_sell(vec![1], Some(resting.order_id), vec![10], 101 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(other_side, Err(OrderBookError::ReplacedOrderOnOtherSide));
+    // a zero price is rejected by the taker checks
+    let zero_price = pair.limit_buy(vec![1], Some(resting.order_id), vec![10], 0, 100 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(zero_price, Err(OrderBookError::L3(L3Error::PriceIsZero)));
+
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook, before);
+    assert_eq!(pair.orderbook.l3.get_order(resting.order_id).unwrap().cqty, 100 * SCALE_8);
+}
+
+#[test]
+fn limit_sell_replacing_an_ask_of_another_owner_or_client_is_rejected() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+
+    let resting = pair
+        .limit_sell(vec![1], None, vec![10], 100 * SCALE_8, SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting ask");
+    let _ = event::drain_events();
+    let before = pair.orderbook.clone();
+
+    let other_client = pair.limit_sell(vec![2], Some(resting.order_id), vec![10], 101 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(other_client, Err(OrderBookError::OrderNotSupportedByClientId));
+    let other_owner = pair.limit_sell(vec![1], Some(resting.order_id), vec![11], 101 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(other_owner, Err(OrderBookError::OrderNotOwnedBySender));
+
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook, before);
+    assert_eq!(pair.orderbook.l3.get_order(resting.order_id).unwrap().owner, vec![10]);
+}
+
+#[test]
+fn marketable_limit_buy_prints_and_moves_lmp_at_the_maker_price() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    // 5 base resting at 1.00
+    let maker_price = SCALE_8;
+    pair.orderbook
+        .place_ask(vec![1], vec![1], vec![2], vec![3], vec![10], maker_price, 5 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    let _ = event::drain_events();
+
+    // 11 quote bid at 1.10 takes the whole maker for 5 quote
+    pair.limit_buy(
+        vec![2],
+        None,
+        vec![20],
+        110_000_000,
+        11 * SCALE_8,
+        0,
+        1,
+        i64::MAX,
+        0,
+        0,
+        TimeInForce::GoodTillCanceled,
+    )
+    .expect("limit buy");
+
+    let trades: Vec<(u64, u64, u64)> = event::drain_events()
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotTrade { price, base_volume, quote_volume, .. } => Some((*price, *base_volume, *quote_volume)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(trades, vec![(maker_price, 5 * SCALE_8, 5 * SCALE_8)]);
+    assert_eq!(pair.l1.lmp(), Some(maker_price));
+    // the price improvement stays with the taker, resting at its limit
+    assert_eq!(pair.orderbook.l2.current_bid_level(110_000_000), Some(6 * SCALE_8));
+}
+
+#[test]
+fn fill_or_kill_limit_buy_counts_only_liquidity_within_the_slippage_bound() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    // matching may move 10% above the best ask of 1.00
+    pair.l1.limit_buy_slippage_limit = Some(1000);
+    pair.orderbook
+        .place_ask(vec![1], vec![1], vec![2], vec![3], vec![10], SCALE_8, 2 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    pair.orderbook
+        .place_ask(vec![1], vec![1], vec![2], vec![3], vec![11], 120_000_000, 5 * SCALE_8, 0, 0, i64::MAX, 0)
+        .expect("place ask");
+    let _ = event::drain_events();
+    let before = pair.orderbook.clone();
+
+    // the limit reaches the 1.20 ask, but only the 2 quote at 1.00 are within the bound
+    let outcome = pair.limit_buy(vec![2], None, vec![20], 120_000_000, 5 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::FillOrKill);
+
+    assert_eq!(outcome, Err(OrderBookError::OrderNotFullyFilled));
+    assert!(!event::drain_events().iter().any(|e| matches!(e, SpotEvent::SpotTrade { .. })));
+    assert_eq!(pair.orderbook.l3.orders, before.l3.orders);
+}
+
+#[test]
+fn expired_maker_between_live_makers_is_skipped() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    let gtc = TimeInForce::GoodTillCanceled;
+
+    // 1.00 holds a live, an expired and a live ask, 1.01 a deeper one
+    for (owner, expires_at) in [(10, i64::MAX), (11, 1_000), (12, i64::MAX)] {
+        pair.limit_sell(vec![1], None, vec![owner], SCALE_8, SCALE_8, 0, 1, expires_at, 0, 0, gtc).expect("ask at 1.00");
+    }
+    pair.limit_sell(vec![1], None, vec![13], SCALE_8 * 101 / 100, SCALE_8, 0, 1, i64::MAX, 0, 0, gtc).expect("ask at 1.01");
+    let _ = event::drain_events();
+
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], SCALE_8 * 101 / 100, SCALE_8 * 301 / 100, 0, 2, i64::MAX, 0, 0, gtc)
+        .expect("bid sweeping the asks");
+    assert_eq!(outcome.filled_base, 3 * SCALE_8);
+    assert_eq!(outcome.status, OrderStatus::Filled);
+    assert_eq!(pair.orderbook.l2.ask_head(), None);
+    assert_eq!(pair.orderbook.l2.bid_head(), None);
+
+    let events = event::drain_events();
+    let expired: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotOrderExpired { maker_account_id, .. } => Some(maker_account_id.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(expired, vec![vec![11]]);
+    let trades = events.iter().filter(|e| matches!(e, SpotEvent::SpotTrade { .. })).count();
+    assert_eq!(trades, 3);
+}
+
+#[test]
+fn limit_order_rebating_more_than_its_amount_is_rejected_before_matching() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_buy(vec![1], None, vec![10], SCALE_8, SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("resting bid");
+    let _ = event::drain_events();
+
+    let result = pair.limit_sell(vec![1], None, vec![20], SCALE_8, SCALE_8, 0, 2, i64::MAX, -10_001, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(result, Err(OrderBookError::MakerRebateTooLarge(-10_001)));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook.l2.bid_head(), Some(SCALE_8));
+}
@@ -0,0 +1,58 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::Pair;
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+#[test]
+fn tidy_heads_collapses_empty_heads_to_the_first_real_level() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_buy(vec![1], None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("limit buy");
+    pair.limit_sell(vec![1], None, vec![11], 110 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("limit sell");
+    let _ = event::drain_events();
+
+    // prices linked ahead of the real levels without any order resting on them
+    for price in [101, 102] {
+        pair.orderbook.l2.insert_price(true, price * SCALE_8).unwrap();
+    }
+    pair.orderbook.l2.insert_price(false, 105 * SCALE_8).unwrap();
+    assert_eq!(pair.orderbook.l2.bid_head(), Some(102 * SCALE_8));
+    assert_eq!(pair.orderbook.l2.ask_head(), Some(105 * SCALE_8));
+
+    assert_eq!(pair.tidy_heads(), 3);
+    assert_eq!(pair.orderbook.l2.bid_head(), Some(100 * SCALE_8));
+    assert_eq!(pair.orderbook.l2.ask_head(), Some(110 * SCALE_8));
+    // every cleared price is reported, so a replayed book drops it as well
+    let mut removed: Vec<(bool, u64)> = event::drain_events()
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotLevelRemoved { pair_id, is_bid, price, .. } if *pair_id == vec![1] => Some((*is_bid, *price)),
+            _ => None,
+        })
+        .collect();
+    removed.sort();
+    assert_eq!(removed, vec![(false, 105 * SCALE_8), (true, 101 * SCALE_8), (true, 102 * SCALE_8)]);
+    // nothing left to clear
+    assert_eq!(pair.tidy_heads(), 0);
+}
+
+#[test]
+fn tidy_heads_empties_a_side_without_orders() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.orderbook.l2.insert_price(false, 105 * SCALE_8).unwrap();
+
+    assert_eq!(pair.tidy_heads(), 1);
+    assert_eq!(pair.orderbook.l2.ask_head(), None);
+    assert_eq!(pair.orderbook.l2.bid_head(), None);
+}
@@ -0,0 +1,108 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::pair::capped_orders;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{OrderStatus, Pair};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+// 0.01 base each
+const TINY: u64 = 1_000_000;
+
+// `count` tiny asks resting at 1.00 in time priority
+fn pair_with_tiny_asks(count: usize) -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    for timestamp in 0..count {
+        pair.limit_sell(vec![1], None, vec![10], SCALE_8, TINY, 0, timestamp as i64, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .expect("maker");
+    }
+    let _ = event::drain_events();
+    pair
+}
+
+fn maker_fills(events: &[SpotEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, SpotEvent::SpotOrderFullyFilled { is_taker_event: false, .. }))
+        .count()
+}
+
+#[test]
+fn matching_stops_after_max_makers_per_order() {
+    let _guard = lock_events();
+    let mut pair = pair_with_tiny_asks(200);
+    pair.set_max_makers_per_order(50);
+    let capped_before = capped_orders();
+
+    // 2.00 quote would take all 200 makers
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], SCALE_8, 2 * SCALE_8, 0, 1000, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel)
+        .expect("capped buy");
+    let events = event::drain_events().into_vec();
+
+    assert_eq!(maker_fills(&events), 50);
+    assert_eq!(outcome.filled_base, 50 * TINY);
+    assert_eq!(outcome.status, OrderStatus::Cancelled);
+    assert_eq!(pair.orderbook.l3.order_count(), 150);
+    assert_eq!(pair.orderbook.l2.current_ask_level(SCALE_8), Some(150 * TINY));
+    assert!(capped_orders() > capped_before);
+}
+
+#[test]
+fn capped_remainder_crossing_the_book_is_cancelled_instead_of_resting() {
+    let _guard = lock_events();
+    let mut pair = pair_with_tiny_asks(20);
+    pair.set_max_makers_per_order(5);
+
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], SCALE_8, SCALE_8, 0, 1000, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("capped buy");
+    let events = event::drain_events().into_vec();
+
+    assert_eq!(maker_fills(&events), 5);
+    assert_eq!(outcome.resting_qty, 0);
+    assert!(pair.orderbook.l2.collect_bid_prices().is_empty());
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotOrderCancelled { maker_account_id, .. } if *maker_account_id == vec![20])));
+}
+
+#[test]
+fn fill_or_kill_beyond_the_maker_cap_is_rejected_before_matching() {
+    let _guard = lock_events();
+    let mut pair = pair_with_tiny_asks(20);
+    pair.set_max_makers_per_order(5);
+
+    // 10 makers are needed, only 5 may be matched
+    let rejected = pair.limit_buy(vec![1], None, vec![20], SCALE_8, 10 * TINY, 0, 1000, i64::MAX, 0, 0, TimeInForce::FillOrKill);
+    let events = event::drain_events().into_vec();
+
+    assert_eq!(rejected, Err(OrderBookError::OrderNotFullyFilled));
+    assert_eq!(maker_fills(&events), 0);
+    assert_eq!(pair.orderbook.l3.order_count(), 20);
+
+    // within the cap the order fills
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], SCALE_8, 5 * TINY, 0, 1001, i64::MAX, 0, 0, TimeInForce::FillOrKill)
+        .expect("fill within the cap");
+    assert_eq!(outcome.status, OrderStatus::Filled);
+    let _ = event::drain_events();
+}
+
+#[test]
+fn zero_max_makers_per_order_does_not_cap_matching() {
+    let _guard = lock_events();
+    let mut pair = pair_with_tiny_asks(200);
+
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], SCALE_8, 2 * SCALE_8, 0, 1000, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel)
+        .expect("uncapped buy");
+    let events = event::drain_events().into_vec();
+
+    assert_eq!(maker_fills(&events), 200);
+    assert_eq!(outcome.status, OrderStatus::Filled);
+}
@@ -0,0 +1,114 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{OrderStatus, Pair};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn new_pair() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    pair.add_client(vec![1], vec![90], vec![91]);
+    pair
+}
+
+#[test]
+fn market_sell_fill_or_kill_walks_past_the_top_level() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    for price in [100, 99] {
+        pair.limit_buy(vec![1], None, vec![10], price * SCALE_8, price * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .expect("limit buy");
+    }
+    let _ = event::drain_events();
+
+    // the best bid alone takes 1 of the 1.5 base, the second level takes the rest
+    pair.market_sell(vec![1], None, vec![20], SCALE_8 + SCALE_8 / 2, 0, 0, i64::MAX, 0, 0, TimeInForce::FillOrKill)
+        .expect("market sell fok");
+
+    let events = event::drain_events();
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::SpotOrderCancelled { .. })));
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotOrderFullyFilled { is_taker_event: true, .. })));
+}
+
+#[test]
+fn market_sell_remainder_is_cancelled_instead_of_resting() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    pair.limit_buy(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("limit buy");
+    let _ = event::drain_events();
+
+    // twice what the single bid can take, under a time in force that would otherwise rest it
+    pair.market_sell(vec![1], None, vec![20], 10 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("market sell");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderCancelled { maker_account_id, .. } if *maker_account_id == vec![20]
+    )));
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderPlaced { maker_account_id, .. } if *maker_account_id == vec![20]
+    )));
+    assert!(pair.orderbook.l3.orders_by_owner(&[20]).is_empty());
+    assert!(pair.orderbook.l2.collect_ask_prices().is_empty());
+}
+
+#[test]
+fn market_buy_remainder_is_cancelled_instead_of_resting() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    pair.limit_sell(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("limit sell");
+    let _ = event::drain_events();
+
+    // twice what the single ask can fill
+    pair.market_buy(vec![1], None, vec![20], 10 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("market buy");
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotOrderFullyFilled { is_taker_event: false, .. })));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderCancelled { maker_account_id, cqty, .. } if *maker_account_id == vec![20] && *cqty == 5 * SCALE_8
+    )));
+    // the cancelled remainder is released back to the owner
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::Unlock { account_id, amnt, .. } if *account_id == vec![20] && *amnt == 5 * SCALE_8
+    )));
+    assert!(pair.orderbook.l3.orders_by_owner(&[20]).is_empty());
+    assert!(pair.orderbook.l2.collect_bid_prices().is_empty());
+    assert!(pair.orderbook.l2.collect_ask_prices().is_empty());
+}
+
+#[test]
+fn market_buy_outcome_reports_a_full_fill() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    pair.limit_sell(vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("limit sell");
+    let _ = event::drain_events();
+
+    // 10 bps taker fee on the 5 quote spent
+    let outcome = pair
+        .market_buy(vec![1], None, vec![20], 5 * SCALE_8, 0, 0, i64::MAX, 0, 10, TimeInForce::ImmediateOrCancel)
+        .expect("market buy");
+    let _ = event::drain_events();
+
+    assert_eq!(outcome.status, OrderStatus::Filled);
+    assert_eq!(outcome.filled_base, 5 * SCALE_8);
+    assert_eq!(outcome.filled_quote, 5 * SCALE_8);
+    assert_eq!(outcome.avg_price, SCALE_8);
+    assert_eq!(outcome.fees, 5 * SCALE_8 / 1000);
+    assert_eq!(outcome.resting_qty, 0);
+}
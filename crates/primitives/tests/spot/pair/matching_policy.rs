@@ -0,0 +1,66 @@
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::orderbook::MatchingPolicy;
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::Pair;
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+/// Places one ask per amount at 1.0 in time priority and returns their ids
+fn pair_with_asks(policy: MatchingPolicy, amounts: &[u64]) -> (Pair, Vec<OrderId>) {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.orderbook.set_matching_policy(policy);
+    let ids = amounts
+        .iter()
+        .enumerate()
+        .map(|(i, amnt)| {
+            pair.limit_sell(vec![1], None, vec![10 + i as u8], SCALE_8, *amnt, 0, i as i64, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+                .expect("maker ask")
+                .order_id
+        })
+        .collect();
+    (pair, ids)
+}
+
+/// Amount filled from each maker by an IOC buy of `amnt`
+fn allocation(policy: MatchingPolicy, amounts: &[u64], amnt: u64) -> Vec<u64> {
+    let (mut pair, ids) = pair_with_asks(policy, amounts);
+    pair.limit_buy(vec![1], None, vec![20], SCALE_8, amnt, 0, 100, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel)
+        .expect("taker bid");
+    let _ = event::drain_events();
+    ids.iter()
+        .zip(amounts)
+        .map(|(id, amnt)| amnt - pair.orderbook.l3.get_order(*id).map(|maker| maker.cqty).unwrap_or(0))
+        .collect()
+}
+
+#[test]
+fn taker_covering_the_level_fills_every_maker_under_both_policies() {
+    let _guard = lock_events();
+    let makers = [200 * SCALE_8, 100 * SCALE_8];
+    assert_eq!(allocation(MatchingPolicy::PriceTime, &makers, 300 * SCALE_8), vec![200 * SCALE_8, 100 * SCALE_8]);
+    assert_eq!(allocation(MatchingPolicy::ProRata, &makers, 300 * SCALE_8), vec![200 * SCALE_8, 100 * SCALE_8]);
+}
+
+#[test]
+fn pro_rata_splits_a_partial_taker_by_maker_size() {
+    let _guard = lock_events();
+    let makers = [200 * SCALE_8, 100 * SCALE_8];
+    // price-time fills the first maker before the second sees anything
+    assert_eq!(allocation(MatchingPolicy::PriceTime, &makers, 150 * SCALE_8), vec![150 * SCALE_8, 0]);
+    assert_eq!(allocation(MatchingPolicy::ProRata, &makers, 150 * SCALE_8), vec![100 * SCALE_8, 50 * SCALE_8]);
+}
+
+#[test]
+fn pro_rata_rounding_leftover_goes_to_makers_in_time_priority() {
+    let _guard = lock_events();
+    // every share rounds down to 0, the 2 leftover units go to the two oldest makers
+    assert_eq!(allocation(MatchingPolicy::ProRata, &[1, 1, 1], 2), vec![1, 1, 0]);
+}
@@ -0,0 +1,102 @@
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::Pair;
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// three base units asked at 1.00 and one more at 2.00
+fn pair_with_asks() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_sell(vec![1], None, vec![10], SCALE_8, 3 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker");
+    pair.limit_sell(vec![1], None, vec![10], 2 * SCALE_8, SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker");
+    let _ = event::drain_events();
+    pair
+}
+
+#[test]
+fn limit_buy_with_reachable_min_fill_executes() {
+    let _guard = lock_events();
+    let mut pair = pair_with_asks();
+
+    let outcome = pair
+        .limit_buy_min_fill(2 * SCALE_8, vec![1], None, vec![20], SCALE_8, 5 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("min fill reachable");
+    let _ = event::drain_events();
+
+    // the level at 1.00 is taken and the rest of the order rests as usual
+    assert_eq!(outcome.filled_quote, 3 * SCALE_8);
+    assert_eq!(outcome.resting_qty, 2 * SCALE_8);
+    assert_eq!(pair.orderbook.l2.collect_ask_prices(), vec![2 * SCALE_8]);
+    assert_eq!(pair.orderbook.l2.bid_head(), Some(SCALE_8));
+}
+
+#[test]
+fn limit_buy_with_unreachable_min_fill_is_rejected_before_matching() {
+    let _guard = lock_events();
+    let mut pair = pair_with_asks();
+
+    // only 3.00 of quote is fillable at or below 1.00
+    let result = pair.limit_buy_min_fill(4 * SCALE_8, vec![1], None, vec![20], SCALE_8, 5 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+
+    assert!(matches!(result, Err(OrderBookError::MinFillNotReachable)));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook.l2.collect_ask_prices(), vec![SCALE_8, 2 * SCALE_8]);
+    assert_eq!(pair.orderbook.l2.current_ask_level(SCALE_8), Some(3 * SCALE_8));
+    assert!(pair.orderbook.l2.collect_bid_prices().is_empty());
+}
+
+#[test]
+fn limit_buy_min_fill_stops_at_the_slippage_bound() {
+    let _guard = lock_events();
+    let mut pair = pair_with_asks();
+    // the level at 2.00 is within the limit price but 100% away from the best ask
+    pair.l1.set_limit_buy_slippage_limit(Some(5000));
+
+    let result = pair.limit_buy_min_fill(4 * SCALE_8, vec![1], None, vec![20], 3 * SCALE_8, 5 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+
+    assert!(matches!(result, Err(OrderBookError::MinFillNotReachable)));
+    assert_eq!(pair.orderbook.l2.collect_ask_prices(), vec![SCALE_8, 2 * SCALE_8]);
+}
+
+#[test]
+fn market_buy_min_fill_counts_every_level() {
+    let _guard = lock_events();
+    let mut pair = pair_with_asks();
+
+    // 3.00 at 1.00 and 2.00 at 2.00 make 5.00 of quote fillable
+    let result = pair.market_buy_min_fill(6 * SCALE_8, vec![1], None, vec![20], 6 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel);
+    assert!(matches!(result, Err(OrderBookError::MinFillNotReachable)));
+    assert!(event::drain_events().into_vec().is_empty());
+
+    let outcome = pair
+        .market_buy_min_fill(5 * SCALE_8, vec![1], None, vec![20], 6 * SCALE_8, 0, 11, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel)
+        .expect("min fill reachable");
+    let _ = event::drain_events();
+    assert!(outcome.filled_quote > 0);
+}
+
+#[test]
+fn zero_min_fill_accepts_an_empty_book() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+
+    let outcome = pair
+        .limit_sell_min_fill(0, vec![1], None, vec![20], SCALE_8, SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("no minimum");
+    let _ = event::drain_events();
+    assert_eq!(outcome.resting_qty, SCALE_8);
+
+    let result = pair.market_sell_min_fill(1, vec![1], None, vec![20], SCALE_8, 0, 11, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel);
+    assert!(matches!(result, Err(OrderBookError::MinFillNotReachable)));
+}
@@ -4,5 +4,15 @@ use std::sync::Mutex;
 pub mod limit_order;
 pub mod market_order;
 pub mod snapshot;
+pub mod time_in_force;
+pub mod balance;
+pub mod batch;
+pub mod matching_policy;
+pub mod slippage;
+pub mod min_fill;
+pub mod client_order_id;
+pub mod maintenance;
+pub mod maker_cap;
+pub mod price_levels;
 
 pub(crate) static EVENT_MUTEX: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
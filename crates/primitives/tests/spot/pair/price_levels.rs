@@ -0,0 +1,108 @@
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{OrderStatus, Pair};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// three bids of 1.00 quote at 1.00, 2.00, 3.00 with the bid side at its cap
+fn pair_at_bid_cap() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.set_max_price_levels(3);
+    for price in 1..=3 {
+        pair.limit_buy(vec![1], None, vec![10], price * SCALE_8, SCALE_8, 0, price as i64, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .expect("bid within the cap");
+    }
+    let _ = event::drain_events();
+    pair
+}
+
+#[test]
+fn bid_at_a_new_price_beyond_the_cap_is_rejected() {
+    let _guard = lock_events();
+    let mut pair = pair_at_bid_cap();
+
+    let rejected = pair.limit_buy(vec![1], None, vec![10], 4 * SCALE_8, SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(rejected, Err(OrderBookError::TooManyPriceLevels));
+    assert!(event::drain_events().into_vec().is_empty());
+    assert_eq!(pair.orderbook.l2.level_count(true), 3);
+
+    // an existing price takes the order
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![10], 2 * SCALE_8, SCALE_8, 0, 11, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("bid at an existing price");
+    assert_eq!(outcome.status, OrderStatus::New);
+    assert_eq!(pair.orderbook.l2.current_bid_level(2 * SCALE_8), Some(2 * SCALE_8));
+
+    // the ask side has a cap of its own
+    pair.limit_sell(vec![1], None, vec![20], 5 * SCALE_8, SCALE_8, 0, 12, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("ask within the cap");
+    let _ = event::drain_events();
+}
+
+#[test]
+fn orders_that_do_not_rest_ignore_the_cap() {
+    let _guard = lock_events();
+    let mut pair = pair_at_bid_cap();
+    pair.limit_sell(vec![1], None, vec![20], 5 * SCALE_8, SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("ask");
+
+    // an immediate or cancel bid at a new price never rests
+    let ioc = pair
+        .limit_buy(vec![1], None, vec![10], 4 * SCALE_8, SCALE_8, 0, 11, i64::MAX, 0, 0, TimeInForce::ImmediateOrCancel)
+        .expect("ioc bid");
+    assert_eq!(ioc.status, OrderStatus::Cancelled);
+
+    // a bid filled completely by the ask does not rest at 5.00 either
+    let filled = pair
+        .limit_buy(vec![1], None, vec![10], 5 * SCALE_8, 5 * SCALE_8, 0, 12, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("crossing bid");
+    assert_eq!(filled.status, OrderStatus::Filled);
+    assert_eq!(pair.orderbook.l2.level_count(true), 3);
+    let _ = event::drain_events();
+}
+
+#[test]
+fn replacing_the_last_order_of_a_level_frees_it() {
+    let _guard = lock_events();
+    let mut pair = pair_at_bid_cap();
+    let last_at_one = pair.orderbook.l3.head(SCALE_8).expect("bid at 1.00");
+
+    let outcome = pair
+        .limit_buy(vec![1], Some(last_at_one), vec![10], 4 * SCALE_8, SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("replacement at a new price");
+    assert_eq!(outcome.status, OrderStatus::New);
+    assert_eq!(pair.orderbook.l2.collect_bid_prices(), vec![4 * SCALE_8, 3 * SCALE_8, 2 * SCALE_8]);
+
+    // a level keeping another order is not freed by the replacement
+    pair.limit_buy(vec![1], None, vec![10], 2 * SCALE_8, SCALE_8, 0, 11, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("bid at an existing price");
+    let first_at_two = pair.orderbook.l3.head(2 * SCALE_8).expect("bid at 2.00");
+    let rejected = pair.limit_buy(vec![1], Some(first_at_two), vec![10], 5 * SCALE_8, SCALE_8, 0, 12, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled);
+    assert_eq!(rejected, Err(OrderBookError::TooManyPriceLevels));
+    assert!(pair.orderbook.l3.get_order(first_at_two).is_ok(), "the rejected replacement leaves the order resting");
+    let _ = event::drain_events();
+}
+
+#[test]
+fn taker_remainder_beyond_the_cap_does_not_rest() {
+    let _guard = lock_events();
+    let mut pair = pair_at_bid_cap();
+
+    let taker = pair
+        .orderbook
+        .place_taker(vec![1], vec![1], vec![], vec![], vec![10], true, 4 * SCALE_8, SCALE_8, 0, 10, i64::MAX, 0)
+        .expect("taker");
+    let rejected = pair.orderbook.rest_taker(vec![1], vec![], vec![], &taker, 0);
+    assert_eq!(rejected, Err(OrderBookError::TooManyPriceLevels));
+    assert_eq!(pair.orderbook.l2.level_count(true), 3);
+    assert!(pair.orderbook.l3.get_order(taker.id).is_err());
+    let _ = event::drain_events();
+}
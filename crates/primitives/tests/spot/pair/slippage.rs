@@ -0,0 +1,103 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{OrderStatus, Pair};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// one base unit resting at each price
+fn pair_with_levels(is_bid: bool, prices: &[u64]) -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    for (timestamp, price) in prices.iter().enumerate() {
+        let amount = if is_bid { *price } else { SCALE_8 };
+        let placed = if is_bid {
+            pair.limit_buy(vec![1], None, vec![10], *price, amount, 0, timestamp as i64, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        } else {
+            pair.limit_sell(vec![1], None, vec![10], *price, amount, 0, timestamp as i64, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        };
+        placed.expect("maker");
+    }
+    let _ = event::drain_events();
+    pair
+}
+
+fn taker_cancelled(events: &[SpotEvent]) -> bool {
+    events.iter().any(|e| matches!(e, SpotEvent::SpotOrderCancelled { maker_account_id, .. } if *maker_account_id == vec![20]))
+}
+
+#[test]
+fn fat_finger_limit_buy_stops_at_the_slippage_bound() {
+    let _guard = lock_events();
+    let mut pair = pair_with_levels(false, &[SCALE_8, 105_000_000, 2 * SCALE_8, 5 * SCALE_8]);
+    // 10% above the best ask of 1.00
+    pair.l1.set_limit_buy_slippage_limit(Some(1000));
+
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], 10 * SCALE_8, 100 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("fat finger buy");
+    let events = event::drain_events().into_vec();
+
+    // the levels within 1.10 are taken, the ones beyond are left alone
+    assert_eq!(pair.orderbook.l2.collect_ask_prices(), vec![2 * SCALE_8, 5 * SCALE_8]);
+    assert_eq!(pair.orderbook.l2.current_ask_level(2 * SCALE_8), Some(SCALE_8));
+    assert_eq!(pair.orderbook.l2.current_ask_level(5 * SCALE_8), Some(SCALE_8));
+    // the remainder is cancelled instead of resting at 10.00 across the book
+    assert_eq!(outcome.status, OrderStatus::Cancelled);
+    assert_eq!(outcome.resting_qty, 0);
+    assert!(pair.orderbook.l2.collect_bid_prices().is_empty());
+    assert!(taker_cancelled(&events));
+}
+
+#[test]
+fn fat_finger_limit_sell_stops_at_the_slippage_bound() {
+    let _guard = lock_events();
+    let mut pair = pair_with_levels(true, &[SCALE_8, 95_000_000, 80_000_000]);
+    // 10% below the best bid of 1.00
+    pair.l1.set_limit_sell_slippage_limit(Some(1000));
+
+    let outcome = pair
+        .limit_sell(vec![1], None, vec![20], 60_000_000, 10 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("fat finger sell");
+    let events = event::drain_events().into_vec();
+
+    // 0.80 is above the limit price but beyond the bound of 0.90
+    assert_eq!(pair.orderbook.l2.collect_bid_prices(), vec![80_000_000]);
+    assert_eq!(outcome.status, OrderStatus::Cancelled);
+    assert!(pair.orderbook.l2.collect_ask_prices().is_empty());
+    assert!(taker_cancelled(&events));
+}
+
+#[test]
+fn limit_buy_without_slippage_limit_sweeps_up_to_its_price() {
+    let _guard = lock_events();
+    let mut pair = pair_with_levels(false, &[SCALE_8, 105_000_000, 2 * SCALE_8, 5 * SCALE_8]);
+    pair.l1.set_limit_buy_slippage_limit(None);
+
+    pair.limit_buy(vec![1], None, vec![20], 10 * SCALE_8, 100 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("sweeping buy");
+    let _ = event::drain_events();
+
+    assert!(pair.orderbook.l2.collect_ask_prices().is_empty());
+}
+
+#[test]
+fn remainder_within_the_slippage_bound_still_rests() {
+    let _guard = lock_events();
+    let mut pair = pair_with_levels(false, &[SCALE_8]);
+    pair.l1.set_limit_buy_slippage_limit(Some(1000));
+
+    // the book runs out before the bound, so nothing is left to cross
+    let outcome = pair
+        .limit_buy(vec![1], None, vec![20], 105_000_000, 3 * SCALE_8, 0, 10, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("buy");
+    let _ = event::drain_events();
+
+    assert_eq!(outcome.status, OrderStatus::PartiallyFilled);
+    assert_eq!(pair.orderbook.l2.collect_bid_prices(), vec![105_000_000]);
+}
@@ -1,5 +1,7 @@
 use offgrid_primitives::spot::event;
-use offgrid_primitives::spot::Pair;
+use offgrid_primitives::spot::orderbook::{BookSnapshot, BOOK_SNAPSHOT_VERSION};
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{Level, Pair};
 use super::EVENT_MUTEX;
 
 fn lock_events() -> std::sync::MutexGuard<'static, ()> {
@@ -86,4 +88,33 @@ fn serialize_and_deserialize_pair_with_orders() {
     assert_eq!(decoded_bid.cqty, 1000);
     assert_eq!(decoded_ask.price, 110);
     assert_eq!(decoded_ask.cqty, 800);
-}
\ No newline at end of file
+}
+#[test]
+fn full_snapshot_carries_l1_and_the_raw_levels() {
+    let _guard = lock_events();
+    let scale = 1_0000_0000;
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.limit_buy(vec![1], None, vec![10], 99_000_000, 99_000_000, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("bid");
+    pair.limit_sell(vec![1], None, vec![10], 101_000_000, 1_0000_0000, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("ask");
+    let _ = event::drain_events();
+    pair.l1.set_lmp(100_000_000);
+    pair.l1.set_market_buy_slippage_limit(Some(500));
+    let _ = pair.orderbook.l2.set_bid_levels(scale, vec![Level { price: 99_000_000, pqty: 1_0000_0000, cqty: 1_0000_0000 }]);
+    let _ = pair.orderbook.l2.set_ask_levels(scale, vec![Level { price: 101_000_000, pqty: 1_0000_0000, cqty: 1_0000_0000 }]);
+
+    let snapshot = pair.full_snapshot(scale, 5).expect("full snapshot");
+
+    assert_eq!(snapshot.version, BOOK_SNAPSHOT_VERSION);
+    assert_eq!(snapshot.lmp, Some(100_000_000));
+    assert_eq!(snapshot.bid_head, Some(99_000_000));
+    assert_eq!(snapshot.ask_head, Some(101_000_000));
+    assert_eq!(snapshot.slippage_limits[2], Some(500));
+    assert_eq!(snapshot.bids, pair.orderbook.l2.get_snapshot_raw(true, scale, 5).unwrap());
+    assert_eq!(snapshot.asks, pair.orderbook.l2.get_snapshot_raw(false, scale, 5).unwrap());
+
+    let encoded = postcard::to_allocvec(&snapshot).expect("serialize BookSnapshot");
+    assert_eq!(postcard::from_bytes::<BookSnapshot>(&encoded).unwrap(), snapshot);
+}
@@ -0,0 +1,131 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{Pair, TimeUnit};
+
+use super::EVENT_MUTEX;
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn new_pair() -> Pair {
+    let mut pair = Pair::new();
+    pair.pair_id = vec![1];
+    pair.base_asset_id = vec![2];
+    pair.quote_asset_id = vec![3];
+    pair
+}
+
+#[test]
+fn good_till_date_order_rests_until_expiry() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    let _ = event::drain_events();
+
+    pair.limit_buy(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        100 * SCALE_8,
+        0,
+        1_000,
+        2_000,
+        5,
+        10,
+        TimeInForce::GoodTillDate,
+    )
+    .expect("limit buy gtd");
+
+    // the remaining rests like a GTC order
+    assert_eq!(pair.orderbook.l2.bid_head(), Some(100 * SCALE_8));
+    let order_id = pair.orderbook.l3.head(100 * SCALE_8).expect("resting gtd order");
+    let _ = event::drain_events();
+
+    pair.orderbook
+        .expire_orders(
+            true,
+            pair.pair_id.clone(),
+            pair.base_asset_id.clone(),
+            pair.quote_asset_id.clone(),
+            2_001,
+        )
+        .expect("expire orders");
+
+    assert!(pair.orderbook.l3.get_order(order_id).is_err());
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SpotEvent::SpotOrderExpired { order_id: expired_id, expires_at, timestamp, .. }
+            if expired_id == &order_id.to_bytes().to_vec() && *expires_at == 2_000 && *timestamp == 2_001
+    )));
+}
+
+#[test]
+fn good_till_date_order_rejects_expiry_before_placement() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    let _ = event::drain_events();
+
+    let result = pair.limit_sell(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        100 * SCALE_8,
+        0,
+        1_000,
+        1_000,
+        5,
+        10,
+        TimeInForce::GoodTillDate,
+    );
+
+    assert_eq!(result, Err(OrderBookError::OrderExpired));
+    let events = event::drain_events();
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::SpotOrderPlaced { .. })));
+}
+
+#[test]
+fn good_till_date_order_compares_expiry_in_seconds() {
+    let _guard = lock_events();
+    let mut pair = new_pair();
+    pair.orderbook.set_time_unit(TimeUnit::Seconds);
+    let _ = event::drain_events();
+
+    // placed at 2_000s, expiring at 1_500s is already past
+    let result = pair.limit_sell(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        100 * SCALE_8,
+        0,
+        2_000,
+        1_500,
+        5,
+        10,
+        TimeInForce::GoodTillDate,
+    );
+    assert_eq!(result, Err(OrderBookError::OrderExpired));
+
+    // one second after placement is still to come
+    pair.limit_sell(
+        vec![1],
+        None,
+        vec![10],
+        100 * SCALE_8,
+        100 * SCALE_8,
+        0,
+        2_000,
+        2_001,
+        5,
+        10,
+        TimeInForce::GoodTillDate,
+    )
+    .expect("limit sell gtd");
+    assert_eq!(pair.orderbook.l2.ask_head(), Some(100 * SCALE_8));
+}
@@ -7,4 +7,6 @@ mod l3;
 #[path = "spot/orderbook/mod.rs"]
 mod orderbook;
 #[path = "spot/pair/mod.rs"]
-mod pair;
\ No newline at end of file
+mod pair;
+#[path = "spot/event.rs"]
+mod event;
@@ -0,0 +1,337 @@
+//! Append-only log of the events emitted on the orderbooks
+//!
+//! Every event numbered in the stream of an orderbook, see `SequencedEvent::book`, is appended to a single
+//! segment file as a record: the length of the record as u32 LE, then the sealed postcard of its `LogEntry`.
+//! Records are fsynced in batches of `sync_every` and whenever no event arrives for a moment, so a crash loses
+//! at most the batch being written. On startup `replay_engine` re-applies the events each orderbook logged after
+//! its snapshot was taken.
+
+use offgrid_primitives::spot::event::{BookSeq, EventReceiver, SequencedEvent, SpotEvent};
+use offgrid_primitives::spot::orderbook::OrderBookError;
+use offgrid_primitives::spot::prices::crc32;
+use offgrid_primitives::spot::MatchingEngine;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::snapshot::seal;
+
+/// Records written between two fsyncs unless configured otherwise
+pub const DEFAULT_SYNC_EVERY: usize = 256;
+
+/// Most undelivered events queued for the event log, dispatching waits for the log beyond it
+pub const EVENT_LOG_QUEUE_CAPACITY: usize = 65_536;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EventLogError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+    #[error("Deserialization error: {0}")]
+    Deserialization(String),
+    #[error("Checksum mismatch at offset {offset}: stored {stored:#010x}, computed {computed:#010x}")]
+    ChecksumMismatch { offset: usize, stored: u32, computed: u32 },
+    #[error("Pair not found: {0}")]
+    PairNotFound(String),
+    #[error("Orderbook {pair_id} is missing event {expected}, the log continues at {found}")]
+    Gap { pair_id: String, expected: u64, found: u64 },
+    #[error("Failed to replay event {seq} of orderbook {pair_id}: {source}")]
+    Replay { pair_id: String, seq: u64, source: OrderBookError },
+}
+
+/// A record of the event log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// global sequence number of the event
+    pub seq: u64,
+    /// orderbook the event was emitted on and its sequence number there
+    pub book: BookSeq,
+    /// the event
+    pub event: SpotEvent,
+}
+
+impl LogEntry {
+    /// Entry of an event emitted on an orderbook, None for events outside of one, which replay has no use for
+    pub fn from_sequenced(sequenced: &SequencedEvent) -> Option<Self> {
+        Some(Self {
+            seq: sequenced.seq,
+            book: sequenced.book.clone()?,
+            event: sequenced.event.clone(),
+        })
+    }
+}
+
+/// Writer of the event log
+///
+/// The events of a book are appended in the order of their book sequence: an event received ahead of an earlier
+/// one of its book, e.g. because the two were published from different threads, waits until the gap is filled.
+/// The log of every book is therefore gapless from the first event it logged.
+pub struct EventLog {
+    file: BufWriter<File>,
+    sync_every: usize,
+    unsynced: usize,
+    last_seq: u64,
+    // book sequence of the last entry appended per pair
+    appended: HashMap<Vec<u8>, u64>,
+    // entries waiting for an earlier event of their book, per pair and keyed by book sequence
+    pending: HashMap<Vec<u8>, BTreeMap<u64, LogEntry>>,
+}
+
+impl EventLog {
+    /// Open the event log at `path`, creating it if needed
+    ///
+    /// A record left incomplete by a crash is cut off, a complete record failing its checksum fails to open.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the segment file
+    /// * `sync_every` - Records written between two fsyncs, at least 1
+    pub fn open<P: AsRef<Path>>(path: P, sync_every: usize) -> Result<Self, EventLogError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let data = fs::read(path)?;
+        let (entries, len) = decode_records(&data)?;
+        if len < data.len() {
+            eprintln!(
+                "Warning: cutting off an incomplete record of {} bytes at the end of the event log {}",
+                data.len() - len,
+                path.display()
+            );
+            file.set_len(len as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        let mut appended: HashMap<Vec<u8>, u64> = HashMap::new();
+        for entry in &entries {
+            let last = appended.entry(entry.book.pair_id.clone()).or_default();
+            *last = (*last).max(entry.book.seq);
+        }
+        Ok(Self {
+            file: BufWriter::new(file),
+            sync_every: sync_every.max(1),
+            unsynced: 0,
+            last_seq: entries.iter().map(|entry| entry.seq).max().unwrap_or(0),
+            appended,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Global sequence number of the last logged event, 0 for an empty log
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Continue the log of a book after `book_seq`, e.g. the `event_seq` of the book restored on startup
+    ///
+    /// The snapshot may be ahead of the log when the last batch was lost, the log then continues after the snapshot.
+    pub fn resume_after(&mut self, pair_id: &[u8], book_seq: u64) {
+        let last = self.appended.entry(pair_id.to_vec()).or_default();
+        *last = (*last).max(book_seq);
+    }
+
+    /// Number of entries waiting for an earlier event of their book
+    pub fn pending(&self) -> usize {
+        self.pending.values().map(BTreeMap::len).sum()
+    }
+
+    /// Append an event, events outside of an orderbook and events already logged are skipped
+    pub fn append(&mut self, sequenced: &SequencedEvent) -> Result<(), EventLogError> {
+        let Some(entry) = LogEntry::from_sequenced(sequenced) else {
+            return Ok(());
+        };
+        let pair_id = entry.book.pair_id.clone();
+        let next = self.appended.get(&pair_id).map_or(1, |last| last + 1);
+        if entry.book.seq < next {
+            return Ok(());
+        }
+        if entry.book.seq > next {
+            self.pending.entry(pair_id).or_default().insert(entry.book.seq, entry);
+            return Ok(());
+        }
+        self.write(entry)?;
+        while let Some(entry) = self.take_pending(&pair_id) {
+            self.write(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the written records and fsync them
+    pub fn sync(&mut self) -> Result<(), EventLogError> {
+        if self.unsynced > 0 {
+            self.file.flush()?;
+            self.file.get_ref().sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    // the entry waiting for the event after the last appended one of the book, if it arrived
+    fn take_pending(&mut self, pair_id: &[u8]) -> Option<LogEntry> {
+        let next = self.appended.get(pair_id).map_or(1, |last| last + 1);
+        let pending = self.pending.get_mut(pair_id)?;
+        let entry = pending.remove(&next);
+        if pending.is_empty() {
+            self.pending.remove(pair_id);
+        }
+        entry
+    }
+
+    fn write(&mut self, entry: LogEntry) -> Result<(), EventLogError> {
+        let record = seal(
+            &postcard::to_allocvec(&entry).map_err(|e| EventLogError::Serialization(e.to_string()))?,
+        );
+        self.file.write_all(&(record.len() as u32).to_le_bytes())?;
+        self.file.write_all(&record)?;
+        self.appended.insert(entry.book.pair_id, entry.book.seq);
+        self.last_seq = self.last_seq.max(entry.seq);
+        self.unsynced += 1;
+        if self.unsynced >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+}
+
+// Decodes the records of a segment, returning them with the length of the complete records
+fn decode_records(data: &[u8]) -> Result<(Vec<LogEntry>, usize), EventLogError> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some(prefix) = data.get(offset..offset + 4) {
+        let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+        let Some(record) = data.get(offset + 4..offset + 4 + len) else {
+            break;
+        };
+        if record.len() < 4 {
+            return Err(EventLogError::ChecksumMismatch { offset, stored: 0, computed: crc32(record) });
+        }
+        let (checksum, body) = record.split_at(4);
+        let stored = u32::from_le_bytes(checksum.try_into().unwrap());
+        let computed = crc32(body);
+        if stored != computed {
+            return Err(EventLogError::ChecksumMismatch { offset, stored, computed });
+        }
+        let entry = postcard::from_bytes(body)
+            .map_err(|e| EventLogError::Deserialization(format!("Failed to deserialize record at {}: {}", offset, e)))?;
+        entries.push(entry);
+        offset += 4 + len;
+    }
+    Ok((entries, offset))
+}
+
+/// Read every complete record of the event log, in the order they were appended
+///
+/// A missing log reads as empty, a record left incomplete by a crash is ignored.
+pub fn read_log<P: AsRef<Path>>(path: P) -> Result<Vec<LogEntry>, EventLogError> {
+    match fs::read(path) {
+        Ok(data) => Ok(decode_records(&data)?.0),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replay the event log onto the pairs of a matching engine restored from a snapshot
+///
+/// Each book is given the events it logged after its `event_seq` in book order, see `Pair::apply_event`,
+/// and continues its sequence after the last one. A book missing an event fails instead of skipping it.
+///
+/// Returns the number of replayed events.
+pub fn replay_engine<P: AsRef<Path>>(path: P, engine: &MatchingEngine) -> Result<usize, EventLogError> {
+    let mut entries = read_log(path)?;
+    entries.sort_by(|a, b| (&a.book.pair_id, a.book.seq).cmp(&(&b.book.pair_id, b.book.seq)));
+
+    let mut replayed = 0;
+    for entry in entries {
+        let pair_id = String::from_utf8_lossy(&entry.book.pair_id).into_owned();
+        let mut pair = engine
+            .get_pair(&entry.book.pair_id)
+            .ok_or_else(|| EventLogError::PairNotFound(pair_id.clone()))?;
+        let expected = pair.orderbook.event_seq + 1;
+        if entry.book.seq < expected {
+            // already part of the snapshot
+            continue;
+        }
+        if entry.book.seq > expected {
+            return Err(EventLogError::Gap { pair_id, expected, found: entry.book.seq });
+        }
+        pair.apply_event(&entry.event)
+            .map_err(|source| EventLogError::Replay { pair_id, seq: entry.book.seq, source })?;
+        pair.orderbook.event_seq = entry.book.seq;
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+/// Spawn the thread appending the events of `event_rx` to the event log
+///
+/// Register `event_rx` with `OverflowPolicy::Block`, a dropped event would leave a gap in the log of its book.
+/// The partial batch is fsynced whenever no event arrives for 100 ms, and on shutdown once the queued events
+/// are written.
+pub fn spawn_event_log_thread(
+    event_rx: EventReceiver,
+    mut log: EventLog,
+    shutdown_flag: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        println!("Event log thread started");
+        loop {
+            if shutdown_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            match event_rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(sequenced) => {
+                    if let Err(e) = log.append(&sequenced) {
+                        eprintln!("Error appending event {} to the event log: {}", sequenced.seq, e);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Err(e) = log.sync() {
+                        eprintln!("Error syncing the event log: {}", e);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // write what was dispatched before the shutdown
+        while let Ok(sequenced) = event_rx.try_recv() {
+            if let Err(e) = log.append(&sequenced) {
+                eprintln!("Error appending event {} to the event log: {}", sequenced.seq, e);
+            }
+        }
+        if let Err(e) = log.sync() {
+            eprintln!("Error syncing the event log: {}", e);
+        }
+        if log.pending() > 0 {
+            eprintln!("Warning: {} events were not logged, an earlier event of their book never arrived", log.pending());
+        }
+        println!("Event log thread stopped");
+    })
+}
+
+/// Get the path of the event log from `EVENT_LOG_PATH`
+/// Returns None when `EVENT_LOG_PATH` is unset, events are then not logged and a restart resumes from the snapshot
+pub fn get_event_log_path() -> Option<PathBuf> {
+    std::env::var("EVENT_LOG_PATH").ok().map(PathBuf::from)
+}
+
+/// Get the number of records written between two fsyncs from `EVENT_LOG_SYNC_EVERY` (default: `DEFAULT_SYNC_EVERY`)
+pub fn get_event_log_sync_every() -> anyhow::Result<usize> {
+    match std::env::var("EVENT_LOG_SYNC_EVERY") {
+        Ok(value) => match value.parse::<usize>() {
+            Ok(0) => anyhow::bail!("EVENT_LOG_SYNC_EVERY must be at least 1"),
+            Ok(sync_every) => Ok(sync_every),
+            Err(e) => anyhow::bail!("invalid EVENT_LOG_SYNC_EVERY {:?}: {}", value, e),
+        },
+        Err(_) => Ok(DEFAULT_SYNC_EVERY),
+    }
+}
@@ -1,77 +1,194 @@
-use offgrid_primitives::spot::orderbook::OrderBook;
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::pair::Pair;
+use offgrid_primitives::spot::MatchingEngine;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Spawn a cron jobs thread that runs periodic tasks
 pub fn spawn_cron_thread(
-    orderbook: Arc<Mutex<OrderBook>>,
+    engine: Arc<Mutex<MatchingEngine>>,
+    interval: Duration,
     shutdown_flag: Arc<AtomicBool>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        println!("Cron jobs thread started");
-        let interval = Duration::from_secs(60); // Run every minute
+        println!("Cron jobs thread started (interval: {:?})", interval);
         loop {
-            if shutdown_flag.load(Ordering::Relaxed) {
-                break;
-            }
-            
-            thread::sleep(interval);
-            
-            // Run cron jobs
-            if let Ok(mut ob) = orderbook.lock() {
-                cleanup_expired_orders(&mut ob);
+            // Wait for interval or shutdown signal
+            let started = Instant::now();
+            while started.elapsed() < interval {
+                if shutdown_flag.load(Ordering::Relaxed) {
+                    println!("Cron jobs thread stopped");
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10).min(interval));
             }
+
+            // Run cron jobs on a handle of the pairs so only the pair being housekept is locked,
+            // expiring and sweeping in batches so its lock is released for order processing in between
+            let now = now_millis();
+            let mut pairs = crate::lock_engine(&engine).share();
+            while run_expiry_batch(&mut pairs, now, EXPIRY_BATCH_SIZE) {}
+            run_dust_sweep(&mut pairs, now);
+            run_book_checksums(&mut pairs, now);
+            // housekeeping emits outside of an order, so its events are published from the global queue
+            event::publish_events();
         }
-        println!("Cron jobs thread stopped");
     })
 }
 
-/// Clean up expired orders from the orderbook.
+/// Get the cron interval from `CRON_INTERVAL_SECS` (default: 60 seconds)
+pub fn get_cron_interval() -> Duration {
+    let secs = std::env::var("CRON_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+/// Most orders expired per side of a pair while the cron thread holds the engine lock
+pub const EXPIRY_BATCH_SIZE: usize = 1_000;
+
+/// Most dust orders swept per pair while the cron thread holds the lock of the pair
+pub const DUST_BATCH_SIZE: usize = 1_000;
+
+/// Levels per side of the book covered by the checksums emitted every cron cycle
+pub const BOOK_CHECKSUM_DEPTH: u32 = 10;
+
+/// Run one cycle of the cron jobs over every pair of the matching engine
+pub fn run_cron_jobs(engine: &mut MatchingEngine, now: i64) {
+    while run_expiry_batch(engine, now, EXPIRY_BATCH_SIZE) {}
+    run_dust_sweep(engine, now);
+    run_book_checksums(engine, now);
+}
+
+/// Expire at most `max_removals` orders per side of every pair
 ///
-/// This uses the underlying `expire_orders` API on the `OrderBook`, which:
-/// - Scans L3 for orders whose `expires_at` is before `now`
-/// - Removes them from L3/L2
-/// - Emits `SpotOrderExpired` and corresponding `Transfer` events
-fn cleanup_expired_orders(orderbook: &mut OrderBook) {
-    // Current UNIX timestamp in milliseconds
-    let now = std::time::SystemTime::now()
+/// The events of every pair are numbered in the stream of its orderbook and queued for `event::publish_events`.
+///
+/// Returns whether a side hit the bound, in which case expired orders may be left for another batch.
+pub fn run_expiry_batch(engine: &mut MatchingEngine, now: i64, max_removals: usize) -> bool {
+    let mut more = false;
+    for mut pair in engine.pairs_mut() {
+        let (hit_bound, events) = pair.capture(|pair| cleanup_expired_orders(pair, now, max_removals));
+        event::requeue_events(events);
+        more |= hit_bound;
+    }
+    more
+}
+
+/// Sweep at most `max_removals` dust orders of every pair
+///
+/// Returns whether a pair hit the bound, in which case dust orders may be left for another batch.
+pub fn run_dust_batch(engine: &mut MatchingEngine, now: i64, max_removals: usize) -> bool {
+    let mut more = false;
+    for mut pair in engine.pairs_mut() {
+        let (hit_bound, events) = pair.capture(|pair| sweep_dust_orders(pair, now, max_removals));
+        event::requeue_events(events);
+        more |= hit_bound;
+    }
+    more
+}
+
+/// Sweep the dust orders of every pair in batches, clear the empty prices left at the heads
+/// and release the client order ids of terminated orders
+pub fn run_dust_sweep(engine: &mut MatchingEngine, now: i64) {
+    while run_dust_batch(engine, now, DUST_BATCH_SIZE) {}
+    for mut pair in engine.pairs_mut() {
+        let (_, events) = pair.capture(|pair| {
+            pair.tidy_heads();
+            pair.prune_client_order_ids();
+        });
+        event::requeue_events(events);
+    }
+}
+
+/// Emit the checksum of the top `BOOK_CHECKSUM_DEPTH` levels of every pair with `SpotBookChecksum`
+///
+/// The checksum is numbered in the stream of its orderbook, so a client checks it against its book after
+/// applying the events before it.
+pub fn run_book_checksums(engine: &mut MatchingEngine, now: i64) {
+    for mut pair in engine.pairs_mut() {
+        let (_, events) = pair.capture(|pair| {
+            pair.orderbook.emit_book_checksum(pair.pair_id.clone(), BOOK_CHECKSUM_DEPTH, now);
+        });
+        event::requeue_events(events);
+    }
+}
+
+// Current UNIX timestamp in milliseconds
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as i64;
+        .unwrap_or_default()
+        .as_millis() as i64
+}
 
-    // Runtime does not yet track concrete pair / asset / managing account ids here,
-    // so we use placeholder identifiers. Downstream consumers can treat these as
-    // "generic" housekeeping events.
-    let pair_id = b"default_pair".to_vec();
-    let base_asset_id = b"base".to_vec();
-    let quote_asset_id = b"quote".to_vec();
-    let managing_account_id = b"manager".to_vec();
+/// Clean up expired orders from the orderbook of a pair.
+///
+/// This uses the underlying `expire_orders_bounded` API on the `OrderBook`, which:
+/// - Looks up the orders whose `expires_at` is before `now` in the expiry index of L3
+/// - Removes at most `max_removals` of them per side from L3/L2
+/// - Emits `SpotOrderExpired` and an `Unlock` of the remaining quantity
+///
+/// Returns whether a side hit the bound.
+fn cleanup_expired_orders(pair: &mut Pair, now: i64, max_removals: usize) -> bool {
+    let mut more = false;
 
     // Expire resting bid orders
-    if let Err(e) = orderbook.expire_orders(
+    match pair.orderbook.expire_orders_bounded(
         true,
-        pair_id.clone(),
-        base_asset_id.clone(),
-        quote_asset_id.clone(),
-        managing_account_id.clone(),
+        pair.pair_id.clone(),
+        pair.base_asset_id.clone(),
+        pair.quote_asset_id.clone(),
         now,
+        max_removals,
     ) {
-        eprintln!("Error expiring bid orders in cron job: {:?}", e);
+        Ok(expired) => more |= expired >= max_removals,
+        Err(e) => eprintln!("Error expiring bid orders in cron job: {:?}", e),
     }
 
     // Expire resting ask orders
-    if let Err(e) = orderbook.expire_orders(
+    match pair.orderbook.expire_orders_bounded(
         false,
-        pair_id,
-        base_asset_id,
-        quote_asset_id,
-        managing_account_id,
+        pair.pair_id.clone(),
+        pair.base_asset_id.clone(),
+        pair.quote_asset_id.clone(),
         now,
+        max_removals,
     ) {
-        eprintln!("Error expiring ask orders in cron job: {:?}", e);
+        Ok(expired) => more |= expired >= max_removals,
+        Err(e) => eprintln!("Error expiring ask orders in cron job: {:?}", e),
     }
+    more
 }
 
+/// Cancel resting orders left with a dust-sized remainder.
+///
+/// This uses the `sweep_dust_bounded` API on the `OrderBook`, which:
+/// - Looks up the orders whose `cqty` is at or below the orderbook's dust limit in the quantity index of L3
+/// - Removes at most `max_removals` of them from L3/L2
+/// - Emits `SpotOrderDustSwept` and an `Unlock` of the remaining quantity
+///
+/// Returns whether the pair hit the bound.
+fn sweep_dust_orders(pair: &mut Pair, now: i64, max_removals: usize) -> bool {
+    match pair.orderbook.sweep_dust_bounded(
+        pair.pair_id.clone(),
+        pair.base_asset_id.clone(),
+        pair.quote_asset_id.clone(),
+        now,
+        max_removals,
+    ) {
+        Ok(0) => false,
+        Ok(swept) => {
+            println!("Swept {} dust orders", swept);
+            swept >= max_removals
+        }
+        Err(e) => {
+            eprintln!("Error sweeping dust orders in cron job: {:?}", e);
+            false
+        }
+    }
+}
@@ -1,7 +1,35 @@
 pub mod network;
 pub mod jobs;
+pub mod logging;
 pub mod metrics;
 pub mod snapshot;
+pub mod event_log;
+pub mod store;
+pub mod proto;
+pub mod ws;
+
+use offgrid_primitives::spot::MatchingEngine;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+static POISON_RECOVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// Lock the shared matching engine, recovering the guard if a thread panicked while holding it
+///
+/// The poison flag is cleared once recovered, so every panic is counted once in `poison_recoveries`.
+pub fn lock_engine(engine: &Mutex<MatchingEngine>) -> MutexGuard<'_, MatchingEngine> {
+    engine.lock().unwrap_or_else(|e| {
+        eprintln!("Warning: matching engine lock was poisoned by a panicked thread, recovering");
+        POISON_RECOVERIES.fetch_add(1, Ordering::Relaxed);
+        engine.clear_poison();
+        e.into_inner()
+    })
+}
+
+/// Number of times `lock_engine` recovered the matching engine lock from poisoning
+pub fn poison_recoveries() -> u64 {
+    POISON_RECOVERIES.load(Ordering::Relaxed)
+}
 
 pub fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
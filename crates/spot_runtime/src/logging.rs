@@ -0,0 +1,169 @@
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use offgrid_primitives::spot::event::{SequencedEvent, SpotEvent};
+use offgrid_primitives::spot::orders::OrderId;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Target of the records emitted by `log_event`
+pub const EVENT_TARGET: &str = "orderbook::events";
+
+/// Logger writing one `LEVEL target message key=value...` line per record to stdout
+#[derive(Debug)]
+pub struct StdoutLogger {
+    filter: LevelFilter,
+}
+
+impl StdoutLogger {
+    pub fn new(filter: LevelFilter) -> Self {
+        Self { filter }
+    }
+
+    /// Render `record` as the line written to stdout
+    pub fn format(record: &Record) -> String {
+        let mut line = format!("{} {} {}", record.level(), record.target(), record.args());
+        let _ = record.key_values().visit(&mut LineVisitor(&mut line));
+        line
+    }
+}
+
+impl Log for StdoutLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            println!("{}", Self::format(record));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+struct LineVisitor<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for LineVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let _ = write!(self.0, " {}={}", key, value);
+        Ok(())
+    }
+}
+
+/// Install `StdoutLogger` as the global logger at the level of `RUST_LOG`
+pub fn init_logging() -> anyhow::Result<()> {
+    let filter = get_log_level();
+    log::set_boxed_logger(Box::new(StdoutLogger::new(filter))).map_err(|e| anyhow::anyhow!("{}", e))?;
+    log::set_max_level(filter);
+    Ok(())
+}
+
+/// Get the log level from `RUST_LOG`, a single level such as `debug` or `off` (default: info)
+pub fn get_log_level() -> LevelFilter {
+    std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| LevelFilter::from_str(level.trim()).ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+/// Log `sequenced` as a structured record with its `seq`, `event`, `pair_id` and `order_id` fields
+/// Order lifecycle events are logged at info, balance and book updates at debug and checksums at trace.
+pub fn log_event(sequenced: &SequencedEvent) {
+    let (name, level) = event_kind(&sequenced.event);
+    if level > log::max_level() {
+        return;
+    }
+    let (pair_id, order_id) = event_ids(&sequenced.event);
+    let fields = [
+        Some(("seq", sequenced.seq.to_string())),
+        Some(("event", name.to_string())),
+        pair_id.map(|pair_id| ("pair_id", display_pair_id(pair_id))),
+        order_id.map(|order_id| ("order_id", display_order_id(order_id))),
+    ];
+    log::logger().log(
+        &Record::builder()
+            .level(level)
+            .target(EVENT_TARGET)
+            .args(format_args!("{}", name))
+            .key_values(&fields)
+            .build(),
+    );
+}
+
+/// Name of the event variant, e.g. `SpotOrderPlaced`
+pub fn event_name(event: &SpotEvent) -> &'static str {
+    event_kind(event).0
+}
+
+/// Pair id carried by the event, None for events without one
+pub fn event_pair_id(event: &SpotEvent) -> Option<&[u8]> {
+    event_ids(event).0
+}
+
+/// Name of the event variant and the level it is logged at
+fn event_kind(event: &SpotEvent) -> (&'static str, Level) {
+    match event {
+        SpotEvent::SpotPairClientAccountChanged { .. } => ("SpotPairClientAccountChanged", Level::Info),
+        SpotEvent::SpotPairAdded { .. } => ("SpotPairAdded", Level::Info),
+        SpotEvent::Transfer { .. } => ("Transfer", Level::Debug),
+        SpotEvent::Lock { .. } => ("Lock", Level::Debug),
+        SpotEvent::Unlock { .. } => ("Unlock", Level::Debug),
+        SpotEvent::SpotOrderBlockChanged { .. } => ("SpotOrderBlockChanged", Level::Debug),
+        SpotEvent::SpotLevelRemoved { .. } => ("SpotLevelRemoved", Level::Debug),
+        SpotEvent::SpotBookChecksum { .. } => ("SpotBookChecksum", Level::Trace),
+        SpotEvent::SpotOrderPlaced { .. } => ("SpotOrderPlaced", Level::Info),
+        SpotEvent::SpotOrderPartiallyFilled { .. } => ("SpotOrderPartiallyFilled", Level::Info),
+        SpotEvent::SpotOrderFullyFilled { .. } => ("SpotOrderFullyFilled", Level::Info),
+        SpotEvent::SpotTakerMatched { .. } => ("SpotTakerMatched", Level::Debug),
+        SpotEvent::SpotTrade { .. } => ("SpotTrade", Level::Info),
+        SpotEvent::SpotOrderCancelled { .. } => ("SpotOrderCancelled", Level::Info),
+        SpotEvent::SpotOrderReduced { .. } => ("SpotOrderReduced", Level::Info),
+        SpotEvent::SpotOrderDustSwept { .. } => ("SpotOrderDustSwept", Level::Info),
+        SpotEvent::SpotOrderExpired { .. } => ("SpotOrderExpired", Level::Info),
+        SpotEvent::SpotOrderIcebergQuantityChanged { .. } => ("SpotOrderIcebergQuantityChanged", Level::Info),
+    }
+}
+
+/// Pair id and order id carried by the event, the taker's for fills and trades
+fn event_ids(event: &SpotEvent) -> (Option<&[u8]>, Option<&[u8]>) {
+    match event {
+        SpotEvent::SpotPairClientAccountChanged { pair_id, .. }
+        | SpotEvent::SpotPairAdded { pair_id, .. }
+        | SpotEvent::SpotOrderBlockChanged { pair_id, .. }
+        | SpotEvent::SpotLevelRemoved { pair_id, .. }
+        | SpotEvent::SpotBookChecksum { pair_id, .. } => (Some(pair_id), None),
+        SpotEvent::Lock { pair_id, order_id, .. }
+        | SpotEvent::Unlock { pair_id, order_id, .. }
+        | SpotEvent::SpotOrderPlaced { pair_id, order_id, .. }
+        | SpotEvent::SpotTakerMatched { pair_id, order_id, .. }
+        | SpotEvent::SpotOrderReduced { pair_id, order_id, .. }
+        | SpotEvent::SpotOrderDustSwept { pair_id, order_id, .. } => (Some(pair_id), Some(order_id)),
+        SpotEvent::SpotOrderPartiallyFilled { pair_id, taker_order_id, .. }
+        | SpotEvent::SpotOrderFullyFilled { pair_id, taker_order_id, .. }
+        | SpotEvent::SpotTrade { pair_id, taker_order_id, .. } => (Some(pair_id), Some(taker_order_id)),
+        SpotEvent::SpotOrderCancelled { order_id, .. }
+        | SpotEvent::SpotOrderExpired { order_id, .. }
+        | SpotEvent::SpotOrderIcebergQuantityChanged { order_id, .. } => (None, Some(order_id)),
+        SpotEvent::Transfer { .. } => (None, None),
+    }
+}
+
+// pair ids are symbols such as `BTC-USD`, anything else is logged as hex
+fn display_pair_id(pair_id: &[u8]) -> String {
+    match std::str::from_utf8(pair_id) {
+        Ok(symbol) if symbol.chars().all(|c| c.is_ascii_graphic()) => symbol.to_string(),
+        _ => hex(pair_id),
+    }
+}
+
+// order ids are ULID bytes
+fn display_order_id(order_id: &[u8]) -> String {
+    match <[u8; 16]>::try_from(order_id) {
+        Ok(bytes) => OrderId::from_bytes(bytes).to_string(),
+        Err(_) => hex(order_id),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
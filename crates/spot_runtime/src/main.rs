@@ -1,6 +1,6 @@
-use offgrid_primitives::spot::MatchingEngine;
-use offgrid_primitives::spot::event::{self, SpotEvent};
-use offgrid_spot_runtime::{version, network as network_module, metrics, snapshot};
+use offgrid_primitives::spot::event::{self, OverflowPolicy};
+use offgrid_primitives::spot::{matching_engine, orderbook, pair};
+use offgrid_spot_runtime::{lock_engine, version, network as network_module, metrics, snapshot, event_log, store, jobs, logging, ws};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
@@ -9,9 +9,15 @@ use std::thread;
 use std::time::Duration;
 use zmq::Context;
 
+/// Maximum number of undelivered events buffered for the ZMQ publisher
+const ZMQ_EVENT_QUEUE_CAPACITY: usize = 100_000;
+
 fn main() -> anyhow::Result<()> {
     println!("Orderbook Server {} starting...", version());
 
+    // Structured logging of the events, at the level of `RUST_LOG`
+    logging::init_logging()?;
+
     // Initialize event bus for event dispatching
     event::init_event_bus();
     println!("Event bus initialized");
@@ -19,29 +25,74 @@ fn main() -> anyhow::Result<()> {
     // Initialize ZMQ context
     let context = Context::new();
 
-    // Get ports from environment or use defaults
-    let (event_port, order_port) = network_module::get_ports()?;
+    // Get endpoints from environment or use defaults
+    let (event_endpoint, order_endpoint) = network_module::get_endpoints()?;
 
     // Create ZMQ server
-    let zmq_server = Arc::new(network_module::ZmqServer::new(&context, event_port, order_port)?);
-    println!("ZMQ server initialized - Events: {}, Orders: {}", event_port, order_port);
+    let socket_options = network_module::get_socket_options()?;
+    let zmq_server = Arc::new(network_module::ZmqServer::with_endpoint_options(
+        &context,
+        &event_endpoint,
+        &order_endpoint,
+        &socket_options,
+    )?);
+    println!("ZMQ server initialized - Events: {}, Orders: {}", event_endpoint, order_endpoint);
 
     // Load matching engine from snapshot or create new
     let snapshot_path = std::env::var("SNAPSHOT_PATH")
         .unwrap_or_else(|_| "./data/snapshot.bin".to_string());
     
     println!("Loading matching engine from snapshot: {}", snapshot_path);
-    let engine = match snapshot::load_snapshot_or_new(&snapshot_path) {
-        Ok(engine) => {
-            println!("Matching engine loaded: {} pairs", engine.pair_count());
-            engine
+    // a snapshot that exists but cannot be read aborts startup, an empty engine would drop every resting order
+    let mut engine = snapshot::load_snapshot_or_new(&snapshot_path)
+        .map_err(|e| anyhow::anyhow!("Failed to load snapshot {}: {}", snapshot_path, e))?;
+    println!("Matching engine loaded: {} pairs", engine.pair_count());
+    
+    // Continue the event sequence where the previous run stopped
+    if let Some(seq) = snapshot::restore_seq(&snapshot_path)
+        .map_err(|e| anyhow::anyhow!("Failed to restore event sequence: {}", e))?
+    {
+        println!("Event sequence restored: {}", seq);
+    }
+
+    // Re-apply the events logged after the snapshot was taken, then keep logging every orderbook event
+    let event_log_writer = match event_log::get_event_log_path() {
+        Some(event_log_path) => {
+            let replayed = event_log::replay_engine(&event_log_path, &engine)
+                .map_err(|e| anyhow::anyhow!("Failed to replay event log {}: {}", event_log_path.display(), e))?;
+            println!("Replayed {} events from {}", replayed, event_log_path.display());
+            let mut log = event_log::EventLog::open(&event_log_path, event_log::get_event_log_sync_every()?)?;
+            event::restore_seq(log.last_seq());
+            for pair_id in engine.list_pairs() {
+                if let Some(pair) = engine.get_pair(&pair_id) {
+                    log.resume_after(&pair_id, pair.orderbook.event_seq);
+                }
+            }
+            Some(log)
         }
-        Err(e) => {
-            eprintln!("Warning: Failed to load snapshot ({}), starting with empty engine", e);
-            MatchingEngine::new()
+        None => None,
+    };
+
+    // Initialize Prometheus metrics (needed for metrics backend)
+    let metrics_registry = Arc::new(metrics::Metrics::new()?);
+
+    // Archive the orders leaving the book, the archive is not part of the snapshot
+    let order_history_writer = match store::get_order_history_path() {
+        Some(order_history_path) => {
+            let order_history_options = store::get_order_history_options()?;
+            let order_history = Arc::new(store::OrderHistoryStore::open_with_options(&order_history_path, &order_history_options)?);
+            let (archive, writer) = store::spawn_order_history_thread(
+                order_history,
+                store::ORDER_HISTORY_QUEUE_CAPACITY,
+                metrics_registry.order_history_write_failures.clone(),
+            );
+            engine.set_order_archive(archive);
+            println!("Archiving terminated orders to {}", order_history_path.display());
+            Some(writer)
         }
+        None => None,
     };
-    
+
     // Create matching engine (shared across threads)
     let matching_engine = Arc::new(Mutex::new(engine));
 
@@ -51,14 +102,20 @@ fn main() -> anyhow::Result<()> {
     // Shutdown flag (shared across threads)
     let shutdown_flag = Arc::new(AtomicBool::new(false));
 
-    // Initialize Prometheus metrics (needed for metrics backend)
-    let metrics_registry = Arc::new(metrics::Metrics::new()?);
-    let metrics_port = metrics::get_metrics_port();
+    let metrics_address = std::net::SocketAddr::new(metrics::get_metrics_bind()?, metrics::get_metrics_port());
+
+    // Liveness of the worker threads, served by `/health`
+    let health = Arc::new(metrics::Health::default());
 
     // Register event backend #1: ZMQ event streaming
-    let zmq_event_receiver = event::register_backend();
+    // bounded so a stalled subscriber socket sheds the oldest events instead of growing without limit
+    let zmq_event_receiver = event::register_backend_bounded(ZMQ_EVENT_QUEUE_CAPACITY, OverflowPolicy::DropOldest);
     let zmq_server_event_backend = zmq_server.clone();
     let shutdown_zmq_backend = shutdown_flag.clone();
+    let health_zmq_backend = health.clone();
+    // tagged wire format of the published events, subscribers decode with `EventCodec::decode`
+    let event_codec = network_module::codec::get_event_codec()?;
+    println!("Publishing events as {:?}", event_codec);
     
     // Spawn thread to consume events from event bus and forward to ZMQ
     let zmq_event_backend_thread = thread::spawn(move || {
@@ -67,19 +124,20 @@ fn main() -> anyhow::Result<()> {
             if shutdown_zmq_backend.load(Ordering::Relaxed) {
                 break;
             }
+            health_zmq_backend.beat(metrics::Component::EventBackend);
             
             match zmq_event_receiver.recv_timeout(Duration::from_millis(100)) {
-                Ok(event) => {
-                    // Serialize event as JSON and send via ZMQ
-                    // serde_bytes will automatically encode Vec<u8> as base64 strings in JSON
-                    match serde_json::to_vec(&event) {
+                Ok(sequenced) => {
+                    // Serialize the `{ seq, event }` envelope with the configured codec and send via ZMQ under the event topic
+                    match event_codec.encode(&sequenced) {
                         Ok(event_data) => {
-                            if let Err(e) = zmq_server_event_backend.publish_event(&event_data) {
+                            let topic = network_module::event_topic(&sequenced.event);
+                            if let Err(e) = zmq_server_event_backend.publish_event_topic(&topic, &event_data) {
                                 eprintln!("Error publishing event to ZMQ: {}", e);
                             }
                         }
                         Err(e) => {
-                            eprintln!("Error serializing event to JSON: {}", e);
+                            eprintln!("Error serializing event with {:?}: {}", event_codec, e);
                         }
                     }
                 }
@@ -88,6 +146,7 @@ fn main() -> anyhow::Result<()> {
                 }
                 Err(mpsc::RecvTimeoutError::Disconnected) => {
                     println!("ZMQ event backend channel disconnected");
+                    health_zmq_backend.mark_unhealthy(metrics::Component::EventBackend);
                     break;
                 }
             }
@@ -95,35 +154,41 @@ fn main() -> anyhow::Result<()> {
         println!("ZMQ event backend thread stopped");
     });
 
-    // Register event backend #2: Metrics
-    let metrics_event_receiver = event::register_backend();
+    // Register event backend #2: Metrics, fed only the events it counts
+    let metrics_event_receiver = event::register_backend_with_filter(metrics::Metrics::event_filter());
     let metrics_registry_for_events = metrics_registry.clone();
     let shutdown_metrics_backend = shutdown_flag.clone();
     
     // Spawn thread to consume events and update metrics
     let metrics_event_backend_thread = thread::spawn(move || {
         println!("Metrics event backend thread started");
+        let mut last_counted = [0u64; 5];
         loop {
             if shutdown_metrics_backend.load(Ordering::Relaxed) {
                 break;
             }
+            // the process-wide counts only grow, the counters are advanced by what was counted since the last read
+            let counted = [
+                event::dropped_events(),
+                offgrid_spot_runtime::poison_recoveries(),
+                matching_engine::pair_lock_poison_recoveries(),
+                orderbook::fee_recipient_fallbacks(),
+                pair::capped_orders(),
+            ];
+            let counters = [
+                &metrics_registry_for_events.events_dropped,
+                &metrics_registry_for_events.engine_lock_poison_recoveries,
+                &metrics_registry_for_events.pair_lock_poison_recoveries,
+                &metrics_registry_for_events.fee_recipient_fallbacks,
+                &metrics_registry_for_events.capped_orders,
+            ];
+            for ((counter, last), count) in counters.into_iter().zip(last_counted.iter_mut()).zip(counted) {
+                counter.inc_by(count.saturating_sub(*last));
+                *last = count;
+            }
             
             match metrics_event_receiver.recv_timeout(Duration::from_millis(100)) {
-                Ok(event) => {
-                    match event.clone() {
-                        SpotEvent::SpotPairClientAccountChanged { .. } => {}
-                        SpotEvent::SpotOrderPlaced { .. } => metrics_registry_for_events.orders_placed.inc(),
-                        SpotEvent::SpotOrderPartiallyFilled { .. } => metrics_registry_for_events.orders_partially_filled.inc(),
-                        SpotEvent::SpotOrderFullyFilled { .. } => metrics_registry_for_events.orders_fully_filled.inc(),
-                        SpotEvent::SpotOrderCancelled { .. } => metrics_registry_for_events.orders_cancelled.inc(),
-                        SpotEvent::SpotOrderExpired { .. } => metrics_registry_for_events.orders_expired.inc(),
-                        SpotEvent::SpotOrderIcebergQuantityChanged { .. } => metrics_registry_for_events.order_iceberg_quantity_changed.inc(),
-                        SpotEvent::Transfer { .. } => {}
-                        SpotEvent::SpotOrderBlockChanged { .. } => {}
-                        SpotEvent::SpotPairAdded { .. } => {}
-                        // matched events already counted above
-                    }
-                }
+                Ok(sequenced) => metrics_registry_for_events.record_event(&sequenced.event),
                 Err(mpsc::RecvTimeoutError::Timeout) => {
                     continue;
                 }
@@ -149,10 +214,9 @@ fn main() -> anyhow::Result<()> {
             }
             
             match logging_event_receiver.recv_timeout(Duration::from_millis(100)) {
-                Ok(event) => {
-                    // Log the event
-                    // TODO: Use proper structured logging library
-                    println!("[EVENT] {:?}", event);
+                Ok(sequenced) => {
+                    // Log the event as a structured record, filtered by `RUST_LOG`
+                    logging::log_event(&sequenced);
                 }
                 Err(mpsc::RecvTimeoutError::Timeout) => {
                     continue;
@@ -166,6 +230,23 @@ fn main() -> anyhow::Result<()> {
         println!("Logging event backend thread stopped");
     });
 
+    // Register event backend #4: WebSocket bridge for clients that cannot speak ZMQ, only when `WS_BIND` is set
+    let ws_thread = match ws::get_ws_bind()? {
+        Some(bind_addr) => Some(ws::spawn_ws_bridge(
+            event::register_backend(),
+            bind_addr,
+            ws::get_ws_max_clients()?,
+            shutdown_flag.clone(),
+        )?),
+        None => None,
+    };
+
+    // Register event backend #5: event log, blocking dispatch rather than dropping an event of a book
+    let event_log_thread = event_log_writer.map(|log| {
+        let receiver = event::register_backend_bounded(event_log::EVENT_LOG_QUEUE_CAPACITY, OverflowPolicy::Block);
+        event_log::spawn_event_log_thread(receiver, log, shutdown_flag.clone())
+    });
+
     // Spawn event streaming thread (for raw order data)
     let event_thread = network_module::spawn_event_streaming_thread(
         zmq_server.clone(),
@@ -179,27 +260,61 @@ fn main() -> anyhow::Result<()> {
         .and_then(|s| s.parse::<u64>().ok())
         .unwrap_or(60); // Default: 60 seconds
     
-    let snapshot_thread = snapshot::spawn_snapshot_thread(
+    let snapshot_thread = snapshot::spawn_adaptive_snapshot_thread(
         matching_engine.clone(),
         snapshot_path.clone(),
-        snapshot_interval,
+        snapshot::get_snapshot_schedule(Duration::from_secs(snapshot_interval)),
         shutdown_flag.clone(),
+        metrics_registry.clone(),
+        health.clone(),
     );
 
-    // Spawn cron jobs thread
-    // TODO: Update to use matching_engine instead of orderbook
-    // let cron_thread = jobs::spawn_cron_thread(
-    //     matching_engine.clone(),
-    //     shutdown_flag.clone(),
-    // );
+    // Spawn cron jobs thread (expires orders and sweeps dust across all pairs)
+    let cron_thread = jobs::spawn_cron_thread(
+        matching_engine.clone(),
+        jobs::get_cron_interval(),
+        shutdown_flag.clone(),
+    );
 
     // Spawn Prometheus metrics HTTP server thread
-    let metrics_thread = metrics::spawn_metrics_thread(
+    let metrics_thread = metrics::spawn_metrics_thread_on(
         metrics_registry.clone(),
+        matching_engine.clone(),
+        health.clone(),
         shutdown_flag.clone(),
-        metrics_port,
+        metrics_address,
     );
-    println!("Prometheus metrics server started on port {}", metrics_port);
+    println!("Prometheus metrics server started on {}", metrics_address);
+
+    // Optionally push metrics to a Prometheus Pushgateway as well
+    let push_thread = metrics::get_push_config()?.map(|config| {
+        metrics::spawn_push_thread(metrics_registry.clone(), shutdown_flag.clone(), config)
+    });
+
+    // Per-client rate limiter, applied before requests reach the matching engine
+    let (orders_per_sec, burst) = network_module::rate_limit::get_rate_limit()?;
+    let mut rate_limiter = network_module::RateLimiter::new(orders_per_sec, burst);
+    println!("Order rate limit: {} orders/sec, burst {}", orders_per_sec, burst);
+
+    // Responses of orders carrying a client order id, replayed to retransmissions instead of processing them twice
+    let (ack_cache_size, ack_cache_ttl_ms) = network_module::acks::get_ack_cache_config()?;
+    let mut ack_cache = network_module::AckCache::new(ack_cache_size, ack_cache_ttl_ms);
+
+    // Orders timestamped longer ago than the maximum age are rejected, guarding against replayed messages
+    let max_order_age_ms = network_module::order_age::get_max_order_age_ms()?;
+    let mut order_age = network_module::OrderAgeGuard::new(max_order_age_ms);
+    order_age.set_time_unit(lock_engine(&matching_engine).time_unit());
+    if order_age.is_enabled() {
+        println!("Maximum order age: {} ms", max_order_age_ms);
+    }
+
+    // With more than one worker, orders are processed on a pool sharded by pair instead of the main loop
+    let worker_threads = network_module::workers::get_worker_threads();
+    let worker_pool = (worker_threads > 1).then(|| {
+        println!("Order processing on {} workers sharded by pair", worker_threads);
+        network_module::WorkerPool::new(&context, worker_threads, matching_engine.clone(), metrics_registry.clone())
+    });
+    let worker_pool = worker_pool.transpose()?;
 
     // Main thread: order processing from gateway using ROUTER socket
     println!("Main order processing thread started");
@@ -221,47 +336,126 @@ fn main() -> anyhow::Result<()> {
             println!("Shutdown signal received in main thread");
             break;
         }
+        health.beat(metrics::Component::MainLoop);
 
-        // Poll for incoming orders (non-blocking)
-        let mut items = [order_router.as_poll_item(zmq::POLLIN)];
+        // Route the responses of the workers back to their clients
+        if let Some((_, responses)) = &worker_pool {
+            for response in responses.try_iter() {
+                let identity = zmq::Message::from(&response.identity[..]);
+                // retransmissions held while the order was in flight get the same response
+                let held = match response.ack_key {
+                    Some(key) => ack_cache.complete(key, response.response.clone()),
+                    None => 0,
+                };
+                for _ in 0..=held {
+                    if let Err(e) = network_module::send_response(order_router, &identity, &response.response) {
+                        eprintln!("Error sending response: {}", e);
+                    }
+                }
+            }
+        }
+
+        // Poll for incoming orders (non-blocking), and for worker responses with a worker pool
+        let mut items = vec![order_router.as_poll_item(zmq::POLLIN)];
+        if let Some((_, responses)) = &worker_pool {
+            items.push(responses.as_poll_item());
+        }
         match zmq::poll(&mut items, 100)? {
             0 => continue, // Timeout, continue loop
+            _ if !items[0].is_readable() => continue, // only responses to route
             _ => {
                 // Receive order message from DEALER client
                 if let Some((identity, msg)) = network_module::receive_order(order_router) {
-                    // Process order
+                    if !rate_limiter.check(&identity) {
+                        metrics_registry.orders_throttled.inc();
+                        if let Err(e) = network_module::send_ack(order_router, &identity, "RATE_LIMITED") {
+                            eprintln!("Error sending rate limit response: {}", e);
+                        }
+                        continue;
+                    }
+
+                    // Process order: decode, run through the matching engine and respond, timed end to end
+                    // with a worker pool, malformed requests are answered here and the rest go to the worker owning the pair
                     let order_data = msg.to_vec();
-                    
-                    // TODO: Parse order message and process through matching engine
-                    // Example:
-                    // let order_result = {
-                    //     let mut engine = matching_engine_main.lock().unwrap();
-                    //     engine.limit_buy(...)?;
-                    // };
-                    
+                    if let Some((pool, _)) = &worker_pool {
+                        match network_module::decode_order_request(&order_data) {
+                            Ok(request) => match network_module::admit_order(
+                                &identity,
+                                request,
+                                &mut ack_cache,
+                                &order_age,
+                                &metrics_registry,
+                            ) {
+                                network_module::Admission::Respond(response) => {
+                                    if let Err(e) = network_module::send_response(order_router, &identity, &response) {
+                                        eprintln!("Error sending response: {}", e);
+                                    }
+                                }
+                                network_module::Admission::Hold => {}
+                                network_module::Admission::Process(request, _) => {
+                                    pool.dispatch(identity.to_vec(), *request)
+                                }
+                            },
+                            Err(e) => {
+                                let response = network_module::malformed_order_response(e);
+                                let response = network_module::encode_order_response(&response);
+                                if let Err(e) = network_module::send_response(order_router, &identity, &response) {
+                                    eprintln!("Error sending response: {}", e);
+                                }
+                            }
+                        }
+                    } else if let Err(e) = network_module::handle_order_message(
+                        order_router,
+                        &identity,
+                        &order_data,
+                        &matching_engine,
+                        &metrics_registry,
+                        &mut ack_cache,
+                        &order_age,
+                    ) {
+                        eprintln!("Error sending response: {}", e);
+                    }
+
                     // Send event to event streaming thread
-                    if let Err(e) = event_tx.send(order_data.to_vec()) {
+                    if let Err(e) = event_tx.send(order_data) {
                         eprintln!("Error sending event: {}", e);
                     }
-                    
-                    // Send acknowledgment back to gateway via ROUTER
-                    if let Err(e) = network_module::send_ack(order_router, &identity, "ACK") {
-                        eprintln!("Error sending ack: {}", e);
-                    }
                 }
             }
         }
     }
 
+    if let Some((pool, _)) = worker_pool {
+        pool.shutdown();
+    }
+
+    if let Err(e) = zmq_server.shutdown() {
+        eprintln!("Error resetting socket linger: {}", e);
+    }
+
     // Wait for all threads to finish
     println!("Waiting for threads to finish...");
     let _ = event_thread.join();
     let _ = zmq_event_backend_thread.join();
     let _ = metrics_event_backend_thread.join();
     let _ = logging_event_backend_thread.join();
-    // let _ = cron_thread.join();
-    let _ = snapshot_thread.join();
+    if let Some(ws_thread) = ws_thread {
+        let _ = ws_thread.join();
+    }
+    if let Some(event_log_thread) = event_log_thread {
+        let _ = event_log_thread.join();
+    }
+    let _ = cron_thread.join();
+    if let Some(order_history_writer) = order_history_writer {
+        order_history_writer.stop();
+    }
+    if let Err(e) = snapshot::stop_and_flush(snapshot_thread, &matching_engine, &snapshot_path, &metrics_registry) {
+        eprintln!("Error saving final snapshot: {}", e);
+    }
     let _ = metrics_thread.join();
+    if let Some(push_thread) = push_thread {
+        let _ = push_thread.join();
+    }
 
     println!("Orderbook Server shutdown complete");
     Ok(())
@@ -0,0 +1,132 @@
+use offgrid_primitives::spot::matching_engine;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Worker threads whose liveness `/health` reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    EventBackend,
+    Snapshot,
+    MainLoop,
+}
+
+impl Component {
+    pub const ALL: [Component; 3] = [Component::EventBackend, Component::Snapshot, Component::MainLoop];
+}
+
+/// Liveness flag and last heartbeat of one worker thread
+#[derive(Debug)]
+struct Liveness {
+    alive: AtomicBool,
+    // milliseconds since `Health::started`
+    last_beat: AtomicU64,
+}
+
+/// Shared liveness state of the worker threads
+/// Each thread beats once per loop iteration, a component is healthy while it is alive and its last beat is
+/// younger than `stale_after`.
+#[derive(Debug)]
+pub struct Health {
+    started: Instant,
+    stale_after: Duration,
+    components: [Liveness; 3],
+}
+
+/// Status of one component as served by `/health`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentStatus {
+    pub component: Component,
+    pub healthy: bool,
+    /// milliseconds since the last heartbeat
+    pub last_beat_ms: u64,
+}
+
+/// Body of the `/health` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    /// false if a thread panicked while holding the matching engine lock
+    pub engine_lock: bool,
+    /// false while the lock of a pair is poisoned by a panicked thread, it is recovered by the next access to the pair
+    pub pair_locks: bool,
+    /// pair locks recovered from poisoning since startup
+    pub pair_lock_recoveries: u64,
+    pub components: Vec<ComponentStatus>,
+}
+
+impl Health {
+    /// Every component starts alive with a fresh heartbeat, so a thread that never starts turns stale after `stale_after`
+    pub fn new(stale_after: Duration) -> Self {
+        let liveness = || Liveness {
+            alive: AtomicBool::new(true),
+            last_beat: AtomicU64::new(0),
+        };
+        Self {
+            started: Instant::now(),
+            stale_after,
+            components: [liveness(), liveness(), liveness()],
+        }
+    }
+
+    /// Record a loop iteration of `component`
+    pub fn beat(&self, component: Component) {
+        let liveness = &self.components[component as usize];
+        liveness.last_beat.store(self.elapsed_ms(), Ordering::Relaxed);
+        liveness.alive.store(true, Ordering::Relaxed);
+    }
+
+    /// Flag `component` as down until its next beat, e.g. when its thread exits
+    pub fn mark_unhealthy(&self, component: Component) {
+        self.components[component as usize].alive.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_healthy(&self, component: Component) -> bool {
+        let liveness = &self.components[component as usize];
+        liveness.alive.load(Ordering::Relaxed) && self.since_beat_ms(liveness) <= self.stale_after.as_millis() as u64
+    }
+
+    /// Status of every component, `engine_poisoned` and the count of `poisoned_pairs` are reported by the caller
+    /// holding the engine mutex
+    pub fn report(&self, engine_poisoned: bool, poisoned_pairs: usize) -> HealthReport {
+        let components: Vec<ComponentStatus> = Component::ALL
+            .iter()
+            .map(|&component| ComponentStatus {
+                component,
+                healthy: self.is_healthy(component),
+                last_beat_ms: self.since_beat_ms(&self.components[component as usize]),
+            })
+            .collect();
+        HealthReport {
+            healthy: !engine_poisoned && poisoned_pairs == 0 && components.iter().all(|status| status.healthy),
+            engine_lock: !engine_poisoned,
+            pair_locks: poisoned_pairs == 0,
+            pair_lock_recoveries: matching_engine::pair_lock_poison_recoveries(),
+            components,
+        }
+    }
+
+    fn since_beat_ms(&self, liveness: &Liveness) -> u64 {
+        self.elapsed_ms().saturating_sub(liveness.last_beat.load(Ordering::Relaxed))
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new(get_health_stale_after())
+    }
+}
+
+/// Get the staleness threshold from `HEALTH_STALE_SECS` (default: 30 seconds)
+pub fn get_health_stale_after() -> Duration {
+    let secs = std::env::var("HEALTH_STALE_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(30);
+    Duration::from_secs(secs)
+}
@@ -1,10 +1,19 @@
+pub mod health;
+
+use crate::snapshot::LevelExport;
+use offgrid_primitives::spot::event::{EventFilter, SpotEvent};
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::{MatchingEngine, Node, Order};
 use prometheus::{Encoder, Registry, TextEncoder};
+use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::Arc;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+pub use health::{Component, Health, HealthReport};
 
 /// Prometheus metrics registry
 pub struct Metrics {
@@ -21,6 +30,24 @@ pub struct Metrics {
     pub orderbook_depth_bid: prometheus::IntGauge,
     pub orderbook_depth_ask: prometheus::IntGauge,
     pub order_processing_duration: prometheus::Histogram,
+    pub order_fill_ratio: prometheus::Histogram,
+    pub snapshot_duration: prometheus::Histogram,
+    pub snapshot_bytes_written: prometheus::IntGauge,
+    pub snapshots_deferred: prometheus::IntCounter,
+    pub snapshot_interval_seconds: prometheus::Gauge,
+    pub events_dropped: prometheus::IntCounter,
+    pub engine_lock_poison_recoveries: prometheus::IntCounter,
+    pub pair_lock_poison_recoveries: prometheus::IntCounter,
+    pub fee_recipient_fallbacks: prometheus::IntCounter,
+    pub capped_orders: prometheus::IntCounter,
+    pub orders_throttled: prometheus::IntCounter,
+    pub orders_stale: prometheus::IntCounter,
+    pub order_history_write_failures: prometheus::IntCounter,
+    pub fees_collected: prometheus::IntCounterVec,
+    pub orderbook_microprice: prometheus::IntGaugeVec,
+    pub orderbook_imbalance: prometheus::GaugeVec,
+    pub resting_orders_total: prometheus::IntGauge,
+    pub orderbook_memory_bytes: prometheus::IntGauge,
 }
 
 impl Metrics {
@@ -77,6 +104,96 @@ impl Metrics {
             )
             .buckets(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]),
         )?;
+        let order_fill_ratio = prometheus::Histogram::with_opts(
+            prometheus::HistogramOpts::new(
+                "orderbook_order_fill_ratio",
+                "Filled share of the amount of taker orders when they finish matching",
+            )
+            // the 0.0 bucket counts the takers that filled nothing
+            .buckets(vec![0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0]),
+        )?;
+        let snapshot_duration = prometheus::Histogram::with_opts(
+            prometheus::HistogramOpts::new(
+                "orderbook_snapshot_duration_seconds",
+                "Time spent writing a snapshot of the matching engine",
+            )
+            .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0]),
+        )?;
+        let snapshot_bytes_written = prometheus::IntGauge::new(
+            "orderbook_snapshot_bytes_written",
+            "Size in bytes of the last snapshot written",
+        )?;
+        let snapshots_deferred = prometheus::IntCounter::new(
+            "orderbook_snapshots_deferred_total",
+            "Total number of snapshots deferred because the engine lock was contended",
+        )?;
+        let snapshot_interval_seconds = prometheus::Gauge::new(
+            "orderbook_snapshot_interval_seconds",
+            "Current interval of the snapshot thread, longer while it backs off",
+        )?;
+
+        let events_dropped = prometheus::IntCounter::new(
+            "orderbook_events_dropped_total",
+            "Number of events dropped by bounded event backends",
+        )?;
+        let engine_lock_poison_recoveries = prometheus::IntCounter::new(
+            "orderbook_engine_lock_poison_recoveries_total",
+            "Number of times the matching engine lock was recovered after a thread panicked holding it",
+        )?;
+        let pair_lock_poison_recoveries = prometheus::IntCounter::new(
+            "orderbook_pair_lock_poison_recoveries_total",
+            "Number of times a pair lock was recovered after a thread panicked holding it",
+        )?;
+        let fee_recipient_fallbacks = prometheus::IntCounter::new(
+            "orderbook_fee_recipient_fallbacks_total",
+            "Number of times a client without a fee recipient fell back to the default fee recipient",
+        )?;
+        let capped_orders = prometheus::IntCounter::new(
+            "orderbook_capped_orders_total",
+            "Number of taker orders whose matching was stopped by the maker cap of their pair",
+        )?;
+        let orders_throttled = prometheus::IntCounter::new(
+            "orderbook_orders_throttled_total",
+            "Total number of order requests rejected by the rate limiter",
+        )?;
+        let orders_stale = prometheus::IntCounter::new(
+            "orderbook_orders_stale_total",
+            "Total number of order requests rejected as older than the maximum order age",
+        )?;
+        let order_history_write_failures = prometheus::IntCounter::new(
+            "orderbook_order_history_write_failures_total",
+            "Total number of terminated orders that failed to be written to the order history",
+        )?;
+        let fees_collected = prometheus::IntCounterVec::new(
+            prometheus::Opts::new(
+                "orderbook_fees_collected_total",
+                "Total fees collected from maker and taker fills, by asset",
+            ),
+            &["asset"],
+        )?;
+        let orderbook_microprice = prometheus::IntGaugeVec::new(
+            prometheus::Opts::new(
+                "orderbook_microprice",
+                "Top of book mid price weighted by the opposite side quantities, by hex encoded pair id",
+            ),
+            &["pair"],
+        )?;
+        let orderbook_imbalance = prometheus::GaugeVec::new(
+            prometheus::Opts::new(
+                "orderbook_imbalance",
+                "Bid minus ask volume over their sum in the top levels, by hex encoded pair id",
+            ),
+            &["pair"],
+        )?;
+
+        let resting_orders_total = prometheus::IntGauge::new(
+            "resting_orders_total",
+            "Number of orders resting on the books of all pairs",
+        )?;
+        let orderbook_memory_bytes = prometheus::IntGauge::new(
+            "orderbook_memory_bytes",
+            "Estimated memory held by the resting orders of all pairs, in bytes",
+        )?;
 
         // Register metrics
         registry.register(Box::new(transfers_total.clone()))?;
@@ -91,6 +208,24 @@ impl Metrics {
         registry.register(Box::new(orderbook_depth_bid.clone()))?;
         registry.register(Box::new(orderbook_depth_ask.clone()))?;
         registry.register(Box::new(order_processing_duration.clone()))?;
+        registry.register(Box::new(order_fill_ratio.clone()))?;
+        registry.register(Box::new(snapshot_duration.clone()))?;
+        registry.register(Box::new(snapshot_bytes_written.clone()))?;
+        registry.register(Box::new(snapshots_deferred.clone()))?;
+        registry.register(Box::new(snapshot_interval_seconds.clone()))?;
+        registry.register(Box::new(events_dropped.clone()))?;
+        registry.register(Box::new(engine_lock_poison_recoveries.clone()))?;
+        registry.register(Box::new(pair_lock_poison_recoveries.clone()))?;
+        registry.register(Box::new(fee_recipient_fallbacks.clone()))?;
+        registry.register(Box::new(capped_orders.clone()))?;
+        registry.register(Box::new(orders_throttled.clone()))?;
+        registry.register(Box::new(orders_stale.clone()))?;
+        registry.register(Box::new(order_history_write_failures.clone()))?;
+        registry.register(Box::new(fees_collected.clone()))?;
+        registry.register(Box::new(orderbook_microprice.clone()))?;
+        registry.register(Box::new(orderbook_imbalance.clone()))?;
+        registry.register(Box::new(resting_orders_total.clone()))?;
+        registry.register(Box::new(orderbook_memory_bytes.clone()))?;
 
         Ok(Self {
             registry,
@@ -106,23 +241,214 @@ impl Metrics {
             orderbook_depth_bid,
             orderbook_depth_ask,
             order_processing_duration,
+            order_fill_ratio,
+            snapshot_duration,
+            snapshot_bytes_written,
+            snapshots_deferred,
+            snapshot_interval_seconds,
+            events_dropped,
+            engine_lock_poison_recoveries,
+            pair_lock_poison_recoveries,
+            fee_recipient_fallbacks,
+            capped_orders,
+            orders_throttled,
+            orders_stale,
+            order_history_write_failures,
+            fees_collected,
+            orderbook_microprice,
+            orderbook_imbalance,
+            resting_orders_total,
+            orderbook_memory_bytes,
+        })
+    }
+
+    /// Filter of the events `record_event` counts, for the metrics backend to skip the others
+    pub fn event_filter() -> EventFilter {
+        EventFilter::new(|event| {
+            matches!(
+                event,
+                SpotEvent::SpotOrderPlaced { .. }
+                    | SpotEvent::SpotOrderPartiallyFilled { .. }
+                    | SpotEvent::SpotOrderFullyFilled { .. }
+                    | SpotEvent::SpotOrderCancelled { .. }
+                    | SpotEvent::SpotOrderExpired { .. }
+                    | SpotEvent::SpotOrderIcebergQuantityChanged { .. }
+                    | SpotEvent::SpotTakerMatched { .. }
+            )
         })
     }
+
+    /// Update the metrics for an event from the event bus
+    pub fn record_event(&self, event: &SpotEvent) {
+        match event {
+            SpotEvent::SpotPairClientAccountChanged { .. } => {}
+            SpotEvent::SpotOrderPlaced { .. } => self.orders_placed.inc(),
+            SpotEvent::SpotOrderPartiallyFilled { .. } => self.orders_partially_filled.inc(),
+            SpotEvent::SpotOrderFullyFilled { .. } => self.orders_fully_filled.inc(),
+            SpotEvent::SpotOrderCancelled { .. } => self.orders_cancelled.inc(),
+            SpotEvent::SpotOrderExpired { .. } => self.orders_expired.inc(),
+            SpotEvent::SpotOrderReduced { .. } => {}
+            SpotEvent::SpotOrderDustSwept { .. } => {}
+            SpotEvent::SpotOrderIcebergQuantityChanged { .. } => self.order_iceberg_quantity_changed.inc(),
+            SpotEvent::Transfer { .. } => {}
+            SpotEvent::Lock { .. } => {}
+            SpotEvent::Unlock { .. } => {}
+            SpotEvent::SpotOrderBlockChanged { .. } => {}
+            SpotEvent::SpotLevelRemoved { .. } => {}
+            SpotEvent::SpotBookChecksum { .. } => {}
+            SpotEvent::SpotPairAdded { .. } => {}
+            SpotEvent::SpotTrade { .. } => {}
+            SpotEvent::SpotTakerMatched { amnt, cqty, .. } => {
+                if *amnt > 0 {
+                    self.order_fill_ratio
+                        .observe(amnt.saturating_sub(*cqty) as f64 / *amnt as f64);
+                }
+            }
+        }
+        self.record_fees(event);
+    }
+
+    /// Update the microprice and the imbalance over the top `depth` levels of every pair
+    ///
+    /// The microprice of a pair without both sides is removed rather than left at a stale value.
+    pub fn record_book_signals(&self, engine: &MatchingEngine, depth: u32) {
+        for pair_id in engine.list_pairs() {
+            let Some(pair) = engine.get_pair(&pair_id) else {
+                continue;
+            };
+            let label = encode_hex(&pair_id);
+            match pair.orderbook.microprice() {
+                Some(price) => self.orderbook_microprice.with_label_values(&[&label]).set(price as i64),
+                None => {
+                    let _ = self.orderbook_microprice.remove_label_values(&[&label]);
+                }
+            }
+            self.orderbook_imbalance
+                .with_label_values(&[&label])
+                .set(pair.orderbook.imbalance(depth));
+        }
+    }
+
+    /// Update the resting order count and the memory estimate from the books of every pair
+    pub fn record_resting_orders(&self, engine: &MatchingEngine) {
+        let mut orders = 0;
+        for pair_id in engine.list_pairs() {
+            if let Some(pair) = engine.get_pair(&pair_id) {
+                orders += pair.orderbook.l3.order_count();
+            }
+        }
+        self.resting_orders_total.set(orders as i64);
+        self.orderbook_memory_bytes.set((orders * RESTING_ORDER_BYTES) as i64);
+    }
+
+    /// Count the fees of a match once: the taker and maker events of a match carry the same
+    /// `base_fee`/`quote_fee`, which already cover both the maker and the taker leg
+    fn record_fees(&self, event: &SpotEvent) {
+        let (base_asset_id, quote_asset_id, base_fee, quote_fee) = match event {
+            SpotEvent::SpotOrderPartiallyFilled {
+                is_taker_event: true,
+                base_asset_id,
+                quote_asset_id,
+                base_fee,
+                quote_fee,
+                ..
+            }
+            | SpotEvent::SpotOrderFullyFilled {
+                is_taker_event: true,
+                base_asset_id,
+                quote_asset_id,
+                base_fee,
+                quote_fee,
+                ..
+            } => (base_asset_id, quote_asset_id, *base_fee, *quote_fee),
+            _ => return,
+        };
+        self.fees_collected
+            .with_label_values(&[&String::from_utf8_lossy(base_asset_id)])
+            .inc_by(base_fee);
+        self.fees_collected
+            .with_label_values(&[&String::from_utf8_lossy(quote_asset_id)])
+            .inc_by(quote_fee);
+    }
+}
+
+/// Default number of levels per side returned by `GET /book`
+const DEFAULT_BOOK_DEPTH: usize = 10;
+
+/// Estimated bytes held per resting order: the order and its queue node, plus the entries keying them by id
+/// in the L3 maps and the owner index. Heap allocations of the ids and owners are not counted.
+pub const RESTING_ORDER_BYTES: usize =
+    size_of::<Order>() + size_of::<Node>() + 3 * (size_of::<OrderId>() + MAP_ENTRY_OVERHEAD);
+
+// control bytes and padding of a hash map entry
+const MAP_ENTRY_OVERHEAD: usize = 16;
+
+/// How often the metrics thread refreshes the gauges it computes from the engine
+pub const ENGINE_STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of levels per side the `orderbook_imbalance` gauge is computed over
+pub const IMBALANCE_DEPTH: u32 = 5;
+
+/// Top of book of a pair as served by `GET /book`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    /// hex encoded pair id
+    pub pair_id: String,
+    /// bid levels, best (highest) price first
+    pub bids: Vec<LevelExport>,
+    /// ask levels, best (lowest) price first
+    pub asks: Vec<LevelExport>,
+}
+
+/// Build the top `depth` L2 levels of both sides of a pair, `None` if the pair does not exist
+pub fn book_snapshot(engine: &MatchingEngine, pair_id: &[u8], depth: usize) -> Option<BookSnapshot> {
+    let pair = engine.get_pair(pair_id)?;
+    let l2 = &pair.orderbook.l2;
+    // a bucket width of one keeps every price level as is
+    let levels = |is_bid: bool| -> Vec<LevelExport> {
+        l2.get_aggregated_snapshot(is_bid, 1, depth)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|[price, pqty, cqty]| LevelExport { price, pqty, cqty })
+            .collect()
+    };
+    Some(BookSnapshot {
+        pair_id: encode_hex(pair_id),
+        bids: levels(true),
+        asks: levels(false),
+    })
 }
 
 /// Spawn Prometheus metrics HTTP server thread
+///
+/// Besides `/metrics` and `/health` it serves `GET /book?pair=<hex>&depth=<n>` from the shared engine.
+/// `/health` answers 503 with the per-component status once a worker thread in `health` goes stale.
 pub fn spawn_metrics_thread(
     metrics: Arc<Metrics>,
+    engine: Arc<Mutex<MatchingEngine>>,
+    health: Arc<Health>,
     shutdown_flag: Arc<AtomicBool>,
     port: u16,
+) -> thread::JoinHandle<()> {
+    let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+    spawn_metrics_thread_on(metrics, engine, health, shutdown_flag, address)
+}
+
+/// Spawn Prometheus metrics HTTP server thread listening on `address` only, e.g. `127.0.0.1:9090`
+pub fn spawn_metrics_thread_on(
+    metrics: Arc<Metrics>,
+    engine: Arc<Mutex<MatchingEngine>>,
+    health: Arc<Health>,
+    shutdown_flag: Arc<AtomicBool>,
+    address: SocketAddr,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        println!("Prometheus metrics thread started on port {}", port);
+        println!("Prometheus metrics thread started on {}", address);
 
-        let listener = match TcpListener::bind(format!("0.0.0.0:{}", port)) {
+        let listener = match TcpListener::bind(address) {
             Ok(listener) => listener,
             Err(e) => {
-                eprintln!("Failed to bind metrics server on port {}: {}", port, e);
+                eprintln!("Failed to bind metrics server on {}: {}", address, e);
                 return;
             }
         };
@@ -132,10 +458,15 @@ pub fn spawn_metrics_thread(
             .set_nonblocking(true)
             .expect("Failed to set non-blocking");
 
+        let mut engine_stats_at: Option<Instant> = None;
         loop {
             if shutdown_flag.load(Ordering::Relaxed) {
                 break;
             }
+            if engine_stats_at.is_none_or(|at| at.elapsed() >= ENGINE_STATS_INTERVAL) {
+                metrics.record_resting_orders(&crate::lock_engine(&engine).share());
+                engine_stats_at = Some(Instant::now());
+            }
 
             match listener.accept() {
                 Ok((mut stream, _)) => {
@@ -143,7 +474,7 @@ pub fn spawn_metrics_thread(
                     stream
                         .set_read_timeout(Some(Duration::from_secs(5)))
                         .expect("Failed to set read timeout");
-                    handle_metrics_request(&mut stream, &metrics);
+                    handle_metrics_request(&mut stream, &metrics, &engine, &health);
                 }
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     // No connection available, continue loop
@@ -161,12 +492,18 @@ pub fn spawn_metrics_thread(
     })
 }
 
-fn handle_metrics_request(stream: &mut TcpStream, metrics: &Metrics) {
+fn handle_metrics_request(stream: &mut TcpStream, metrics: &Metrics, engine: &Mutex<MatchingEngine>, health: &Health) {
     let mut buffer = [0; 1024];
     let _ = stream.read(&mut buffer);
 
     let request = String::from_utf8_lossy(&buffer);
-    let response = if request.starts_with("GET /metrics") {
+    let response = if request.starts_with("GET /book") {
+        handle_book_request(&request, engine)
+    } else if request.starts_with("GET /metrics") {
+        // book signals are computed on scrape, holding each pair lock only while its top levels are read
+        // the guard of the engine lock drops at the end of this statement, not after the scrape
+        let pairs = crate::lock_engine(engine).share();
+        metrics.record_book_signals(&pairs, IMBALANCE_DEPTH);
         let encoder = TextEncoder::new();
         let metric_families = metrics.registry.gather();
         let mut buffer = Vec::new();
@@ -179,7 +516,7 @@ fn handle_metrics_request(stream: &mut TcpStream, metrics: &Metrics) {
             String::from_utf8_lossy(&buffer)
         )
     } else if request.starts_with("GET /health") {
-        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK".to_string()
+        handle_health_request(engine, health)
     } else {
         "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nNot Found".to_string()
     };
@@ -188,6 +525,68 @@ fn handle_metrics_request(stream: &mut TcpStream, metrics: &Metrics) {
     let _ = stream.flush();
 }
 
+fn handle_health_request(engine: &Mutex<MatchingEngine>, health: &Health) -> String {
+    // read before `lock_engine` recovers the lock
+    let engine_poisoned = engine.is_poisoned();
+    let poisoned_pairs = crate::lock_engine(engine).poisoned_pairs();
+    let report = health.report(engine_poisoned, poisoned_pairs);
+    let status = if report.healthy { "200 OK" } else { "503 Service Unavailable" };
+    let body = serde_json::to_string(&report).unwrap_or_default();
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn handle_book_request(request: &str, engine: &Mutex<MatchingEngine>) -> String {
+    // request line: `GET /book?pair=<hex>&depth=<n> HTTP/1.1`
+    let target = request.split_whitespace().nth(1).unwrap_or_default();
+    let query = target.split_once('?').map(|(_, query)| query).unwrap_or_default();
+    let mut pair_id = None;
+    let mut depth = Some(DEFAULT_BOOK_DEPTH);
+    for (key, value) in query.split('&').filter_map(|param| param.split_once('=')) {
+        match key {
+            "pair" => pair_id = decode_hex(value),
+            "depth" => depth = value.parse::<usize>().ok(),
+            _ => {}
+        }
+    }
+    let (Some(pair_id), Some(depth)) = (pair_id, depth) else {
+        return "HTTP/1.1 400 Bad Request\r\nContent-Length: 11\r\n\r\nBad Request".to_string();
+    };
+
+    // hold the engine lock only to take a handle and the pair lock while the levels are copied out
+    let pairs = crate::lock_engine(engine).share();
+    let snapshot = book_snapshot(&pairs, &pair_id, depth);
+    match snapshot.map(|snapshot| serde_json::to_string(&snapshot)) {
+        Some(Ok(body)) => format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ),
+        Some(Err(_)) => {
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 21\r\n\r\nInternal Server Error".to_string()
+        }
+        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nNot Found".to_string(),
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
 /// Get metrics port from environment variable or use default
 pub fn get_metrics_port() -> u16 {
     std::env::var("METRICS_PORT")
@@ -195,3 +594,127 @@ pub fn get_metrics_port() -> u16 {
         .parse::<u16>()
         .unwrap_or(9090)
 }
+
+/// Get the interface the metrics server binds to from `METRICS_BIND` (default: `0.0.0.0`, all interfaces)
+pub fn get_metrics_bind() -> anyhow::Result<IpAddr> {
+    match std::env::var("METRICS_BIND") {
+        Ok(value) => value
+            .parse::<IpAddr>()
+            .map_err(|e| anyhow::anyhow!("invalid METRICS_BIND {:?}: {}", value, e)),
+        Err(_) => Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+    }
+}
+
+/// Pushgateway target the metrics are periodically pushed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushConfig {
+    /// base url of the pushgateway, e.g. `http://pushgateway:9091`
+    pub url: String,
+    pub interval: Duration,
+    pub job: String,
+    pub instance: String,
+}
+
+/// Get pushgateway settings from `PUSHGATEWAY_URL`, `PUSH_INTERVAL_SECS`, `PUSH_JOB` and `PUSH_INSTANCE`
+/// Returns None when `PUSHGATEWAY_URL` is unset, pushing is disabled then
+/// Fails on a `PUSH_INTERVAL_SECS` of 0, the thread would push without pause
+pub fn get_push_config() -> anyhow::Result<Option<PushConfig>> {
+    let Ok(url) = std::env::var("PUSHGATEWAY_URL") else {
+        return Ok(None);
+    };
+    let interval = std::env::var("PUSH_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(15);
+    if interval == 0 {
+        anyhow::bail!("PUSH_INTERVAL_SECS must be at least 1");
+    }
+    Ok(Some(PushConfig {
+        url,
+        interval: Duration::from_secs(interval),
+        job: std::env::var("PUSH_JOB").unwrap_or_else(|_| "offgrid-spot-runtime".to_string()),
+        instance: std::env::var("PUSH_INSTANCE").unwrap_or_else(|_| "default".to_string()),
+    }))
+}
+
+/// Most time a push may spend connecting to, writing to or reading from the pushgateway
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Percent-encodes a label value for a path segment of the pushgateway url, keeping only unreserved characters
+fn encode_label(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Push the current metrics to the pushgateway, grouped by the `job` and `instance` labels
+pub fn push_metrics(metrics: &Metrics, config: &PushConfig) -> anyhow::Result<()> {
+    let address = config
+        .url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow::anyhow!("pushgateway url must start with http://"))?
+        .trim_end_matches('/');
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&metrics.registry.gather(), &mut body)?;
+
+    let socket_address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("pushgateway address {} does not resolve", address))?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, PUSH_TIMEOUT)?;
+    stream.set_read_timeout(Some(PUSH_TIMEOUT))?;
+    stream.set_write_timeout(Some(PUSH_TIMEOUT))?;
+    let request = format!(
+        "POST /metrics/job/{}/instance/{} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        encode_label(&config.job),
+        encode_label(&config.instance),
+        address,
+        encoder.format_type(),
+        body.len()
+    );
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(&body)?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        anyhow::bail!("pushgateway responded with status {}", status);
+    }
+    Ok(())
+}
+
+/// Spawn a thread pushing the metrics to the pushgateway every `config.interval`
+pub fn spawn_push_thread(
+    metrics: Arc<Metrics>,
+    shutdown_flag: Arc<AtomicBool>,
+    config: PushConfig,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        println!("Prometheus push thread started, pushing to {} every {:?}", config.url, config.interval);
+        let mut last_push: Option<std::time::Instant> = None;
+
+        loop {
+            if shutdown_flag.load(Ordering::Relaxed) {
+                break;
+            }
+
+            if last_push.is_none_or(|at| at.elapsed() >= config.interval) {
+                if let Err(e) = push_metrics(&metrics, &config) {
+                    eprintln!("Error pushing metrics: {}", e);
+                }
+                last_push = Some(std::time::Instant::now());
+            }
+            thread::sleep(Duration::from_millis(100).min(config.interval));
+        }
+
+        println!("Prometheus push thread stopped");
+    })
+}
@@ -0,0 +1,178 @@
+use offgrid_primitives::spot::clock::ClockHandle;
+use offgrid_primitives::spot::{Clock, SystemClock};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::proto::{order_request::Request, OrderRequest};
+
+/// ROUTER identity of the client and the client order id it tagged the order with
+pub type AckKey = (Vec<u8>, Vec<u8>);
+
+/// Encoded response of one order and when it was cached
+#[derive(Debug, Clone)]
+struct Ack {
+    response: Vec<u8>,
+    cached_at: i64,
+    // position in `AckCache::recency`
+    used: u64,
+}
+
+/// What the cache knows of an order carrying a client order id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AckState {
+    /// the order was answered with this encoded response
+    Answered(Vec<u8>),
+    /// the order was dispatched and is not answered yet
+    InFlight,
+}
+
+/// Least recently used cache of the responses sent for orders carrying a client order id
+/// A retransmitted order is answered with the cached response instead of reaching the matching engine again.
+/// At most `capacity` responses are kept, each for `ttl_ms` after it was cached. An order is also tracked
+/// from its dispatch until it is answered, so a retransmission arriving meanwhile is held instead of processed.
+#[derive(Debug)]
+pub struct AckCache {
+    capacity: usize,
+    ttl_ms: i64,
+    clock: ClockHandle,
+    acks: HashMap<AckKey, Ack>,
+    // dispatched orders not answered yet -> retransmissions held until they are
+    in_flight: HashMap<AckKey, usize>,
+    // use counter -> key, the first entry is the least recently used
+    recency: BTreeMap<u64, AckKey>,
+    uses: u64,
+}
+
+impl AckCache {
+    pub fn new(capacity: usize, ttl_ms: i64) -> Self {
+        Self::with_clock(capacity, ttl_ms, SystemClock)
+    }
+
+    pub fn with_clock(capacity: usize, ttl_ms: i64, clock: impl Clock + 'static) -> Self {
+        Self {
+            capacity,
+            ttl_ms,
+            clock: ClockHandle::new(clock),
+            acks: HashMap::new(),
+            in_flight: HashMap::new(),
+            recency: BTreeMap::new(),
+            uses: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.acks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.acks.is_empty()
+    }
+
+    /// Cached response for `key`, None if it was never cached, evicted or is older than the TTL
+    pub fn get(&mut self, key: &AckKey) -> Option<Vec<u8>> {
+        let now = self.clock.now_millis();
+        let ack = self.acks.get(key)?;
+        if now.saturating_sub(ack.cached_at) > self.ttl_ms {
+            self.remove(key);
+            return None;
+        }
+        let used = self.next_use();
+        let ack = self.acks.get_mut(key)?;
+        self.recency.remove(&ack.used);
+        ack.used = used;
+        self.recency.insert(used, key.clone());
+        Some(ack.response.clone())
+    }
+
+    /// State of the order cached under `key`, None if it was never seen, evicted or is older than the TTL
+    pub fn lookup(&mut self, key: &AckKey) -> Option<AckState> {
+        if self.in_flight.contains_key(key) {
+            return Some(AckState::InFlight);
+        }
+        self.get(key).map(AckState::Answered)
+    }
+
+    /// Track the order under `key` as dispatched until `complete` answers it, nothing is tracked when the cache is
+    /// disabled
+    pub fn mark_in_flight(&mut self, key: AckKey) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.in_flight.insert(key, 0);
+    }
+
+    /// Hold a retransmission of the in-flight order under `key`, it is answered when the order is
+    pub fn hold(&mut self, key: &AckKey) {
+        if let Some(held) = self.in_flight.get_mut(key) {
+            *held += 1;
+        }
+    }
+
+    /// Cache the response of the in-flight order under `key`
+    /// - returns the number of held retransmissions to answer with the same response.
+    pub fn complete(&mut self, key: AckKey, response: Vec<u8>) -> usize {
+        let held = self.in_flight.remove(&key).unwrap_or(0);
+        self.insert(key, response);
+        held
+    }
+
+    /// Cache the response sent for `key`, evicting the least recently used one when full
+    pub fn insert(&mut self, key: AckKey, response: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.acks.len() >= self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.acks.remove(&oldest);
+        }
+        let used = self.next_use();
+        self.recency.insert(used, key.clone());
+        self.acks.insert(
+            key,
+            Ack {
+                response,
+                cached_at: self.clock.now_millis(),
+                used,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &AckKey) {
+        if let Some(ack) = self.acks.remove(key) {
+            self.recency.remove(&ack.used);
+        }
+    }
+
+    fn next_use(&mut self) -> u64 {
+        self.uses += 1;
+        self.uses
+    }
+}
+
+/// Client order id of a placement, None for cancels, amends and orders sent without one
+pub fn request_client_order_id(request: &OrderRequest) -> Option<&[u8]> {
+    let client_order_id = match request.request.as_ref()? {
+        Request::Limit(order) => &order.client_order_id,
+        Request::Market(order) => &order.client_order_id,
+        Request::Cancel(_) | Request::Amend(_) => return None,
+    };
+    (!client_order_id.is_empty()).then_some(client_order_id.as_slice())
+}
+
+/// Cache key of a request received from `identity`, None if its response is not cached
+pub fn ack_key(identity: &[u8], request: &OrderRequest) -> Option<AckKey> {
+    request_client_order_id(request).map(|client_order_id| (identity.to_vec(), client_order_id.to_vec()))
+}
+
+/// Get the ACK cache size and TTL in milliseconds from `ACK_CACHE_SIZE` and `ACK_CACHE_TTL_SECS` (default: 10000 responses for 300 seconds)
+pub fn get_ack_cache_config() -> anyhow::Result<(usize, i64)> {
+    let size = std::env::var("ACK_CACHE_SIZE")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse::<usize>()?;
+    let ttl_secs = std::env::var("ACK_CACHE_TTL_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse::<i64>()?;
+    Ok((size, ttl_secs.saturating_mul(1000)))
+}
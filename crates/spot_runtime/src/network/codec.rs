@@ -0,0 +1,66 @@
+use anyhow::{anyhow, bail, Result};
+use offgrid_primitives::spot::event::SequencedEvent;
+
+/// Wire format of the events published by the ZMQ event backend
+/// Every payload starts with the one-byte tag of its codec so subscribers can pick the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum EventCodec {
+    /// `serde_json`, byte vectors are encoded as arrays of numbers
+    #[default]
+    Json = 1,
+    /// `postcard`, the compact binary format the snapshots are stored in
+    Postcard = 2,
+}
+
+impl EventCodec {
+    /// One-byte tag prefixed to every payload of the codec
+    pub fn tag(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(EventCodec::Json),
+            2 => Some(EventCodec::Postcard),
+            _ => None,
+        }
+    }
+
+    /// Parse a codec name as set in `EVENT_CODEC`, case-insensitive
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(EventCodec::Json),
+            "postcard" => Some(EventCodec::Postcard),
+            _ => None,
+        }
+    }
+
+    /// Encode `event` prefixed with the codec tag
+    pub fn encode(self, event: &SequencedEvent) -> Result<Vec<u8>> {
+        let mut payload = vec![self.tag()];
+        match self {
+            EventCodec::Json => serde_json::to_writer(&mut payload, event)?,
+            EventCodec::Postcard => payload.extend(postcard::to_allocvec(event)?),
+        }
+        Ok(payload)
+    }
+
+    /// Decode a payload produced by `encode` with any codec, read from its tag
+    pub fn decode(payload: &[u8]) -> Result<SequencedEvent> {
+        let (&tag, body) = payload.split_first().ok_or_else(|| anyhow!("event payload is empty"))?;
+        match Self::from_tag(tag) {
+            Some(EventCodec::Json) => Ok(serde_json::from_slice(body)?),
+            Some(EventCodec::Postcard) => Ok(postcard::from_bytes(body)?),
+            None => bail!("unknown event codec tag {}", tag),
+        }
+    }
+}
+
+/// Get the event codec from `EVENT_CODEC`, `json` or `postcard` (default: json)
+pub fn get_event_codec() -> Result<EventCodec> {
+    match std::env::var("EVENT_CODEC") {
+        Ok(name) => EventCodec::from_name(&name).ok_or_else(|| anyhow!("unknown EVENT_CODEC {}", name)),
+        Err(_) => Ok(EventCodec::default()),
+    }
+}
@@ -1,10 +1,37 @@
-use anyhow::Result;
-use std::sync::Arc;
+pub mod acks;
+pub mod codec;
+pub mod order_age;
+pub mod rate_limit;
+pub mod workers;
+
+use anyhow::{anyhow, bail, Result};
+use offgrid_primitives::spot::event::{self, EventQueue, SpotEvent};
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::MatchingEngine;
+use prost::Message;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
-use zmq::{Context, Socket, PUB, ROUTER};
+use zmq::{Context, Socket, PUB, REP, ROUTER};
+
+pub use acks::{AckCache, AckKey, AckState};
+pub use codec::EventCodec;
+pub use order_age::OrderAgeGuard;
+pub use rate_limit::RateLimiter;
+pub use workers::{pair_shard, WorkerPool, WorkerResponse, WorkerResponses};
+
+use crate::metrics::Metrics;
+use crate::proto::{self, order_request::Request, OrderRequest, OrderResponse, ResponseStatus};
+
+/// Endpoint libzmq sends ZAP authentication requests to
+const ZAP_ENDPOINT: &str = "inproc://zeromq.zap.01";
+/// ZAP domain set on the secured sockets
+const ZAP_DOMAIN: &str = "offgrid";
 
 /// ZMQ server for handling event streaming and order processing
 pub struct ZmqServer {
@@ -22,20 +49,120 @@ pub struct ZmqServer {
 unsafe impl Send for ZmqServer {}
 unsafe impl Sync for ZmqServer {}
 
+/// Socket options applied to the PUB and ROUTER sockets before binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// send high-water mark in messages, PUB drops messages beyond it (default 1000)
+    pub sndhwm: i32,
+    /// receive high-water mark in messages (default 1000)
+    pub rcvhwm: i32,
+    /// milliseconds pending messages are kept after close, -1 waits forever (default 0)
+    pub linger_ms: i32,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            sndhwm: 1000,
+            rcvhwm: 1000,
+            linger_ms: 0,
+        }
+    }
+}
+
+impl SocketOptions {
+    fn apply(&self, socket: &Socket) -> Result<()> {
+        socket.set_sndhwm(self.sndhwm)?;
+        socket.set_rcvhwm(self.rcvhwm)?;
+        socket.set_linger(self.linger_ms)?;
+        Ok(())
+    }
+}
+
 impl ZmqServer {
     /// Create a new ZMQ server with PUB socket for events and ROUTER socket for orders
     pub fn new(context: &Context, event_port: u16, order_port: u16) -> Result<Self> {
-        // Create PUB socket for event streaming
-        let event_pub = context.socket(PUB)?;
-        event_pub.bind(&format!("tcp://*:{}", event_port))?;
+        Self::new_endpoints(context, &tcp_endpoint(event_port), &tcp_endpoint(order_port))
+    }
 
-        // Create ROUTER socket for order processing (dealer pattern)
-        let order_router = context.socket(ROUTER)?;
-        order_router.bind(&format!("tcp://*:{}", order_port))?;
+    /// Create a new ZMQ server bound to full endpoints, e.g. `ipc:///tmp/orders.ipc` for a co-located gateway
+    pub fn new_endpoints(context: &Context, event_endpoint: &str, order_endpoint: &str) -> Result<Self> {
+        Self::with_endpoint_options(context, event_endpoint, order_endpoint, &SocketOptions::default())
+    }
+
+    /// Create a new ZMQ server applying `options` to both sockets
+    pub fn with_options(context: &Context, event_port: u16, order_port: u16, options: &SocketOptions) -> Result<Self> {
+        Self::with_endpoint_options(context, &tcp_endpoint(event_port), &tcp_endpoint(order_port), options)
+    }
+
+    /// Create a new ZMQ server bound to full endpoints applying `options` to both sockets
+    pub fn with_endpoint_options(
+        context: &Context,
+        event_endpoint: &str,
+        order_endpoint: &str,
+        options: &SocketOptions,
+    ) -> Result<Self> {
+        Self::bind(context, event_endpoint, order_endpoint, options, None)
+    }
+
+    /// Create a new ZMQ server with CURVE encryption on both sockets
+    /// Only clients whose CURVE public key is in `allowed_client_keys` pass the ZAP handshake
+    pub fn new_secure(
+        context: &Context,
+        event_port: u16,
+        order_port: u16,
+        server_secret_key: &[u8],
+        allowed_client_keys: Vec<[u8; 32]>,
+    ) -> Result<Self> {
+        Self::new_secure_endpoints(
+            context,
+            &tcp_endpoint(event_port),
+            &tcp_endpoint(order_port),
+            &SocketOptions::default(),
+            server_secret_key,
+            allowed_client_keys,
+        )
+    }
+
+    /// Create a new ZMQ server with CURVE encryption bound to full endpoints applying `options` to both sockets
+    pub fn new_secure_endpoints(
+        context: &Context,
+        event_endpoint: &str,
+        order_endpoint: &str,
+        options: &SocketOptions,
+        server_secret_key: &[u8],
+        allowed_client_keys: Vec<[u8; 32]>,
+    ) -> Result<Self> {
+        if zmq::has("curve") != Some(true) {
+            bail!("libzmq was built without CURVE support");
+        }
+        spawn_zap_handler(context, allowed_client_keys.into_iter().collect())?;
+        Self::bind(context, event_endpoint, order_endpoint, options, Some(server_secret_key))
+    }
+
+    /// Set up and bind both sockets, as CURVE servers when `server_secret_key` is given
+    fn bind(
+        context: &Context,
+        event_endpoint: &str,
+        order_endpoint: &str,
+        options: &SocketOptions,
+        server_secret_key: Option<&[u8]>,
+    ) -> Result<Self> {
+        let socket = |kind, endpoint: &str| -> Result<Socket> {
+            let socket = context.socket(kind)?;
+            options.apply(&socket)?;
+            if let Some(server_secret_key) = server_secret_key {
+                secure_socket(&socket, server_secret_key)?;
+            }
+            socket.bind(endpoint)?;
+            Ok(socket)
+        };
 
         Ok(Self {
-            event_pub,
-            order_router,
+            // PUB socket for event streaming
+            event_pub: socket(PUB, event_endpoint)?,
+            // ROUTER socket for order processing (dealer pattern)
+            order_router: socket(ROUTER, order_endpoint)?,
         })
     }
 
@@ -45,10 +172,104 @@ impl ZmqServer {
         Ok(())
     }
 
+    /// Publish an event as a `[topic, payload]` multipart message
+    /// SUB clients filter on the topic frame with a `SUBSCRIBE` prefix
+    pub fn publish_event_topic(&self, topic: &[u8], event: &[u8]) -> Result<()> {
+        self.event_pub.send(topic, zmq::SNDMORE)?;
+        self.event_pub.send(event, 0)?;
+        Ok(())
+    }
+
     /// Get a reference to the order router socket
     pub fn order_router(&self) -> &Socket {
         &self.order_router
     }
+
+    /// Get a reference to the event publisher socket
+    pub fn event_pub(&self) -> &Socket {
+        &self.event_pub
+    }
+
+    /// Drop pending messages on close so shutdown does not block on slow peers
+    pub fn shutdown(&self) -> Result<()> {
+        self.event_pub.set_linger(0)?;
+        self.order_router.set_linger(0)?;
+        Ok(())
+    }
+}
+
+fn tcp_endpoint(port: u16) -> String {
+    tcp_endpoint_on("*", port)
+}
+
+/// TCP endpoint on `iface`, `*` for all interfaces; IPv6 addresses are bracketed
+fn tcp_endpoint_on(iface: &str, port: u16) -> String {
+    if iface.contains(':') {
+        format!("tcp://[{}]:{}", iface, port)
+    } else {
+        format!("tcp://{}:{}", iface, port)
+    }
+}
+
+fn secure_socket(socket: &Socket, server_secret_key: &[u8]) -> Result<()> {
+    socket.set_curve_server(true)?;
+    socket.set_curve_secretkey(server_secret_key)?;
+    socket.set_zap_domain(ZAP_DOMAIN)?;
+    Ok(())
+}
+
+/// Spawn the ZAP handler that authenticates CURVE clients against the allowed keys
+/// The thread exits once the context is terminated
+fn spawn_zap_handler(context: &Context, allowed_client_keys: HashSet<[u8; 32]>) -> Result<thread::JoinHandle<()>> {
+    let handler = context.socket(REP)?;
+    handler.bind(ZAP_ENDPOINT)?;
+    Ok(thread::spawn(move || {
+        // Request: [version, request_id, domain, address, identity, mechanism, client_key]
+        while let Ok(request) = handler.recv_multipart(0) {
+            if request.len() < 6 {
+                continue;
+            }
+            let authorized = request[5] == b"CURVE"
+                && request
+                    .get(6)
+                    .and_then(|key| <[u8; 32]>::try_from(key.as_slice()).ok())
+                    .is_some_and(|key| allowed_client_keys.contains(&key));
+            let (status_code, status_text): (&[u8], &[u8]) = if authorized {
+                (b"200", b"OK")
+            } else {
+                (b"400", b"client key not allowed")
+            };
+            // Reply: [version, request_id, status_code, status_text, user_id, metadata]
+            let reply: [&[u8]; 6] = [&request[0], &request[1], status_code, status_text, b"", b""];
+            if handler.send_multipart(reply, 0).is_err() {
+                break;
+            }
+        }
+    }))
+}
+
+/// Topic an event is published under: its pair id, or the event type for events without one
+pub fn event_topic(event: &SpotEvent) -> Vec<u8> {
+    match event {
+        SpotEvent::SpotPairClientAccountChanged { pair_id, .. }
+        | SpotEvent::SpotPairAdded { pair_id, .. }
+        | SpotEvent::Lock { pair_id, .. }
+        | SpotEvent::Unlock { pair_id, .. }
+        | SpotEvent::SpotOrderBlockChanged { pair_id, .. }
+        | SpotEvent::SpotLevelRemoved { pair_id, .. }
+        | SpotEvent::SpotTakerMatched { pair_id, .. }
+        | SpotEvent::SpotTrade { pair_id, .. }
+        | SpotEvent::SpotOrderReduced { pair_id, .. }
+        | SpotEvent::SpotOrderDustSwept { pair_id, .. }
+        | SpotEvent::SpotBookChecksum { pair_id, .. }
+        | SpotEvent::SpotOrderPlaced { pair_id, .. }
+        | SpotEvent::SpotOrderPartiallyFilled { pair_id, .. }
+        | SpotEvent::SpotOrderFullyFilled { pair_id, .. } => pair_id.clone(),
+        SpotEvent::Transfer { .. } => b"Transfer".to_vec(),
+        SpotEvent::SpotOrderCancelled { .. } => b"SpotOrderCancelled".to_vec(),
+        SpotEvent::SpotOrderExpired { .. } => b"SpotOrderExpired".to_vec(),
+        SpotEvent::SpotOrderIcebergQuantityChanged { .. } => b"SpotOrderIcebergQuantityChanged".to_vec(),
+    }
 }
 
 /// Spawn a thread that streams events via ZMQ PUB socket
@@ -110,6 +331,277 @@ pub fn send_ack(order_router: &Socket, identity: &zmq::Message, ack: &str) -> Re
     Ok(())
 }
 
+/// Send an encoded response back to the client via ROUTER socket
+pub fn send_response(order_router: &Socket, identity: &zmq::Message, response: &[u8]) -> Result<()> {
+    // ROUTER socket sends: [identity, empty, message]
+    order_router.send(identity.as_ref(), zmq::SNDMORE)?;
+    order_router.send(&[] as &[u8], zmq::SNDMORE)?;
+    order_router.send(response, 0)?;
+    Ok(())
+}
+
+/// What becomes of a decoded order before it reaches the matching engine, see `admit_order`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    /// answer the order with this encoded response without processing it
+    Respond(Vec<u8>),
+    /// a retransmission of an order still in flight, answered when that order is
+    Hold,
+    /// process the order, completing its ack key in the cache with the response
+    Process(Box<OrderRequest>, Option<AckKey>),
+}
+
+/// Check a decoded order against the ack cache and the order age before it reaches the matching engine
+/// - an order already answered for the same client order id gets the cached response.
+/// - a retransmission of an order still in flight is held in `acks`.
+/// - any other order older than `order_age` allows is rejected.
+/// - an order to process is tracked as in flight until its ack key is completed.
+pub fn admit_order(
+    identity: &[u8],
+    request: OrderRequest,
+    acks: &mut AckCache,
+    order_age: &OrderAgeGuard,
+    metrics: &Metrics,
+) -> Admission {
+    let ack_key = acks::ack_key(identity, &request);
+    if let Some(key) = &ack_key {
+        match acks.lookup(key) {
+            Some(AckState::Answered(response)) => return Admission::Respond(response),
+            Some(AckState::InFlight) => {
+                acks.hold(key);
+                return Admission::Hold;
+            }
+            None => {}
+        }
+    }
+    if order_age.is_stale(&request) {
+        metrics.orders_stale.inc();
+        return Admission::Respond(encode_order_response(&stale_order_response(request)));
+    }
+    if let Some(key) = &ack_key {
+        acks.mark_in_flight(key.clone());
+    }
+    Admission::Process(Box::new(request), ack_key)
+}
+
+/// Handle one order message from the ROUTER socket: decode it, run it through the matching engine
+/// and send the encoded response back to `identity`, observing the whole round in
+/// `order_processing_duration`
+/// The order is checked with `admit_order` first, so it may be answered without reaching the engine.
+pub fn handle_order_message(
+    order_router: &Socket,
+    identity: &zmq::Message,
+    order_data: &[u8],
+    engine: &Mutex<MatchingEngine>,
+    metrics: &Metrics,
+    acks: &mut AckCache,
+    order_age: &OrderAgeGuard,
+) -> Result<()> {
+    let _timer = metrics.order_processing_duration.start_timer();
+    let response = match decode_order_request(order_data) {
+        Ok(request) => match admit_order(identity, request, acks, order_age, metrics) {
+            Admission::Respond(response) => response,
+            // orders are processed one at a time here, nothing is in flight while the next one is read
+            Admission::Hold => return Ok(()),
+            Admission::Process(request, ack_key) => {
+                // the engine lock is only held to take a handle, the order then locks its pair alone
+                let engine = crate::lock_engine(engine).share();
+                let response = encode_order_response(&process_order_request(&engine, *request));
+                if let Some(key) = ack_key {
+                    acks.complete(key, response.clone());
+                }
+                response
+            }
+        },
+        Err(e) => encode_order_response(&malformed_order_response(e)),
+    };
+    send_response(order_router, identity, &response)
+}
+
+/// Decode an order request received from the gateway
+/// Fails on malformed bytes and on requests without a limit/market/cancel/amend body
+pub fn decode_order_request(bytes: &[u8]) -> Result<OrderRequest> {
+    let request = OrderRequest::decode(bytes)?;
+    if request.request.is_none() {
+        bail!("order request has no body");
+    }
+    Ok(request)
+}
+
+/// Encode an order response to send back to the gateway
+pub fn encode_order_response(response: &OrderResponse) -> Vec<u8> {
+    response.encode_to_vec()
+}
+
+/// Route a decoded order request to the matching engine and publish the emitted events
+/// Rejections are reported in the response instead of failing the caller
+/// The response echoes the request's correlation id
+pub fn process_order_request(engine: &MatchingEngine, request: OrderRequest) -> OrderResponse {
+    let correlation_id = request.correlation_id.clone();
+    // cancels and amends act on a known order, placements learn theirs from the events
+    let requested_order_id = match &request.request {
+        Some(Request::Cancel(order)) => Some(order.order_id.clone()),
+        Some(Request::Amend(order)) => Some(order.order_id.clone()),
+        _ => None,
+    };
+    match execute_order_request(engine, request) {
+        Ok(events) => {
+            let event_count = events.len() as u32;
+            let filled = events.iter().any(|event| {
+                matches!(event, SpotEvent::SpotOrderPartiallyFilled { .. } | SpotEvent::SpotOrderFullyFilled { .. })
+            });
+            let order_id = requested_order_id.unwrap_or_else(|| resulting_order_id(&events));
+            event::publish_event_queue(events);
+            OrderResponse {
+                accepted: true,
+                error: String::new(),
+                event_count,
+                correlation_id,
+                status: if filled { ResponseStatus::Filled } else { ResponseStatus::Accepted } as i32,
+                order_id,
+            }
+        }
+        Err(e) => {
+            // the events a failed operation emitted before failing are queued globally, not answered with
+            event::publish_events();
+            OrderResponse {
+                accepted: false,
+                error: e.to_string(),
+                event_count: 0,
+                correlation_id,
+                status: ResponseStatus::Rejected as i32,
+                order_id: Vec::new(),
+            }
+        }
+    }
+}
+
+/// Response for bytes that could not be decoded into an order request
+pub fn malformed_order_response(error: impl std::fmt::Display) -> OrderResponse {
+    OrderResponse {
+        accepted: false,
+        error: format!("malformed order request: {}", error),
+        event_count: 0,
+        correlation_id: Vec::new(),
+        status: ResponseStatus::Malformed as i32,
+        order_id: Vec::new(),
+    }
+}
+
+/// Response for an order rejected by the `OrderAgeGuard` as older than the maximum order age
+pub fn stale_order_response(request: OrderRequest) -> OrderResponse {
+    OrderResponse {
+        accepted: false,
+        error: "order timestamp is older than the maximum order age".to_string(),
+        event_count: 0,
+        correlation_id: request.correlation_id,
+        status: ResponseStatus::Rejected as i32,
+        order_id: Vec::new(),
+    }
+}
+
+/// Id of the taker order in the events of a placement, empty if none was created
+fn resulting_order_id(events: &EventQueue) -> Vec<u8> {
+    events
+        .iter()
+        .find_map(|event| match event {
+            SpotEvent::SpotOrderPartiallyFilled { taker_order_id, .. }
+            | SpotEvent::SpotOrderFullyFilled { taker_order_id, .. } => Some(taker_order_id.clone()),
+            SpotEvent::SpotOrderPlaced { order_id, .. } => Some(order_id.clone()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn execute_order_request(engine: &MatchingEngine, request: OrderRequest) -> Result<EventQueue> {
+    let request = request.request.ok_or_else(|| anyhow!("order request has no body"))?;
+    let pair_id = match &request {
+        Request::Limit(order) => &order.pair_id,
+        Request::Market(order) => &order.pair_id,
+        Request::Cancel(order) => &order.pair_id,
+        Request::Amend(order) => &order.pair_id,
+    };
+    if !engine.has_pair(pair_id) {
+        bail!("pair does not exist");
+    }
+
+    let events = match request {
+        Request::Limit(order) => {
+            let taker_fee_bps = to_taker_fee_bps(order.taker_fee_bps)?;
+            let time_in_force = to_time_in_force(order.time_in_force)?;
+            if order.is_bid {
+                engine.limit_buy(
+                    order.cid, order.pair_id, None, order.owner, order.price, order.amount, order.iceberg_quantity,
+                    order.timestamp, order.expires_at, order.maker_fee_bps, taker_fee_bps, time_in_force,
+                )?
+            } else {
+                engine.limit_sell(
+                    order.cid, order.pair_id, None, order.owner, order.price, order.amount, order.iceberg_quantity,
+                    order.timestamp, order.expires_at, order.maker_fee_bps, taker_fee_bps, time_in_force,
+                )?
+            }
+        }
+        Request::Market(order) => {
+            let taker_fee_bps = to_taker_fee_bps(order.taker_fee_bps)?;
+            let time_in_force = to_time_in_force(order.time_in_force)?;
+            if order.is_bid {
+                engine.market_buy(
+                    order.cid, order.pair_id, None, order.owner, order.amount, order.iceberg_quantity,
+                    order.timestamp, order.expires_at, order.maker_fee_bps, taker_fee_bps, time_in_force,
+                )?
+            } else {
+                engine.market_sell(
+                    order.cid, order.pair_id, None, order.owner, order.amount, order.iceberg_quantity,
+                    order.timestamp, order.expires_at, order.maker_fee_bps, taker_fee_bps, time_in_force,
+                )?
+            }
+        }
+        Request::Cancel(order) => {
+            let order_id = to_order_id(&order.order_id)?;
+            engine.cancel_order(order.cid, order.pair_id, order_id, order.owner, order.is_bid)?
+        }
+        // an amend replaces the resting order through the existing order id of a limit order
+        Request::Amend(order) => {
+            let order_id = to_order_id(&order.order_id)?;
+            let taker_fee_bps = to_taker_fee_bps(order.taker_fee_bps)?;
+            let time_in_force = to_time_in_force(order.time_in_force)?;
+            if order.is_bid {
+                engine.limit_buy(
+                    order.cid, order.pair_id, Some(order_id), order.owner, order.price, order.amount, order.iceberg_quantity,
+                    order.timestamp, order.expires_at, order.maker_fee_bps, taker_fee_bps, time_in_force,
+                )?
+            } else {
+                engine.limit_sell(
+                    order.cid, order.pair_id, Some(order_id), order.owner, order.price, order.amount, order.iceberg_quantity,
+                    order.timestamp, order.expires_at, order.maker_fee_bps, taker_fee_bps, time_in_force,
+                )?
+            }
+        }
+    };
+    Ok(events)
+}
+
+fn to_order_id(bytes: &[u8]) -> Result<OrderId> {
+    let bytes: [u8; 16] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("order id must be 16 bytes, got {}", bytes.len()))?;
+    Ok(OrderId::from_bytes(bytes))
+}
+
+fn to_taker_fee_bps(taker_fee_bps: u32) -> Result<u16> {
+    u16::try_from(taker_fee_bps).map_err(|_| anyhow!("taker fee bps {} is out of range", taker_fee_bps))
+}
+
+fn to_time_in_force(time_in_force: i32) -> Result<TimeInForce> {
+    match proto::TimeInForce::try_from(time_in_force) {
+        Ok(proto::TimeInForce::GoodTillCanceled) => Ok(TimeInForce::GoodTillCanceled),
+        Ok(proto::TimeInForce::FillOrKill) => Ok(TimeInForce::FillOrKill),
+        Ok(proto::TimeInForce::ImmediateOrCancel) => Ok(TimeInForce::ImmediateOrCancel),
+        Ok(proto::TimeInForce::GoodTillDate) => Ok(TimeInForce::GoodTillDate),
+        Err(_) => bail!("unsupported time in force {}", time_in_force),
+    }
+}
+
 /// Get default ports from environment variables or use defaults
 pub fn get_ports() -> Result<(u16, u16)> {
     let event_port = std::env::var("EVENT_PORT")
@@ -121,3 +613,40 @@ pub fn get_ports() -> Result<(u16, u16)> {
     Ok((event_port, order_port))
 }
 
+/// Get the interface the TCP sockets bind to from `ZMQ_BIND_IFACE` (default: `*`, all interfaces)
+pub fn get_bind_iface() -> Result<String> {
+    match std::env::var("ZMQ_BIND_IFACE") {
+        Ok(iface) if iface == "*" => Ok(iface),
+        Ok(iface) => match iface.parse::<IpAddr>() {
+            Ok(address) => Ok(address.to_string()),
+            Err(e) => bail!("invalid ZMQ_BIND_IFACE {:?}: {}", iface, e),
+        },
+        Err(_) => Ok("*".to_string()),
+    }
+}
+
+/// Get endpoints from `EVENT_ENDPOINT` and `ORDER_ENDPOINT`, falling back to TCP on the configured interface and ports
+pub fn get_endpoints() -> Result<(String, String)> {
+    let (event_port, order_port) = get_ports()?;
+    let iface = get_bind_iface()?;
+    let event_endpoint = std::env::var("EVENT_ENDPOINT").unwrap_or_else(|_| tcp_endpoint_on(&iface, event_port));
+    let order_endpoint = std::env::var("ORDER_ENDPOINT").unwrap_or_else(|_| tcp_endpoint_on(&iface, order_port));
+    Ok((event_endpoint, order_endpoint))
+}
+
+/// Get socket options from `ZMQ_SNDHWM`, `ZMQ_RCVHWM` and `ZMQ_LINGER_MS` or use defaults
+pub fn get_socket_options() -> Result<SocketOptions> {
+    let defaults = SocketOptions::default();
+    let read = |name: &str, default: i32| -> Result<i32> {
+        match std::env::var(name) {
+            Ok(value) => Ok(value.parse::<i32>()?),
+            Err(_) => Ok(default),
+        }
+    };
+    Ok(SocketOptions {
+        sndhwm: read("ZMQ_SNDHWM", defaults.sndhwm)?,
+        rcvhwm: read("ZMQ_RCVHWM", defaults.rcvhwm)?,
+        linger_ms: read("ZMQ_LINGER_MS", defaults.linger_ms)?,
+    })
+}
+
@@ -0,0 +1,76 @@
+use offgrid_primitives::spot::clock::{ClockHandle, TimeUnit};
+use offgrid_primitives::spot::{Clock, SystemClock};
+
+use crate::proto::{order_request::Request, OrderRequest};
+
+/// Rejects orders whose `timestamp` is older than `max_order_age_ms`, so a gateway cannot replay a stale signed order
+/// Orders timestamped more than `max_order_age_ms` ahead of the clock are rejected too, they would stay fresh
+/// for as long as they are ahead. A `max_order_age_ms` of 0 disables the check. Cancels carry no timestamp and
+/// are never stale.
+#[derive(Debug)]
+pub struct OrderAgeGuard {
+    max_order_age_ms: i64,
+    clock: ClockHandle,
+    // unit of the order timestamps, the one of the matching engine
+    time_unit: TimeUnit,
+}
+
+impl OrderAgeGuard {
+    pub fn new(max_order_age_ms: i64) -> Self {
+        Self::with_clock(max_order_age_ms, SystemClock)
+    }
+
+    pub fn with_clock(max_order_age_ms: i64, clock: impl Clock + 'static) -> Self {
+        Self {
+            max_order_age_ms,
+            clock: ClockHandle::new(clock),
+            time_unit: TimeUnit::Millis,
+        }
+    }
+
+    /// Reads the order timestamps in `time_unit`, see `MatchingEngine::time_unit`
+    pub fn set_time_unit(&mut self, time_unit: TimeUnit) {
+        self.time_unit = time_unit;
+    }
+
+    /// Guard accepting every order
+    pub fn disabled() -> Self {
+        Self::new(0)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_order_age_ms > 0
+    }
+
+    /// Whether `request` was timestamped before `now - max_order_age_ms` or after `now + max_order_age_ms`
+    /// With timestamps in seconds, the maximum age is rounded down to whole seconds.
+    pub fn is_stale(&self, request: &OrderRequest) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        let timestamp = match &request.request {
+            Some(Request::Limit(order)) => order.timestamp,
+            Some(Request::Market(order)) => order.timestamp,
+            Some(Request::Amend(order)) => order.timestamp,
+            Some(Request::Cancel(_)) | None => return false,
+        };
+        let now = self.time_unit.from_millis(self.clock.now_millis());
+        let max_order_age = self.time_unit.from_millis(self.max_order_age_ms);
+        timestamp < now.saturating_sub(max_order_age) || timestamp > now.saturating_add(max_order_age)
+    }
+}
+
+/// Get the maximum order age from `MAX_ORDER_AGE_MS` (default: unset, orders of any age are accepted)
+/// A negative age is rejected rather than read as disabled.
+pub fn get_max_order_age_ms() -> anyhow::Result<i64> {
+    match std::env::var("MAX_ORDER_AGE_MS") {
+        Ok(value) => {
+            let max_order_age_ms = value.parse::<i64>()?;
+            if max_order_age_ms < 0 {
+                anyhow::bail!("MAX_ORDER_AGE_MS must not be negative, got {}", max_order_age_ms);
+            }
+            Ok(max_order_age_ms)
+        }
+        Err(_) => Ok(0),
+    }
+}
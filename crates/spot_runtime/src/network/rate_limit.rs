@@ -0,0 +1,99 @@
+use offgrid_primitives::spot::clock::ClockHandle;
+use offgrid_primitives::spot::{Clock, SystemClock};
+use std::collections::HashMap;
+
+/// Token bucket state of one ROUTER client, in thousandths of a token
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens_milli: u64,
+    updated_at: i64,
+}
+
+/// Per-client token bucket rate limiter keyed by the ROUTER identity frame
+/// Each client may send `burst` requests at once, refilled at `orders_per_sec`
+/// A bucket idle for long enough to refill completely is dropped, a new bucket starts full anyway.
+#[derive(Debug)]
+pub struct RateLimiter {
+    orders_per_sec: u64,
+    burst: u64,
+    clock: ClockHandle,
+    buckets: HashMap<Vec<u8>, Bucket>,
+    // when the buckets were last checked for idle ones
+    swept_at: i64,
+}
+
+impl RateLimiter {
+    pub fn new(orders_per_sec: u64, burst: u64) -> Self {
+        Self::with_clock(orders_per_sec, burst, SystemClock)
+    }
+
+    pub fn with_clock(orders_per_sec: u64, burst: u64, clock: impl Clock + 'static) -> Self {
+        let clock = ClockHandle::new(clock);
+        let swept_at = clock.now_millis();
+        Self {
+            orders_per_sec,
+            burst,
+            clock,
+            buckets: HashMap::new(),
+            swept_at,
+        }
+    }
+
+    /// Takes one token for `identity`, returns false if the client is over its limit
+    pub fn check(&mut self, identity: &[u8]) -> bool {
+        let now = self.clock.now_millis();
+        self.evict_idle(now);
+        let capacity = self.burst * 1000;
+        let bucket = self.buckets.entry(identity.to_vec()).or_insert(Bucket {
+            tokens_milli: capacity,
+            updated_at: now,
+        });
+
+        // `orders_per_sec` tokens per second is `orders_per_sec` thousandths of a token per millisecond
+        let elapsed = now.saturating_sub(bucket.updated_at).max(0) as u64;
+        bucket.tokens_milli = bucket
+            .tokens_milli
+            .saturating_add(elapsed.saturating_mul(self.orders_per_sec))
+            .min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens_milli < 1000 {
+            return false;
+        }
+        bucket.tokens_milli -= 1000;
+        true
+    }
+
+    /// Number of clients with a bucket
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Milliseconds an empty bucket takes to refill completely, None if buckets never refill
+    fn refill_millis(&self) -> Option<i64> {
+        (self.orders_per_sec != 0).then(|| (self.burst * 1000).div_ceil(self.orders_per_sec) as i64)
+    }
+
+    /// Drops the buckets idle for at least the refill time, checked at most once per refill time
+    fn evict_idle(&mut self, now: i64) {
+        let Some(refill) = self.refill_millis() else {
+            return;
+        };
+        if now.saturating_sub(self.swept_at) < refill {
+            return;
+        }
+        self.buckets.retain(|_, bucket| now.saturating_sub(bucket.updated_at) < refill);
+        self.swept_at = now;
+    }
+}
+
+/// Get order rate limit (orders/sec, burst) from environment or use defaults
+pub fn get_rate_limit() -> anyhow::Result<(u64, u64)> {
+    let orders_per_sec = std::env::var("ORDER_RATE_LIMIT")
+        .unwrap_or_else(|_| "1000".to_string())
+        .parse::<u64>()?;
+    let burst = std::env::var("ORDER_RATE_BURST")
+        .unwrap_or_else(|_| "2000".to_string())
+        .parse::<u64>()?;
+    Ok((orders_per_sec, burst))
+}
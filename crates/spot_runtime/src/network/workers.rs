@@ -0,0 +1,167 @@
+use offgrid_primitives::spot::prices::crc32;
+use anyhow::Result;
+use offgrid_primitives::spot::MatchingEngine;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use zmq::{Context, Socket, PAIR};
+
+use crate::metrics::Metrics;
+use crate::proto::{order_request::Request, OrderRequest};
+
+use super::acks::{ack_key, AckKey};
+use super::{encode_order_response, process_order_request};
+
+/// Order request handed to a worker, tagged with the ROUTER identity of the client it came from
+struct Job {
+    identity: Vec<u8>,
+    request: OrderRequest,
+}
+
+/// Encoded response of a worker, to be routed back to `identity` by the thread owning the ROUTER socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerResponse {
+    pub identity: Vec<u8>,
+    pub response: Vec<u8>,
+    /// key to cache the response under for retransmissions, None if the order has no client order id
+    pub ack_key: Option<AckKey>,
+}
+
+/// Worker index of a pair among `workers` threads, stable across restarts
+pub fn pair_shard(pair_id: &[u8], workers: usize) -> usize {
+    crc32(pair_id) as usize % workers.max(1)
+}
+
+/// Pair an order request acts on, None for a request without a body
+pub fn request_pair_id(request: &OrderRequest) -> Option<&[u8]> {
+    match request.request.as_ref()? {
+        Request::Limit(order) => Some(&order.pair_id),
+        Request::Market(order) => Some(&order.pair_id),
+        Request::Cancel(order) => Some(&order.pair_id),
+        Request::Amend(order) => Some(&order.pair_id),
+    }
+}
+
+/// Numbers the inproc endpoints of the pools sharing a context
+static POOLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Pool of order-processing threads, each owning the pairs `pair_shard` maps to it
+///
+/// ZMQ sockets must stay on one thread, so the main loop keeps the ROUTER socket: it dispatches decoded
+/// requests to the pool and sends back the responses the workers hand to the `WorkerResponses` returned
+/// by `new`. Requests of one pair are processed by one worker in arrival order.
+pub struct WorkerPool {
+    senders: Vec<mpsc::Sender<Job>>,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+/// Responses of a `WorkerPool`, taken by the thread owning the ROUTER socket
+///
+/// After handing over a response a worker signals an inproc PAIR socket, so the main loop polls it next
+/// to the ROUTER socket and wakes up for responses instead of polling more often.
+pub struct WorkerResponses {
+    receiver: mpsc::Receiver<WorkerResponse>,
+    completions: Socket,
+}
+
+impl WorkerResponses {
+    /// Item readable once a worker handed over a response, to poll next to the ROUTER socket
+    pub fn as_poll_item(&self) -> zmq::PollItem<'_> {
+        self.completions.as_poll_item(zmq::POLLIN)
+    }
+
+    /// The responses handed over so far, consuming their completion signals
+    pub fn try_iter(&self) -> mpsc::TryIter<'_, WorkerResponse> {
+        // drained first, a response handed over meanwhile signals the next poll
+        while self.completions.recv_bytes(zmq::DONTWAIT).is_ok() {}
+        self.receiver.try_iter()
+    }
+
+    /// Wait up to `timeout` for the next response
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<WorkerResponse, mpsc::RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+impl WorkerPool {
+    pub fn new(
+        context: &Context,
+        workers: usize,
+        engine: Arc<Mutex<MatchingEngine>>,
+        metrics: Arc<Metrics>,
+    ) -> Result<(Self, WorkerResponses)> {
+        let endpoint = format!("inproc://workers.completions.{}", POOLS.fetch_add(1, Ordering::Relaxed));
+        let completions = context.socket(PAIR)?;
+        completions.bind(&endpoint)?;
+        let signal = context.socket(PAIR)?;
+        signal.connect(&endpoint)?;
+        // the workers share the sending end, a socket must not be used by two threads at once
+        let signal = Arc::new(Mutex::new(signal));
+
+        let (response_tx, response_rx) = mpsc::channel();
+        let mut senders = Vec::new();
+        let mut handles = Vec::new();
+        for worker in 0..workers.max(1) {
+            let (job_tx, job_rx) = mpsc::channel::<Job>();
+            let engine = engine.clone();
+            let metrics = metrics.clone();
+            let response_tx = response_tx.clone();
+            let signal = signal.clone();
+            senders.push(job_tx);
+            handles.push(thread::spawn(move || {
+                println!("Order worker {} started", worker);
+                // the channel disconnects once the pool is shut down
+                for job in job_rx {
+                    let _timer = metrics.order_processing_duration.start_timer();
+                    let ack_key = ack_key(&job.identity, &job.request);
+                    let response = process_order_request(&crate::lock_engine(&engine).share(), job.request);
+                    let response = WorkerResponse {
+                        identity: job.identity,
+                        response: encode_order_response(&response),
+                        ack_key,
+                    };
+                    if response_tx.send(response).is_err() {
+                        break;
+                    }
+                    // a full queue already has signals for the main loop to wake up on
+                    let signal = signal.lock().unwrap_or_else(|e| e.into_inner());
+                    let _ = signal.send(&[][..], zmq::DONTWAIT);
+                }
+                println!("Order worker {} stopped", worker);
+            }));
+        }
+        Ok((Self { senders, handles }, WorkerResponses { receiver: response_rx, completions }))
+    }
+
+    pub fn workers(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// Queue `request` on the worker owning its pair
+    pub fn dispatch(&self, identity: impl Into<Vec<u8>>, request: OrderRequest) {
+        let shard = pair_shard(request_pair_id(&request).unwrap_or_default(), self.workers());
+        let job = Job { identity: identity.into(), request };
+        if self.senders[shard].send(job).is_err() {
+            eprintln!("Order worker {} is gone, dropping request", shard);
+        }
+    }
+
+    /// Stop accepting requests and wait for the workers to finish the queued ones
+    pub fn shutdown(self) {
+        drop(self.senders);
+        for handle in self.handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Get the number of order-processing workers from `WORKER_THREADS` (default: 1, order handling stays on the main loop)
+pub fn get_worker_threads() -> usize {
+    std::env::var("WORKER_THREADS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1)
+}
@@ -0,0 +1,3 @@
+//! Gateway protocol messages generated from `proto/gateway.proto`
+
+include!(concat!(env!("OUT_DIR"), "/orderbook.rs"));
@@ -1,12 +1,27 @@
-use offgrid_primitives::spot::MatchingEngine;
+//! Snapshots of the matching engine state
+//!
+//! Every snapshot value is serialized with postcard, the same format the primitives tests round-trip
+//! the orderbook with, so there is a single snapshot format to keep compatible.
+//!
+//! Postcard does not tag fields, so adding one to the saved state breaks every snapshot on disk. The
+//! engine snapshot therefore starts with `SNAPSHOT_MAGIC` and `SNAPSHOT_VERSION`, and `decode_snapshot`
+//! migrates the layouts of older versions, see `offgrid_primitives::spot::legacy`.
+
+use offgrid_primitives::spot::event;
+use offgrid_primitives::spot::legacy::{MatchingEngineV0, MatchingEngineV1};
+use offgrid_primitives::spot::prices::crc32;
+use offgrid_primitives::spot::{MatchingEngine, Pair};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use crate::metrics::{Component, Health, Metrics};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SnapshotError {
@@ -16,6 +31,84 @@ pub enum SnapshotError {
     Serialization(String),
     #[error("Deserialization error: {0}")]
     Deserialization(String),
+    #[error("Checksum mismatch: stored {stored:#010x}, computed {computed:#010x}")]
+    ChecksumMismatch { stored: u32, computed: u32 },
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Pair not found: {0}")]
+    PairNotFound(String),
+}
+
+/// Prefixes `data` with its CRC32 so corruption is detected on load
+pub(crate) fn seal(data: &[u8]) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(data.len() + 4);
+    sealed.extend_from_slice(&crc32(data).to_le_bytes());
+    sealed.extend_from_slice(data);
+    sealed
+}
+
+/// Verifies and strips the CRC32 prefix written by `seal`
+pub(crate) fn unseal(sealed: &[u8]) -> Result<&[u8], SnapshotError> {
+    if sealed.len() < 4 {
+        // too short to even hold the checksum, e.g. a truncated write
+        return Err(SnapshotError::ChecksumMismatch { stored: 0, computed: crc32(sealed) });
+    }
+    let (prefix, data) = sealed.split_at(4);
+    let stored = u32::from_le_bytes(prefix.try_into().unwrap());
+    let computed = crc32(data);
+    if stored != computed {
+        return Err(SnapshotError::ChecksumMismatch { stored, computed });
+    }
+    Ok(data)
+}
+
+/// Bytes every versioned snapshot starts with, a file without them is a snapshot saved before versioning
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"OGSN";
+
+/// Format version written by `save_snapshot`
+/// Bump it whenever the saved state changes, and migrate the previous layout in `decode_snapshot`.
+/// - 1: the first versioned format.
+/// - 2: orderbooks carry the sequence number of their last event, see `crate::event_log`.
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// Serializes the engine state as `SNAPSHOT_MAGIC`, `SNAPSHOT_VERSION` and the sealed postcard of the state
+pub fn encode_snapshot(engine: &MatchingEngine) -> Result<Vec<u8>, SnapshotError> {
+    let data = postcard::to_allocvec(engine)
+        .map_err(|e| SnapshotError::Serialization(format!("Failed to serialize: {}", e)))?;
+    let mut encoded = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 2 + 4 + data.len());
+    encoded.extend_from_slice(&SNAPSHOT_MAGIC);
+    encoded.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    encoded.extend_from_slice(&seal(&data));
+    Ok(encoded)
+}
+
+/// Deserializes a snapshot written by `encode_snapshot` or by a version before it
+/// - a file without `SNAPSHOT_MAGIC` is decoded with the unversioned layout, which carried no checksum.
+/// - an older version is decoded with the layout it was saved with, see `offgrid_primitives::spot::legacy`.
+/// - a version newer than `SNAPSHOT_VERSION` fails with `SnapshotError::UnsupportedVersion`.
+pub fn decode_snapshot(data: &[u8]) -> Result<MatchingEngine, SnapshotError> {
+    let Some(versioned) = data.strip_prefix(&SNAPSHOT_MAGIC) else {
+        return decode_exact::<MatchingEngineV0>(data).map(MatchingEngine::from);
+    };
+    if versioned.len() < 2 {
+        // the header itself was cut short
+        return Err(SnapshotError::ChecksumMismatch { stored: 0, computed: crc32(versioned) });
+    }
+    let (version, sealed) = versioned.split_at(2);
+    match u16::from_le_bytes(version.try_into().unwrap()) {
+        SNAPSHOT_VERSION => decode_exact(unseal(sealed)?),
+        1 => decode_exact::<MatchingEngineV1>(unseal(sealed)?).map(MatchingEngine::from),
+        version => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+// rejects trailing bytes, so a file that is not a snapshot is unlikely to decode
+fn decode_exact<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Result<T, SnapshotError> {
+    match postcard::take_from_bytes(data) {
+        Ok((value, [])) => Ok(value),
+        Ok((_, rest)) => Err(SnapshotError::Deserialization(format!("{} trailing bytes", rest.len()))),
+        Err(e) => Err(SnapshotError::Deserialization(format!("Failed to deserialize: {}", e))),
+    }
 }
 
 impl From<postcard::Error> for SnapshotError {
@@ -28,14 +121,16 @@ impl From<postcard::Error> for SnapshotError {
 /// 
 /// This saves the entire state (all pairs and their orderbooks) to a binary file.
 /// The snapshot can be loaded later to recover the state after a server restart.
+/// The file starts with the format version and a CRC32 of the serialized state, both checked on load.
 /// 
 /// # Arguments
 /// * `engine` - Reference to the MatchingEngine to snapshot
 /// * `path` - Path where the snapshot will be saved
-pub fn save_snapshot<P: AsRef<Path>>(engine: &MatchingEngine, path: P) -> Result<(), SnapshotError> {
-    // Serialize to binary format using postcard
-    let data = postcard::to_allocvec(engine)
-        .map_err(|e| SnapshotError::Serialization(format!("Failed to serialize: {}", e)))?;
+///
+/// Returns the number of bytes written.
+pub fn save_snapshot<P: AsRef<Path>>(engine: &MatchingEngine, path: P) -> Result<usize, SnapshotError> {
+    // Serialize to binary format using postcard, prefixed with the version and checksum
+    let data = encode_snapshot(engine)?;
 
     // Atomic write: write to temp file first, then rename
     let path_ref = path.as_ref();
@@ -54,12 +149,14 @@ pub fn save_snapshot<P: AsRef<Path>>(engine: &MatchingEngine, path: P) -> Result
     // Atomically rename (this is atomic on most filesystems)
     fs::rename(&temp_path, path_ref)?;
     
-    Ok(())
+    Ok(data.len())
 }
 
 /// Load a snapshot of the matching engine state from disk
 /// 
 /// This restores the entire state (all pairs and their orderbooks) from a previously saved snapshot.
+/// Fails with `SnapshotError::ChecksumMismatch` if the file is corrupted or truncated, a snapshot saved
+/// before versioning is migrated, see `decode_snapshot`.
 /// 
 /// # Arguments
 /// * `path` - Path to the snapshot file to load
@@ -79,11 +176,135 @@ pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<MatchingEngine, Snapshot
     let mut data = Vec::new();
     file.read_to_end(&mut data)?;
 
-    // Deserialize using postcard
-    let engine = postcard::from_bytes(&data)
-        .map_err(|e| SnapshotError::Deserialization(format!("Failed to deserialize: {}", e)))?;
+    decode_snapshot(&data)
+}
+
+/// Path of the `snapshot:seq` entry stored next to the snapshot, e.g. `snapshot.seq` for `snapshot.bin`
+pub fn seq_path<P: AsRef<Path>>(snapshot_path: P) -> PathBuf {
+    snapshot_path.as_ref().with_extension("seq")
+}
+
+/// Save the last emitted event sequence number next to the snapshot
+///
+/// Call it while holding the engine lock, so the sequence matches the saved state.
+///
+/// # Arguments
+/// * `snapshot_path` - Path of the snapshot the sequence belongs to
+/// * `seq` - Sequence number of the last emitted event
+pub fn save_seq<P: AsRef<Path>>(snapshot_path: P, seq: u64) -> Result<(), SnapshotError> {
+    let data = seal(&postcard::to_allocvec(&seq)?);
+    let path = seq_path(snapshot_path);
+    let temp_path = path.with_extension("seq.tmp");
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &path)?;
+
+    Ok(())
+}
 
-    Ok(engine)
+/// Load the event sequence number saved next to the snapshot, None if it was never saved
+///
+/// # Arguments
+/// * `snapshot_path` - Path of the snapshot the sequence belongs to
+pub fn load_seq<P: AsRef<Path>>(snapshot_path: P) -> Result<Option<u64>, SnapshotError> {
+    let data = match fs::read(seq_path(snapshot_path)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let seq = postcard::from_bytes(unseal(&data)?)
+        .map_err(|e| SnapshotError::Deserialization(format!("Failed to deserialize seq: {}", e)))?;
+    Ok(Some(seq))
+}
+
+/// Continue the event sequence from the number saved next to the snapshot, if any
+///
+/// # Arguments
+/// * `snapshot_path` - Path of the snapshot the sequence belongs to
+pub fn restore_seq<P: AsRef<Path>>(snapshot_path: P) -> Result<Option<u64>, SnapshotError> {
+    let seq = load_seq(snapshot_path)?;
+    if let Some(seq) = seq {
+        event::restore_seq(seq);
+    }
+    Ok(seq)
+}
+
+/// Readable view of a pair in a snapshot, exported as JSON for debugging
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairExport {
+    pub pair_id: String,
+    pub base_asset_id: String,
+    pub quote_asset_id: String,
+    /// last match price
+    pub lmp: Option<u64>,
+    /// bid levels, best (highest) price first
+    pub bids: Vec<LevelExport>,
+    /// ask levels, best (lowest) price first
+    pub asks: Vec<LevelExport>,
+}
+
+/// One price level of a `PairExport`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelExport {
+    pub price: u64,
+    /// public quantity
+    pub pqty: u64,
+    /// current quantity
+    pub cqty: u64,
+}
+
+impl PairExport {
+    pub fn from_pair(pair: &Pair) -> Self {
+        let l2 = &pair.orderbook.l2;
+        let bids = l2
+            .collect_bid_prices()
+            .into_iter()
+            .map(|price| LevelExport {
+                price,
+                pqty: l2.public_bid_level(price).unwrap_or(0),
+                cqty: l2.current_bid_level(price).unwrap_or(0),
+            })
+            .collect();
+        let asks = l2
+            .collect_ask_prices()
+            .into_iter()
+            .map(|price| LevelExport {
+                price,
+                pqty: l2.public_ask_level(price).unwrap_or(0),
+                cqty: l2.current_ask_level(price).unwrap_or(0),
+            })
+            .collect();
+        Self {
+            pair_id: String::from_utf8_lossy(&pair.pair_id).into_owned(),
+            base_asset_id: String::from_utf8_lossy(&pair.base_asset_id).into_owned(),
+            quote_asset_id: String::from_utf8_lossy(&pair.quote_asset_id).into_owned(),
+            lmp: pair.l1.lmp,
+            bids,
+            asks,
+        }
+    }
+}
+
+/// Export a pair of a saved snapshot as pretty JSON
+///
+/// This lets operators inspect the persisted state of a pair without decoding the binary snapshot by hand.
+///
+/// # Arguments
+/// * `path` - Path to the snapshot file
+/// * `pair_id` - Id of the pair to export
+pub fn export_json<P: AsRef<Path>>(path: P, pair_id: &[u8]) -> Result<String, SnapshotError> {
+    let engine = load_snapshot(path)?;
+    let pair = engine
+        .get_pair(pair_id)
+        .ok_or_else(|| SnapshotError::PairNotFound(String::from_utf8_lossy(pair_id).into_owned()))?;
+    serde_json::to_string_pretty(&PairExport::from_pair(&pair))
+        .map_err(|e| SnapshotError::Serialization(format!("Failed to export JSON: {}", e)))
 }
 
 /// Load a snapshot or create a new matching engine if snapshot doesn't exist
@@ -104,6 +325,73 @@ pub fn load_snapshot_or_new<P: AsRef<Path>>(path: P) -> Result<MatchingEngine, S
     }
 }
 
+/// Cadence of the snapshot thread, backing off while the pair locks are contended
+///
+/// Every interval the thread times how long it waits for the lock of every pair, the locks a save
+/// holds in turn while trading goes on. A wait above
+/// `contention_threshold` defers the snapshot and doubles the interval. The interval never grows past the
+/// time left until `max_interval` has passed since the last snapshot, and a snapshot is then taken
+/// whatever the contention. An uncontended lock takes the snapshot and restores `base_interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotSchedule {
+    base_interval: Duration,
+    max_interval: Duration,
+    contention_threshold: Duration,
+    interval: Duration,
+    since_snapshot: Duration,
+}
+
+impl SnapshotSchedule {
+    pub fn new(base_interval: Duration, max_interval: Duration, contention_threshold: Duration) -> Self {
+        Self {
+            base_interval,
+            max_interval: max_interval.max(base_interval),
+            contention_threshold,
+            interval: base_interval,
+            since_snapshot: Duration::ZERO,
+        }
+    }
+
+    /// Snapshot every `interval` whatever the contention
+    pub fn fixed(interval: Duration) -> Self {
+        Self::new(interval, interval, Duration::MAX)
+    }
+
+    /// Time to wait before the next snapshot
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Called once the current interval has passed and the pair locks were acquired after `lock_wait`
+    /// Returns whether to take the snapshot now, the interval is updated for the next one.
+    pub fn on_lock_acquired(&mut self, lock_wait: Duration) -> bool {
+        self.since_snapshot += self.interval;
+        if lock_wait > self.contention_threshold && self.since_snapshot < self.max_interval {
+            self.interval = (self.interval * 2).min(self.max_interval - self.since_snapshot);
+            false
+        } else {
+            self.interval = self.base_interval;
+            self.since_snapshot = Duration::ZERO;
+            true
+        }
+    }
+}
+
+/// Get the adaptive snapshot cadence around `interval`
+/// - `SNAPSHOT_CONTENTION_MS` is the pair lock wait deferring a snapshot, unset keeps the interval fixed.
+/// - `SNAPSHOT_MAX_INTERVAL_SECONDS` is the longest time between snapshots, 10 times `interval` by default.
+pub fn get_snapshot_schedule(interval: Duration) -> SnapshotSchedule {
+    let max_interval = std::env::var("SNAPSHOT_MAX_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(interval * 10);
+    match std::env::var("SNAPSHOT_CONTENTION_MS").ok().and_then(|s| s.parse::<u64>().ok()) {
+        Some(threshold_ms) => SnapshotSchedule::new(interval, max_interval, Duration::from_millis(threshold_ms)),
+        None => SnapshotSchedule::fixed(interval),
+    }
+}
+
 /// Spawn a snapshot thread that periodically saves the matching engine state
 /// 
 /// # Arguments
@@ -111,48 +399,112 @@ pub fn load_snapshot_or_new<P: AsRef<Path>>(path: P) -> Result<MatchingEngine, S
 /// * `snapshot_path` - Path where snapshots will be saved
 /// * `interval_seconds` - How often to take snapshots (in seconds)
 /// * `shutdown_flag` - Flag to signal shutdown
+/// * `metrics` - Metrics recording the duration and size of each snapshot
+/// * `health` - Liveness state the thread beats on every iteration
 pub fn spawn_snapshot_thread(
     engine: Arc<Mutex<MatchingEngine>>,
     snapshot_path: String,
     interval_seconds: u64,
     shutdown_flag: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+) -> thread::JoinHandle<()> {
+    spawn_adaptive_snapshot_thread(
+        engine,
+        snapshot_path,
+        SnapshotSchedule::fixed(Duration::from_secs(interval_seconds)),
+        shutdown_flag,
+        metrics,
+        health,
+    )
+}
+
+/// Spawn a snapshot thread like `spawn_snapshot_thread` on the cadence of `schedule`
+///
+/// A deferred snapshot is counted in `snapshots_deferred` and the current interval is kept in `snapshot_interval_seconds`.
+pub fn spawn_adaptive_snapshot_thread(
+    engine: Arc<Mutex<MatchingEngine>>,
+    snapshot_path: String,
+    mut schedule: SnapshotSchedule,
+    shutdown_flag: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        println!("Snapshot thread started (interval: {}s, path: {})", interval_seconds, snapshot_path);
-        let _interval = Duration::from_secs(interval_seconds);
+        println!("Snapshot thread started (interval: {:?}, path: {})", schedule.interval(), snapshot_path);
         
         loop {
+            metrics.snapshot_interval_seconds.set(schedule.interval().as_secs_f64());
             // Wait for interval or shutdown signal
-            for _ in 0..(interval_seconds * 10) {
+            for _ in 0..schedule.interval().as_millis().div_ceil(100) {
                 if shutdown_flag.load(Ordering::Relaxed) {
-                    // Before shutdown, save one final snapshot
-                    println!("Taking final snapshot before shutdown...");
-                    if let Ok(engine_guard) = engine.lock() {
-                        if let Err(e) = save_snapshot(&*engine_guard, &snapshot_path) {
-                            eprintln!("Error saving final snapshot: {}", e);
-                        } else {
-                            println!("Final snapshot saved successfully");
-                        }
-                    }
+                    // the final snapshot is taken by `stop_and_flush` once order processing has stopped
                     println!("Snapshot thread stopped");
                     return;
                 }
+                health.beat(Component::Snapshot);
                 thread::sleep(Duration::from_millis(100));
             }
             
-            // Take snapshot
-            if let Ok(engine_guard) = engine.lock() {
-                match save_snapshot(&*engine_guard, &snapshot_path) {
-                    Ok(()) => {
-                        println!("Snapshot saved successfully to {}", snapshot_path);
-                    }
-                    Err(e) => {
-                        eprintln!("Error saving snapshot: {}", e);
+            // Take snapshot on a handle of the pairs, locking one pair at a time so the others keep trading
+            let pairs = crate::lock_engine(&engine).share();
+            if !schedule.on_lock_acquired(pairs.pair_lock_wait()) {
+                metrics.snapshots_deferred.inc();
+                println!("Snapshot deferred, pair locks contended (next in {:?})", schedule.interval());
+                health.beat(Component::Snapshot);
+                continue;
+            }
+            let started = Instant::now();
+            match save_snapshot(&pairs, &snapshot_path) {
+                Ok(bytes) => {
+                    record_snapshot(&metrics, started, bytes);
+                    println!("Snapshot saved successfully to {} ({} bytes)", snapshot_path, bytes);
+                    // read once every pair is saved, so a restart never reuses the sequence number of a saved event
+                    // the sequence of a failed save is not saved, it would skip the events missing from the last snapshot
+                    if let Err(e) = save_seq(&snapshot_path, event::current_seq()) {
+                        eprintln!("Error saving event sequence: {}", e);
                     }
                 }
-            } else {
-                eprintln!("Failed to acquire lock for snapshot");
+                Err(e) => {
+                    eprintln!("Error saving snapshot: {}", e);
+                }
             }
+            health.beat(Component::Snapshot);
         }
     })
 }
+
+fn record_snapshot(metrics: &Metrics, started: Instant, bytes: usize) {
+    metrics.snapshot_duration.observe(started.elapsed().as_secs_f64());
+    metrics.snapshot_bytes_written.set(bytes as i64);
+}
+
+/// Stop the snapshot thread and save one final snapshot synchronously
+///
+/// Call it after the shutdown flag is set and the order processing loop has exited, so the
+/// snapshot on disk matches the state at the moment of shutdown.
+///
+/// # Arguments
+/// * `snapshot_thread` - Handle returned by `spawn_snapshot_thread`
+/// * `engine` - Shared reference to the MatchingEngine
+/// * `snapshot_path` - Path where the snapshot will be saved
+/// * `metrics` - Metrics recording the duration and size of the snapshot
+///
+/// Returns the number of bytes written.
+pub fn stop_and_flush<P: AsRef<Path>>(
+    snapshot_thread: thread::JoinHandle<()>,
+    engine: &Mutex<MatchingEngine>,
+    snapshot_path: P,
+    metrics: &Metrics,
+) -> Result<usize, SnapshotError> {
+    let _ = snapshot_thread.join();
+
+    println!("Taking final snapshot before shutdown...");
+    let engine_guard = crate::lock_engine(engine);
+    let started = Instant::now();
+    let bytes = save_snapshot(&*engine_guard, &snapshot_path)?;
+    record_snapshot(metrics, started, bytes);
+    save_seq(&snapshot_path, event::current_seq())?;
+    println!("Final snapshot saved successfully");
+    Ok(bytes)
+}
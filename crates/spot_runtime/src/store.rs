@@ -0,0 +1,268 @@
+//! RocksDB-backed cold storage of the orders that left the book
+//!
+//! Filled, cancelled and expired orders are removed from L3 and otherwise only survive as events.
+//! `OrderHistoryStore` keeps them queryable by id, each value sealed and serialized with postcard
+//! like the snapshot files. The orders are handed over while their pair is locked, so they are queued to
+//! a writer thread, see `spawn_order_history_thread`, rather than written to the database there.
+
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::{Order, OrderArchive, TerminalState};
+use rust_rocksdb::{DBCompressionType, Options, WriteOptions, DB};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::snapshot::{seal, unseal, SnapshotError};
+
+/// Key prefix of the archived orders within the keys of their pair, `pair:{pair_id}:order_history:{id}`
+const ORDER_HISTORY_PREFIX: &str = "order_history:";
+
+/// Most terminated orders queued for the writer thread, archiving waits for the writer beyond it
+pub const ORDER_HISTORY_QUEUE_CAPACITY: usize = 65_536;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OrderHistoryError {
+    #[error("Store error: {0}")]
+    Store(String),
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+    #[error("Order {id} is corrupted: stored checksum {stored:#010x}, computed {computed:#010x}")]
+    ChecksumMismatch { id: OrderId, stored: u32, computed: u32 },
+    #[error("Failed to deserialize order {id}: {reason}")]
+    Deserialization { id: OrderId, reason: String },
+}
+
+impl From<rust_rocksdb::Error> for OrderHistoryError {
+    fn from(err: rust_rocksdb::Error) -> Self {
+        OrderHistoryError::Store(err.to_string())
+    }
+}
+
+impl From<postcard::Error> for OrderHistoryError {
+    fn from(err: postcard::Error) -> Self {
+        OrderHistoryError::Serialization(err.to_string())
+    }
+}
+
+/// An order as it left the book
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedOrder {
+    /// the order with the quantities it had when it terminated, 0 for a filled order
+    pub order: Order,
+    pub state: TerminalState,
+}
+
+/// RocksDB tuning of the order history, the default keeps the RocksDB defaults
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderHistoryOptions {
+    /// bytes buffered in memory before they are flushed to a file, None keeps the RocksDB default
+    pub write_buffer_size: Option<usize>,
+    /// compression of the files, None keeps the RocksDB default
+    pub compression: Option<DBCompressionType>,
+    /// most files kept open, -1 for no limit
+    pub max_open_files: i32,
+    /// skip the write-ahead log, orders archived since the last flush are lost on a crash
+    pub disable_wal: bool,
+}
+
+impl Default for OrderHistoryOptions {
+    fn default() -> Self {
+        Self {
+            write_buffer_size: None,
+            compression: None,
+            max_open_files: -1,
+            disable_wal: false,
+        }
+    }
+}
+
+impl OrderHistoryOptions {
+    fn db_options(&self) -> Options {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        if let Some(write_buffer_size) = self.write_buffer_size {
+            options.set_write_buffer_size(write_buffer_size);
+        }
+        if let Some(compression) = self.compression {
+            options.set_compression_type(compression);
+        }
+        options.set_max_open_files(self.max_open_files);
+        options
+    }
+}
+
+pub struct OrderHistoryStore {
+    db: DB,
+    disable_wal: bool,
+}
+
+impl OrderHistoryStore {
+    /// Open the database at `path`, creating it if missing
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, OrderHistoryError> {
+        Self::open_with_options(path, &OrderHistoryOptions::default())
+    }
+
+    /// Open the database at `path` tuned with `options`, creating it if missing
+    pub fn open_with_options<P: AsRef<Path>>(path: P, options: &OrderHistoryOptions) -> Result<Self, OrderHistoryError> {
+        Ok(Self {
+            db: DB::open(&options.db_options(), path)?,
+            disable_wal: options.disable_wal,
+        })
+    }
+
+    fn order_history_key(pair_id: &[u8], id: OrderId) -> Vec<u8> {
+        let mut key = b"pair:".to_vec();
+        key.extend_from_slice(pair_id);
+        key.push(b':');
+        key.extend_from_slice(Self::legacy_order_history_key(id).as_bytes());
+        key
+    }
+
+    /// Key of an order archived before the keys were namespaced by pair
+    fn legacy_order_history_key(id: OrderId) -> String {
+        format!("{}{}", ORDER_HISTORY_PREFIX, id)
+    }
+
+    /// Write `order` of pair `pair_id` under `pair:{pair_id}:order_history:{id}`, replacing an earlier entry of
+    /// the same id on the pair
+    pub fn archive_order(&self, pair_id: &[u8], order: &Order, state: TerminalState) -> Result<(), OrderHistoryError> {
+        let archived = ArchivedOrder { order: order.clone(), state };
+        let data = postcard::to_allocvec(&archived)?;
+        let mut write_options = WriteOptions::default();
+        write_options.disable_wal(self.disable_wal);
+        self.db.put_opt(Self::order_history_key(pair_id, order.id), seal(&data), &write_options)?;
+        Ok(())
+    }
+
+    /// The archived order of pair `pair_id` with id `id`, None if no order of that id terminated on the pair
+    /// - an order archived under the earlier `order_history:{id}` key is read from there. It carries no pair, but
+    ///   order ids are ULIDs unique across pairs unless a pair was given another id generator.
+    pub fn get_order_history(&self, pair_id: &[u8], id: OrderId) -> Result<Option<ArchivedOrder>, OrderHistoryError> {
+        let sealed = match self.db.get(Self::order_history_key(pair_id, id))? {
+            Some(sealed) => sealed,
+            None => match self.db.get(Self::legacy_order_history_key(id))? {
+                Some(sealed) => sealed,
+                None => return Ok(None),
+            },
+        };
+        let data = unseal(&sealed).map_err(|e| match e {
+            SnapshotError::ChecksumMismatch { stored, computed } => OrderHistoryError::ChecksumMismatch { id, stored, computed },
+            e => OrderHistoryError::Deserialization { id, reason: e.to_string() },
+        })?;
+        postcard::from_bytes(data)
+            .map(Some)
+            .map_err(|e| OrderHistoryError::Deserialization { id, reason: e.to_string() })
+    }
+}
+
+/// The thread writing the terminated orders queued by the archive of `spawn_order_history_thread`
+pub struct OrderHistoryWriter {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl OrderHistoryWriter {
+    /// Writes what was archived so far and stops the thread, orders archived afterwards are dropped
+    ///
+    /// Call it once nothing terminates orders anymore, i.e. after the order processing and cron threads stopped.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+}
+
+/// Spawns the thread writing the terminated orders to `store`, and returns the archive queueing them
+/// for `MatchingEngine::set_order_archive`
+///
+/// The archive only queues the order, so no database write happens while a pair is locked. Once
+/// `capacity` orders are queued it waits for the writer rather than losing an order. A failed write is
+/// logged and counted in `write_failures`, the order is not archived then.
+pub fn spawn_order_history_thread(
+    store: Arc<OrderHistoryStore>,
+    capacity: usize,
+    write_failures: prometheus::IntCounter,
+) -> (OrderArchive, OrderHistoryWriter) {
+    let (tx, rx) = mpsc::sync_channel::<(Vec<u8>, Order, TerminalState)>(capacity);
+    let archive = OrderArchive::new(move |pair_id, order, state| {
+        // the receiver only goes away once the writer was stopped
+        let _ = tx.send((pair_id.to_vec(), order.clone(), state));
+    });
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = stop.clone();
+    let handle = thread::spawn(move || {
+        println!("Order history thread started");
+        let write = |pair_id: Vec<u8>, order: Order, state: TerminalState| {
+            if let Err(e) = store.archive_order(&pair_id, &order, state) {
+                write_failures.inc();
+                eprintln!("Error archiving order {}: {}", order.id, e);
+            }
+        };
+        loop {
+            if stop_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok((pair_id, order, state)) => write(pair_id, order, state),
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // write what was archived before the stop
+        while let Ok((pair_id, order, state)) = rx.try_recv() {
+            write(pair_id, order, state);
+        }
+        println!("Order history thread stopped");
+    });
+    (archive, OrderHistoryWriter { stop, handle })
+}
+
+/// Get the path of the order history database from `ORDER_HISTORY_PATH`
+/// Returns None when `ORDER_HISTORY_PATH` is unset, terminated orders are not archived then
+pub fn get_order_history_path() -> Option<PathBuf> {
+    std::env::var("ORDER_HISTORY_PATH").ok().map(PathBuf::from)
+}
+
+/// Get the tuning of the order history from `ORDER_HISTORY_WRITE_BUFFER_SIZE`, `ORDER_HISTORY_COMPRESSION`
+/// (`none`, `snappy`, `zlib`, `bz2`, `lz4`, `lz4hc` or `zstd`), `ORDER_HISTORY_MAX_OPEN_FILES` and
+/// `ORDER_HISTORY_DISABLE_WAL` (`true` or `false`), each unset one keeps its default
+pub fn get_order_history_options() -> anyhow::Result<OrderHistoryOptions> {
+    let defaults = OrderHistoryOptions::default();
+    let compression = match std::env::var("ORDER_HISTORY_COMPRESSION") {
+        Ok(name) => Some(parse_compression(&name)?),
+        Err(_) => defaults.compression,
+    };
+    Ok(OrderHistoryOptions {
+        write_buffer_size: match std::env::var("ORDER_HISTORY_WRITE_BUFFER_SIZE") {
+            Ok(value) => Some(value.parse::<usize>()?),
+            Err(_) => defaults.write_buffer_size,
+        },
+        compression,
+        max_open_files: match std::env::var("ORDER_HISTORY_MAX_OPEN_FILES") {
+            Ok(value) => value.parse::<i32>()?,
+            Err(_) => defaults.max_open_files,
+        },
+        disable_wal: match std::env::var("ORDER_HISTORY_DISABLE_WAL") {
+            Ok(value) => value.parse::<bool>()?,
+            Err(_) => defaults.disable_wal,
+        },
+    })
+}
+
+/// Compression type of a name of `ORDER_HISTORY_COMPRESSION`
+pub fn parse_compression(name: &str) -> anyhow::Result<DBCompressionType> {
+    Ok(match name {
+        "none" => DBCompressionType::None,
+        "snappy" => DBCompressionType::Snappy,
+        "zlib" => DBCompressionType::Zlib,
+        "bz2" => DBCompressionType::Bz2,
+        "lz4" => DBCompressionType::Lz4,
+        "lz4hc" => DBCompressionType::Lz4hc,
+        "zstd" => DBCompressionType::Zstd,
+        _ => anyhow::bail!("unknown order history compression {}", name),
+    })
+}
@@ -0,0 +1,264 @@
+pub mod protocol;
+
+use anyhow::{anyhow, Result};
+use offgrid_primitives::spot::event::{EventReceiver, SpotEvent};
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+use crate::logging::{event_name, event_pair_id};
+
+/// Messages queued for a WebSocket client before it is disconnected as too slow
+pub const WS_CLIENT_QUEUE_CAPACITY: usize = 1024;
+
+/// Clients the bridge serves at once unless configured, see `get_ws_max_clients`
+pub const DEFAULT_WS_MAX_CLIENTS: usize = 256;
+
+/// Time a client has to complete the upgrade request once connected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a client's thread waits for the client to send something before it writes the queued messages
+const CLIENT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Events a WebSocket client receives, set by sending it as a JSON text message
+///
+/// e.g. `{"pairs": ["BTC-USD"], "types": ["SpotOrderPlaced", "SpotTrade"]}`; an empty list does not filter,
+/// so a new connection receives every event. A pair filter drops events that carry no pair id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// pair ids as symbols, e.g. `BTC-USD`
+    #[serde(default)]
+    pub pairs: Vec<String>,
+    /// event variant names, e.g. `SpotOrderPlaced`
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+impl Subscription {
+    pub fn matches(&self, event: &SpotEvent) -> bool {
+        let pair_matches = self.pairs.is_empty()
+            || event_pair_id(event).is_some_and(|pair_id| self.pairs.iter().any(|pair| pair.as_bytes() == pair_id));
+        let type_matches = self.types.is_empty() || self.types.iter().any(|name| name == event_name(event));
+        pair_matches && type_matches
+    }
+}
+
+// Bridge side of a connected client
+struct Client {
+    peer: SocketAddr,
+    // clone of the connection, shut down to unblock the client's threads
+    stream: TcpStream,
+    tx: mpsc::SyncSender<String>,
+    subscription: Arc<Mutex<Subscription>>,
+    open: Arc<AtomicBool>,
+}
+
+impl Client {
+    fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Queue `payload` if `event` is subscribed, returns whether the client is kept
+    fn forward(&self, event: &SpotEvent, payload: &str) -> bool {
+        if !self.is_open() {
+            return false;
+        }
+        if !self.subscription.lock().unwrap_or_else(|e| e.into_inner()).matches(event) {
+            return true;
+        }
+        match self.tx.try_send(payload.to_string()) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                eprintln!(
+                    "Warning: WebSocket client {} fell {} messages behind, disconnecting it",
+                    self.peer, WS_CLIENT_QUEUE_CAPACITY
+                );
+                false
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.open.store(false, Ordering::Relaxed);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Spawn a thread forwarding the events of `event_rx` as JSON text messages to WebSocket clients on `bind_addr`
+///
+/// Each message is a `SequencedEvent` serialized with `serde_json`, byte vectors as arrays of numbers.
+/// A client narrows what it receives by sending a `Subscription`, which is acknowledged with
+/// `{"subscribed": <subscription>}`. Every client has its own queue of `WS_CLIENT_QUEUE_CAPACITY`
+/// messages written by its own thread, so a slow client is disconnected instead of holding up the bus.
+/// The WebSocket protocol is spoken by `tungstenite`, with the limits of `protocol::config`.
+/// A connection beyond `max_clients` is answered with `503 Service Unavailable` and closed, 0 disables the limit.
+pub fn spawn_ws_bridge(
+    event_rx: EventReceiver,
+    bind_addr: SocketAddr,
+    max_clients: usize,
+    shutdown_flag: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(bind_addr)?;
+    listener.set_nonblocking(true)?;
+    Ok(thread::spawn(move || {
+        println!("WebSocket bridge thread started on {}", bind_addr);
+        let mut clients: Vec<Client> = Vec::new();
+        loop {
+            if shutdown_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            loop {
+                match listener.accept() {
+                    Ok((mut stream, peer)) if max_clients > 0 && clients.len() >= max_clients => {
+                        eprintln!("Warning: WebSocket client {} refused, {} clients are connected", peer, clients.len());
+                        let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                    Ok((stream, peer)) => match accept_client(stream, peer) {
+                        Ok(client) => clients.push(client),
+                        Err(e) => eprintln!("Error accepting WebSocket client {}: {}", peer, e),
+                    },
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        eprintln!("Error accepting WebSocket connection: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            match event_rx.recv_timeout(Duration::from_millis(50)) {
+                // nobody to serialize the event for
+                Ok(_) if clients.is_empty() => {}
+                Ok(sequenced) => match serde_json::to_string(&sequenced) {
+                    Ok(payload) => clients.retain(|client| client.forward(&sequenced.event, &payload)),
+                    Err(e) => eprintln!("Error serializing event for WebSocket clients: {}", e),
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    println!("WebSocket bridge channel disconnected");
+                    break;
+                }
+            }
+            clients.retain(Client::is_open);
+        }
+        println!("WebSocket bridge thread stopped");
+    }))
+}
+
+/// Start the thread serving a new connection, the handshake runs on it so a slow client does not stall the bridge
+fn accept_client(stream: TcpStream, peer: SocketAddr) -> std::io::Result<Client> {
+    stream.set_nonblocking(false)?;
+    let (tx, rx) = mpsc::sync_channel(WS_CLIENT_QUEUE_CAPACITY);
+    let client = Client {
+        peer,
+        stream: stream.try_clone()?,
+        tx,
+        subscription: Arc::new(Mutex::new(Subscription::default())),
+        open: Arc::new(AtomicBool::new(true)),
+    };
+    let subscription = client.subscription.clone();
+    let open = client.open.clone();
+    thread::spawn(move || serve_client(stream, rx, subscription, open));
+    Ok(client)
+}
+
+/// Complete the handshake, then write the queued messages and read the client's until the bridge or the client hangs up
+///
+/// One thread reads and writes the connection: it waits up to `CLIENT_POLL_INTERVAL` for the client
+/// and writes what was queued in between.
+fn serve_client(
+    stream: TcpStream,
+    rx: mpsc::Receiver<String>,
+    subscription: Arc<Mutex<Subscription>>,
+    open: Arc<AtomicBool>,
+) {
+    let handshake = stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .and_then(|_| protocol::handshake(stream))
+        .and_then(|websocket| {
+            websocket.get_ref().set_read_timeout(Some(CLIENT_POLL_INTERVAL))?;
+            Ok(websocket)
+        });
+    let mut websocket = match handshake {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            eprintln!("WebSocket handshake failed: {}", e);
+            open.store(false, Ordering::Relaxed);
+            return;
+        }
+    };
+
+    'serve: while read_client_message(&mut websocket, &subscription) {
+        // the channel disconnects once the bridge dropped the client
+        loop {
+            match rx.try_recv() {
+                Ok(payload) => {
+                    if websocket.send(Message::text(payload)).is_err() {
+                        break 'serve;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => break 'serve,
+            }
+        }
+    }
+    open.store(false, Ordering::Relaxed);
+    let _ = websocket.get_ref().shutdown(Shutdown::Both);
+}
+
+/// Apply the subscription a client sends, returns whether the connection is kept
+///
+/// Pings are answered and a close is echoed by `tungstenite`, binary messages and pongs are ignored.
+fn read_client_message(websocket: &mut WebSocket<TcpStream>, subscription: &Mutex<Subscription>) -> bool {
+    match websocket.read() {
+        Ok(Message::Text(text)) => {
+            let reply = match serde_json::from_str::<Subscription>(&text) {
+                Ok(update) => {
+                    let reply = serde_json::json!({ "subscribed": update }).to_string();
+                    *subscription.lock().unwrap_or_else(|e| e.into_inner()) = update;
+                    reply
+                }
+                Err(e) => serde_json::json!({ "error": format!("invalid subscription: {}", e) }).to_string(),
+            };
+            websocket.send(Message::text(reply)).is_ok()
+        }
+        Ok(Message::Close(_)) => {
+            let _ = websocket.flush();
+            false
+        }
+        Ok(_) => true,
+        // nothing was sent within the poll interval
+        Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => true,
+        Err(_) => false,
+    }
+}
+
+/// Get the address of the WebSocket bridge from `WS_BIND`, e.g. `127.0.0.1:8081`
+/// Returns None when `WS_BIND` is unset, the bridge is disabled then
+pub fn get_ws_bind() -> Result<Option<SocketAddr>> {
+    match std::env::var("WS_BIND") {
+        Ok(value) => value
+            .parse::<SocketAddr>()
+            .map(Some)
+            .map_err(|e| anyhow!("invalid WS_BIND {:?}: {}", value, e)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Get the most clients the WebSocket bridge serves at once from `WS_MAX_CLIENTS`
+/// (default: `DEFAULT_WS_MAX_CLIENTS`, 0 disables the limit)
+pub fn get_ws_max_clients() -> Result<usize> {
+    match std::env::var("WS_MAX_CLIENTS") {
+        Ok(value) => value.parse::<usize>().map_err(|e| anyhow!("invalid WS_MAX_CLIENTS {:?}: {}", value, e)),
+        Err(_) => Ok(DEFAULT_WS_MAX_CLIENTS),
+    }
+}
@@ -0,0 +1,51 @@
+use std::io::{self, Write};
+use std::net::TcpStream;
+use tungstenite::error::ProtocolError;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Error, WebSocket};
+
+/// Largest frame payload accepted from a client, subscribe messages are far smaller
+pub const MAX_CLIENT_FRAME_BYTES: usize = 64 * 1024;
+
+/// Value of `Sec-WebSocket-Accept` answering the `Sec-WebSocket-Key` of a client
+pub fn accept_key(key: &str) -> String {
+    tungstenite::handshake::derive_accept_key(key.trim().as_bytes())
+}
+
+/// Limits of a client connection
+///
+/// A frame or message beyond `MAX_CLIENT_FRAME_BYTES` fails the read, so do an unmasked frame
+/// (RFC 6455, section 5.1) and a control frame beyond 125 bytes (section 5.5).
+pub fn config() -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_frame_size(Some(MAX_CLIENT_FRAME_BYTES))
+        .max_message_size(Some(MAX_CLIENT_FRAME_BYTES))
+        .accept_unmasked_frames(false)
+}
+
+/// Read the HTTP upgrade request of a client and answer it with `101 Switching Protocols`
+///
+/// The request must be a `GET` with `Upgrade: websocket`, `Connection: Upgrade`, `Sec-WebSocket-Version: 13` and a
+/// `Sec-WebSocket-Key`, and the client must wait for the answer before it sends a frame. Another version is answered
+/// with `426 Upgrade Required` and any other invalid request with `400 Bad Request`, both fail.
+/// A request not complete within the read timeout of `stream` fails with `TimedOut`.
+pub fn handshake(stream: TcpStream) -> io::Result<WebSocket<TcpStream>> {
+    // the handshake takes the stream, a clone answers the request it rejects
+    let mut reply = stream.try_clone()?;
+    match tungstenite::accept_with_config(stream, Some(config())) {
+        Ok(websocket) => Ok(websocket),
+        Err(HandshakeError::Interrupted(_)) => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "upgrade request was not complete in time"))
+        }
+        Err(HandshakeError::Failure(Error::Io(e))) => Err(e),
+        Err(HandshakeError::Failure(Error::Protocol(ProtocolError::MissingSecWebSocketVersionHeader))) => {
+            let _ = reply.write_all(b"HTTP/1.1 426 Upgrade Required\r\nSec-WebSocket-Version: 13\r\nContent-Length: 0\r\n\r\n");
+            Err(io::Error::new(io::ErrorKind::InvalidData, "upgrade request is not for WebSocket version 13"))
+        }
+        Err(HandshakeError::Failure(e)) => {
+            let _ = reply.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+            Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{MatchingEngine, MockClock};
+use offgrid_spot_runtime::metrics::Metrics;
+use offgrid_spot_runtime::network::acks::{ack_key, AckKey};
+use offgrid_spot_runtime::network::{
+    admit_order, handle_order_message, receive_order, AckCache, Admission, OrderAgeGuard, ZmqServer,
+};
+use offgrid_spot_runtime::proto::{order_request::Request, LimitOrder, OrderRequest, OrderResponse};
+use prost::Message;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn key(client_order_id: &[u8]) -> AckKey {
+    (b"gateway-1".to_vec(), client_order_id.to_vec())
+}
+
+fn limit_order(is_bid: bool, owner: u8, amount: u64, client_order_id: &[u8]) -> OrderRequest {
+    OrderRequest {
+        request: Some(Request::Limit(LimitOrder {
+            cid: vec![1],
+            pair_id: b"BTC-USD".to_vec(),
+            owner: vec![owner],
+            is_bid,
+            price: SCALE_8,
+            amount,
+            timestamp: 1,
+            expires_at: i64::MAX,
+            time_in_force: TimeInForce::GoodTillCanceled as i32,
+            client_order_id: client_order_id.to_vec(),
+            ..Default::default()
+        })),
+        correlation_id: b"req-1".to_vec(),
+    }
+}
+
+#[test]
+fn least_recently_used_response_is_evicted() {
+    let mut acks = AckCache::with_clock(2, 1000, MockClock::new(0));
+    acks.insert(key(b"a"), b"ack-a".to_vec());
+    acks.insert(key(b"b"), b"ack-b".to_vec());
+    // reading `a` makes `b` the least recently used
+    assert_eq!(acks.get(&key(b"a")), Some(b"ack-a".to_vec()));
+    acks.insert(key(b"c"), b"ack-c".to_vec());
+
+    assert_eq!(acks.len(), 2);
+    assert_eq!(acks.get(&key(b"b")), None);
+    assert_eq!(acks.get(&key(b"a")), Some(b"ack-a".to_vec()));
+    assert_eq!(acks.get(&key(b"c")), Some(b"ack-c".to_vec()));
+}
+
+#[test]
+fn response_expires_after_the_ttl() {
+    let clock = MockClock::new(0);
+    let mut acks = AckCache::with_clock(10, 1000, clock.clone());
+    acks.insert(key(b"a"), b"ack-a".to_vec());
+
+    clock.advance(1000);
+    assert_eq!(acks.get(&key(b"a")), Some(b"ack-a".to_vec()));
+    clock.advance(1);
+    assert_eq!(acks.get(&key(b"a")), None);
+    assert!(acks.is_empty());
+}
+
+#[test]
+fn retransmission_of_an_in_flight_order_is_held_until_it_is_answered() {
+    let metrics = Metrics::new().unwrap();
+    let order_age = OrderAgeGuard::disabled();
+    let mut acks = AckCache::with_clock(10, 1000, MockClock::new(0));
+    let request = limit_order(true, 20, SCALE_8, b"a");
+
+    let first = admit_order(b"gateway-1", request.clone(), &mut acks, &order_age, &metrics);
+    assert_eq!(first, Admission::Process(Box::new(request.clone()), Some(key(b"a"))));
+    // the retransmission arrives while a worker still processes the order
+    let retransmitted = admit_order(b"gateway-1", request.clone(), &mut acks, &order_age, &metrics);
+    assert_eq!(retransmitted, Admission::Hold);
+
+    assert_eq!(acks.complete(key(b"a"), b"ack-a".to_vec()), 1);
+    let late = admit_order(b"gateway-1", request, &mut acks, &order_age, &metrics);
+    assert_eq!(late, Admission::Respond(b"ack-a".to_vec()));
+}
+
+#[test]
+fn orders_without_a_client_order_id_are_not_cached() {
+    assert_eq!(ack_key(b"gateway-1", &limit_order(true, 20, SCALE_8, b"")), None);
+    assert_eq!(ack_key(b"gateway-1", &limit_order(true, 20, SCALE_8, b"order-1")), Some(key(b"order-1")));
+}
+
+#[test]
+fn retransmitted_order_is_answered_with_the_original_ack() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    // two base units asked at 1.00
+    engine
+        .limit_sell(vec![1], b"BTC-USD".to_vec(), None, vec![10], SCALE_8, 2 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .expect("maker");
+    let engine = Mutex::new(engine);
+    let metrics = Metrics::new().unwrap();
+    let mut acks = AckCache::new(16, 60_000);
+
+    let dir = tempfile::tempdir().unwrap();
+    let order_endpoint = format!("ipc://{}", dir.path().join("orders.ipc").display());
+    let event_endpoint = format!("ipc://{}", dir.path().join("events.ipc").display());
+    let context = zmq::Context::new();
+    let server = ZmqServer::new_endpoints(&context, &event_endpoint, &order_endpoint).unwrap();
+    let dealer = context.socket(zmq::DEALER).unwrap();
+    dealer.set_linger(0).unwrap();
+    dealer.set_rcvtimeo(5000).unwrap();
+    dealer.connect(&order_endpoint).unwrap();
+
+    // the gateway retries a buy of one unit it did not see acknowledged
+    let request = limit_order(true, 20, SCALE_8, b"order-1").encode_to_vec();
+    let mut acks_received = Vec::new();
+    for _ in 0..2 {
+        dealer.send_multipart([&b""[..], &request], 0).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (identity, msg) = loop {
+            if let Some(order) = receive_order(server.order_router()) {
+                break order;
+            }
+            assert!(Instant::now() < deadline, "no order received");
+            thread::sleep(Duration::from_millis(10));
+        };
+        handle_order_message(server.order_router(), &identity, &msg, &engine, &metrics, &mut acks, &OrderAgeGuard::disabled()).unwrap();
+        acks_received.push(dealer.recv_multipart(0).unwrap()[1].clone());
+    }
+
+    assert_eq!(acks_received[0], acks_received[1]);
+    let response = OrderResponse::decode(acks_received[0].as_slice()).unwrap();
+    assert!(response.accepted, "{}", response.error);
+    // only one of the two units was taken
+    let engine = engine.lock().unwrap();
+    let pair = engine.get_pair(b"BTC-USD").unwrap();
+    assert_eq!(pair.orderbook.l2.current_ask_level(SCALE_8), Some(SCALE_8));
+    assert_eq!(metrics.order_processing_duration.get_sample_count(), 2);
+}
@@ -0,0 +1,43 @@
+use offgrid_primitives::spot::event::{SequencedEvent, SpotEvent};
+use offgrid_spot_runtime::network::EventCodec;
+
+fn transfer(seq: u64) -> SequencedEvent {
+    SequencedEvent {
+        seq,
+        event: SpotEvent::Transfer {
+            cid: vec![1],
+            from: vec![10],
+            to: vec![20],
+            asset: b"BTC".to_vec(),
+            amnt: 1_0000_0000,
+            timestamp: 1,
+        },
+        book: None,
+    }
+}
+
+#[test]
+fn events_round_trip_through_every_codec() {
+    for codec in [EventCodec::Json, EventCodec::Postcard] {
+        let event = transfer(7);
+        let payload = codec.encode(&event).expect("encode event");
+        assert_eq!(payload[0], codec.tag());
+        assert_eq!(EventCodec::decode(&payload).expect("decode event"), event, "{:?}", codec);
+    }
+}
+
+#[test]
+fn codec_names_and_tags_are_parsed() {
+    assert_eq!(EventCodec::from_name("JSON"), Some(EventCodec::Json));
+    assert_eq!(EventCodec::from_name("postcard"), Some(EventCodec::Postcard));
+    assert_eq!(EventCodec::from_name("bincode"), None);
+    assert_eq!(EventCodec::from_tag(EventCodec::Postcard.tag()), Some(EventCodec::Postcard));
+}
+
+#[test]
+fn unknown_or_missing_tag_is_rejected() {
+    assert!(EventCodec::decode(&[]).is_err());
+    let mut payload = EventCodec::Json.encode(&transfer(1)).unwrap();
+    payload[0] = 0xff;
+    assert!(EventCodec::decode(&payload).is_err());
+}
@@ -0,0 +1,166 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_primitives::spot::prices::crc32;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::MatchingEngine;
+use offgrid_spot_runtime::jobs::{run_cron_jobs, run_expiry_batch, spawn_cron_thread, BOOK_CHECKSUM_DEPTH};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// the event queue is global, keep tests that emit and drain events apart
+static EVENT_MUTEX: Mutex<()> = Mutex::new(());
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn resting_orders(engine: &Mutex<MatchingEngine>, pair_id: &[u8]) -> usize {
+    engine.lock().unwrap().get_pair(pair_id).unwrap().orderbook.l3.orders.len()
+}
+
+#[test]
+fn cron_cycle_expires_orders_across_pairs() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    for pair_id in [b"BTC-USD".to_vec(), b"ETH-USD".to_vec()] {
+        engine.add_pair(vec![1], vec![2], vec![3], pair_id.clone(), 0);
+        engine
+            .limit_sell(vec![1], pair_id, None, vec![10], 100 * SCALE_8, SCALE_8, 0, 0, 1_000, 0, 0, TimeInForce::GoodTillDate)
+            .unwrap();
+    }
+    engine
+        .limit_sell(vec![1], b"BTC-USD".to_vec(), None, vec![11], 101 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    let engine = Arc::new(Mutex::new(engine));
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_cron_thread(engine.clone(), Duration::from_millis(20), shutdown.clone());
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while resting_orders(&engine, b"ETH-USD") > 0 || resting_orders(&engine, b"BTC-USD") > 1 {
+        assert!(Instant::now() < deadline, "cron did not expire the orders");
+        thread::sleep(Duration::from_millis(10));
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    // only the good till canceled order is left
+    let engine = engine.lock().unwrap();
+    let btc = engine.get_pair(b"BTC-USD").unwrap();
+    assert_eq!(btc.orderbook.l3.orders.len(), 1);
+    assert_eq!(btc.orderbook.l2.collect_ask_prices(), vec![101 * SCALE_8]);
+    assert!(engine.get_pair(b"ETH-USD").unwrap().orderbook.l2.collect_ask_prices().is_empty());
+}
+
+#[test]
+fn expired_bid_is_refunded_in_the_pair_quote_asset() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    for mut pair in engine.pairs_mut() {
+        pair.base_asset_id = b"BTC".to_vec();
+        pair.quote_asset_id = b"USD".to_vec();
+    }
+    engine
+        .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![10], 100 * SCALE_8, SCALE_8, 0, 0, 1_000, 0, 0, TimeInForce::GoodTillDate)
+        .unwrap();
+    let _ = event::drain_events();
+
+    run_cron_jobs(&mut engine, 2_000);
+
+    let events = event::drain_events();
+    assert!(events.iter().any(|e| matches!(e, SpotEvent::SpotOrderExpired { is_bid: true, .. })));
+    assert!(!events.iter().any(|e| matches!(e, SpotEvent::Transfer { .. })));
+    let unlocks: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::Unlock { account_id, asset, .. } => Some((account_id.clone(), asset.clone())),
+            _ => None,
+        })
+        .collect();
+    // the locked quote goes back to the owner, nothing is paid from another account
+    assert_eq!(unlocks, vec![(vec![10], b"USD".to_vec())]);
+}
+
+#[test]
+fn expiry_batches_stop_once_no_expired_order_is_left() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    for i in 0..25 {
+        engine
+            .limit_sell(vec![1], b"BTC-USD".to_vec(), None, vec![10], (100 + i) * SCALE_8, SCALE_8, 0, 0, 1_000, 0, 0, TimeInForce::GoodTillDate)
+            .unwrap();
+    }
+    let _ = event::drain_events();
+
+    let mut batches = 1;
+    while run_expiry_batch(&mut engine, 2_000, 10) {
+        batches += 1;
+    }
+
+    // two full batches and a last one with the remaining five
+    assert_eq!(batches, 3);
+    assert!(engine.get_pair(b"BTC-USD").unwrap().orderbook.l3.orders.is_empty());
+    let expired = event::events_of_type(|e| matches!(e, SpotEvent::SpotOrderExpired { .. }));
+    assert_eq!(expired.len(), 25);
+}
+
+// the checksum a client computes from the levels it rebuilt from the placements it received
+fn client_checksum(events: &[SpotEvent], pair: &[u8]) -> u32 {
+    let mut levels: BTreeMap<(bool, u64), u64> = BTreeMap::new();
+    for e in events {
+        if let SpotEvent::SpotOrderPlaced { pair_id, is_bid, price, cqty, .. } = e
+            && pair_id == pair
+        {
+            *levels.entry((*is_bid, *price)).or_default() += cqty;
+        }
+    }
+    let format = |value: u64| format!("{}.{:08}", value / SCALE_8, value % SCALE_8);
+    let asks = levels.iter().filter(|((is_bid, _), _)| !is_bid);
+    let bids = levels.iter().filter(|((is_bid, _), _)| *is_bid).rev();
+    let payload: Vec<String> = asks.chain(bids).map(|((_, price), cqty)| format!("{}:{}", format(*price), format(*cqty))).collect();
+    crc32(payload.join(",").as_bytes())
+}
+
+#[test]
+fn cron_cycle_emits_the_checksum_of_every_book() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    for pair_id in [b"BTC-USD".to_vec(), b"ETH-USD".to_vec()] {
+        engine.add_pair(vec![1], vec![2], vec![3], pair_id, 0);
+    }
+    let _ = event::drain_events();
+    for (pair_id, price) in [(&b"BTC-USD"[..], 100 * SCALE_8), (b"ETH-USD", 10 * SCALE_8)] {
+        // placed on the pair directly, its events stay queued for the test
+        let mut pair = engine.get_pair(pair_id).unwrap();
+        for (owner, offset) in [(10u8, 1), (11, 2)] {
+            pair.limit_sell(vec![1], None, vec![owner], price + offset * SCALE_8, offset * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+                .unwrap();
+            pair.limit_buy(vec![1], None, vec![owner], price - offset * SCALE_8, 3 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+                .unwrap();
+        }
+    }
+    let mut events = event::drain_events().into_vec();
+
+    run_cron_jobs(&mut engine, 2_000);
+    events.extend(event::drain_events().into_vec());
+
+    let checksums: Vec<(Vec<u8>, u32, u32, i64)> = events
+        .iter()
+        .filter_map(|e| match e {
+            SpotEvent::SpotBookChecksum { pair_id, top_n, checksum, timestamp } => Some((pair_id.clone(), *top_n, *checksum, *timestamp)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(checksums.len(), 2);
+    for (pair_id, top_n, checksum, timestamp) in checksums {
+        assert_eq!(top_n, BOOK_CHECKSUM_DEPTH);
+        assert_eq!(timestamp, 2_000);
+        assert_eq!(checksum, client_checksum(&events, &pair_id));
+    }
+}
@@ -0,0 +1,75 @@
+use offgrid_spot_runtime::network::{receive_order, send_ack, ZmqServer};
+use std::thread;
+use std::time::{Duration, Instant};
+use zmq::{Context, CurveKeyPair, DEALER};
+
+fn curve_dealer(context: &Context, server_key: &[u8], client: &CurveKeyPair, port: u16) -> zmq::Socket {
+    let dealer = context.socket(DEALER).expect("dealer socket");
+    dealer.set_curve_serverkey(server_key).expect("server key");
+    dealer.set_curve_publickey(&client.public_key).expect("client public key");
+    dealer.set_curve_secretkey(&client.secret_key).expect("client secret key");
+    dealer.set_linger(0).expect("linger");
+    dealer.connect(&format!("tcp://127.0.0.1:{}", port)).expect("connect");
+    dealer
+}
+
+fn curve_available() -> bool {
+    let available = zmq::has("curve") == Some(true);
+    if !available {
+        eprintln!("skipping: libzmq was built without CURVE support");
+    }
+    available
+}
+
+fn poll_order(server: &ZmqServer, timeout: Duration) -> Option<(zmq::Message, zmq::Message)> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(order) = receive_order(server.order_router()) {
+            return Some(order);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    None
+}
+
+#[test]
+fn curve_authenticated_client_round_trips() {
+    if !curve_available() {
+        return;
+    }
+    let context = Context::new();
+    let server_keys = CurveKeyPair::new().unwrap();
+    let client_keys = CurveKeyPair::new().unwrap();
+    let server = ZmqServer::new_secure(&context, 47611, 47612, &server_keys.secret_key, vec![client_keys.public_key])
+        .expect("secure server");
+
+    let dealer = curve_dealer(&context, &server_keys.public_key, &client_keys, 47612);
+    dealer.send_multipart([&b""[..], b"ping"], 0).unwrap();
+
+    let (identity, msg) = poll_order(&server, Duration::from_secs(5)).expect("order from authorized client");
+    assert_eq!(msg.as_ref(), b"ping");
+    send_ack(server.order_router(), &identity, "ACK").unwrap();
+
+    dealer.set_rcvtimeo(5000).unwrap();
+    let reply = dealer.recv_multipart(0).expect("ack from server");
+    assert_eq!(reply, vec![Vec::<u8>::new(), b"ACK".to_vec()]);
+}
+
+#[test]
+fn curve_rejects_unauthorized_client() {
+    if !curve_available() {
+        return;
+    }
+    let context = Context::new();
+    let server_keys = CurveKeyPair::new().unwrap();
+    let allowed_keys = CurveKeyPair::new().unwrap();
+    let intruder_keys = CurveKeyPair::new().unwrap();
+    let server = ZmqServer::new_secure(&context, 47621, 47622, &server_keys.secret_key, vec![allowed_keys.public_key])
+        .expect("secure server");
+
+    let dealer = curve_dealer(&context, &server_keys.public_key, &intruder_keys, 47622);
+    dealer.set_sndtimeo(500).unwrap();
+    let _ = dealer.send_multipart([&b""[..], b"ping"], 0);
+
+    assert!(poll_order(&server, Duration::from_secs(1)).is_none());
+}
@@ -0,0 +1,210 @@
+use offgrid_primitives::spot::event::{self, BookSeq, EventReceiver, OverflowPolicy, SequencedEvent, SpotEvent};
+use offgrid_primitives::spot::MatchingEngine;
+use offgrid_spot_runtime::event_log::{read_log, replay_engine, EventLog, EventLogError};
+use offgrid_spot_runtime::jobs::run_cron_jobs;
+use offgrid_spot_runtime::network::process_order_request;
+use offgrid_spot_runtime::proto::{order_request::Request, CancelOrder, LimitOrder, OrderRequest, TimeInForce};
+use offgrid_spot_runtime::snapshot::{load_snapshot, save_snapshot};
+use std::fs;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// the event bus is global, so the tests of this file take turns
+static BUS: Mutex<()> = Mutex::new(());
+
+fn limit(pair_id: &[u8], owner: u8, is_bid: bool, price: u64, amount: u64, expires_at: i64, time_in_force: TimeInForce) -> OrderRequest {
+    OrderRequest {
+        request: Some(Request::Limit(LimitOrder {
+            cid: vec![1],
+            pair_id: pair_id.to_vec(),
+            owner: vec![owner],
+            is_bid,
+            price,
+            amount,
+            timestamp: 1,
+            expires_at,
+            maker_fee_bps: 10,
+            taker_fee_bps: 20,
+            time_in_force: time_in_force as i32,
+            ..Default::default()
+        })),
+        correlation_id: Vec::new(),
+    }
+}
+
+fn submit(engine: &MatchingEngine, request: OrderRequest) -> Vec<u8> {
+    let response = process_order_request(engine, request);
+    assert!(response.accepted, "{}", response.error);
+    response.order_id
+}
+
+// the events dispatched to `receiver` until the bus goes quiet
+fn received(receiver: &EventReceiver) -> Vec<SequencedEvent> {
+    let mut events = Vec::new();
+    while let Ok(sequenced) = receiver.recv_timeout(Duration::from_millis(200)) {
+        events.push(sequenced);
+    }
+    events
+}
+
+fn engine_with_pairs() -> MatchingEngine {
+    let mut engine = MatchingEngine::new();
+    for (pair_id, base, quote) in [(&b"BTC-USD"[..], &b"BTC"[..], &b"USD"[..]), (b"ETH-USD", b"ETH", b"USD")] {
+        engine.add_pair(vec![1], vec![2], vec![3], pair_id.to_vec(), 0);
+        let mut pair = engine.get_pair(pair_id).unwrap();
+        pair.base_asset_id = base.to_vec();
+        pair.quote_asset_id = quote.to_vec();
+    }
+    engine
+}
+
+#[test]
+fn replayed_event_log_reproduces_a_multi_pair_session() {
+    let _bus = BUS.lock().unwrap_or_else(|e| e.into_inner());
+    event::init_event_bus();
+    let receiver = event::register_backend_bounded(100_000, OverflowPolicy::Block);
+    let dir = tempfile::tempdir().unwrap();
+    let log_path = dir.path().join("events.log");
+    let snapshot_path = dir.path().join("snapshot.bin");
+    let mut log = EventLog::open(&log_path, 4).unwrap();
+
+    let mut engine = engine_with_pairs();
+    let gtc = TimeInForce::GoodTillCanceled;
+    submit(&engine, limit(b"BTC-USD", 20, false, 101 * SCALE_8, 3 * SCALE_8, i64::MAX, gtc));
+    submit(&engine, limit(b"BTC-USD", 30, true, 99 * SCALE_8, 200 * SCALE_8, i64::MAX, gtc));
+    submit(&engine, limit(b"ETH-USD", 40, false, 11 * SCALE_8, 5 * SCALE_8, 1_000, gtc));
+    for sequenced in received(&receiver) {
+        log.append(&sequenced).unwrap();
+    }
+    // the snapshot is taken mid-session, the log goes on
+    save_snapshot(&engine, &snapshot_path).unwrap();
+
+    // a partial fill, a fill emptying a level, a cancel and an expiry on the two pairs
+    submit(&engine, limit(b"BTC-USD", 21, false, 102 * SCALE_8, SCALE_8, i64::MAX, gtc));
+    submit(&engine, limit(b"BTC-USD", 31, true, 101 * SCALE_8, 101 * SCALE_8, i64::MAX, TimeInForce::ImmediateOrCancel));
+    submit(&engine, limit(b"BTC-USD", 32, true, 102 * SCALE_8, 2 * 102 * SCALE_8, i64::MAX, gtc));
+    let bid = submit(&engine, limit(b"ETH-USD", 41, true, 9 * SCALE_8, 9 * SCALE_8, i64::MAX, gtc));
+    submit(&engine, limit(b"ETH-USD", 42, true, 8 * SCALE_8, 16 * SCALE_8, i64::MAX, gtc));
+    submit(
+        &engine,
+        OrderRequest {
+            request: Some(Request::Cancel(CancelOrder {
+                cid: vec![1],
+                pair_id: b"ETH-USD".to_vec(),
+                owner: vec![41],
+                is_bid: true,
+                order_id: bid,
+            })),
+            correlation_id: Vec::new(),
+        },
+    );
+    run_cron_jobs(&mut engine, 2_000);
+    event::publish_events();
+    for sequenced in received(&receiver) {
+        log.append(&sequenced).unwrap();
+    }
+    log.sync().unwrap();
+    assert_eq!(log.pending(), 0);
+    {
+        let eth = engine.get_pair(b"ETH-USD").unwrap();
+        assert!(eth.orderbook.l2.collect_ask_prices().is_empty(), "the ask expired");
+        assert_eq!(eth.orderbook.l2.collect_bid_prices(), vec![8 * SCALE_8]);
+    }
+
+    let restored = load_snapshot(&snapshot_path).unwrap();
+    assert_ne!(restored, engine);
+    assert!(replay_engine(&log_path, &restored).unwrap() > 0);
+    assert_eq!(restored, engine);
+    for pair_id in [&b"BTC-USD"[..], b"ETH-USD"] {
+        let pair = restored.get_pair(pair_id).unwrap();
+        assert_eq!(pair.orderbook.verify_invariants(), Ok(()));
+    }
+    assert_eq!(restored.get_pair(b"BTC-USD").unwrap().l1.lmp, Some(102 * SCALE_8));
+
+    // replaying again finds every event already applied
+    assert_eq!(replay_engine(&log_path, &restored).unwrap(), 0);
+}
+
+fn book_event(pair_id: &[u8], book_seq: u64, seq: u64) -> SequencedEvent {
+    SequencedEvent {
+        seq,
+        event: SpotEvent::SpotBookChecksum { pair_id: pair_id.to_vec(), top_n: 1, checksum: seq as u32, timestamp: 1 },
+        book: Some(BookSeq { pair_id: pair_id.to_vec(), seq: book_seq }),
+    }
+}
+
+#[test]
+fn events_of_a_book_are_logged_in_book_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.log");
+    let mut log = EventLog::open(&path, 1).unwrap();
+
+    // published from two threads, the second event of the book arrives first
+    log.append(&book_event(b"A", 2, 12)).unwrap();
+    log.append(&book_event(b"B", 1, 11)).unwrap();
+    assert_eq!(log.pending(), 1);
+    log.append(&book_event(b"A", 1, 10)).unwrap();
+    // events outside of an orderbook and events already logged are skipped
+    log.append(&SequencedEvent { book: None, ..book_event(b"A", 3, 13) }).unwrap();
+    log.append(&book_event(b"A", 1, 10)).unwrap();
+    assert_eq!(log.pending(), 0);
+    assert_eq!(log.last_seq(), 12);
+
+    let logged: Vec<(Vec<u8>, u64)> = read_log(&path)
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.book.pair_id, entry.book.seq))
+        .collect();
+    assert_eq!(logged, vec![(b"B".to_vec(), 1), (b"A".to_vec(), 1), (b"A".to_vec(), 2)]);
+}
+
+#[test]
+fn incomplete_record_is_cut_off_and_a_corrupt_one_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.log");
+    {
+        let mut log = EventLog::open(&path, 8).unwrap();
+        log.append(&book_event(b"A", 1, 1)).unwrap();
+        log.append(&book_event(b"A", 2, 2)).unwrap();
+        log.sync().unwrap();
+    }
+    let complete = fs::metadata(&path).unwrap().len();
+
+    // a crash in the middle of a record
+    fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+    assert_eq!(read_log(&path).unwrap().len(), 2);
+    let mut log = EventLog::open(&path, 1).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), complete);
+    assert_eq!(log.last_seq(), 2);
+    log.append(&book_event(b"A", 3, 3)).unwrap();
+    drop(log);
+    assert_eq!(read_log(&path).unwrap().len(), 3);
+
+    let mut data = fs::read(&path).unwrap();
+    data[6] ^= 0xff;
+    fs::write(&path, &data).unwrap();
+    assert!(matches!(read_log(&path), Err(EventLogError::ChecksumMismatch { offset: 0, .. })));
+    assert!(matches!(EventLog::open(&path, 1), Err(EventLogError::ChecksumMismatch { .. })));
+}
+
+#[test]
+fn book_missing_an_event_fails_to_replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.log");
+    let engine = engine_with_pairs();
+    let mut log = EventLog::open(&path, 1).unwrap();
+    // the log of the book resumes after event 5, the restored book stopped at 0
+    log.resume_after(b"BTC-USD", 5);
+    log.append(&book_event(b"BTC-USD", 6, 6)).unwrap();
+
+    assert!(matches!(
+        replay_engine(&path, &engine),
+        Err(EventLogError::Gap { expected: 1, found: 6, .. })
+    ));
+    let unknown = dir.path().join("unknown.log");
+    EventLog::open(&unknown, 1).unwrap().append(&book_event(b"XRP-USD", 1, 1)).unwrap();
+    assert!(matches!(replay_engine(&unknown, &engine), Err(EventLogError::PairNotFound(_))));
+}
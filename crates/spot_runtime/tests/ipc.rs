@@ -0,0 +1,34 @@
+use offgrid_spot_runtime::network::{receive_order, send_ack, ZmqServer};
+use std::thread;
+use std::time::{Duration, Instant};
+use zmq::{Context, DEALER};
+
+#[test]
+fn dealer_round_trips_over_ipc() {
+    let dir = tempfile::tempdir().unwrap();
+    let event_endpoint = format!("ipc://{}", dir.path().join("events.ipc").display());
+    let order_endpoint = format!("ipc://{}", dir.path().join("orders.ipc").display());
+
+    let context = Context::new();
+    let server = ZmqServer::new_endpoints(&context, &event_endpoint, &order_endpoint).expect("ipc server");
+
+    let dealer = context.socket(DEALER).unwrap();
+    dealer.set_linger(0).unwrap();
+    dealer.set_rcvtimeo(5000).unwrap();
+    dealer.connect(&order_endpoint).unwrap();
+    dealer.send_multipart([&b""[..], b"ping"], 0).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let (identity, msg) = loop {
+        if let Some(order) = receive_order(server.order_router()) {
+            break order;
+        }
+        assert!(Instant::now() < deadline, "no order received over ipc");
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(msg.as_ref(), b"ping");
+
+    send_ack(server.order_router(), &identity, "ACK").unwrap();
+    let reply = dealer.recv_multipart(0).expect("ack over ipc");
+    assert_eq!(reply, vec![Vec::<u8>::new(), b"ACK".to_vec()]);
+}
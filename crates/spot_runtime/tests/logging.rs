@@ -0,0 +1,132 @@
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use offgrid_primitives::spot::event::{SequencedEvent, SpotEvent};
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_spot_runtime::logging::{log_event, StdoutLogger, EVENT_TARGET};
+use std::collections::HashMap;
+use std::sync::{Mutex, Once};
+
+/// Record of `log_event` as seen by the logger
+#[derive(Debug, Clone)]
+struct Captured {
+    level: log::Level,
+    target: String,
+    fields: HashMap<String, String>,
+}
+
+static CAPTURED: Mutex<Vec<Captured>> = Mutex::new(Vec::new());
+static INIT: Once = Once::new();
+
+struct CaptureLogger;
+
+struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let mut fields = HashMap::new();
+        record.key_values().visit(&mut FieldVisitor(&mut fields)).unwrap();
+        CAPTURED.lock().unwrap().push(Captured {
+            level: record.level(),
+            target: record.target().to_string(),
+            fields,
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+// every test of the binary shares the global logger and its captured records
+fn capture(max_level: LevelFilter, f: impl FnOnce()) -> Vec<Captured> {
+    static SERIAL: Mutex<()> = Mutex::new(());
+    let _guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    INIT.call_once(|| log::set_logger(&CaptureLogger).unwrap());
+    log::set_max_level(max_level);
+    CAPTURED.lock().unwrap().clear();
+    f();
+    std::mem::take(&mut *CAPTURED.lock().unwrap())
+}
+
+fn order_placed(order_id: OrderId) -> SequencedEvent {
+    SequencedEvent {
+        seq: 42,
+        event: SpotEvent::SpotOrderPlaced {
+            cid: vec![1],
+            pair_id: b"BTC-USD".to_vec(),
+            base_asset_id: vec![2],
+            quote_asset_id: vec![3],
+            order_id: order_id.to_bytes().to_vec(),
+            maker_account_id: vec![20],
+            is_bid: true,
+            price: 100,
+            amnt: 1000,
+            iqty: 0,
+            cqty: 1000,
+            pqty: 1000,
+            timestamp: 1,
+            expires_at: i64::MAX,
+            fee_bps: 0,
+        },
+        book: None,
+    }
+}
+
+#[test]
+fn placed_order_is_logged_with_its_order_id() {
+    let order_id = OrderId::from_parts(1, 2);
+    let records = capture(LevelFilter::Info, || log_event(&order_placed(order_id)));
+
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.level, log::Level::Info);
+    assert_eq!(record.target, EVENT_TARGET);
+    assert_eq!(record.fields["event"], "SpotOrderPlaced");
+    assert_eq!(record.fields["pair_id"], "BTC-USD");
+    assert_eq!(record.fields["order_id"], order_id.to_string());
+    assert_eq!(record.fields["seq"], "42");
+}
+
+#[test]
+fn events_below_the_level_are_not_logged() {
+    let transfer = SequencedEvent {
+        seq: 1,
+        event: SpotEvent::Transfer {
+            cid: vec![1],
+            from: vec![10],
+            to: vec![20],
+            asset: b"BTC".to_vec(),
+            amnt: 1,
+            timestamp: 1,
+        },
+        book: None,
+    };
+    assert!(capture(LevelFilter::Info, || log_event(&transfer)).is_empty());
+
+    let records = capture(LevelFilter::Debug, || log_event(&transfer));
+    assert_eq!(records.len(), 1);
+    assert!(!records[0].fields.contains_key("order_id"));
+}
+
+#[test]
+fn stdout_line_carries_the_fields() {
+    let fields = [("order_id", "01ABC")];
+    let line = StdoutLogger::format(
+        &Record::builder()
+            .level(log::Level::Info)
+            .target(EVENT_TARGET)
+            .args(format_args!("SpotOrderPlaced"))
+            .key_values(&fields)
+            .build(),
+    );
+    assert_eq!(line, "INFO orderbook::events SpotOrderPlaced order_id=01ABC");
+}
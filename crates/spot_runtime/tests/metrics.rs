@@ -0,0 +1,433 @@
+use offgrid_primitives::spot::event::{self, EventQueue, SpotEvent};
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::Pair;
+use offgrid_primitives::spot::MatchingEngine;
+use offgrid_spot_runtime::metrics::{
+    spawn_metrics_thread, spawn_metrics_thread_on, spawn_push_thread, BookSnapshot, Component, Health, HealthReport, Metrics, PushConfig,
+    IMBALANCE_DEPTH, RESTING_ORDER_BYTES,
+};
+use offgrid_spot_runtime::network::{handle_order_message, receive_order, AckCache, OrderAgeGuard, ZmqServer};
+use offgrid_spot_runtime::proto::{order_request::Request, LimitOrder, OrderRequest, OrderResponse};
+use prometheus::core::Metric;
+use prost::Message;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// both tests go through the global event queue
+static EVENT_MUTEX: Mutex<()> = Mutex::new(());
+
+fn lock_events() -> std::sync::MutexGuard<'static, ()> {
+    EVENT_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn scrape(port: u16) -> String {
+    get(port, "/metrics")
+}
+
+fn get(port: u16, path: &str) -> String {
+    for _ in 0..50 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream.write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes()).unwrap();
+            let mut body = String::new();
+            stream.read_to_string(&mut body).unwrap();
+            return body;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("metrics server did not start");
+}
+
+fn metric_value(body: &str, series: &str) -> u64 {
+    body.lines()
+        .find_map(|line| line.strip_prefix(series))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_else(|| panic!("{} not found in scrape", series))
+}
+
+#[test]
+fn fees_of_a_trade_are_exposed_per_asset() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = b"BTC-USD".to_vec();
+    pair.base_asset_id = b"BTC".to_vec();
+    pair.quote_asset_id = b"USD".to_vec();
+    pair.add_client(vec![1], vec![90], vec![91]);
+
+    // maker ask 10 BTC @ 100 paying 10 bps, taker buys with 500 USD paying 30 bps
+    pair.limit_sell(
+        vec![1], None, vec![10], 100 * SCALE_8, 10 * SCALE_8, 0, 1, i64::MAX, 10, 30,
+        TimeInForce::GoodTillCanceled,
+    )
+    .unwrap();
+    pair.limit_buy(
+        vec![1], None, vec![20], 110 * SCALE_8, 500 * SCALE_8, 0, 1, i64::MAX, 10, 30,
+        TimeInForce::GoodTillCanceled,
+    )
+    .unwrap();
+    let events = event::drain_events();
+
+    let (mut base_fee, mut quote_fee) = (0, 0);
+    for event in events.iter() {
+        if let SpotEvent::SpotOrderPartiallyFilled { is_taker_event: true, base_fee: b, quote_fee: q, .. }
+        | SpotEvent::SpotOrderFullyFilled { is_taker_event: true, base_fee: b, quote_fee: q, .. } = event
+        {
+            base_fee += b;
+            quote_fee += q;
+        }
+    }
+    assert!(base_fee > 0 && quote_fee > 0, "trade should charge fees in both assets");
+
+    let metrics = Arc::new(Metrics::new().unwrap());
+    for event in events.iter() {
+        metrics.record_event(event);
+    }
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_metrics_thread(metrics, Arc::new(Mutex::new(MatchingEngine::new())), Arc::new(Health::default()), shutdown.clone(), 47651);
+    let body = scrape(47651);
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert_eq!(metric_value(&body, "orderbook_fees_collected_total{asset=\"BTC\"}"), base_fee);
+    assert_eq!(metric_value(&body, "orderbook_fees_collected_total{asset=\"USD\"}"), quote_fee);
+}
+
+#[test]
+fn handling_an_order_observes_processing_duration() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    let engine = Mutex::new(engine);
+    let metrics = Metrics::new().unwrap();
+    assert_eq!(metrics.order_processing_duration.get_sample_count(), 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let order_endpoint = format!("ipc://{}", dir.path().join("orders.ipc").display());
+    let event_endpoint = format!("ipc://{}", dir.path().join("events.ipc").display());
+    let context = zmq::Context::new();
+    let server = ZmqServer::new_endpoints(&context, &event_endpoint, &order_endpoint).unwrap();
+    let dealer = context.socket(zmq::DEALER).unwrap();
+    dealer.set_linger(0).unwrap();
+    dealer.set_rcvtimeo(5000).unwrap();
+    dealer.connect(&order_endpoint).unwrap();
+
+    let request = OrderRequest {
+        request: Some(Request::Limit(LimitOrder {
+            cid: vec![1],
+            pair_id: b"BTC-USD".to_vec(),
+            owner: vec![20],
+            is_bid: true,
+            price: 100 * SCALE_8,
+            amount: 100 * SCALE_8,
+            timestamp: 1,
+            expires_at: i64::MAX,
+            ..Default::default()
+        })),
+        correlation_id: b"req-1".to_vec(),
+    };
+    dealer.send_multipart([&b""[..], &request.encode_to_vec()], 0).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let (identity, msg) = loop {
+        if let Some(order) = receive_order(server.order_router()) {
+            break order;
+        }
+        assert!(Instant::now() < deadline, "no order received");
+        thread::sleep(Duration::from_millis(10));
+    };
+    handle_order_message(server.order_router(), &identity, &msg, &engine, &metrics, &mut AckCache::new(0, 0), &OrderAgeGuard::disabled()).unwrap();
+
+    assert_eq!(metrics.order_processing_duration.get_sample_count(), 1);
+    let frames = dealer.recv_multipart(0).unwrap();
+    let response = OrderResponse::decode(frames[1].as_slice()).unwrap();
+    assert!(response.accepted, "{}", response.error);
+}
+
+fn read_request(stream: &mut TcpStream) -> (String, String) {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed before the request was complete");
+        data.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&data).to_string();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let (head, body) = text.split_at(end + 4);
+            let length: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0);
+            if body.len() >= length {
+                return (head.to_string(), body.to_string());
+            }
+        }
+    }
+}
+
+#[test]
+fn metrics_are_pushed_to_the_pushgateway_on_the_interval() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let metrics = Arc::new(Metrics::new().unwrap());
+    metrics.orders_placed.inc_by(3);
+
+    let config = PushConfig {
+        url: format!("http://{}", address),
+        interval: Duration::from_millis(200),
+        job: "test-job".to_string(),
+        instance: "test 1/a".to_string(),
+    };
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_push_thread(metrics, shutdown.clone(), config);
+
+    let started = Instant::now();
+    for _ in 0..2 {
+        let (mut stream, _) = listener.accept().unwrap();
+        let (head, body) = read_request(&mut stream);
+        assert!(head.starts_with("POST /metrics/job/test-job/instance/test%201%2Fa HTTP/1.1\r\n"), "{}", head);
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4"), "{}", head);
+        assert!(body.contains("orderbook_orders_placed_total 3"), "{}", body);
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(200), "second push came before the interval");
+
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+}
+
+#[test]
+fn half_filled_taker_records_fill_ratio_near_half() {
+    let _guard = lock_events();
+    let mut pair = Pair::new();
+    pair.pair_id = b"BTC-USD".to_vec();
+    pair.base_asset_id = b"BTC".to_vec();
+    pair.quote_asset_id = b"USD".to_vec();
+
+    // maker ask 5 BTC @ 1, taker bids 10 USD @ 1.1: the maker absorbs about half of the taker
+    pair.limit_sell(
+        vec![1], None, vec![10], SCALE_8, 5 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    )
+    .unwrap();
+    pair.limit_buy(
+        vec![1], None, vec![20], SCALE_8 * 11 / 10, 10 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+        TimeInForce::GoodTillCanceled,
+    )
+    .unwrap();
+    let events = event::drain_events();
+
+    let metrics = Metrics::new().unwrap();
+    for event in events.iter() {
+        metrics.record_event(event);
+    }
+
+    // the ask matched nothing on entry and is observed at 0
+    assert_eq!(metrics.order_fill_ratio.get_sample_count(), 2);
+    let zero_fills = metrics.order_fill_ratio.metric().get_histogram().get_bucket()[0].get_cumulative_count();
+    assert_eq!(zero_fills, 1);
+    let ratio = metrics.order_fill_ratio.get_sample_sum();
+    assert!((ratio - 0.5).abs() < 0.1, "fill ratio {}", ratio);
+}
+
+#[test]
+fn book_route_serves_top_levels_of_a_pair() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    for price in [99, 98] {
+        engine
+            .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![10], price * SCALE_8, 100 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .unwrap();
+    }
+    engine
+        .limit_sell(vec![1], b"BTC-USD".to_vec(), None, vec![11], 101 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    let expected = engine.get_pair(b"BTC-USD").unwrap().orderbook.l2.clone();
+    let engine = Arc::new(Mutex::new(engine));
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_metrics_thread(Arc::new(Metrics::new().unwrap()), engine, Arc::new(Health::default()), shutdown.clone(), 47652);
+    let found = get(47652, "/book?pair=4254432d555344&depth=1");
+    let unknown = get(47652, "/book?pair=00ff");
+    let malformed = get(47652, "/book?pair=zz");
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(found.starts_with("HTTP/1.1 200 OK"));
+    let (_, body) = found.split_once("\r\n\r\n").unwrap();
+    let book: BookSnapshot = serde_json::from_str(body).unwrap();
+    assert_eq!(book.pair_id, "4254432d555344");
+    // depth 1 keeps only the best level of each side
+    assert_eq!(book.bids.len(), 1);
+    assert_eq!(book.bids[0].price, 99 * SCALE_8);
+    assert_eq!(Some(book.bids[0].cqty), expected.current_bid_level(99 * SCALE_8));
+    assert_eq!(book.asks.len(), 1);
+    assert_eq!(book.asks[0].price, 101 * SCALE_8);
+    assert_eq!(Some(book.asks[0].cqty), expected.current_ask_level(101 * SCALE_8));
+
+    assert!(unknown.starts_with("HTTP/1.1 404"));
+    assert!(malformed.starts_with("HTTP/1.1 400"));
+}
+
+#[test]
+fn unhealthy_snapshot_thread_flips_health_to_503() {
+    let health = Arc::new(Health::new(Duration::from_secs(30)));
+    for component in Component::ALL {
+        health.beat(component);
+    }
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_metrics_thread(
+        Arc::new(Metrics::new().unwrap()),
+        Arc::new(Mutex::new(MatchingEngine::new())),
+        health.clone(),
+        shutdown.clone(),
+        47653,
+    );
+    let ready = get(47653, "/health");
+    health.mark_unhealthy(Component::Snapshot);
+    let degraded = get(47653, "/health");
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(ready.starts_with("HTTP/1.1 200 OK"));
+    assert!(degraded.starts_with("HTTP/1.1 503"));
+    let (_, body) = degraded.split_once("\r\n\r\n").unwrap();
+    let report: HealthReport = serde_json::from_str(body).unwrap();
+    assert!(!report.healthy);
+    assert!(report.engine_lock);
+    assert!(report.pair_locks);
+    for status in report.components {
+        assert_eq!(status.healthy, status.component != Component::Snapshot, "{:?}", status.component);
+    }
+}
+
+#[test]
+fn component_without_a_recent_beat_is_stale() {
+    let health = Health::new(Duration::from_millis(50));
+    health.beat(Component::MainLoop);
+    assert!(health.is_healthy(Component::MainLoop));
+    thread::sleep(Duration::from_millis(100));
+    assert!(!health.is_healthy(Component::MainLoop));
+    health.beat(Component::MainLoop);
+    assert!(health.report(false, 0).components.iter().any(|s| s.component == Component::MainLoop && s.healthy));
+    // a poisoned engine lock or pair lock fails the whole report
+    assert!(!health.report(true, 0).healthy);
+    let report = health.report(false, 1);
+    assert!(!report.healthy && !report.pair_locks);
+}
+
+#[test]
+fn metrics_server_bound_to_loopback_accepts_loopback_connections() {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let address = "127.0.0.1:47654".parse().unwrap();
+    let handle = spawn_metrics_thread_on(
+        Arc::new(Metrics::new().unwrap()),
+        Arc::new(Mutex::new(MatchingEngine::new())),
+        Arc::new(Health::default()),
+        shutdown.clone(),
+        address,
+    );
+    let body = scrape(47654);
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(body.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", body);
+}
+
+#[test]
+fn book_signals_are_recorded_per_pair() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    engine.add_pair(vec![1], vec![2], vec![3], b"ETH-USD".to_vec(), 0);
+    // 3 base bid @ 100 against 1 base ask @ 104
+    engine
+        .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![10], 100 * SCALE_8, 300 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    engine
+        .limit_sell(vec![1], b"BTC-USD".to_vec(), None, vec![11], 104 * SCALE_8, SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    event::drain_events();
+
+    let metrics = Metrics::new().unwrap();
+    metrics.record_book_signals(&engine, IMBALANCE_DEPTH);
+
+    assert_eq!(metrics.orderbook_microprice.with_label_values(&["4254432d555344"]).get(), (103 * SCALE_8) as i64);
+    assert_eq!(metrics.orderbook_imbalance.with_label_values(&["4254432d555344"]).get(), 0.5);
+    // the empty pair has no microprice and a neutral imbalance
+    let microprices = metrics.registry.gather().into_iter().find(|family| family.get_name() == "orderbook_microprice").unwrap();
+    assert_eq!(microprices.get_metric().len(), 1);
+    assert_eq!(metrics.orderbook_imbalance.with_label_values(&["4554482d555344"]).get(), 0.0);
+}
+
+#[test]
+fn resting_order_gauges_follow_the_books_on_the_update_tick() {
+    let _guard = lock_events();
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    engine.add_pair(vec![1], vec![2], vec![3], b"ETH-USD".to_vec(), 0);
+    for (pair_id, orders) in [(b"BTC-USD", 3), (b"ETH-USD", 2)] {
+        for i in 0..orders {
+            engine
+                .limit_buy(vec![1], pair_id.to_vec(), None, vec![10], (90 + i) * SCALE_8, 100 * SCALE_8, 0, 0, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+                .unwrap();
+        }
+    }
+    event::drain_events();
+
+    // the metrics thread refreshes the engine gauges right away and then every `ENGINE_STATS_INTERVAL`
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_metrics_thread(
+        Arc::new(Metrics::new().unwrap()),
+        Arc::new(Mutex::new(engine)),
+        Arc::new(Health::default()),
+        shutdown.clone(),
+        47655,
+    );
+    let body = scrape(47655);
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert_eq!(metric_value(&body, "resting_orders_total"), 5);
+    assert_eq!(metric_value(&body, "orderbook_memory_bytes"), 5 * RESTING_ORDER_BYTES as u64);
+}
+
+#[test]
+fn metrics_filter_keeps_level_changes_off_the_metrics_backend() {
+    let _guard = lock_events();
+    event::init_event_bus();
+    let receiver = event::register_backend_with_filter(Metrics::event_filter());
+    let block_changed = |timestamp| SpotEvent::SpotOrderBlockChanged {
+        pair_id: b"BTC-USD".to_vec(),
+        is_bid: true,
+        price: 100 * SCALE_8,
+        pqty: SCALE_8,
+        cqty: SCALE_8,
+        timestamp,
+    };
+    let cancelled = SpotEvent::SpotOrderCancelled {
+        cid: vec![1],
+        order_id: vec![7; 16],
+        maker_account_id: vec![10],
+        is_bid: true,
+        price: 100 * SCALE_8,
+        amnt: SCALE_8,
+        iqty: 0,
+        pqty: SCALE_8,
+        cqty: SCALE_8,
+        timestamp: 2,
+        expires_at: i64::MAX,
+    };
+    event::publish_event_queue(EventQueue::from_vec(vec![block_changed(1), cancelled.clone(), block_changed(3)]));
+
+    // the cancel is counted, the level changes around it never reach the backend
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap().event, cancelled);
+    assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+}
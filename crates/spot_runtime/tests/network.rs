@@ -0,0 +1,183 @@
+use offgrid_primitives::spot::MatchingEngine;
+use offgrid_spot_runtime::network::{
+    decode_order_request, encode_order_response, process_order_request, receive_order, send_response, ZmqServer,
+};
+use offgrid_spot_runtime::proto::{
+    order_request::Request, AmendOrder, CancelOrder, LimitOrder, MarketOrder, OrderRequest, OrderResponse,
+    ResponseStatus, TimeInForce,
+};
+use prost::Message;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn limit_order(is_bid: bool) -> LimitOrder {
+    LimitOrder {
+        cid: vec![1],
+        pair_id: b"BTC-USD".to_vec(),
+        owner: vec![20],
+        is_bid,
+        price: 100 * SCALE_8,
+        amount: 100 * SCALE_8,
+        iceberg_quantity: 0,
+        timestamp: 1,
+        expires_at: i64::MAX,
+        maker_fee_bps: -2,
+        taker_fee_bps: 10,
+        time_in_force: TimeInForce::GoodTillCanceled as i32,
+        client_order_id: Vec::new(),
+    }
+}
+
+fn round_trip(request: Request) {
+    let request = OrderRequest { request: Some(request), correlation_id: b"req-1".to_vec() };
+    let decoded = decode_order_request(&request.encode_to_vec()).expect("decode order request");
+    assert_eq!(decoded, request);
+}
+
+#[test]
+fn limit_request_round_trips() {
+    round_trip(Request::Limit(limit_order(true)));
+}
+
+#[test]
+fn market_request_round_trips() {
+    round_trip(Request::Market(MarketOrder {
+        cid: vec![1],
+        pair_id: b"BTC-USD".to_vec(),
+        owner: vec![20],
+        is_bid: false,
+        amount: SCALE_8,
+        iceberg_quantity: 0,
+        timestamp: 1,
+        expires_at: i64::MAX,
+        maker_fee_bps: 0,
+        taker_fee_bps: 10,
+        time_in_force: TimeInForce::ImmediateOrCancel as i32,
+        client_order_id: Vec::new(),
+    }));
+}
+
+#[test]
+fn cancel_request_round_trips() {
+    round_trip(Request::Cancel(CancelOrder {
+        cid: vec![1],
+        pair_id: b"BTC-USD".to_vec(),
+        owner: vec![20],
+        is_bid: true,
+        order_id: vec![7; 16],
+    }));
+}
+
+#[test]
+fn amend_request_round_trips() {
+    round_trip(Request::Amend(AmendOrder {
+        cid: vec![1],
+        pair_id: b"BTC-USD".to_vec(),
+        owner: vec![20],
+        order_id: vec![7; 16],
+        is_bid: false,
+        price: 101 * SCALE_8,
+        amount: 2 * SCALE_8,
+        iceberg_quantity: SCALE_8,
+        timestamp: 2,
+        expires_at: 1_000,
+        maker_fee_bps: 5,
+        taker_fee_bps: 10,
+        time_in_force: TimeInForce::GoodTillDate as i32,
+    }));
+}
+
+#[test]
+fn response_round_trips() {
+    let response = OrderResponse {
+        accepted: false,
+        error: "pair does not exist".to_string(),
+        event_count: 0,
+        correlation_id: b"req-1".to_vec(),
+        status: ResponseStatus::Rejected as i32,
+        order_id: vec![],
+    };
+    let decoded = OrderResponse::decode(encode_order_response(&response).as_slice()).expect("decode response");
+    assert_eq!(decoded, response);
+}
+
+#[test]
+fn malformed_bytes_are_rejected() {
+    assert!(decode_order_request(&[0xff, 0xff, 0xff]).is_err());
+    // a well-formed but empty request carries no order
+    assert!(decode_order_request(&[]).is_err());
+}
+
+#[test]
+fn decoded_request_is_routed_to_the_matching_engine() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+
+    let request = OrderRequest { request: Some(Request::Limit(limit_order(true))), correlation_id: vec![] };
+    let response = process_order_request(&engine, request);
+    assert!(response.accepted, "{}", response.error);
+    assert!(response.event_count > 0);
+
+    let mut unknown_pair = limit_order(false);
+    unknown_pair.pair_id = b"ETH-USD".to_vec();
+    let request = OrderRequest { request: Some(Request::Limit(unknown_pair)), correlation_id: vec![] };
+    let response = process_order_request(&engine, request);
+    assert!(!response.accepted);
+    assert_eq!(response.status, ResponseStatus::Rejected as i32);
+    assert_eq!(response.error, "pair does not exist");
+}
+
+#[test]
+fn responses_echo_correlation_ids_of_interleaved_requests() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let order_endpoint = format!("ipc://{}", dir.path().join("orders.ipc").display());
+    let event_endpoint = format!("ipc://{}", dir.path().join("events.ipc").display());
+    let context = zmq::Context::new();
+    let server = ZmqServer::new_endpoints(&context, &event_endpoint, &order_endpoint).expect("server");
+
+    let dealer = context.socket(zmq::DEALER).unwrap();
+    dealer.set_linger(0).unwrap();
+    dealer.set_rcvtimeo(5000).unwrap();
+    dealer.connect(&order_endpoint).unwrap();
+
+    let mut unknown_pair = limit_order(false);
+    unknown_pair.pair_id = b"ETH-USD".to_vec();
+    let requests = [
+        OrderRequest { request: Some(Request::Limit(limit_order(true))), correlation_id: b"req-a".to_vec() },
+        OrderRequest { request: Some(Request::Limit(unknown_pair)), correlation_id: b"req-b".to_vec() },
+    ];
+    for request in &requests {
+        dealer.send_multipart([&b""[..], &request.encode_to_vec()], 0).unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut handled = 0;
+    while handled < requests.len() {
+        assert!(Instant::now() < deadline, "requests were not received");
+        let Some((identity, msg)) = receive_order(server.order_router()) else {
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        };
+        let request = decode_order_request(&msg).unwrap();
+        let response = process_order_request(&engine, request);
+        send_response(server.order_router(), &identity, &encode_order_response(&response)).unwrap();
+        handled += 1;
+    }
+
+    let mut responses = Vec::new();
+    for _ in 0..requests.len() {
+        let frames = dealer.recv_multipart(0).expect("response");
+        responses.push(OrderResponse::decode(frames[1].as_slice()).unwrap());
+    }
+    assert_eq!(responses[0].correlation_id, b"req-a".to_vec());
+    assert_eq!(responses[0].status, ResponseStatus::Accepted as i32);
+    assert_eq!(responses[0].order_id.len(), 16);
+    assert_eq!(responses[1].correlation_id, b"req-b".to_vec());
+    assert_eq!(responses[1].status, ResponseStatus::Rejected as i32);
+    assert!(responses[1].order_id.is_empty());
+}
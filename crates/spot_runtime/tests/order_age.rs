@@ -0,0 +1,112 @@
+use offgrid_primitives::spot::clock::TimeUnit;
+use offgrid_primitives::spot::{MatchingEngine, MockClock};
+use offgrid_spot_runtime::metrics::Metrics;
+use offgrid_spot_runtime::network::{handle_order_message, receive_order, AckCache, OrderAgeGuard, ZmqServer};
+use offgrid_spot_runtime::proto::{order_request::Request, CancelOrder, LimitOrder, OrderRequest, OrderResponse, ResponseStatus, TimeInForce};
+use prost::Message;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn limit_order(timestamp: i64, correlation_id: &[u8]) -> OrderRequest {
+    OrderRequest {
+        request: Some(Request::Limit(LimitOrder {
+            cid: vec![1],
+            pair_id: b"BTC-USD".to_vec(),
+            owner: vec![20],
+            is_bid: true,
+            price: 100 * SCALE_8,
+            amount: 100 * SCALE_8,
+            timestamp,
+            expires_at: i64::MAX,
+            time_in_force: TimeInForce::GoodTillCanceled as i32,
+            ..Default::default()
+        })),
+        correlation_id: correlation_id.to_vec(),
+    }
+}
+
+#[test]
+fn only_orders_older_than_the_maximum_age_are_stale() {
+    let guard = OrderAgeGuard::with_clock(1000, MockClock::new(10_000));
+    assert!(guard.is_stale(&limit_order(8_999, b"")));
+    assert!(!guard.is_stale(&limit_order(9_000, b"")));
+    // cancels carry no timestamp
+    let cancel = OrderRequest {
+        request: Some(Request::Cancel(CancelOrder::default())),
+        correlation_id: Vec::new(),
+    };
+    assert!(!guard.is_stale(&cancel));
+
+    assert!(!OrderAgeGuard::disabled().is_stale(&limit_order(0, b"")));
+}
+
+#[test]
+fn orders_timestamped_too_far_ahead_are_stale() {
+    let guard = OrderAgeGuard::with_clock(1000, MockClock::new(10_000));
+    assert!(!guard.is_stale(&limit_order(11_000, b"")));
+    assert!(guard.is_stale(&limit_order(11_001, b"")));
+    assert!(guard.is_stale(&limit_order(i64::MAX, b"")));
+}
+
+#[test]
+fn timestamps_are_read_in_the_time_unit_of_the_engine() {
+    let mut guard = OrderAgeGuard::with_clock(5_000, MockClock::new(60_000));
+    guard.set_time_unit(TimeUnit::Seconds);
+    // 60 s on the clock, a maximum age of 5 s
+    assert!(!guard.is_stale(&limit_order(55, b"")));
+    assert!(!guard.is_stale(&limit_order(65, b"")));
+    assert!(guard.is_stale(&limit_order(54, b"")));
+    assert!(guard.is_stale(&limit_order(66, b"")));
+    // a timestamp in milliseconds is far in the future of a clock read in seconds
+    assert!(guard.is_stale(&limit_order(60_000, b"")));
+}
+
+#[test]
+fn stale_order_is_rejected_while_a_fresh_one_is_accepted() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    let engine = Mutex::new(engine);
+    let metrics = Metrics::new().unwrap();
+    let mut acks = AckCache::new(0, 0);
+    let order_age = OrderAgeGuard::with_clock(5_000, MockClock::new(60_000));
+
+    let dir = tempfile::tempdir().unwrap();
+    let order_endpoint = format!("ipc://{}", dir.path().join("orders.ipc").display());
+    let event_endpoint = format!("ipc://{}", dir.path().join("events.ipc").display());
+    let context = zmq::Context::new();
+    let server = ZmqServer::new_endpoints(&context, &event_endpoint, &order_endpoint).unwrap();
+    let dealer = context.socket(zmq::DEALER).unwrap();
+    dealer.set_linger(0).unwrap();
+    dealer.set_rcvtimeo(5000).unwrap();
+    dealer.connect(&order_endpoint).unwrap();
+
+    // a message signed a minute ago is replayed, then a fresh one is sent
+    let mut responses = Vec::new();
+    for request in [limit_order(0, b"replayed"), limit_order(59_000, b"fresh")] {
+        dealer.send_multipart([&b""[..], &request.encode_to_vec()], 0).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (identity, msg) = loop {
+            if let Some(order) = receive_order(server.order_router()) {
+                break order;
+            }
+            assert!(Instant::now() < deadline, "no order received");
+            thread::sleep(Duration::from_millis(10));
+        };
+        handle_order_message(server.order_router(), &identity, &msg, &engine, &metrics, &mut acks, &order_age).unwrap();
+        let frames = dealer.recv_multipart(0).unwrap();
+        responses.push(OrderResponse::decode(frames[1].as_slice()).unwrap());
+    }
+
+    assert!(!responses[0].accepted);
+    assert_eq!(responses[0].status, ResponseStatus::Rejected as i32);
+    assert_eq!(responses[0].correlation_id, b"replayed".to_vec());
+    assert!(responses[1].accepted, "{}", responses[1].error);
+    assert_eq!(metrics.orders_stale.get(), 1);
+    // only the fresh order rests on the book
+    let engine = engine.lock().unwrap();
+    let pair = engine.get_pair(b"BTC-USD").unwrap();
+    assert_eq!(pair.orderbook.l2.current_bid_level(100 * SCALE_8), Some(100 * SCALE_8));
+}
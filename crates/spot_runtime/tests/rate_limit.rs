@@ -0,0 +1,67 @@
+use offgrid_primitives::spot::MockClock;
+use offgrid_spot_runtime::network::RateLimiter;
+
+#[test]
+fn burst_is_consumed_then_rejected() {
+    let clock = MockClock::new(0);
+    let mut limiter = RateLimiter::with_clock(10, 3, clock);
+
+    for _ in 0..3 {
+        assert!(limiter.check(b"gateway-1"));
+    }
+    assert!(!limiter.check(b"gateway-1"));
+}
+
+#[test]
+fn tokens_refill_over_time_up_to_burst() {
+    let clock = MockClock::new(0);
+    let mut limiter = RateLimiter::with_clock(10, 2, clock.clone());
+    assert!(limiter.check(b"gateway-1"));
+    assert!(limiter.check(b"gateway-1"));
+    assert!(!limiter.check(b"gateway-1"));
+
+    // 10 orders/sec refills one token every 100 ms
+    clock.advance(50);
+    assert!(!limiter.check(b"gateway-1"));
+    clock.advance(50);
+    assert!(limiter.check(b"gateway-1"));
+    assert!(!limiter.check(b"gateway-1"));
+
+    // a long idle period refills only up to the burst
+    clock.advance(10_000);
+    assert!(limiter.check(b"gateway-1"));
+    assert!(limiter.check(b"gateway-1"));
+    assert!(!limiter.check(b"gateway-1"));
+}
+
+#[test]
+fn identities_have_separate_buckets() {
+    let clock = MockClock::new(0);
+    let mut limiter = RateLimiter::with_clock(1, 1, clock);
+    assert!(limiter.check(b"gateway-1"));
+    assert!(!limiter.check(b"gateway-1"));
+    assert!(limiter.check(b"gateway-2"));
+}
+
+#[test]
+fn idle_buckets_are_evicted_once_refilled() {
+    let clock = MockClock::new(0);
+    // 10 orders/sec refills a burst of 2 in 200 ms
+    let mut limiter = RateLimiter::with_clock(10, 2, clock.clone());
+    for n in 0..100u8 {
+        assert!(limiter.check(&[n]));
+    }
+    assert_eq!(limiter.tracked_clients(), 100);
+
+    clock.advance(150);
+    assert!(limiter.check(b"gateway-1"));
+    assert!(limiter.check(b"gateway-1"));
+    clock.advance(50);
+    assert!(limiter.check(b"gateway-2"));
+    // only the buckets used within the refill time are left
+    assert_eq!(limiter.tracked_clients(), 2);
+    // the kept bucket still holds what its client used
+    assert!(!limiter.check(b"gateway-1"));
+    clock.advance(100);
+    assert!(limiter.check(b"gateway-1"));
+}
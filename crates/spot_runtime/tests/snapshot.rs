@@ -0,0 +1,393 @@
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{matching_engine, MatchingEngine};
+use offgrid_spot_runtime::metrics::{Health, Metrics};
+use offgrid_spot_runtime::snapshot::{
+    export_json, load_seq, load_snapshot, save_seq, save_snapshot, seq_path, spawn_adaptive_snapshot_thread,
+    spawn_snapshot_thread, stop_and_flush,
+    PairExport,
+    SnapshotError,
+    SNAPSHOT_MAGIC,
+    SNAPSHOT_VERSION,
+    SnapshotSchedule,
+};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn engine_with_pair() -> MatchingEngine {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    engine
+}
+
+#[test]
+fn snapshot_round_trips_with_checksum() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let engine = engine_with_pair();
+
+    save_snapshot(&engine, &path).unwrap();
+    assert_eq!(load_snapshot(&path).unwrap(), engine);
+}
+
+#[test]
+fn snapshot_restores_fee_recipients_and_dust() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let mut engine = engine_with_pair();
+    for mut pair in engine.pairs_mut() {
+        pair.orderbook.fee_recipients.insert(b"client".to_vec(), b"fee_account".to_vec());
+        pair.orderbook.set_default_fee_recipient(Some(b"default_fee_account".to_vec()));
+        pair.orderbook.set_dust(42);
+    }
+
+    save_snapshot(&engine, &path).unwrap();
+    let loaded = load_snapshot(&path).unwrap();
+    let pair = loaded.get_pair(b"BTC-USD").unwrap();
+    assert_eq!(pair.orderbook.fee_recipients.get(b"client".as_slice()), Some(&b"fee_account".to_vec()));
+    assert_eq!(pair.orderbook.default_fee_recipient, Some(b"default_fee_account".to_vec()));
+    assert_eq!(pair.orderbook.dust, 42);
+}
+
+#[test]
+fn flipped_byte_fails_with_checksum_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    save_snapshot(&engine_with_pair(), &path).unwrap();
+
+    let mut data = fs::read(&path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0x01;
+    fs::write(&path, &data).unwrap();
+
+    assert!(matches!(load_snapshot(&path), Err(SnapshotError::ChecksumMismatch { .. })));
+}
+
+#[test]
+fn truncated_snapshot_fails_with_checksum_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    save_snapshot(&engine_with_pair(), &path).unwrap();
+
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..data.len() / 2]).unwrap();
+    assert!(matches!(load_snapshot(&path), Err(SnapshotError::ChecksumMismatch { .. })));
+
+    // cut inside the version header
+    fs::write(&path, &data[..5]).unwrap();
+    assert!(matches!(load_snapshot(&path), Err(SnapshotError::ChecksumMismatch { .. })));
+}
+
+#[test]
+fn snapshot_of_a_newer_version_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    save_snapshot(&engine_with_pair(), &path).unwrap();
+
+    let mut data = fs::read(&path).unwrap();
+    data[SNAPSHOT_MAGIC.len()..SNAPSHOT_MAGIC.len() + 2].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    fs::write(&path, &data).unwrap();
+    assert!(matches!(load_snapshot(&path), Err(SnapshotError::UnsupportedVersion(v)) if v == SNAPSHOT_VERSION + 1));
+}
+
+#[test]
+fn snapshot_saved_before_versioning_is_migrated() {
+    // saved by the engine before snapshots carried a version or a checksum: one BTC-USD pair of client 1
+    // with asks of 3 at 101 and 1 at 102, and bids of 200 at 99 and 490 at 98
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/snapshot_v0.bin");
+    let engine = load_snapshot(path).unwrap();
+    assert_eq!(engine.pair_count(), 1);
+    {
+        let pair = engine.get_pair(b"BTC-USD").unwrap();
+        assert_eq!(pair.base_asset_id, b"BTC".to_vec());
+        assert_eq!(pair.clients, vec![vec![1]]);
+        assert_eq!(pair.orderbook.l2.collect_ask_prices(), vec![101 * SCALE_8, 102 * SCALE_8]);
+        assert_eq!(pair.orderbook.l2.collect_bid_prices(), vec![99 * SCALE_8, 98 * SCALE_8]);
+        // the fields added since are derived from the saved state
+        assert_eq!(pair.orderbook.l2.best_bid(), Some((99 * SCALE_8, 200 * SCALE_8)));
+        assert_eq!(pair.orderbook.l2.best_ask(), Some((101 * SCALE_8, 3 * SCALE_8)));
+        assert_eq!(pair.orderbook.l3.orders.len(), 4);
+        assert_eq!(pair.orderbook.l3.orders_by_owner(&[30]).len(), 1);
+        assert_eq!(pair.orderbook.verify_invariants(), Ok(()));
+    }
+
+    // the migrated engine is saved with the current version
+    let dir = tempfile::tempdir().unwrap();
+    let resaved = dir.path().join("snapshot.bin");
+    save_snapshot(&engine, &resaved).unwrap();
+    assert!(fs::read(&resaved).unwrap().starts_with(&SNAPSHOT_MAGIC));
+    assert_eq!(load_snapshot(&resaved).unwrap(), engine);
+}
+
+#[test]
+fn snapshot_of_version_1_is_migrated() {
+    // saved with format version 1, before orderbooks numbered their events: one BTC-USD pair of client 1
+    // with asks of 2 at 101 and 1 at 102, and bids of 200 at 99 and 490 at 98, last traded at 101
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/snapshot_v1.bin");
+    let engine = load_snapshot(path).unwrap();
+    assert_eq!(engine.pair_count(), 1);
+    {
+        let pair = engine.get_pair(b"BTC-USD").unwrap();
+        assert_eq!(pair.orderbook.l2.collect_ask_prices(), vec![101 * SCALE_8, 102 * SCALE_8]);
+        assert_eq!(pair.orderbook.l2.collect_bid_prices(), vec![99 * SCALE_8, 98 * SCALE_8]);
+        assert_eq!(pair.orderbook.l2.best_ask(), Some((101 * SCALE_8, 2 * SCALE_8)));
+        assert_eq!(pair.l1.lmp, Some(101 * SCALE_8));
+        // the book has not emitted a numbered event yet
+        assert_eq!(pair.orderbook.event_seq, 0);
+        assert_eq!(pair.orderbook.verify_invariants(), Ok(()));
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let resaved = dir.path().join("snapshot.bin");
+    save_snapshot(&engine, &resaved).unwrap();
+    assert_eq!(fs::read(&resaved).unwrap()[SNAPSHOT_MAGIC.len()..SNAPSHOT_MAGIC.len() + 2], SNAPSHOT_VERSION.to_le_bytes());
+    assert_eq!(load_snapshot(&resaved).unwrap(), engine);
+}
+
+#[test]
+fn corrupted_seq_fails_with_checksum_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    save_seq(&path, 42).unwrap();
+
+    let mut data = fs::read(seq_path(&path)).unwrap();
+    data[0] ^= 0xff;
+    fs::write(seq_path(&path), &data).unwrap();
+    assert!(matches!(load_seq(&path), Err(SnapshotError::ChecksumMismatch { .. })));
+}
+
+#[test]
+fn snapshot_tick_records_duration_and_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let handle = spawn_snapshot_thread(
+        Arc::new(Mutex::new(engine_with_pair())),
+        path.display().to_string(),
+        1,
+        shutdown.clone(),
+        metrics.clone(),
+        Arc::new(Health::default()),
+    );
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while metrics.snapshot_duration.get_sample_count() == 0 {
+        assert!(Instant::now() < deadline, "no snapshot tick recorded");
+        thread::sleep(Duration::from_millis(50));
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(metrics.snapshot_duration.get_sample_count() >= 1);
+    assert_eq!(metrics.snapshot_bytes_written.get(), fs::metadata(&path).unwrap().len() as i64);
+}
+
+#[test]
+fn exported_json_parses_back_to_the_saved_book() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let engine = engine_with_pair();
+    let pair_id = b"BTC-USD".to_vec();
+    for (price, amount) in [(101, 2), (100, 1)] {
+        engine
+            .limit_sell(
+                vec![1], pair_id.clone(), None, vec![10], price * SCALE_8, amount * SCALE_8, 0, 1, i64::MAX, 0, 0,
+                TimeInForce::GoodTillCanceled,
+            )
+            .unwrap();
+    }
+    engine
+        .limit_buy(
+            vec![1], pair_id.clone(), None, vec![20], 90 * SCALE_8, 50 * SCALE_8, 0, 1, i64::MAX, 0, 0,
+            TimeInForce::GoodTillCanceled,
+        )
+        .unwrap();
+    save_snapshot(&engine, &path).unwrap();
+
+    let json = export_json(&path, &pair_id).unwrap();
+    let exported: PairExport = serde_json::from_str(&json).unwrap();
+    let pair = engine.get_pair(&pair_id).unwrap();
+    assert_eq!(exported, PairExport::from_pair(&pair));
+    assert_eq!(exported.pair_id, "BTC-USD");
+    assert_eq!(exported.lmp, pair.l1.lmp);
+    assert_eq!(exported.asks.iter().map(|level| level.price).collect::<Vec<_>>(), vec![100 * SCALE_8, 101 * SCALE_8]);
+    assert_eq!(exported.bids.len(), 1);
+    assert_eq!(exported.bids[0].price, 90 * SCALE_8);
+
+    assert!(matches!(export_json(&path, b"ETH-USD"), Err(SnapshotError::PairNotFound(_))));
+}
+
+#[test]
+fn stop_and_flush_saves_state_mutated_right_before_shutdown() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let engine = Arc::new(Mutex::new(engine_with_pair()));
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    // the interval is long enough that no periodic snapshot is taken during the test
+    let handle = spawn_snapshot_thread(engine.clone(), path.display().to_string(), 60, shutdown.clone(), metrics.clone(), Arc::new(Health::default()));
+    engine.lock().unwrap().add_pair(vec![1], vec![2], vec![3], b"ETH-USD".to_vec(), 1);
+    shutdown.store(true, Ordering::Relaxed);
+
+    let bytes = stop_and_flush(handle, &engine, &path, &metrics).unwrap();
+    assert_eq!(bytes as u64, fs::metadata(&path).unwrap().len());
+    let restored = load_snapshot(&path).unwrap();
+    assert_eq!(restored.pair_count(), 2);
+    assert!(restored.has_pair(&b"ETH-USD".to_vec()));
+    assert_eq!(metrics.snapshot_duration.get_sample_count(), 1);
+}
+
+#[test]
+fn snapshot_recovers_an_engine_lock_poisoned_by_a_panicked_thread() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let engine = Arc::new(Mutex::new(engine_with_pair()));
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let poisoner = engine.clone();
+    let panicked = thread::spawn(move || {
+        let _guard = poisoner.lock().unwrap();
+        panic!("order handler panicked holding the engine lock");
+    })
+    .join();
+    assert!(panicked.is_err());
+    assert!(engine.is_poisoned());
+    let recoveries = offgrid_spot_runtime::poison_recoveries();
+
+    let handle = spawn_snapshot_thread(
+        engine.clone(),
+        path.display().to_string(),
+        1,
+        shutdown.clone(),
+        metrics.clone(),
+        Arc::new(Health::default()),
+    );
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while metrics.snapshot_duration.get_sample_count() == 0 {
+        assert!(Instant::now() < deadline, "no snapshot taken after the lock was poisoned");
+        thread::sleep(Duration::from_millis(50));
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert_eq!(load_snapshot(&path).unwrap().pair_count(), 1);
+    assert!(offgrid_spot_runtime::poison_recoveries() > recoveries);
+    assert!(!engine.is_poisoned());
+}
+
+#[test]
+fn snapshot_recovers_a_pair_lock_poisoned_by_a_panicked_thread() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let engine = engine_with_pair();
+    let poisoned = engine.pair_lock(b"BTC-USD").unwrap();
+    let panicked = thread::spawn(move || {
+        let _guard = poisoned.lock().unwrap();
+        panic!("order handler panicked holding the pair lock");
+    })
+    .join();
+    assert!(panicked.is_err());
+    assert_eq!(engine.poisoned_pairs(), 1);
+    let recoveries = matching_engine::pair_lock_poison_recoveries();
+
+    save_snapshot(&engine, &path).expect("snapshot of the poisoned pair");
+    assert_eq!(load_snapshot(&path).unwrap(), engine);
+    assert_eq!(engine.poisoned_pairs(), 0);
+    assert!(matching_engine::pair_lock_poison_recoveries() > recoveries);
+}
+
+#[test]
+fn snapshot_schedule_backs_off_under_contention_and_recovers() {
+    let second = Duration::from_secs(1);
+    let mut schedule = SnapshotSchedule::new(second, 10 * second, Duration::from_millis(50));
+    let contended = Duration::from_millis(200);
+
+    // 1s + 2s + 4s pass without a snapshot
+    assert!(!schedule.on_lock_acquired(contended));
+    assert_eq!(schedule.interval(), 2 * second);
+    assert!(!schedule.on_lock_acquired(contended));
+    assert_eq!(schedule.interval(), 4 * second);
+    assert!(!schedule.on_lock_acquired(contended));
+    // the backoff stops at the 10s since the last snapshot, which is then taken whatever the contention
+    assert_eq!(schedule.interval(), 3 * second);
+    assert!(schedule.on_lock_acquired(contended));
+    assert_eq!(schedule.interval(), second);
+
+    // contention dropping restores the normal cadence right away
+    assert!(!schedule.on_lock_acquired(contended));
+    assert!(schedule.on_lock_acquired(Duration::from_millis(1)));
+    assert_eq!(schedule.interval(), second);
+}
+
+#[test]
+fn snapshot_thread_defers_while_a_pair_lock_is_held() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.bin");
+    let engine = Arc::new(Mutex::new(engine_with_pair()));
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let schedule = SnapshotSchedule::new(Duration::from_millis(100), Duration::from_secs(60), Duration::from_millis(20));
+
+    let pair = engine.lock().unwrap().pair_lock(b"BTC-USD").unwrap();
+    let guard = pair.lock().unwrap();
+    let handle = spawn_adaptive_snapshot_thread(
+        engine.clone(),
+        path.display().to_string(),
+        schedule,
+        shutdown.clone(),
+        metrics.clone(),
+        Arc::new(Health::default()),
+    );
+    // the thread waits for the pair lock well past the threshold, the engine lock stays free
+    thread::sleep(Duration::from_millis(400));
+    assert!(engine.try_lock().is_ok());
+    assert!(!path.exists());
+    drop(guard);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while metrics.snapshot_duration.get_sample_count() == 0 {
+        assert!(Instant::now() < deadline, "no snapshot after the contention dropped");
+        thread::sleep(Duration::from_millis(50));
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert_eq!(metrics.snapshots_deferred.get(), 1);
+    assert!(path.exists());
+    assert!(seq_path(&path).exists());
+    assert_eq!(metrics.snapshot_interval_seconds.get(), 0.1);
+}
+
+#[test]
+fn failed_snapshot_does_not_save_the_event_sequence() {
+    let dir = tempfile::tempdir().unwrap();
+    // a directory in the way of the snapshot file fails every save
+    let path = dir.path().join("snapshot.bin");
+    fs::create_dir_all(path.with_extension("tmp")).unwrap();
+    let engine = Arc::new(Mutex::new(engine_with_pair()));
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let handle = spawn_adaptive_snapshot_thread(
+        engine,
+        path.display().to_string(),
+        SnapshotSchedule::fixed(Duration::from_millis(100)),
+        shutdown.clone(),
+        metrics.clone(),
+        Arc::new(Health::default()),
+    );
+    thread::sleep(Duration::from_millis(450));
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(!path.exists());
+    assert_eq!(load_seq(&path).unwrap(), None);
+    assert_eq!(metrics.snapshot_duration.get_sample_count(), 0);
+}
@@ -0,0 +1,23 @@
+use offgrid_primitives::spot::event::{self, SpotEvent};
+use offgrid_spot_runtime::snapshot::{load_seq, restore_seq, save_seq};
+
+#[test]
+fn restored_sequence_continues_after_saved_seq() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshot_path = dir.path().join("snapshot.bin");
+    assert_eq!(load_seq(&snapshot_path).unwrap(), None);
+
+    save_seq(&snapshot_path, 42).unwrap();
+    assert_eq!(load_seq(&snapshot_path).unwrap(), Some(42));
+
+    // a fresh process starts from zero and picks the sequence up from the snapshot
+    assert_eq!(restore_seq(&snapshot_path).unwrap(), Some(42));
+    event::emit_event(SpotEvent::SpotPairAdded {
+        cid: vec![1],
+        pair_id: b"BTC-USD".to_vec(),
+        timestamp: 1,
+    });
+    let sequenced = event::drain_sequenced_events();
+    assert_eq!(sequenced.len(), 1);
+    assert_eq!(sequenced[0].seq, 43);
+}
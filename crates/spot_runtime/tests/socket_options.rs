@@ -0,0 +1,63 @@
+use offgrid_spot_runtime::network::{SocketOptions, ZmqServer};
+use zmq::Context;
+
+#[test]
+fn socket_options_are_applied_to_both_sockets() {
+    let context = Context::new();
+    let options = SocketOptions {
+        sndhwm: 5000,
+        rcvhwm: 2500,
+        linger_ms: 250,
+    };
+    let server = ZmqServer::with_options(&context, 47641, 47642, &options).expect("server");
+
+    for socket in [server.event_pub(), server.order_router()] {
+        assert_eq!(socket.get_sndhwm().unwrap(), 5000);
+        assert_eq!(socket.get_rcvhwm().unwrap(), 2500);
+        assert_eq!(socket.get_linger().unwrap(), 250);
+    }
+
+    server.shutdown().unwrap();
+    assert_eq!(server.event_pub().get_linger().unwrap(), 0);
+    assert_eq!(server.order_router().get_linger().unwrap(), 0);
+}
+
+#[test]
+fn default_server_does_not_linger() {
+    let context = Context::new();
+    let server = ZmqServer::new(&context, 47643, 47644).expect("server");
+    assert_eq!(server.event_pub().get_sndhwm().unwrap(), SocketOptions::default().sndhwm);
+    assert_eq!(server.order_router().get_linger().unwrap(), 0);
+}
+
+#[test]
+fn socket_options_are_applied_to_a_secure_server() {
+    if zmq::has("curve") != Some(true) {
+        eprintln!("skipping: libzmq was built without CURVE support");
+        return;
+    }
+    let context = Context::new();
+    let options = SocketOptions {
+        sndhwm: 5000,
+        rcvhwm: 2500,
+        linger_ms: 250,
+    };
+    let server_keys = zmq::CurveKeyPair::new().unwrap();
+    let server = ZmqServer::new_secure_endpoints(
+        &context,
+        "tcp://127.0.0.1:47645",
+        "tcp://127.0.0.1:47646",
+        &options,
+        &server_keys.secret_key,
+        Vec::new(),
+    )
+    .expect("secure server");
+
+    for socket in [server.event_pub(), server.order_router()] {
+        assert_eq!(socket.get_sndhwm().unwrap(), 5000);
+        assert_eq!(socket.get_rcvhwm().unwrap(), 2500);
+        assert_eq!(socket.get_linger().unwrap(), 250);
+        assert!(socket.is_curve_server().unwrap());
+    }
+    server.shutdown().unwrap();
+}
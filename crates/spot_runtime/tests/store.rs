@@ -0,0 +1,174 @@
+use offgrid_primitives::spot::orders::OrderId;
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::{MatchingEngine, Order, SequentialIdGenerator, TerminalState};
+use offgrid_spot_runtime::store::{
+    parse_compression, spawn_order_history_thread, ArchivedOrder, OrderHistoryOptions, OrderHistoryStore, OrderHistoryWriter,
+};
+use std::sync::Arc;
+
+const SCALE_8: u64 = 1_0000_0000;
+
+fn archived_engine(store: &Arc<OrderHistoryStore>) -> (MatchingEngine, OrderHistoryWriter, prometheus::IntCounter) {
+    let write_failures = prometheus::IntCounter::new("write_failures", "write failures").unwrap();
+    let (archive, writer) = spawn_order_history_thread(store.clone(), 16, write_failures.clone());
+    let mut engine = MatchingEngine::new();
+    engine.set_order_archive(archive);
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    for mut pair in engine.pairs_mut() {
+        pair.orderbook.set_id_generator(SequentialIdGenerator::new(1));
+    }
+    (engine, writer, write_failures)
+}
+
+#[test]
+fn cancelled_order_is_archived_and_retrievable_by_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(OrderHistoryStore::open(dir.path().join("history")).unwrap());
+    let (engine, writer, write_failures) = archived_engine(&store);
+    let order_id = OrderId::from(1u128);
+
+    engine
+        .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    engine.cancel_order(vec![1], b"BTC-USD".to_vec(), order_id, vec![10], true).unwrap();
+    // stopping the writer writes what was queued
+    writer.stop();
+
+    let archived = store.get_order_history(b"BTC-USD", order_id).unwrap().expect("archived order");
+    assert_eq!(archived.state, TerminalState::Cancelled);
+    assert_eq!(archived.order.id, order_id);
+    assert_eq!(archived.order.price, 100 * SCALE_8);
+    assert_eq!(archived.order.cqty, 100 * SCALE_8);
+    assert_eq!(write_failures.get(), 0);
+}
+
+#[test]
+fn resting_order_is_not_archived() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(OrderHistoryStore::open(dir.path().join("history")).unwrap());
+    let (engine, writer, _) = archived_engine(&store);
+
+    engine
+        .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    writer.stop();
+    assert_eq!(store.get_order_history(b"BTC-USD", OrderId::from(1u128)).unwrap(), None);
+}
+
+#[test]
+fn filled_maker_and_taker_are_archived_as_filled() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(OrderHistoryStore::open(dir.path().join("history")).unwrap());
+    let (engine, writer, _) = archived_engine(&store);
+
+    engine
+        .limit_sell(vec![1], b"BTC-USD".to_vec(), None, vec![10], 100 * SCALE_8, SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    engine
+        .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![20], 100 * SCALE_8, 100 * SCALE_8, 0, 2, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    writer.stop();
+
+    for id in [1u128, 2] {
+        let archived = store.get_order_history(b"BTC-USD", OrderId::from(id)).unwrap().expect("archived order");
+        assert_eq!(archived.state, TerminalState::Filled);
+        assert_eq!(archived.order.cqty, 0);
+    }
+    assert_eq!(store.get_order_history(b"BTC-USD", OrderId::from(3u128)).unwrap(), None);
+}
+
+#[test]
+fn archived_orders_survive_reopening_the_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history");
+    {
+        let store = Arc::new(OrderHistoryStore::open(&path).unwrap());
+        let (engine, writer, _) = archived_engine(&store);
+        // more orders than the queue holds, archiving waits for the writer instead of losing one
+        for owner in 0..40u8 {
+            engine
+                .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![owner], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+                .unwrap();
+        }
+        for (id, owner) in (1u128..=40).zip(0..40u8) {
+            engine.cancel_order(vec![1], b"BTC-USD".to_vec(), OrderId::from(id), vec![owner], true).unwrap();
+        }
+        writer.stop();
+    }
+
+    // the database lock was released with the last handle of the store
+    let store = OrderHistoryStore::open(&path).unwrap();
+    for id in 1u128..=40 {
+        let archived = store.get_order_history(b"BTC-USD", OrderId::from(id)).unwrap().expect("archived order");
+        assert_eq!(archived.state, TerminalState::Cancelled);
+    }
+}
+
+#[test]
+fn rapid_archiving_through_one_handle_keeps_every_order_current() {
+    let dir = tempfile::tempdir().unwrap();
+    // opened once, every write reuses the handle
+    let store = OrderHistoryStore::open(dir.path().join("history")).unwrap();
+    let order = |id: u128| Order { id: OrderId::from(id), price: 100 * SCALE_8, cqty: id as u64, ..Default::default() };
+
+    for id in 1u128..=50 {
+        store.archive_order(b"BTC-USD", &order(id), TerminalState::Cancelled).unwrap();
+    }
+    // a later write of an id replaces the earlier one
+    for id in 1u128..=10 {
+        store.archive_order(b"BTC-USD", &order(id), TerminalState::Expired).unwrap();
+    }
+
+    for id in 1u128..=50 {
+        let archived = store.get_order_history(b"BTC-USD", OrderId::from(id)).unwrap().expect("archived order");
+        let state = if id <= 10 { TerminalState::Expired } else { TerminalState::Cancelled };
+        assert_eq!(archived, ArchivedOrder { order: order(id), state });
+    }
+}
+
+#[test]
+fn lz4_compressed_history_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history");
+    let options = OrderHistoryOptions {
+        write_buffer_size: Some(4 << 20),
+        compression: Some(parse_compression("lz4").unwrap()),
+        max_open_files: 64,
+        disable_wal: false,
+    };
+    let order = Order { id: OrderId::from(7u128), price: 100 * SCALE_8, cqty: SCALE_8, ..Default::default() };
+    {
+        let store = OrderHistoryStore::open_with_options(&path, &options).unwrap();
+        store.archive_order(b"BTC-USD", &order, TerminalState::Filled).unwrap();
+    }
+
+    let store = OrderHistoryStore::open_with_options(&path, &options).unwrap();
+    let archived = store.get_order_history(b"BTC-USD", order.id).unwrap().expect("archived order");
+    assert_eq!(archived, ArchivedOrder { order, state: TerminalState::Filled });
+    assert!(parse_compression("brotli").is_err());
+}
+
+#[test]
+fn pairs_sharing_order_ids_are_archived_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(OrderHistoryStore::open(dir.path().join("history")).unwrap());
+    let (mut engine, writer, _) = archived_engine(&store);
+    engine.add_pair(vec![1], vec![2], vec![3], b"ETH-USD".to_vec(), 0);
+    engine.get_pair(b"ETH-USD").unwrap().orderbook.set_id_generator(SequentialIdGenerator::new(1));
+    let order_id = OrderId::from(1u128);
+
+    // both pairs number their orders from 1
+    for (pair_id, price) in [(&b"BTC-USD"[..], 100 * SCALE_8), (b"ETH-USD", 10 * SCALE_8)] {
+        engine
+            .limit_buy(vec![1], pair_id.to_vec(), None, vec![10], price, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+            .unwrap();
+        engine.cancel_order(vec![1], pair_id.to_vec(), order_id, vec![10], true).unwrap();
+    }
+    writer.stop();
+
+    for (pair_id, price) in [(&b"BTC-USD"[..], 100 * SCALE_8), (b"ETH-USD", 10 * SCALE_8)] {
+        let archived = store.get_order_history(pair_id, order_id).unwrap().expect("archived order");
+        assert_eq!(archived.order.price, price);
+    }
+    assert_eq!(store.get_order_history(b"XRP-USD", order_id).unwrap(), None);
+}
@@ -0,0 +1,65 @@
+use offgrid_primitives::spot::event::SpotEvent;
+use offgrid_spot_runtime::network::{event_topic, ZmqServer};
+use std::thread;
+use std::time::Duration;
+use zmq::{Context, SUB};
+
+fn level_removed(pair_id: &[u8]) -> SpotEvent {
+    SpotEvent::SpotLevelRemoved {
+        pair_id: pair_id.to_vec(),
+        is_bid: true,
+        price: 100,
+        timestamp: 1,
+    }
+}
+
+fn subscriber(context: &Context, port: u16, topic: &[u8]) -> zmq::Socket {
+    let sub = context.socket(SUB).expect("sub socket");
+    sub.set_subscribe(topic).expect("subscribe");
+    sub.set_rcvtimeo(1000).expect("receive timeout");
+    sub.connect(&format!("tcp://127.0.0.1:{}", port)).expect("connect");
+    sub
+}
+
+#[test]
+fn event_topic_is_pair_id_or_event_type() {
+    assert_eq!(event_topic(&level_removed(b"BTC-USD")), b"BTC-USD".to_vec());
+    let transfer = SpotEvent::Transfer {
+        cid: vec![],
+        from: vec![1],
+        to: vec![2],
+        asset: b"BTC".to_vec(),
+        amnt: 1,
+        timestamp: 1,
+    };
+    assert_eq!(event_topic(&transfer), b"Transfer".to_vec());
+}
+
+#[test]
+fn subscribers_only_receive_their_pair_topic() {
+    let context = Context::new();
+    let server = ZmqServer::new(&context, 47631, 47632).expect("server");
+    let btc = subscriber(&context, 47631, b"BTC-USD");
+    let eth = subscriber(&context, 47631, b"ETH-USD");
+    // give the subscriptions time to reach the PUB socket
+    thread::sleep(Duration::from_millis(300));
+
+    for pair_id in [&b"BTC-USD"[..], b"ETH-USD", b"BTC-USD"] {
+        let event = level_removed(pair_id);
+        let payload = serde_json::to_vec(&event).unwrap();
+        server.publish_event_topic(&event_topic(&event), &payload).unwrap();
+    }
+
+    let received = |sub: &zmq::Socket| {
+        let mut topics = Vec::new();
+        while let Ok(frames) = sub.recv_multipart(0) {
+            assert_eq!(frames.len(), 2);
+            let event: SpotEvent = serde_json::from_slice(&frames[1]).unwrap();
+            assert_eq!(event_topic(&event), frames[0]);
+            topics.push(frames[0].clone());
+        }
+        topics
+    };
+    assert_eq!(received(&btc), vec![b"BTC-USD".to_vec(), b"BTC-USD".to_vec()]);
+    assert_eq!(received(&eth), vec![b"ETH-USD".to_vec()]);
+}
@@ -0,0 +1,115 @@
+use offgrid_primitives::spot::MatchingEngine;
+use offgrid_spot_runtime::metrics::Metrics;
+use offgrid_spot_runtime::network::{pair_shard, WorkerPool, WorkerResponse};
+use offgrid_spot_runtime::proto::{order_request::Request, LimitOrder, OrderRequest, OrderResponse, TimeInForce};
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const SCALE_8: u64 = 1_0000_0000;
+const ORDERS_PER_PAIR: u64 = 50;
+
+fn limit_buy(pair_id: &[u8], owner: u8, price: u64, n: u64) -> OrderRequest {
+    OrderRequest {
+        request: Some(Request::Limit(LimitOrder {
+            cid: vec![1],
+            pair_id: pair_id.to_vec(),
+            owner: vec![owner],
+            is_bid: true,
+            price,
+            amount: SCALE_8,
+            iceberg_quantity: 0,
+            timestamp: n as i64,
+            expires_at: i64::MAX,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            time_in_force: TimeInForce::GoodTillCanceled as i32,
+            client_order_id: Vec::new(),
+        })),
+        correlation_id: n.to_be_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn pair_shard_is_stable_and_in_range() {
+    for workers in 1..8 {
+        let shard = pair_shard(b"BTC-USD", workers);
+        assert!(shard < workers);
+        assert_eq!(shard, pair_shard(b"BTC-USD", workers));
+    }
+    assert_eq!(pair_shard(b"BTC-USD", 0), 0);
+}
+
+#[test]
+fn two_pairs_on_two_workers_do_not_cross_contaminate() {
+    // pick a second pair owned by the other worker
+    let first = b"BTC-USD".to_vec();
+    let second = ["ETH-USD", "SOL-USD", "XRP-USD", "ADA-USD"]
+        .iter()
+        .map(|pair| pair.as_bytes().to_vec())
+        .find(|pair| pair_shard(pair, 2) != pair_shard(&first, 2))
+        .unwrap();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], first.clone(), 0);
+    engine.add_pair(vec![1], vec![2], vec![3], second.clone(), 0);
+    let engine = Arc::new(Mutex::new(engine));
+    let (pool, responses) = WorkerPool::new(&zmq::Context::new(), 2, engine.clone(), Arc::new(Metrics::new().unwrap())).unwrap();
+    assert_eq!(pool.workers(), 2);
+
+    // each client feeds its own pair from its own thread
+    thread::scope(|scope| {
+        for (identity, pair_id, price) in [(b"client-a", &first, 100 * SCALE_8), (b"client-b", &second, 200 * SCALE_8)] {
+            let pool = &pool;
+            scope.spawn(move || {
+                for n in 0..ORDERS_PER_PAIR {
+                    pool.dispatch(identity.to_vec(), limit_buy(pair_id, identity[7], price, n));
+                }
+            });
+        }
+    });
+
+    let mut answered: HashMap<Vec<u8>, Vec<u64>> = HashMap::new();
+    for _ in 0..2 * ORDERS_PER_PAIR {
+        let response = responses.recv_timeout(Duration::from_secs(5)).expect("worker response");
+        let decoded = OrderResponse::decode(response.response.as_slice()).unwrap();
+        assert!(decoded.accepted, "{}", decoded.error);
+        let n = u64::from_be_bytes(decoded.correlation_id.try_into().unwrap());
+        answered.entry(response.identity).or_default().push(n);
+    }
+    pool.shutdown();
+
+    // every client got exactly its own answers, in the order it sent them
+    let in_order: Vec<u64> = (0..ORDERS_PER_PAIR).collect();
+    assert_eq!(answered[&b"client-a".to_vec()], in_order);
+    assert_eq!(answered[&b"client-b".to_vec()], in_order);
+
+    let engine = engine.lock().unwrap();
+    let first_l2 = &engine.get_pair(&first).unwrap().orderbook.l2;
+    let second_l2 = &engine.get_pair(&second).unwrap().orderbook.l2;
+    assert_eq!(first_l2.current_bid_level(100 * SCALE_8), Some(ORDERS_PER_PAIR * SCALE_8));
+    assert_eq!(first_l2.current_bid_level(200 * SCALE_8), None);
+    assert_eq!(second_l2.current_bid_level(200 * SCALE_8), Some(ORDERS_PER_PAIR * SCALE_8));
+    assert_eq!(second_l2.current_bid_level(100 * SCALE_8), None);
+}
+
+#[test]
+fn completed_order_wakes_up_a_poll_of_the_completions() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    let engine = Arc::new(Mutex::new(engine));
+    let (pool, responses) = WorkerPool::new(&zmq::Context::new(), 2, engine, Arc::new(Metrics::new().unwrap())).unwrap();
+
+    let mut items = [responses.as_poll_item()];
+    assert_eq!(zmq::poll(&mut items, 0).unwrap(), 0);
+    pool.dispatch(b"client-a".to_vec(), limit_buy(b"BTC-USD", 1, 100 * SCALE_8, 0));
+    // no need to poll with a short timeout, the poll returns once the response is handed over
+    assert_eq!(zmq::poll(&mut items, 5_000).unwrap(), 1);
+    let answered: Vec<WorkerResponse> = responses.try_iter().collect();
+    assert_eq!(answered.len(), 1);
+    assert_eq!(answered[0].identity, b"client-a".to_vec());
+    assert_eq!(zmq::poll(&mut items, 0).unwrap(), 0);
+    pool.shutdown();
+}
@@ -0,0 +1,242 @@
+use offgrid_primitives::spot::event::{self, SequencedEvent, SpotEvent};
+use offgrid_primitives::spot::time_in_force::TimeInForce;
+use offgrid_primitives::spot::MatchingEngine;
+use offgrid_spot_runtime::ws::protocol::{accept_key, config, MAX_CLIENT_FRAME_BYTES};
+use offgrid_spot_runtime::ws::{spawn_ws_bridge, DEFAULT_WS_MAX_CLIENTS};
+use std::io::{self, Cursor, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+const SCALE_8: u64 = 1_0000_0000;
+
+// the bridges see every published event, so tests publishing to the bus run one at a time
+static BUS_MUTEX: Mutex<()> = Mutex::new(());
+
+fn lock_bus() -> std::sync::MutexGuard<'static, ()> {
+    BUS_MUTEX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Connect to the bridge on `port` once it listens
+fn connect_tcp(port: u16) -> TcpStream {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let stream = loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+            Err(e) => panic!("WebSocket bridge did not start: {}", e),
+        }
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream
+}
+
+/// Connect and upgrade as a client
+fn connect(port: u16) -> WebSocket<TcpStream> {
+    let (websocket, response) = tungstenite::client(format!("ws://127.0.0.1:{}/", port), connect_tcp(port)).expect("upgrade");
+    assert_eq!(response.status(), 101);
+    websocket
+}
+
+fn send_text(websocket: &mut WebSocket<TcpStream>, text: &str) {
+    websocket.send(Message::text(text)).unwrap();
+}
+
+fn read_text(websocket: &mut WebSocket<TcpStream>) -> String {
+    match websocket.read().expect("read message") {
+        Message::Text(text) => text.to_string(),
+        message => panic!("expected a text message, got {:?}", message),
+    }
+}
+
+/// Send a raw upgrade request and read the answer until the bridge closes the connection
+fn upgrade_response(port: u16, request: &str) -> String {
+    let mut stream = connect_tcp(port);
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    response
+}
+
+fn place_bid() {
+    let mut engine = MatchingEngine::new();
+    engine.add_pair(vec![1], vec![2], vec![3], b"BTC-USD".to_vec(), 0);
+    let events = engine
+        .limit_buy(vec![1], b"BTC-USD".to_vec(), None, vec![10], 100 * SCALE_8, 100 * SCALE_8, 0, 1, i64::MAX, 0, 0, TimeInForce::GoodTillCanceled)
+        .unwrap();
+    event::publish_event_queue(events);
+}
+
+#[test]
+fn accept_key_matches_the_rfc_example() {
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+/// Stream reading scripted frames and recording the replies
+struct ScriptedStream {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl ScriptedStream {
+    fn new(input: &[u8]) -> Self {
+        Self { input: Cursor::new(input.to_vec()), output: Vec::new() }
+    }
+}
+
+impl Read for ScriptedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for ScriptedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+const UPGRADE: &str = "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                       Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+
+#[test]
+fn handshake_rejects_an_invalid_upgrade_request() {
+    let _guard = lock_bus();
+    event::init_event_bus();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_ws_bridge(event::register_backend(), "127.0.0.1:47664".parse().unwrap(), DEFAULT_WS_MAX_CLIENTS, shutdown.clone()).unwrap();
+
+    let response = upgrade_response(47664, &UPGRADE.replace("Version: 13", "Version: 8"));
+    assert!(response.starts_with("HTTP/1.1 426"), "{}", response);
+    assert!(response.contains("Sec-WebSocket-Version: 13"));
+
+    for request in [UPGRADE.replace("GET", "POST"), UPGRADE.replace("Upgrade: websocket", "Upgrade: h2c")] {
+        let response = upgrade_response(47664, &request);
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+}
+
+/// Read the first message a server reads from `input`
+fn read_as_server(input: &[u8]) -> tungstenite::Result<Message> {
+    WebSocket::from_raw_socket(ScriptedStream::new(input), Role::Server, Some(config())).read()
+}
+
+#[test]
+fn client_frames_must_be_masked_and_small() {
+    // masked ping of 1 byte
+    let ping = [0x89, 0x81, 0, 0, 0, 0, 7];
+    assert_eq!(read_as_server(&ping).unwrap(), Message::Ping(vec![7].into()));
+    // the same ping unmasked
+    assert!(read_as_server(&[0x89, 0x01, 7]).is_err());
+
+    // masked ping of 126 bytes
+    let mut ping = vec![0x89, 0x80 | 126, 0, 126, 0, 0, 0, 0];
+    ping.extend(std::iter::repeat_n(0, 126));
+    assert!(read_as_server(&ping).is_err());
+
+    // masked text frame one byte beyond the limit
+    let len = MAX_CLIENT_FRAME_BYTES as u64 + 1;
+    let mut text = vec![0x81, 0x80 | 127];
+    text.extend_from_slice(&len.to_be_bytes());
+    text.extend_from_slice(&[0, 0, 0, 0]);
+    text.extend(std::iter::repeat_n(b'a', len as usize));
+    assert!(read_as_server(&text).is_err());
+}
+
+#[test]
+fn connected_client_receives_a_placed_order_as_json() {
+    let _guard = lock_bus();
+    event::init_event_bus();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_ws_bridge(event::register_backend(), "127.0.0.1:47661".parse().unwrap(), DEFAULT_WS_MAX_CLIENTS, shutdown.clone()).unwrap();
+    let mut stream = connect(47661);
+    // the bridge registers the client on accept, wait for it to be served before publishing
+    send_text(&mut stream, "{}");
+    assert_eq!(read_text(&mut stream), r#"{"subscribed":{"pairs":[],"types":[]}}"#);
+
+    place_bid();
+    let placed = loop {
+        let sequenced: SequencedEvent = serde_json::from_str(&read_text(&mut stream)).unwrap();
+        if let SpotEvent::SpotOrderPlaced { pair_id, price, .. } = sequenced.event {
+            break (pair_id, price);
+        }
+    };
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert_eq!(placed, (b"BTC-USD".to_vec(), 100 * SCALE_8));
+}
+
+#[test]
+fn subscription_narrows_the_events_to_a_type() {
+    let _guard = lock_bus();
+    event::init_event_bus();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_ws_bridge(event::register_backend(), "127.0.0.1:47662".parse().unwrap(), DEFAULT_WS_MAX_CLIENTS, shutdown.clone()).unwrap();
+    let mut stream = connect(47662);
+    send_text(&mut stream, r#"{"pairs":["BTC-USD"],"types":["SpotOrderPlaced"]}"#);
+    assert_eq!(read_text(&mut stream), r#"{"subscribed":{"pairs":["BTC-USD"],"types":["SpotOrderPlaced"]}}"#);
+
+    // the lock and level events published ahead of the placement are filtered out
+    place_bid();
+    let sequenced: SequencedEvent = serde_json::from_str(&read_text(&mut stream)).unwrap();
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(matches!(sequenced.event, SpotEvent::SpotOrderPlaced { .. }), "{:?}", sequenced.event);
+}
+
+#[test]
+fn connection_beyond_the_limit_is_refused() {
+    let _guard = lock_bus();
+    event::init_event_bus();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_ws_bridge(event::register_backend(), "127.0.0.1:47663".parse().unwrap(), 1, shutdown.clone()).unwrap();
+    let mut first = connect(47663);
+    send_text(&mut first, "{}");
+    assert_eq!(read_text(&mut first), r#"{"subscribed":{"pairs":[],"types":[]}}"#);
+
+    let mut second = TcpStream::connect(("127.0.0.1", 47663)).unwrap();
+    second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = String::new();
+    second.read_to_string(&mut response).unwrap();
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 503"), "unexpected response: {}", response);
+}
+
+#[test]
+fn bridge_answers_a_ping_and_echoes_a_close() {
+    let _guard = lock_bus();
+    event::init_event_bus();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = spawn_ws_bridge(event::register_backend(), "127.0.0.1:47665".parse().unwrap(), DEFAULT_WS_MAX_CLIENTS, shutdown.clone()).unwrap();
+    let mut websocket = connect(47665);
+
+    websocket.send(Message::Ping(vec![1, 2, 3].into())).unwrap();
+    assert_eq!(websocket.read().unwrap(), Message::Pong(vec![1, 2, 3].into()));
+
+    websocket.close(None).unwrap();
+    let closed = loop {
+        match websocket.read() {
+            Ok(Message::Close(_)) => continue,
+            Ok(message) => panic!("expected the close to be echoed, got {:?}", message),
+            Err(e) => break e,
+        }
+    };
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert!(matches!(closed, tungstenite::Error::ConnectionClosed), "{:?}", closed);
+}